@@ -1,15 +1,38 @@
-use std::{rc::Rc, time::Duration};
+use std::{sync::Arc, time::Duration};
 
-use actix_web::{dev::Server, middleware::Logger, web, App, HttpServer};
-use rate_limiter_rs::{builders::RedisSettings, factory::RateLimiterFactory};
+use actix_web::{
+    dev::Server,
+    guard::{self, Guard},
+    http::KeepAlive,
+    middleware::Logger,
+    web, App, HttpServer,
+};
+use rate_limiter_rs::{
+    actix::RateLimiterMiddlewareFactory,
+    builders::RedisSettings,
+    client_ip::TrustedProxies,
+    config::{RateLimiterConfig, WindowConfig},
+    factory::RateLimiterFactory,
+    RateLimiter, RequestIdentifier,
+};
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    middleware::rate_limiter::RateLimiterMiddlewareFactory,
-    routes::{health_check::health_check, intensity::get_intensity::get_intensity},
-    settings::AppSettings,
+    routes::{
+        health_check::health_check, intensity::get_intensity::get_intensity,
+        synthetic::synthetic_ping,
+    },
+    settings::{AppSettings, RateLimiterAlgorithm},
 };
 
+/// The policy applied to the synthetic monitoring route, shared by all uptime probes
+const SYNTHETIC_MONITORING_POLICY: &str = "10/min";
+/// The header uptime probes send the shared secret of the synthetic monitoring route in
+pub const SYNTHETIC_MONITORING_TOKEN_HTTP_HEADER_NAME: &str = "x-synthetic-monitoring-token";
+/// The identifier synthetic monitoring requests are rate limited under, so that probes never
+/// consume the quota of the IP addresses they are fired from
+const SYNTHETIC_MONITORING_IDENTIFIER: &str = "synthetic-monitoring";
+
 pub struct Application {
     http_server: Server,
     port: u16,
@@ -18,33 +41,85 @@ pub struct Application {
 impl Application {
     /// Builds the main app entrypoint
     pub fn build(settings: AppSettings) -> Self {
-        let rate_limiter = RateLimiterFactory::fixed_window()
-            .with_window_size(settings.rate_limiter.window_size)
-            .with_window_duration(Duration::from_secs(
-                settings.rate_limiter.window_duration_seconds,
-            ))
-            .with_redis_settings(RedisSettings {
-                host: settings.rate_limiter.redis_server.host,
-                port: settings.rate_limiter.redis_server.port,
-            })
-            .build()
-            .expect("unable to setup rate limiter component");
+        let redis_settings = RedisSettings {
+            host: settings.rate_limiter.redis_server.host,
+            port: settings.rate_limiter.redis_server.port,
+            ..RedisSettings::default()
+        };
+        let window_config = WindowConfig {
+            window_size: Some(settings.rate_limiter.window_size),
+            window_duration_seconds: Some(settings.rate_limiter.window_duration_seconds),
+            window_duration: None,
+            redis: Some(redis_settings.clone()),
+            redis_url: None,
+        };
+        let rate_limiter_config = match settings.rate_limiter.algorithm {
+            RateLimiterAlgorithm::FixedWindow => RateLimiterConfig::FixedWindow(window_config),
+            RateLimiterAlgorithm::SlidingWindow => RateLimiterConfig::SlidingWindow(window_config),
+        };
+        let rate_limiter: Arc<dyn RateLimiter + Send + Sync> = Arc::from(
+            RateLimiterFactory::from_config(&rate_limiter_config)
+                .expect("unable to setup rate limiter component"),
+        );
+        let synthetic_rate_limiter: Arc<dyn RateLimiter + Send + Sync> = Arc::new(
+            RateLimiterFactory::from_policy(SYNTHETIC_MONITORING_POLICY)
+                .expect("invalid synthetic monitoring policy")
+                .with_redis_settings(redis_settings)
+                .build()
+                .expect("unable to setup synthetic monitoring rate limiter component"),
+        );
+
+        let trusted_proxies = web::Data::new(
+            TrustedProxies::new(&settings.rate_limiter.trusted_proxies)
+                .expect("invalid trusted proxies"),
+        );
+
+        let synthetic_monitoring_token = settings.synthetic_monitoring.token;
 
         let server = HttpServer::new(move || {
-            App::new()
+            let app = App::new()
+                .app_data(trusted_proxies.clone())
                 .wrap(Logger::default())
                 .wrap(TracingLogger::default())
                 .route("/health_check", web::get().to(health_check))
                 .service(
                     web::scope("/carbon/intensity")
-                        .wrap(RateLimiterMiddlewareFactory::with_rate_limiter(Rc::new(
+                        .wrap(RateLimiterMiddlewareFactory::with_rate_limiter(
                             rate_limiter.clone(),
-                        )))
+                        ))
                         .route("", web::get().to(get_intensity)),
-                )
+                );
+
+            // the route is only served to the probes, so that no one else can use up their quota
+            let Some(token) = &synthetic_monitoring_token else {
+                return app;
+            };
+            app.service(
+                web::scope("/synthetic")
+                    .guard(synthetic_monitor(token.clone()))
+                    .wrap(
+                        RateLimiterMiddlewareFactory::with_rate_limiter(
+                            synthetic_rate_limiter.clone(),
+                        )
+                        .with_request_identifier(
+                            RequestIdentifier::Internal(
+                                SYNTHETIC_MONITORING_IDENTIFIER.to_string(),
+                            ),
+                        ),
+                    )
+                    .route("/ping", web::get().to(synthetic_ping)),
+            )
         });
 
+        let keep_alive = match settings.concurrency.keep_alive_seconds {
+            0 => KeepAlive::Disabled,
+            seconds => KeepAlive::Timeout(Duration::from_secs(seconds)),
+        };
+
         let actix_server = server
+            .workers(settings.concurrency.workers)
+            .keep_alive(keep_alive)
+            .max_connections(settings.concurrency.max_connections)
             .bind((settings.http_server.host, settings.http_server.port))
             .expect("unable to build app");
 
@@ -63,3 +138,13 @@ impl Application {
         Ok(self.http_server)
     }
 }
+
+/// Returns the guard matching the requests of the uptime probes, sending the given shared secret
+fn synthetic_monitor(token: String) -> impl Guard {
+    guard::fn_guard(move |ctx| {
+        ctx.head()
+            .headers()
+            .get(SYNTHETIC_MONITORING_TOKEN_HTTP_HEADER_NAME)
+            .is_some_and(|value| value.as_bytes() == token.as_bytes())
+    })
+}
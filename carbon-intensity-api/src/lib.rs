@@ -1,4 +1,3 @@
 pub mod application;
-pub mod middleware;
 pub mod routes;
 pub mod settings;
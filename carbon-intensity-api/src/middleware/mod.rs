@@ -1 +0,0 @@
-pub mod rate_limiter;
@@ -0,0 +1,5 @@
+use actix_web::{HttpResponse, Responder};
+
+pub async fn synthetic_ping() -> std::io::Result<impl Responder> {
+    Ok(HttpResponse::Ok().body("pong"))
+}
@@ -1,10 +1,15 @@
+use std::{num::NonZeroUsize, thread};
+
 use config::{Config, ConfigError, Environment};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct AppSettings {
     pub http_server: ServerSettings,
+    pub concurrency: ConcurrencySettings,
     pub rate_limiter: RateLimiterSettings,
+    #[serde(default)]
+    pub synthetic_monitoring: SyntheticMonitoringSettings,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -13,15 +18,72 @@ pub struct ServerSettings {
     pub port: u16,
 }
 
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ConcurrencySettings {
+    pub workers: usize,
+    pub keep_alive_seconds: u64,
+    pub max_connections: usize,
+}
+
+impl Default for ConcurrencySettings {
+    fn default() -> Self {
+        ConcurrencySettings {
+            workers: default_workers(),
+            keep_alive_seconds: DEFAULT_KEEP_ALIVE_SECONDS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl ConcurrencySettings {
+    /// Checks the settings can be used to configure the HTTP server, which would otherwise panic
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers == 0 {
+            return Err(ConfigError::Message(
+                "concurrency.workers must be greater than 0".to_string(),
+            ));
+        }
+        if self.max_connections == 0 {
+            return Err(ConfigError::Message(
+                "concurrency.max_connections must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RateLimiterSettings {
+    pub algorithm: RateLimiterAlgorithm,
     pub window_size: u64,
     pub window_duration_seconds: u64,
     pub redis_server: ServerSettings,
+    /// The networks of the proxies trusted to set the client IP address in the `Forwarded` and
+    /// `X-Forwarded-For` headers, like `10.0.0.0/8`
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SyntheticMonitoringSettings {
+    /// The shared secret uptime probes send in the `X-Synthetic-Monitoring-Token` header. The
+    /// synthetic monitoring route is only served when set, and only to the requests sending it.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimiterAlgorithm {
+    FixedWindow,
+    SlidingWindow,
 }
 
 const DEFAULT_HTTP_SERVER_HOST: &str = "0.0.0.0";
 const DEFAULT_HTTP_SERVER_PORT: u16 = 9000;
+// Same defaults as actix-web
+const DEFAULT_KEEP_ALIVE_SECONDS: u64 = 5;
+const DEFAULT_MAX_CONNECTIONS: usize = 25_000;
+const DEFAULT_RATE_LIMITER_ALGORITHM: &str = "fixed_window";
 const DEFAULT_RATE_LIMITER_WINDOW_SIZE: u64 = 5;
 const DEFAULT_RATE_LIMITER_WINDOW_DURATION_SECONDS: u64 = 60;
 const DEFAULT_REDIS_SERVER_HOST: &str = "127.0.0.1";
@@ -32,6 +94,13 @@ impl AppSettings {
         let config_builder = Config::builder()
             .set_default("http_server.host", DEFAULT_HTTP_SERVER_HOST)?
             .set_default("http_server.port", DEFAULT_HTTP_SERVER_PORT)?
+            .set_default("concurrency.workers", default_workers() as u64)?
+            .set_default("concurrency.keep_alive_seconds", DEFAULT_KEEP_ALIVE_SECONDS)?
+            .set_default(
+                "concurrency.max_connections",
+                DEFAULT_MAX_CONNECTIONS as u64,
+            )?
+            .set_default("rate_limiter.algorithm", DEFAULT_RATE_LIMITER_ALGORITHM)?
             .set_default("rate_limiter.window_size", DEFAULT_RATE_LIMITER_WINDOW_SIZE)?
             .set_default(
                 "rate_limiter.window_duration_seconds",
@@ -43,10 +112,67 @@ impl AppSettings {
             .add_source(
                 Environment::with_prefix("app")
                     .prefix_separator("__")
-                    .separator("__"),
+                    .separator("__")
+                    .list_separator(",")
+                    .with_list_parse_key("rate_limiter.trusted_proxies")
+                    .try_parsing(true),
             )
             .build()?;
 
-        config_builder.try_deserialize()
+        let settings: AppSettings = config_builder.try_deserialize()?;
+        settings.concurrency.validate()?;
+        Ok(settings)
+    }
+}
+
+/// Returns one worker per CPU available to the process. On Linux this honours the cgroup CPU
+/// quota, so that small containers don't spawn a worker per core of the underlying host.
+fn default_workers() -> usize {
+    thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{default_workers, ConcurrencySettings};
+
+    #[test]
+    fn should_default_to_one_worker_per_available_cpu() {
+        let settings = ConcurrencySettings::default();
+
+        assert!(settings.workers > 0);
+        assert_eq!(settings.workers, default_workers());
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn should_reject_zero_workers() {
+        let settings = ConcurrencySettings {
+            workers: 0,
+            ..ConcurrencySettings::default()
+        };
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn should_reject_zero_max_connections() {
+        let settings = ConcurrencySettings {
+            max_connections: 0,
+            ..ConcurrencySettings::default()
+        };
+
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn should_allow_disabling_keep_alive() {
+        let settings = ConcurrencySettings {
+            keep_alive_seconds: 0,
+            ..ConcurrencySettings::default()
+        };
+
+        assert!(settings.validate().is_ok());
     }
 }
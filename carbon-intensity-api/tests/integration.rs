@@ -4,14 +4,17 @@ use std::{
 };
 
 use carbon_intensity_api::{
-    application::Application,
-    middleware::rate_limiter::{
-        RATE_LIMITER_REMAINING_REQUEST_HTTP_HEADER_NAME, RATE_LIMITER_RETRY_AFTER_HTTP_HEADER_NAME,
-    },
+    application::{Application, SYNTHETIC_MONITORING_TOKEN_HTTP_HEADER_NAME},
     routes::intensity::entities::CarbonIntensityData,
-    settings::{AppSettings, RateLimiterSettings, ServerSettings},
+    settings::{
+        AppSettings, ConcurrencySettings, RateLimiterAlgorithm, RateLimiterSettings,
+        ServerSettings, SyntheticMonitoringSettings,
+    },
 };
 use rand::Rng;
+use rate_limiter_rs::actix::{
+    RATE_LIMITER_REMAINING_REQUEST_HTTP_HEADER_NAME, RATE_LIMITER_RETRY_AFTER_HTTP_HEADER_NAME,
+};
 
 use reqwest::{
     header::{HeaderName, HeaderValue},
@@ -20,17 +23,23 @@ use reqwest::{
 
 const REDIS_HOST: &str = "127.0.0.1";
 const REDIS_PORT: u16 = 7379;
+/// The test client connects from localhost, and sets the client IP address in X-Forwarded-For
+const LOCALHOST: &str = "127.0.0.1";
+/// The shared secret of the synthetic monitoring route
+const SYNTHETIC_MONITORING_TOKEN: &str = "synthetic-monitoring-token";
 
 #[tokio::test]
 async fn should_return_200_if_within_request_limit() {
     //arrange
     let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
         window_size: 5,
         window_duration_seconds: 15,
         redis_server: ServerSettings {
             host: REDIS_HOST.to_string(),
             port: REDIS_PORT,
         },
+        trusted_proxies: vec![LOCALHOST.to_string()],
     };
     let api_endpoint = spawn_app(rate_limiter_settings);
     let x_forwarded_for_ip_address = generate_random_ip();
@@ -58,12 +67,14 @@ async fn should_return_200_if_within_request_limit() {
 async fn should_return_200_if_unable_to_check_rate_limit() {
     //arrange
     let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
         window_size: 5,
         window_duration_seconds: 15,
         redis_server: ServerSettings {
             host: "127.0.0.1".to_string(),
             port: 1234,
         },
+        trusted_proxies: vec![LOCALHOST.to_string()],
     };
     let api_endpoint = spawn_app(rate_limiter_settings);
     let x_forwarded_for_ip_address = generate_random_ip();
@@ -82,12 +93,14 @@ async fn should_return_200_if_unable_to_check_rate_limit() {
 async fn should_return_429_if_request_is_throttled() {
     //arrange
     let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
         window_size: 5,
         window_duration_seconds: 15,
         redis_server: ServerSettings {
             host: REDIS_HOST.to_string(),
             port: REDIS_PORT,
         },
+        trusted_proxies: vec![LOCALHOST.to_string()],
     };
     let api_endpoint = spawn_app(rate_limiter_settings.clone());
     let x_forwarded_for_ip_address = generate_random_ip();
@@ -112,12 +125,14 @@ async fn should_return_429_if_request_is_throttled() {
 async fn should_never_return_429_on_non_rate_limited_endpoints() {
     //arrange
     let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
         window_size: 5,
         window_duration_seconds: 15,
         redis_server: ServerSettings {
             host: REDIS_HOST.to_string(),
             port: REDIS_PORT,
         },
+        trusted_proxies: vec![LOCALHOST.to_string()],
     };
     let api_endpoint = spawn_app(rate_limiter_settings.clone());
     let x_forwarded_for_ip_address = generate_random_ip();
@@ -139,13 +154,102 @@ async fn should_never_return_429_on_non_rate_limited_endpoints() {
     }
 }
 
+#[tokio::test]
+async fn should_not_consume_client_quota_on_synthetic_monitoring_endpoint() {
+    //arrange
+    let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
+        window_size: 5,
+        window_duration_seconds: 15,
+        redis_server: ServerSettings {
+            host: REDIS_HOST.to_string(),
+            port: REDIS_PORT,
+        },
+        trusted_proxies: vec![LOCALHOST.to_string()],
+    };
+    let api_endpoint = spawn_app(rate_limiter_settings.clone());
+    let x_forwarded_for_ip_address = generate_random_ip();
+
+    //act
+    let ping_response = get_test_client()
+        .get(format!("{api_endpoint}/synthetic/ping"))
+        .header(
+            HeaderName::from_str("X-Forwarded-For").unwrap(),
+            HeaderValue::from_str(&x_forwarded_for_ip_address.to_string()).unwrap(),
+        )
+        .header(
+            SYNTHETIC_MONITORING_TOKEN_HTTP_HEADER_NAME,
+            SYNTHETIC_MONITORING_TOKEN,
+        )
+        .send()
+        .await
+        .expect("failed to query synthetic ping endpoint.");
+    let intensity_response = get_intensity_data(api_endpoint, x_forwarded_for_ip_address).await;
+
+    //assert
+    assert!(ping_response.status().is_success());
+    assert_eq!(
+        intensity_response
+            .headers()
+            .get(RATE_LIMITER_REMAINING_REQUEST_HTTP_HEADER_NAME)
+            .unwrap(),
+        &(rate_limiter_settings.window_size - 1).to_string()
+    );
+}
+
+#[tokio::test]
+async fn should_serve_synthetic_monitoring_endpoint_only_to_probes() {
+    //arrange
+    let rate_limiter_settings = RateLimiterSettings {
+        algorithm: RateLimiterAlgorithm::FixedWindow,
+        window_size: 5,
+        window_duration_seconds: 15,
+        redis_server: ServerSettings {
+            host: REDIS_HOST.to_string(),
+            port: REDIS_PORT,
+        },
+        trusted_proxies: vec![LOCALHOST.to_string()],
+    };
+    let api_endpoint = spawn_app(rate_limiter_settings);
+    let ping = |token: Option<&'static str>| {
+        let request = get_test_client().get(format!("{api_endpoint}/synthetic/ping"));
+        let request = match token {
+            Some(token) => request.header(SYNTHETIC_MONITORING_TOKEN_HTTP_HEADER_NAME, token),
+            None => request,
+        };
+        async move {
+            request
+                .send()
+                .await
+                .expect("failed to query synthetic ping endpoint.")
+        }
+    };
+
+    //act
+    let mut anonymous_responses = vec![];
+    for token in [None, Some("guessed-token")].into_iter().cycle().take(12) {
+        anonymous_responses.push(ping(token).await);
+    }
+    let probe_response = ping(Some(SYNTHETIC_MONITORING_TOKEN)).await;
+
+    //assert
+    for response in anonymous_responses {
+        assert_eq!(response.status().as_u16(), 404);
+    }
+    assert!(probe_response.status().is_success());
+}
+
 fn spawn_app(rate_limiter_settings: RateLimiterSettings) -> String {
     let app_settings = AppSettings {
         http_server: ServerSettings {
             host: "127.0.0.1".to_string(),
             port: 0,
         },
+        concurrency: ConcurrencySettings::default(),
         rate_limiter: rate_limiter_settings,
+        synthetic_monitoring: SyntheticMonitoringSettings {
+            token: Some(SYNTHETIC_MONITORING_TOKEN.to_string()),
+        },
     };
 
     let app = Application::build(app_settings);
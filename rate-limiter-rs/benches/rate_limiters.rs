@@ -0,0 +1,169 @@
+//! Benchmarks of the checks of the rate limiters, against an ephemeral Redis container, comparing
+//! the algorithms, the way they run their commands, and the way they connect to Redis.
+//!
+//! ```shell
+//! cargo bench --features testcontainers
+//! ```
+//!
+//! Besides the throughput measured by criterion, each benchmark reports the median and p99
+//! latency of a check, and the number of commands Redis processed per check, so that regressions
+//! of the transaction and script paths show up even when the throughput is noisy.
+use std::{
+    hint::black_box,
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rate_limiter_rs::{testing::RedisContainer, RateLimiter, RequestIdentifier};
+
+/// The number of distinct request identifiers checked in turn
+const IDENTIFIERS: u32 = 1_000;
+
+/// The number of checks sampled to compute the latency percentiles and commands per check
+const SAMPLES: usize = 2_000;
+
+type BoxedRateLimiter = Box<dyn RateLimiter + Send + Sync>;
+
+/// Returns the rate limiters to compare, by name, all connected to the given Redis server.
+fn rate_limiters(redis: &RedisContainer) -> Vec<(&'static str, BoxedRateLimiter)> {
+    let fixed_window = || {
+        redis
+            .fixed_window()
+            .with_window_size(100)
+            .with_window_duration(Duration::from_secs(60))
+            .with_shared_connections(1)
+    };
+    let sliding_window = || {
+        redis
+            .sliding_window()
+            .with_window_size(100)
+            .with_window_duration(Duration::from_secs(60))
+            .with_shared_connections(1)
+    };
+
+    let rate_limiters = vec![
+        ("fixed_window/transaction", fixed_window().build_boxed()),
+        (
+            "fixed_window/script",
+            fixed_window().with_scripted_checks(true).build_boxed(),
+        ),
+        (
+            "fixed_window/function",
+            fixed_window().with_redis_functions(true).build_boxed(),
+        ),
+        (
+            "fixed_window/hash_storage",
+            fixed_window().with_hash_storage(64).build_boxed(),
+        ),
+        (
+            "fixed_window/connection_per_check",
+            redis
+                .fixed_window()
+                .with_window_size(100)
+                .with_window_duration(Duration::from_secs(60))
+                .build_boxed(),
+        ),
+        ("sliding_window/transaction", sliding_window().build_boxed()),
+        (
+            "sliding_window/script",
+            sliding_window().with_scripted_checks(true).build_boxed(),
+        ),
+        (
+            "sliding_window/function",
+            sliding_window().with_redis_functions(true).build_boxed(),
+        ),
+        #[cfg(feature = "pool")]
+        (
+            "fixed_window/connection_pool",
+            redis
+                .fixed_window()
+                .with_window_size(100)
+                .with_window_duration(Duration::from_secs(60))
+                .with_connection_pool(8)
+                .build_boxed(),
+        ),
+    ];
+
+    rate_limiters
+        .into_iter()
+        .map(|(name, rate_limiter)| {
+            (
+                name,
+                rate_limiter.unwrap_or_else(|e| panic!("unable to build {}: {}", name, e)),
+            )
+        })
+        .collect()
+}
+
+/// Returns the request identifier of the given check, cycling through [IDENTIFIERS].
+fn request_identifier(check: u32) -> RequestIdentifier {
+    RequestIdentifier::Ip(IpAddr::V4(Ipv4Addr::from(
+        0x0a00_0000 + check % IDENTIFIERS,
+    )))
+}
+
+/// Returns the number of commands processed so far by the given Redis server.
+fn total_commands_processed(con: &mut redis::Connection) -> u64 {
+    let info: String = redis::cmd("INFO")
+        .arg("stats")
+        .query(con)
+        .expect("unable to read Redis stats");
+    info.lines()
+        .find_map(|line| line.strip_prefix("total_commands_processed:"))
+        .and_then(|count| count.trim().parse().ok())
+        .expect("missing total_commands_processed stat")
+}
+
+/// Prints the median and p99 latency of the checks of the given rate limiter, and the number of
+/// commands Redis processed per check, over [SAMPLES] checks.
+fn report(name: &str, rate_limiter: &BoxedRateLimiter, redis: &RedisContainer) {
+    let settings = redis.redis_settings();
+    let mut con = redis::Client::open(format!("redis://{}:{}", settings.host, settings.port))
+        .and_then(|client| client.get_connection())
+        .expect("unable to connect to Redis");
+
+    let commands_before = total_commands_processed(&mut con);
+    let mut latencies: Vec<Duration> = (0..SAMPLES as u32)
+        .map(|check| {
+            let started_at = Instant::now();
+            rate_limiter
+                .check_request(request_identifier(check))
+                .expect("check failed");
+            started_at.elapsed()
+        })
+        .collect();
+    // the INFO command reading the stats is counted as well
+    let commands = total_commands_processed(&mut con) - commands_before - 1;
+
+    latencies.sort();
+    println!(
+        "{}: p50 {:?}, p99 {:?}, {:.2} Redis commands per check",
+        name,
+        latencies[SAMPLES / 2],
+        latencies[SAMPLES * 99 / 100],
+        commands as f64 / SAMPLES as f64
+    );
+}
+
+fn bench_checks(c: &mut Criterion) {
+    let redis = RedisContainer::start().expect("unable to start Redis container");
+    let mut group = c.benchmark_group("checks");
+    group.throughput(Throughput::Elements(1));
+
+    for (name, rate_limiter) in rate_limiters(&redis) {
+        let mut check = 0;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                check += 1;
+                black_box(rate_limiter.check_request(request_identifier(check))).unwrap()
+            })
+        });
+        report(name, &rate_limiter, &redis);
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_checks);
+criterion_main!(benches);
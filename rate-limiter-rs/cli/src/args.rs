@@ -0,0 +1,281 @@
+//! Module that includes the parsing of the command line arguments.
+use std::{net::IpAddr, time::Duration};
+
+use rate_limiter_rs::{hash_tags::HashTag, overrides::LimitOverride, RequestIdentifier};
+
+/// The URL of the Redis server used when none is given
+const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379";
+
+/// The usage printed with `--help`, or along with invalid arguments
+pub const USAGE: &str = "\
+Inspects and manages the keys of a rate limiter stored in Redis
+
+Usage: rate-limiter-cli [OPTIONS] <COMMAND>
+
+Commands:
+  inspect <IDENTIFIER>                  Shows the count, limit and expiry of a request identifier
+  reset <IDENTIFIER>                    Deletes all the state stored for a request identifier
+  override set <IDENTIFIER> [LIMITS]    Grants bespoke limits to a request identifier
+  override remove <IDENTIFIER>          Removes the bespoke limits of a request identifier
+  list [PATTERN]                        Lists the request keys matching a pattern, like `ip_*`
+
+Identifiers:
+  ip:<ADDRESS>, custom:<KEY>:<VALUE>, internal:<SERVICE>
+
+Options:
+  --redis-url <URL>              The Redis server to connect to [default: redis://127.0.0.1:6379]
+  --algorithm <ALGORITHM>        fixed_window or sliding_window [default: fixed_window]
+  --window-size <SIZE>           The window size of the rate limiter, or of an override
+  --window-duration <SECONDS>    The window duration of the rate limiter, or of an override
+  --hash-tag <HASH_TAG>          identifier or custom_key_name, if keys are hash tagged
+  -h, --help                     Prints this help
+";
+
+/// Represents the parsed command line arguments
+pub struct Args {
+    /// The URL of the Redis server holding the keys
+    pub redis_url: String,
+
+    /// The algorithm of the rate limiter owning the keys
+    pub algorithm: Algorithm,
+
+    /// The window size of the rate limiter, or of the override to set
+    pub window_size: Option<u64>,
+
+    /// The window duration of the rate limiter, or of the override to set
+    pub window_duration: Option<Duration>,
+
+    /// The hash tag of the keys, if any
+    pub hash_tag: Option<HashTag>,
+
+    /// The command to run
+    pub command: Command,
+}
+
+/// Enum that represents the supported rate limiting algorithms
+#[derive(Debug, PartialEq)]
+pub enum Algorithm {
+    FixedWindow,
+    SlidingWindow,
+}
+
+/// Enum that represents the supported commands
+pub enum Command {
+    Inspect(RequestIdentifier),
+    Reset(RequestIdentifier),
+    SetOverride(RequestIdentifier, LimitOverride),
+    RemoveOverride(RequestIdentifier),
+    List(String),
+    Help,
+}
+
+/// Parses the given command line arguments, excluding the name of the binary.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut redis_url = DEFAULT_REDIS_URL.to_string();
+    let mut algorithm = Algorithm::FixedWindow;
+    let mut window_size = None;
+    let mut window_duration = None;
+    let mut hash_tag = None;
+    let mut positionals = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", option))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => positionals = vec!["help".to_string()],
+            "--redis-url" => redis_url = value(&arg)?,
+            "--algorithm" => algorithm = parse_algorithm(&value(&arg)?)?,
+            "--window-size" => window_size = Some(parse_number(&arg, &value(&arg)?)?),
+            "--window-duration" => {
+                window_duration = Some(Duration::from_secs(parse_number(&arg, &value(&arg)?)?))
+            }
+            "--hash-tag" => hash_tag = Some(parse_hash_tag(&value(&arg)?)?),
+            option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+            _ => positionals.push(arg),
+        }
+    }
+
+    let command = match positionals
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["help"] => Command::Help,
+        ["inspect", identifier] => Command::Inspect(parse_identifier(identifier)?),
+        ["reset", identifier] => Command::Reset(parse_identifier(identifier)?),
+        ["override", "set", identifier] => {
+            if window_size.is_none() && window_duration.is_none() {
+                return Err("override set requires --window-size or --window-duration".to_string());
+            }
+            Command::SetOverride(
+                parse_identifier(identifier)?,
+                LimitOverride {
+                    window_size,
+                    window_duration,
+                },
+            )
+        }
+        ["override", "remove", identifier] => {
+            Command::RemoveOverride(parse_identifier(identifier)?)
+        }
+        ["list"] => Command::List("*".to_string()),
+        ["list", pattern] => Command::List(pattern.to_string()),
+        [] => return Err("missing command".to_string()),
+        _ => return Err(format!("invalid command: {}", positionals.join(" "))),
+    };
+
+    Ok(Args {
+        redis_url,
+        algorithm,
+        window_size,
+        window_duration,
+        hash_tag,
+        command,
+    })
+}
+
+/// Parses a request identifier, like `ip:172.28.0.6`, `custom:tenant:acme` or `internal:billing`.
+pub fn parse_identifier(identifier: &str) -> Result<RequestIdentifier, String> {
+    let invalid = || format!("invalid identifier: {}", identifier);
+
+    match identifier.split_once(':').ok_or_else(invalid)? {
+        ("ip", address) => address
+            .parse::<IpAddr>()
+            .map(RequestIdentifier::Ip)
+            .map_err(|_| invalid()),
+        ("custom", custom) => {
+            let (key, value) = custom.split_once(':').ok_or_else(invalid)?;
+            Ok(RequestIdentifier::Custom {
+                key: key.to_string(),
+                value: value.to_string(),
+            })
+        }
+        ("internal", service) if !service.is_empty() => {
+            Ok(RequestIdentifier::Internal(service.to_string()))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Utility method that parses a rate limiting algorithm.
+fn parse_algorithm(algorithm: &str) -> Result<Algorithm, String> {
+    match algorithm {
+        "fixed_window" => Ok(Algorithm::FixedWindow),
+        "sliding_window" => Ok(Algorithm::SlidingWindow),
+        _ => Err(format!("invalid algorithm: {}", algorithm)),
+    }
+}
+
+/// Utility method that parses a hash tag.
+fn parse_hash_tag(hash_tag: &str) -> Result<HashTag, String> {
+    match hash_tag {
+        "identifier" => Ok(HashTag::Identifier),
+        "custom_key_name" => Ok(HashTag::CustomKeyName),
+        _ => Err(format!("invalid hash tag: {}", hash_tag)),
+    }
+}
+
+/// Utility method that parses the numeric value of the given option.
+fn parse_number(option: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rate_limiter_rs::{hash_tags::HashTag, RequestIdentifier};
+    use rstest::rstest;
+
+    use super::{parse_args, parse_identifier, Algorithm, Command};
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn should_parse_ip_identifier() {
+        let identifier = parse_identifier("ip:172.28.0.6").unwrap();
+
+        assert!(matches!(identifier, RequestIdentifier::Ip(ip) if ip.to_string() == "172.28.0.6"))
+    }
+
+    #[test]
+    fn should_parse_custom_identifier() {
+        let identifier = parse_identifier("custom:tenant:acme:eu").unwrap();
+
+        assert!(matches!(
+            identifier,
+            RequestIdentifier::Custom { key, value } if key == "tenant" && value == "acme:eu"
+        ))
+    }
+
+    #[test]
+    fn should_parse_internal_identifier() {
+        let identifier = parse_identifier("internal:billing").unwrap();
+
+        assert!(matches!(identifier, RequestIdentifier::Internal(service) if service == "billing"))
+    }
+
+    #[rstest]
+    #[case::missing_kind("172.28.0.6")]
+    #[case::invalid_ip("ip:localhost")]
+    #[case::missing_custom_value("custom:tenant")]
+    #[case::missing_service("internal:")]
+    #[case::unknown_kind("user:42")]
+    fn should_reject_invalid_identifier(#[case] identifier: &str) {
+        assert!(parse_identifier(identifier).is_err())
+    }
+
+    #[test]
+    fn should_parse_options() {
+        let args = parse_args(args(
+            "--redis-url redis://redis:6380 --algorithm sliding_window --window-size 10 \
+            --window-duration 60 --hash-tag identifier inspect ip:1.2.3.4",
+        ))
+        .unwrap();
+
+        assert_eq!(args.redis_url, "redis://redis:6380");
+        assert_eq!(args.algorithm, Algorithm::SlidingWindow);
+        assert_eq!(args.window_size, Some(10));
+        assert_eq!(args.window_duration, Some(Duration::from_secs(60)));
+        assert_eq!(args.hash_tag, Some(HashTag::Identifier));
+        assert!(matches!(args.command, Command::Inspect(_)));
+    }
+
+    #[test]
+    fn should_parse_override_set_command() {
+        let args = parse_args(args("override set internal:billing --window-size 500")).unwrap();
+
+        assert!(matches!(
+            args.command,
+            Command::SetOverride(_, limit_override)
+                if limit_override.window_size == Some(500) && limit_override.window_duration.is_none()
+        ))
+    }
+
+    #[rstest]
+    #[case::list_all("list", "*")]
+    #[case::list_pattern("list ip_*", "ip_*")]
+    fn should_parse_list_command(#[case] command: &str, #[case] expected_pattern: &str) {
+        let args = parse_args(args(command)).unwrap();
+
+        assert!(matches!(args.command, Command::List(pattern) if pattern == expected_pattern))
+    }
+
+    #[rstest]
+    #[case::missing_command("")]
+    #[case::unknown_command("delete ip:1.2.3.4")]
+    #[case::unknown_option("--verbose list")]
+    #[case::missing_option_value("list --redis-url")]
+    #[case::override_without_limits("override set ip:1.2.3.4")]
+    fn should_reject_invalid_args(#[case] command: &str) {
+        assert!(parse_args(args(command)).is_err())
+    }
+}
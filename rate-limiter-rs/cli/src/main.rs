@@ -0,0 +1,128 @@
+//! Command line tool to inspect, reset, override and list the keys of a rate limiter stored in
+//! Redis, using the same key format as the library, so that operators don't have to hand-craft
+//! `redis-cli` commands matching its internal encoding.
+//!
+//! ```shell
+//! rate-limiter-cli --window-size 100 --window-duration 60 inspect ip:172.28.0.6
+//! rate-limiter-cli override set custom:tenant:acme --window-size 1000
+//! rate-limiter-cli --algorithm sliding_window list 'ip_*'
+//! ```
+use std::{env, process::ExitCode, time::Duration};
+
+use args::{parse_args, Algorithm, Args, Command, USAGE};
+use rate_limiter_rs::{
+    errors::RateLimiterError, factory::RateLimiterFactory, listing::ListCursor, RedisAdmin,
+};
+
+mod args;
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the parsed command against the rate limiter described by the given arguments.
+fn run(args: Args) -> Result<(), RateLimiterError> {
+    if let Command::Help = args.command {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let rate_limiter = build_rate_limiter(&args)?;
+    match args.command {
+        Command::Inspect(request_identifier) => {
+            let inspection = rate_limiter.inspect(request_identifier)?;
+            println!("key: {}", inspection.key);
+            println!("count: {}/{}", inspection.count, inspection.limit);
+            println!("window: {}s", inspection.window_duration.as_secs());
+            println!("overridden: {}", inspection.overridden);
+            println!("throttled: {}", inspection.is_throttled());
+            println!("expire in: {}", format_expire_in(inspection.expire_in));
+        }
+        Command::Reset(request_identifier) => {
+            let deleted = rate_limiter.purge_identifier(request_identifier)?;
+            println!("deleted {} keys", deleted);
+        }
+        Command::SetOverride(request_identifier, limit_override) => {
+            rate_limiter.set_limit_override(request_identifier, &limit_override)?;
+            println!("override set");
+        }
+        Command::RemoveOverride(request_identifier) => {
+            if rate_limiter.remove_limit_override(request_identifier)? {
+                println!("override removed");
+            } else {
+                println!("no override set");
+            }
+        }
+        Command::List(pattern) => {
+            let mut cursor = Some(ListCursor::default());
+            while let Some(current_cursor) = cursor {
+                let listing = rate_limiter.list_keys(&pattern, current_cursor)?;
+                for listed_key in listing.keys {
+                    println!("{}\t{}", listed_key.key, listed_key.count);
+                }
+                cursor = listing.next_cursor;
+            }
+        }
+        Command::Help => unreachable!(),
+    }
+    Ok(())
+}
+
+/// Builds the rate limiter described by the given arguments, with limit overrides enabled so
+/// that inspections report them.
+fn build_rate_limiter(args: &Args) -> Result<Box<dyn RedisAdmin + Send + Sync>, RateLimiterError> {
+    match args.algorithm {
+        Algorithm::FixedWindow => {
+            let mut builder = RateLimiterFactory::fixed_window()
+                .with_redis_url(&args.redis_url)
+                .with_limit_overrides(true);
+            if let Some(window_size) = args.window_size {
+                builder = builder.with_window_size(window_size);
+            }
+            if let Some(window_duration) = args.window_duration {
+                builder = builder.with_window_duration(window_duration);
+            }
+            if let Some(hash_tag) = args.hash_tag {
+                builder = builder.with_hash_tag(hash_tag);
+            }
+            Ok(Box::new(builder.build()?))
+        }
+        Algorithm::SlidingWindow => {
+            let mut builder = RateLimiterFactory::sliding_window()
+                .with_redis_url(&args.redis_url)
+                .with_limit_overrides(true);
+            if let Some(window_size) = args.window_size {
+                builder = builder.with_window_size(window_size);
+            }
+            if let Some(window_duration) = args.window_duration {
+                builder = builder.with_window_duration(window_duration);
+            }
+            if let Some(hash_tag) = args.hash_tag {
+                builder = builder.with_hash_tag(hash_tag);
+            }
+            Ok(Box::new(builder.build()?))
+        }
+    }
+}
+
+/// Utility method that formats the remaining time to live of a key.
+fn format_expire_in(expire_in: Option<Duration>) -> String {
+    match expire_in {
+        Some(expire_in) => format!("{}ms", expire_in.as_millis()),
+        None => "never".to_string(),
+    }
+}
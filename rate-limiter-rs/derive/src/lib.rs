@@ -0,0 +1,272 @@
+//! Derive and attribute macros for the `rate-limiter-rs` crate.
+//!
+//! This crate is not meant to be used directly: enable the `derive` feature of `rate-limiter-rs`
+//! instead, which re-exports the macros below.
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, FnArg, ItemFn, LitStr, ReturnType};
+
+/// Derives `ToRequestIdentifier` for a struct, turning it into a deterministic
+/// `RequestIdentifier::Custom` request identifier.
+///
+/// The key of the identifier is the struct name in snake case, unless overridden with
+/// `#[rate_limit_key(name = "...")]`. The value is made of all the fields, in declaration order,
+/// encoded as `<field>=<value>` and separated by `:`. Fields are formatted with their
+/// `ToString` implementation, and can be excluded with `#[rate_limit_key(skip)]`.
+///
+/// ```ignore
+/// #[derive(RateLimitKey)]
+/// struct ClientKey {
+///     tenant: String,
+///     user: String,
+/// }
+///
+/// // yields the `rl:cst_client_key:tenant=acme:user=dili91` request key
+/// ```
+#[proc_macro_derive(RateLimitKey, attributes(rate_limit_key))]
+pub fn derive_rate_limit_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_rate_limit_key(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_rate_limit_key(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let key = match key_name_override(&input)? {
+        Some(key) => key,
+        None => to_snake_case(&input.ident.to_string()),
+    };
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "RateLimitKey can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut components = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        if is_skipped(field)? {
+            continue;
+        }
+
+        let (label, member) = match &field.ident {
+            Some(ident) => (ident.to_string(), quote!(#ident)),
+            None => {
+                let index = syn::Index::from(index);
+                (index.index.to_string(), quote!(#index))
+            }
+        };
+
+        components.push(quote! {
+            ::std::format!(
+                "{}={}",
+                #label,
+                ::rate_limiter_rs::encode_key_component(
+                    &::std::string::ToString::to_string(&self.#member)
+                )
+            )
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::rate_limiter_rs::ToRequestIdentifier for #name #ty_generics #where_clause {
+            fn to_request_identifier(&self) -> ::rate_limiter_rs::RequestIdentifier {
+                let components: ::std::vec::Vec<::std::string::String> = ::std::vec![#(#components),*];
+
+                ::rate_limiter_rs::RequestIdentifier::Custom {
+                    key: ::std::string::String::from(#key),
+                    value: components.join(":"),
+                }
+            }
+        }
+    })
+}
+
+/// Wraps an async actix-web handler with the rate limiter registered under the given policy name,
+/// in the `RateLimiterRegistry` added to the app data, rate limiting requests by the IP address
+/// of the client.
+///
+/// The wrapped handler takes the `HttpRequest` as an additional first argument, and returns a
+/// `RateLimited` responder: the attribute goes below the route attribute, if any.
+///
+/// ```ignore
+/// #[get("/search")]
+/// #[rate_limited(policy = "search")]
+/// async fn search(query: web::Query<SearchQuery>) -> impl Responder {
+///     // ...
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn rate_limited(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut policy = None;
+    let args_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("policy") {
+            policy = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported rate_limited attribute"))
+        }
+    });
+    parse_macro_input!(args with args_parser);
+    let handler = parse_macro_input!(input as ItemFn);
+
+    expand_rate_limited(policy, handler)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_rate_limited(policy: Option<LitStr>, handler: ItemFn) -> syn::Result<TokenStream2> {
+    let policy = policy.ok_or_else(|| {
+        syn::Error::new(
+            Span::call_site(),
+            "missing policy, like #[rate_limited(policy = \"search\")]",
+        )
+    })?;
+    if handler.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            handler.sig.fn_token,
+            "rate_limited can only be applied to async handlers",
+        ));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = handler;
+
+    // the arguments of the wrapper are named after their position, so that patterns
+    // destructuring extractors are left to the original handler
+    let mut wrapper_sig = sig.clone();
+    let mut arguments = Vec::new();
+    for (index, input) in wrapper_sig.inputs.iter_mut().enumerate() {
+        match input {
+            FnArg::Typed(pat_type) => {
+                let argument = format_ident!("__rate_limited_arg{}", index);
+                *pat_type.pat = parse_quote!(#argument);
+                arguments.push(argument);
+            }
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "rate_limited can't be applied to methods",
+                ))
+            }
+        }
+    }
+    wrapper_sig.inputs.insert(
+        0,
+        parse_quote!(__rate_limited_req: ::actix_web::HttpRequest),
+    );
+
+    let output = match &sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+    wrapper_sig.output = parse_quote!(-> ::rate_limiter_rs::actix::RateLimited<#output>);
+
+    let mut handler_sig = sig;
+    handler_sig.ident = format_ident!("__rate_limited_handler");
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #wrapper_sig {
+            #handler_sig #block
+
+            ::rate_limiter_rs::actix::run_rate_limited(
+                __rate_limited_req,
+                #policy,
+                __rate_limited_handler(#(#arguments),*),
+            )
+            .await
+        }
+    })
+}
+
+/// Utility method that reads the `#[rate_limit_key(name = "...")]` struct attribute, if any.
+fn key_name_override(input: &DeriveInput) -> syn::Result<Option<String>> {
+    let mut key = None;
+
+    for attr in &input.attrs {
+        if !attr.path().is_ident("rate_limit_key") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                key = Some(value.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rate_limit_key struct attribute"))
+            }
+        })?;
+    }
+
+    Ok(key)
+}
+
+/// Utility method that tells whether a field is marked with `#[rate_limit_key(skip)]`.
+fn is_skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skipped = false;
+
+    for attr in &field.attrs {
+        if !attr.path().is_ident("rate_limit_key") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skipped = true;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported rate_limit_key field attribute"))
+            }
+        })?;
+    }
+
+    Ok(skipped)
+}
+
+/// Utility method that converts a type name like `ClientKey` into `client_key`.
+fn to_snake_case(name: &str) -> String {
+    let mut snake_case = String::with_capacity(name.len() + 4);
+    let mut previous_is_lowercase = false;
+
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if previous_is_lowercase {
+                snake_case.push('_');
+            }
+            snake_case.extend(c.to_lowercase());
+            previous_is_lowercase = false;
+        } else {
+            snake_case.push(c);
+            previous_is_lowercase = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+
+    snake_case
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_snake_case;
+
+    #[test]
+    fn should_convert_type_names_to_snake_case() {
+        assert_eq!(to_snake_case("ClientKey"), "client_key");
+        assert_eq!(to_snake_case("Tenant"), "tenant");
+        assert_eq!(to_snake_case("ApiKey2Scope"), "api_key2_scope");
+    }
+}
@@ -0,0 +1,234 @@
+//! Module that includes the parsing of the command line arguments.
+use std::time::Duration;
+
+use rate_limiter_rs::{durations::parse_duration, policy::Policy};
+
+use crate::traffic::{Pattern, Traffic};
+
+/// The URL of the Redis server used when none is given
+const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379";
+
+/// The usage printed with `--help`, or along with invalid arguments
+pub const USAGE: &str = "\
+Replays a traffic pattern against a rate limiter, reporting the share of allowed, throttled and
+failed checks over time
+
+Usage: rate-limiter-loadgen --policy <POLICY> [OPTIONS]
+
+Patterns:
+  steady      Requests evenly spaced in time, from the same clients in turn
+  bursty      Requests sent all at once every burst interval, at the same average rate
+  rotating    Requests evenly spaced in time, from clients replaced every rotation period
+
+Options:
+  --policy <POLICY>              The policy of the rate limiter, like `100/min` or `10/s burst 20`
+  --redis-url <URL>              The Redis server to connect to [default: redis://127.0.0.1:6379]
+  --algorithm <ALGORITHM>        fixed_window or sliding_window [default: sliding_window]
+  --pattern <PATTERN>            steady, bursty or rotating [default: steady]
+  --rate <REQUESTS>              The average number of requests per second [default: 10]
+  --clients <CLIENTS>            The number of clients sending requests at once [default: 1]
+  --duration <DURATION>          The duration of the run, like `30s` or `5m` [default: 1m]
+  --burst-interval <DURATION>    The interval between the bursts of requests [default: 10s]
+  --rotate-every <DURATION>      The period after which clients are replaced [default: 30s]
+  --report-interval <DURATION>   The interval of the reported outcomes [default: 1s]
+  -h, --help                     Prints this help
+";
+
+/// Represents the parsed command line arguments
+pub struct Args {
+    /// The URL of the Redis server holding the counters
+    pub redis_url: String,
+
+    /// The algorithm of the rate limiter
+    pub algorithm: Algorithm,
+
+    /// The policy of the rate limiter
+    pub policy: Policy,
+
+    /// The traffic replayed against the rate limiter
+    pub traffic: Traffic,
+
+    /// The duration of the run
+    pub duration: Duration,
+
+    /// The interval of the reported outcomes
+    pub report_interval: Duration,
+}
+
+/// Enum that represents the supported rate limiting algorithms
+#[derive(Debug, PartialEq)]
+pub enum Algorithm {
+    FixedWindow,
+    SlidingWindow,
+}
+
+/// Parses the given command line arguments, excluding the name of the binary. Returns `None`
+/// when the help is requested.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut redis_url = DEFAULT_REDIS_URL.to_string();
+    let mut algorithm = Algorithm::SlidingWindow;
+    let mut policy = None;
+    let mut pattern = "steady".to_string();
+    let mut rate = 10.0;
+    let mut clients = 1;
+    let mut duration = Duration::from_secs(60);
+    let mut burst_interval = Duration::from_secs(10);
+    let mut rotate_every = Duration::from_secs(30);
+    let mut report_interval = Duration::from_secs(1);
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", option))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--redis-url" => redis_url = value(&arg)?,
+            "--algorithm" => algorithm = parse_algorithm(&value(&arg)?)?,
+            "--policy" => {
+                let value = value(&arg)?;
+                policy = Some(value.parse().map_err(|e| format!("{}: {}", value, e))?)
+            }
+            "--pattern" => pattern = value(&arg)?,
+            "--rate" => rate = parse_positive(&arg, &value(&arg)?)?,
+            "--clients" => clients = parse_count(&arg, &value(&arg)?)?,
+            "--duration" => duration = parse_period(&arg, &value(&arg)?)?,
+            "--burst-interval" => burst_interval = parse_period(&arg, &value(&arg)?)?,
+            "--rotate-every" => rotate_every = parse_period(&arg, &value(&arg)?)?,
+            "--report-interval" => report_interval = parse_period(&arg, &value(&arg)?)?,
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    let pattern = match pattern.as_str() {
+        "steady" => Pattern::Steady,
+        "bursty" => Pattern::Bursty {
+            interval: burst_interval,
+        },
+        "rotating" => Pattern::Rotating {
+            every: rotate_every,
+        },
+        _ => return Err(format!("invalid pattern: {}", pattern)),
+    };
+    let policy = policy.ok_or_else(|| "missing --policy".to_string())?;
+
+    Ok(Some(Args {
+        redis_url,
+        algorithm,
+        policy,
+        traffic: Traffic {
+            pattern,
+            rate,
+            clients,
+        },
+        duration,
+        report_interval,
+    }))
+}
+
+/// Utility method that parses a rate limiting algorithm.
+fn parse_algorithm(algorithm: &str) -> Result<Algorithm, String> {
+    match algorithm {
+        "fixed_window" => Ok(Algorithm::FixedWindow),
+        "sliding_window" => Ok(Algorithm::SlidingWindow),
+        _ => Err(format!("invalid algorithm: {}", algorithm)),
+    }
+}
+
+/// Utility method that parses the positive numeric value of the given option.
+fn parse_positive(option: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite() && *number > 0.0)
+        .ok_or_else(|| format!("invalid value for {}: {}", option, value))
+}
+
+/// Utility method that parses the non-zero count of the given option.
+fn parse_count(option: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .ok_or_else(|| format!("invalid value for {}: {}", option, value))
+}
+
+/// Utility method that parses the non-zero duration of the given option.
+fn parse_period(option: &str, value: &str) -> Result<Duration, String> {
+    parse_duration(value)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("invalid value for {}: {}", option, value))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use crate::traffic::Pattern;
+
+    use super::{parse_args, Algorithm};
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn should_parse_options() {
+        let args = parse_args(args(
+            "--redis-url redis://redis:6380 --algorithm fixed_window --policy 100/min \
+            --pattern bursty --burst-interval 5s --rate 2.5 --clients 10 --duration 5m \
+            --report-interval 10s",
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(args.redis_url, "redis://redis:6380");
+        assert_eq!(args.algorithm, Algorithm::FixedWindow);
+        assert_eq!(args.policy.limit, 100);
+        assert_eq!(
+            args.traffic.pattern,
+            Pattern::Bursty {
+                interval: Duration::from_secs(5)
+            }
+        );
+        assert_eq!(args.traffic.rate, 2.5);
+        assert_eq!(args.traffic.clients, 10);
+        assert_eq!(args.duration, Duration::from_secs(300));
+        assert_eq!(args.report_interval, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn should_default_to_steady_traffic() {
+        let args = parse_args(args("--policy 10/s")).unwrap().unwrap();
+
+        assert_eq!(args.algorithm, Algorithm::SlidingWindow);
+        assert_eq!(args.traffic.pattern, Pattern::Steady);
+        assert_eq!(args.traffic.rate, 10.0);
+        assert_eq!(args.traffic.clients, 1);
+        assert_eq!(args.duration, Duration::from_secs(60));
+    }
+
+    #[rstest]
+    #[case::short("-h")]
+    #[case::long("--rate 10 --help")]
+    fn should_parse_help(#[case] command: &str) {
+        assert!(parse_args(args(command)).unwrap().is_none())
+    }
+
+    #[rstest]
+    #[case::missing_policy("--rate 10")]
+    #[case::invalid_policy("--policy lots")]
+    #[case::unknown_pattern("--policy 10/s --pattern random")]
+    #[case::zero_rate("--policy 10/s --rate 0")]
+    #[case::fractional_clients("--policy 10/s --clients 0.5")]
+    #[case::zero_duration("--policy 10/s --duration 0s")]
+    #[case::unknown_argument("--policy 10/s --verbose")]
+    #[case::missing_option_value("--policy")]
+    fn should_reject_invalid_args(#[case] command: &str) {
+        assert!(parse_args(args(command)).is_err())
+    }
+}
@@ -0,0 +1,121 @@
+//! Load generator replaying traffic patterns against a rate limiter, and reporting the share of
+//! allowed, throttled and failed checks over time, so that a policy can be validated against the
+//! expected traffic before it is deployed.
+//!
+//! ```shell
+//! rate-limiter-loadgen --policy 100/min --rate 2 --clients 10
+//! rate-limiter-loadgen --policy '10/s burst 20' --pattern bursty --burst-interval 5s
+//! rate-limiter-loadgen --policy 100/min --pattern rotating --rotate-every 30s --duration 5m
+//! ```
+//!
+//! Checks are performed one at a time: when they are slower than the requested rate, the
+//! requests are sent late, and the reported intervals hold fewer checks.
+use std::{
+    env,
+    process::ExitCode,
+    thread,
+    time::{Instant, SystemTime},
+};
+
+use args::{parse_args, Algorithm, Args, USAGE};
+use rate_limiter_rs::{
+    durations::format_duration, errors::RateLimiterError, factory::RateLimiterFactory, RateLimiter,
+    RateLimiterResponse, RequestIdentifier,
+};
+use report::{Outcome, Report};
+
+mod args;
+mod report;
+mod traffic;
+
+/// The key of the custom request identifiers of the simulated clients
+const CLIENT_KEY: &str = "loadgen";
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Replays the traffic described by the given arguments, printing the outcomes of the checks.
+fn run(args: Args) -> Result<(), RateLimiterError> {
+    let rate_limiter = build_rate_limiter(&args)?;
+    // identifies the clients of this run, so that they don't share counters with previous runs
+    let run_id = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_e| RateLimiterError::ComputeError)?
+        .as_secs();
+
+    println!(
+        "replaying {:?} traffic of {} clients at {} requests/s for {}, against {} requests per {}",
+        args.traffic.pattern,
+        args.traffic.clients,
+        args.traffic.rate,
+        format_duration(args.duration),
+        args.policy.window_size(),
+        format_duration(args.policy.window_duration())
+    );
+
+    let mut report = Report::new(args.report_interval);
+    let started_at = Instant::now();
+    for request in 0..args.traffic.requests(args.duration) {
+        if let Some(wait) = args
+            .traffic
+            .send_at(request)
+            .checked_sub(started_at.elapsed())
+        {
+            thread::sleep(wait);
+        }
+
+        let outcome = match rate_limiter.check_request(RequestIdentifier::Custom {
+            key: CLIENT_KEY.to_string(),
+            value: format!("{}_{}", run_id, args.traffic.client(request)),
+        }) {
+            Ok(RateLimiterResponse::RequestAllowed(_)) => Outcome::Allowed,
+            Ok(RateLimiterResponse::RequestThrottled(_)) => Outcome::Throttled,
+            Err(_) => Outcome::Error,
+        };
+        for line in report.record(started_at.elapsed(), outcome) {
+            println!("{}", line);
+        }
+    }
+
+    for line in report.finish() {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Builds the rate limiter described by the given arguments.
+fn build_rate_limiter(args: &Args) -> Result<Box<dyn RateLimiter + Send + Sync>, RateLimiterError> {
+    match args.algorithm {
+        Algorithm::FixedWindow => RateLimiterFactory::fixed_window()
+            .with_redis_url(&args.redis_url)
+            .with_window_size(args.policy.window_size())
+            .with_window_duration(args.policy.window_duration())
+            .with_shared_connections(1)
+            .build_boxed(),
+        Algorithm::SlidingWindow => RateLimiterFactory::sliding_window()
+            .with_redis_url(&args.redis_url)
+            .with_window_size(args.policy.window_size())
+            .with_window_duration(args.policy.window_duration())
+            .with_shared_connections(1)
+            .build_boxed(),
+    }
+}
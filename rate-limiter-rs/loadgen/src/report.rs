@@ -0,0 +1,131 @@
+//! Module that includes the report of the outcomes of the checks over time.
+use std::time::Duration;
+
+use rate_limiter_rs::durations::format_duration;
+
+/// Enum that represents the outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Allowed,
+    Throttled,
+    Error,
+}
+
+/// Represents the number of checks of each outcome
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Counts {
+    pub allowed: u64,
+    pub throttled: u64,
+    pub errors: u64,
+}
+
+impl Counts {
+    fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Allowed => self.allowed += 1,
+            Outcome::Throttled => self.throttled += 1,
+            Outcome::Error => self.errors += 1,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.allowed + self.throttled + self.errors
+    }
+
+    /// Formats the counts, along with their share of all the checks.
+    fn format(&self) -> String {
+        let share = |count: u64| count as f64 * 100.0 / self.total().max(1) as f64;
+        format!(
+            "allowed {} ({:.1}%)  throttled {} ({:.1}%)  errors {} ({:.1}%)",
+            self.allowed,
+            share(self.allowed),
+            self.throttled,
+            share(self.throttled),
+            self.errors,
+            share(self.errors)
+        )
+    }
+}
+
+/// Represents the outcomes of the checks of a run, aggregated by reporting interval
+pub struct Report {
+    interval: Duration,
+    current_interval: u32,
+    current: Counts,
+    totals: Counts,
+}
+
+impl Report {
+    /// Creates a report aggregating the outcomes over the given interval.
+    pub fn new(interval: Duration) -> Self {
+        Report {
+            interval,
+            current_interval: 0,
+            current: Counts::default(),
+            totals: Counts::default(),
+        }
+    }
+
+    /// Records the outcome of a check completed at the given time since the start of the run.
+    /// Returns the lines of the intervals completed before it, if any.
+    pub fn record(&mut self, elapsed: Duration, outcome: Outcome) -> Vec<String> {
+        let interval = (elapsed.as_nanos() / self.interval.as_nanos().max(1)) as u32;
+        let mut lines = vec![];
+        while self.current_interval < interval {
+            lines.push(self.complete_interval());
+        }
+        self.current.record(outcome);
+        self.totals.record(outcome);
+        lines
+    }
+
+    /// Returns the lines of the last interval and of the totals of the run.
+    pub fn finish(mut self) -> Vec<String> {
+        vec![
+            self.complete_interval(),
+            format!("{:>8}  {}", "total", self.totals.format()),
+        ]
+    }
+
+    /// Returns the line of the current interval, and moves on to the next one.
+    fn complete_interval(&mut self) -> String {
+        self.current_interval += 1;
+        let line = format!(
+            "{:>8}  {}",
+            format_duration(self.interval * self.current_interval),
+            self.current.format()
+        );
+        self.current = Counts::default();
+        line
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Outcome, Report};
+
+    #[test]
+    fn should_report_each_interval_and_the_totals() {
+        //arrange
+        let mut report = Report::new(Duration::from_secs(1));
+
+        //act
+        let mut lines = report.record(Duration::from_millis(100), Outcome::Allowed);
+        lines.extend(report.record(Duration::from_millis(900), Outcome::Throttled));
+        lines.extend(report.record(Duration::from_millis(2500), Outcome::Error));
+        lines.extend(report.finish());
+
+        //assert
+        assert_eq!(
+            lines,
+            vec![
+                "      1s  allowed 1 (50.0%)  throttled 1 (50.0%)  errors 0 (0.0%)",
+                "      2s  allowed 0 (0.0%)  throttled 0 (0.0%)  errors 0 (0.0%)",
+                "      3s  allowed 0 (0.0%)  throttled 0 (0.0%)  errors 1 (100.0%)",
+                "   total  allowed 1 (33.3%)  throttled 1 (33.3%)  errors 1 (33.3%)",
+            ]
+        );
+    }
+}
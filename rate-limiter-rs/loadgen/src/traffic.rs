@@ -0,0 +1,110 @@
+//! Module that includes the traffic patterns replayed against the rate limiter.
+use std::time::Duration;
+
+/// Enum that represents the supported traffic patterns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pattern {
+    /// Requests evenly spaced in time, from a fixed set of clients in turn
+    Steady,
+    /// Requests sent all at once every given interval, at the same average rate
+    Bursty { interval: Duration },
+    /// Requests evenly spaced in time, from a set of clients replaced by new ones every given
+    /// period, like clients rotating their IP addresses or API keys
+    Rotating { every: Duration },
+}
+
+/// Represents the traffic of a run: the requests of a number of clients, sent at a given
+/// average rate, following a pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Traffic {
+    /// the pattern of the requests
+    pub pattern: Pattern,
+    /// the average number of requests per second
+    pub rate: f64,
+    /// the number of clients sending requests at the same time
+    pub clients: u64,
+}
+
+impl Traffic {
+    /// Returns the number of requests sent over the given duration.
+    pub fn requests(&self, duration: Duration) -> u64 {
+        (self.rate * duration.as_secs_f64()).floor() as u64
+    }
+
+    /// Returns when the given request is sent, since the start of the run.
+    pub fn send_at(&self, request: u64) -> Duration {
+        match self.pattern {
+            Pattern::Steady | Pattern::Rotating { .. } => {
+                Duration::from_secs_f64(request as f64 / self.rate)
+            }
+            Pattern::Bursty { interval } => {
+                let burst_size = (self.rate * interval.as_secs_f64()).round().max(1.0) as u64;
+                interval * (request / burst_size) as u32
+            }
+        }
+    }
+
+    /// Returns the client sending the given request.
+    pub fn client(&self, request: u64) -> u64 {
+        let client = request % self.clients;
+        match self.pattern {
+            Pattern::Steady | Pattern::Bursty { .. } => client,
+            Pattern::Rotating { every } => {
+                let generation = self.send_at(request).as_nanos() / every.as_nanos().max(1);
+                client + self.clients * generation as u64
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Pattern, Traffic};
+
+    fn traffic(pattern: Pattern) -> Traffic {
+        Traffic {
+            pattern,
+            rate: 10.0,
+            clients: 2,
+        }
+    }
+
+    #[test]
+    fn should_space_steady_requests_evenly() {
+        let traffic = traffic(Pattern::Steady);
+
+        assert_eq!(traffic.requests(Duration::from_secs(60)), 600);
+        assert_eq!(traffic.send_at(0), Duration::ZERO);
+        assert_eq!(traffic.send_at(15), Duration::from_millis(1500));
+        assert_eq!(
+            (0..4).map(|r| traffic.client(r)).collect::<Vec<_>>(),
+            vec![0, 1, 0, 1]
+        );
+    }
+
+    #[test]
+    fn should_send_bursts_at_the_same_average_rate() {
+        let traffic = traffic(Pattern::Bursty {
+            interval: Duration::from_secs(5),
+        });
+
+        assert_eq!(traffic.send_at(0), Duration::ZERO);
+        assert_eq!(traffic.send_at(49), Duration::ZERO);
+        assert_eq!(traffic.send_at(50), Duration::from_secs(5));
+        assert_eq!(traffic.send_at(149), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn should_rotate_clients() {
+        let traffic = traffic(Pattern::Rotating {
+            every: Duration::from_secs(1),
+        });
+
+        assert_eq!(traffic.client(0), 0);
+        assert_eq!(traffic.client(9), 1);
+        assert_eq!(traffic.client(10), 2);
+        assert_eq!(traffic.client(25), 5);
+    }
+}
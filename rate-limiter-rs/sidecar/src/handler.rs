@@ -0,0 +1,261 @@
+//! Module that includes the handling of the HTTP requests, regardless of the server serving them.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use rate_limiter_rs::{
+    errors::RateLimiterError, factory::RateLimiterFactory, RateLimiter, RateLimiterResponse,
+    RequestIdentifier, ThrottleReason,
+};
+use serde::{Deserialize, Serialize};
+
+/// Represents the body of a `POST /check` request
+#[derive(Deserialize)]
+pub struct CheckRequest {
+    /// The request identifier to check, like `{"Ip": "172.28.0.6"}`
+    pub identifier: RequestIdentifier,
+
+    /// The policy to check the request identifier against, like `100/min`
+    pub policy: String,
+}
+
+/// Represents the body of a `POST /check` response
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CheckResponse {
+    /// Whether the request is allowed
+    pub allowed: bool,
+
+    /// The maximum number of requests allowed in a single window
+    pub limit: u64,
+
+    /// The number of requests left in the current window
+    pub remaining: u64,
+
+    /// The milliseconds until the budget of the current window is restored
+    pub reset_in_millis: u64,
+
+    /// The milliseconds after which a throttled request should be retried, if throttled
+    pub retry_in_millis: Option<u64>,
+
+    /// The reason why the request was throttled, if throttled
+    pub reason: Option<ThrottleReason>,
+}
+
+impl From<RateLimiterResponse> for CheckResponse {
+    fn from(response: RateLimiterResponse) -> Self {
+        let (status, remaining, throttled) = match response {
+            RateLimiterResponse::RequestAllowed(allowed) => {
+                (allowed.status, allowed.remaining_request_counter, None)
+            }
+            RateLimiterResponse::RequestThrottled(throttled) => (
+                throttled.status,
+                0,
+                Some((throttled.retry_in, throttled.reason)),
+            ),
+        };
+
+        CheckResponse {
+            allowed: throttled.is_none(),
+            limit: status.limit,
+            remaining,
+            reset_in_millis: status
+                .reset_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+                .as_millis() as u64,
+            retry_in_millis: throttled.map(|(retry_in, _)| retry_in.as_millis() as u64),
+            reason: throttled.map(|(_, reason)| reason),
+        }
+    }
+}
+
+/// Represents the body of an error response
+#[derive(Serialize)]
+struct ErrorResponse {
+    /// The description of the error
+    error: String,
+}
+
+/// Represents a response to send, with its status code and JSON body
+#[derive(Debug, PartialEq)]
+pub struct HttpResponse {
+    /// The status code of the response
+    pub status: u16,
+
+    /// The JSON body of the response
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Utility method that builds an error response, with the given message.
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        HttpResponse {
+            status,
+            body: serde_json::to_string(&ErrorResponse {
+                error: message.into(),
+            })
+            .expect("error responses are always serializable"),
+        }
+    }
+}
+
+/// Handles the requests of the sidecar, building one rate limiter per distinct policy on first
+/// use, all of them sharing the same Redis server.
+pub struct Handler {
+    redis_url: String,
+    rate_limiters: Mutex<HashMap<String, Arc<dyn RateLimiter + Send + Sync>>>,
+}
+
+impl Handler {
+    /// Creates a handler of the requests, checking them against the given Redis server.
+    pub fn new(redis_url: impl Into<String>) -> Self {
+        Handler {
+            redis_url: redis_url.into(),
+            rate_limiters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Handles the request with the given method, path and body.
+    pub fn handle(&self, method: &str, path: &str, body: &[u8]) -> HttpResponse {
+        match (method, path) {
+            ("GET", "/health") => HttpResponse {
+                status: 200,
+                body: "{\"status\":\"ok\"}".to_string(),
+            },
+            ("POST", "/check") => self.check(body),
+            (_, "/health" | "/check") => HttpResponse::error(405, "method not allowed"),
+            _ => HttpResponse::error(404, "not found"),
+        }
+    }
+
+    /// Utility method that handles a `POST /check` request.
+    fn check(&self, body: &[u8]) -> HttpResponse {
+        let request: CheckRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(error) => return HttpResponse::error(400, error.to_string()),
+        };
+        let rate_limiter = match self.rate_limiter(&request.policy) {
+            Ok(rate_limiter) => rate_limiter,
+            Err(
+                error @ (RateLimiterError::PolicyError(_) | RateLimiterError::InvalidConfig(_)),
+            ) => return HttpResponse::error(400, error.to_string()),
+            Err(error) => return HttpResponse::error(500, error.to_string()),
+        };
+
+        match rate_limiter.check_request(request.identifier) {
+            Ok(response) => HttpResponse {
+                status: 200,
+                body: serde_json::to_string(&CheckResponse::from(response))
+                    .expect("check responses are always serializable"),
+            },
+            Err(error) => HttpResponse::error(500, error.to_string()),
+        }
+    }
+
+    /// Utility method that returns the rate limiter of the given policy, building it on first use.
+    fn rate_limiter(
+        &self,
+        policy: &str,
+    ) -> Result<Arc<dyn RateLimiter + Send + Sync>, RateLimiterError> {
+        let mut rate_limiters = self.rate_limiters.lock().unwrap();
+        if let Some(rate_limiter) = rate_limiters.get(policy) {
+            return Ok(rate_limiter.clone());
+        }
+
+        let rate_limiter: Arc<dyn RateLimiter + Send + Sync> = Arc::from(
+            RateLimiterFactory::from_policy(policy)?
+                .with_redis_url(&self.redis_url)
+                .build_boxed()?,
+        );
+        rate_limiters.insert(policy.to_string(), rate_limiter.clone());
+        Ok(rate_limiter)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rate_limiter_rs::{
+        RateLimitStatus, RateLimiterResponse, RequestAllowed, RequestThrottled, ThrottleReason,
+    };
+    use rstest::rstest;
+
+    use super::{CheckResponse, Handler};
+
+    fn status(reset_in: Duration) -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 10,
+            window_duration: Duration::from_secs(60),
+            used: 10,
+            reset_at: SystemTime::now() + reset_in,
+        }
+    }
+
+    #[test]
+    fn should_convert_allowed_response() {
+        let response = CheckResponse::from(RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter: 3,
+            status: status(Duration::from_secs(30)),
+        }));
+
+        assert!(response.allowed);
+        assert_eq!(response.limit, 10);
+        assert_eq!(response.remaining, 3);
+        assert!(response.reset_in_millis <= 30_000);
+        assert_eq!(response.retry_in_millis, None);
+        assert_eq!(response.reason, None);
+    }
+
+    #[test]
+    fn should_convert_throttled_response() {
+        let response =
+            CheckResponse::from(RateLimiterResponse::RequestThrottled(RequestThrottled {
+                retry_in: Duration::from_millis(1500),
+                reason: ThrottleReason::QuotaExceeded,
+                status: status(Duration::ZERO),
+            }));
+
+        assert!(!response.allowed);
+        assert_eq!(response.remaining, 0);
+        assert_eq!(response.reset_in_millis, 0);
+        assert_eq!(response.retry_in_millis, Some(1500));
+        assert_eq!(response.reason, Some(ThrottleReason::QuotaExceeded));
+    }
+
+    #[rstest]
+    #[case::health("GET", "/health", 200)]
+    #[case::check_method_not_allowed("GET", "/check", 405)]
+    #[case::health_method_not_allowed("POST", "/health", 405)]
+    #[case::not_found("POST", "/checks", 404)]
+    fn should_route_requests(#[case] method: &str, #[case] path: &str, #[case] expected: u16) {
+        let handler = Handler::new("redis://127.0.0.1:6379");
+
+        assert_eq!(handler.handle(method, path, b"").status, expected)
+    }
+
+    #[test]
+    fn should_reject_malformed_check_request() {
+        let handler = Handler::new("redis://127.0.0.1:6379");
+
+        assert_eq!(handler.handle("POST", "/check", b"{").status, 400)
+    }
+
+    #[rstest]
+    #[case::invalid_policy("100 per fortnight")]
+    #[case::sub_resolution_window("10000 per second burst 1")]
+    fn should_reject_check_request_of_unenforceable_policy(#[case] policy: &str) {
+        let handler = Handler::new("redis://127.0.0.1:6379");
+        let body = format!(
+            r#"{{"identifier": {{"Ip": "172.28.0.6"}}, "policy": "{}"}}"#,
+            policy
+        );
+
+        assert_eq!(
+            handler.handle("POST", "/check", body.as_bytes()).status,
+            400
+        )
+    }
+}
@@ -0,0 +1,109 @@
+//! Sidecar exposing the rate limiter over HTTP, so that services not written in Rust can share
+//! the same Redis backed limits by calling it over localhost.
+//!
+//! ```shell
+//! curl -X POST localhost:8080/check \
+//!     -d '{"identifier": {"Ip": "172.28.0.6"}, "policy": "100/min"}'
+//! {"allowed":true,"limit":100,"remaining":99,"reset_in_millis":59998,"retry_in_millis":null,"reason":null}
+//! ```
+//!
+//! The request identifier uses the JSON format of the library, like `{"Ip": "172.28.0.6"}`,
+//! `{"Custom": {"key": "tenant", "value": "acme"}}` or `{"Internal": "billing"}`, and the
+//! policy the [policy syntax](https://docs.rs/rate-limiter-rs/latest/rate_limiter_rs/policy/index.html),
+//! enforced with a sliding window. Checks are answered with a `200 OK` status, whether the
+//! request is allowed or not. `GET /health` answers with a `200 OK` status while the sidecar is up.
+//!
+//! The sidecar is configured with the following environment variables:
+//!
+//! | Variable | Description | Default |
+//! | -------- | ----------- | ------- |
+//! | `RATE_LIMITER_LISTEN_ADDR` | The address to listen on | `127.0.0.1:8080` |
+//! | `RATE_LIMITER_REDIS_URL` | The Redis server holding the limits | `redis://127.0.0.1:6379` |
+//! | `RATE_LIMITER_WORKERS` | The number of requests handled concurrently | `4` |
+//!
+//! All the policies share the same request keys, so a request identifier checked against several
+//! policies is counted once per check, in each of them.
+use std::{env, process::ExitCode, sync::Arc, thread};
+
+use handler::Handler;
+use tiny_http::{Header, Request, Response, Server};
+
+mod handler;
+
+/// The address listened on when none is configured
+const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8080";
+
+/// The URL of the Redis server used when none is configured
+const DEFAULT_REDIS_URL: &str = "redis://127.0.0.1:6379";
+
+/// The number of workers used when none is configured
+const DEFAULT_WORKERS: usize = 4;
+
+fn main() -> ExitCode {
+    let listen_addr =
+        env::var("RATE_LIMITER_LISTEN_ADDR").unwrap_or_else(|_| DEFAULT_LISTEN_ADDR.to_string());
+    let redis_url =
+        env::var("RATE_LIMITER_REDIS_URL").unwrap_or_else(|_| DEFAULT_REDIS_URL.to_string());
+    let workers = match env::var("RATE_LIMITER_WORKERS") {
+        Ok(workers) => match workers.parse::<usize>() {
+            Ok(workers) if workers > 0 => workers,
+            _ => {
+                eprintln!("error: invalid RATE_LIMITER_WORKERS: {}", workers);
+                return ExitCode::FAILURE;
+            }
+        },
+        Err(_) => DEFAULT_WORKERS,
+    };
+
+    let server = match Server::http(&listen_addr) {
+        Ok(server) => Arc::new(server),
+        Err(error) => {
+            eprintln!("error: failed to listen on {}: {}", listen_addr, error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let handler = Arc::new(Handler::new(redis_url));
+    eprintln!("listening on {}", listen_addr);
+
+    let workers: Vec<_> = (0..workers)
+        .map(|_| {
+            let server = server.clone();
+            let handler = handler.clone();
+            thread::spawn(move || {
+                while let Ok(request) = server.recv() {
+                    serve(&handler, request);
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    ExitCode::SUCCESS
+}
+
+/// Serves the given HTTP request with the given handler.
+fn serve(handler: &Handler, mut request: Request) {
+    let mut body = vec![];
+    let response = match request.as_reader().read_to_end(&mut body) {
+        Ok(_) => handler.handle(
+            request.method().as_str(),
+            request.url().split('?').next().unwrap_or_default(),
+            &body,
+        ),
+        Err(error) => {
+            eprintln!("error: failed to read request body: {}", error);
+            return;
+        }
+    };
+
+    let content_type = Header::from_bytes("Content-Type", "application/json")
+        .expect("the content type header is valid");
+    if let Err(error) = request.respond(
+        Response::from_string(response.body)
+            .with_status_code(response.status)
+            .with_header(content_type),
+    ) {
+        eprintln!("error: failed to send response: {}", error);
+    }
+}
@@ -0,0 +1,590 @@
+//! Module that includes the [actix-web](https://actix.rs/) middleware of the rate limiter.
+//! Requires the `actix` feature.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use actix_web::{web, App};
+//! use rate_limiter_rs::{
+//!     actix::RateLimiterMiddlewareFactory, factory::RateLimiterFactory, RateLimiter,
+//! };
+//!
+//! let rate_limiter: Arc<dyn RateLimiter + Send + Sync> =
+//!     Arc::new(RateLimiterFactory::fixed_window().build().unwrap());
+//!
+//! let app = App::new().service(
+//!     web::scope("/api")
+//!         .wrap(RateLimiterMiddlewareFactory::with_rate_limiter(rate_limiter.clone()))
+//!         .route("", web::get().to(|| async { "ok" })),
+//! );
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Requests are rate limited by the IP address of the peer of the connection, or by the client
+//! listed in the `Forwarded` and `X-Forwarded-For` headers when the peer is one of the
+//! [trusted proxies](crate::client_ip) registered as `web::Data<TrustedProxies>` app data, unless a different [key extractor](RateLimiterMiddlewareFactory::with_key_extractor) is
+//! configured. Allowed requests reach the wrapped service, and their responses carry the
+//! remaining requests of the window in the `X-Remaining-Request` header, along with the
+//! [rate limit headers](crate::headers). Throttled requests fail
+//! with the error built by the [throttle handler](RateLimiterMiddlewareFactory::with_throttle_handler),
+//! by default a [RateLimitError] answered with a `429 Too Many Requests` status, or a
+//! `503 Service Unavailable` status when shed, and a `Retry-After` header.
+//!
+//! Requests are let through when the check fails, as the middleware can't tell a misbehaving
+//! client apart from an unavailable Redis: [circuit breakers](../breaker/index.html) should be
+//! configured on the rate limiter to serve checks differently.
+//!
+//! ## Rate limited handlers
+//!
+//! With the `derive` feature enabled, single handlers can be wrapped with a rate limiter of the
+//! [registry](crate::registry::RateLimiterRegistry), or [reloadable
+//! registry](crate::reload::ReloadableRegistry), added to the app data, by name, instead of
+//! wrapping their scope with the middleware. The attribute goes below the route attribute, if
+//! any:
+//!
+//! ```ignore
+//! use actix_web::{get, web, App};
+//! use rate_limiter_rs::actix::rate_limited;
+//!
+//! #[get("/search")]
+//! #[rate_limited(policy = "search")]
+//! async fn search() -> &'static str {
+//!     "results"
+//! }
+//!
+//! let app = App::new()
+//!     .app_data(web::Data::new(registry))
+//!     .service(search);
+//! ```
+//!
+//! Wrapped handlers are rate limited by the IP address of the client, and answer like the
+//! middleware. Requests are let through when the registry or the rate limiter are missing, or
+//! when the check fails.
+use std::{
+    future::{ready, Future, Ready},
+    net::IpAddr,
+    pin::Pin,
+    rc::Rc,
+    str::FromStr,
+    sync::Arc,
+};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderName, HeaderValue},
+        StatusCode,
+    },
+    web, CustomizeResponder, Either, Error as ActixWebError, HttpRequest, HttpResponse,
+    HttpResponseBuilder, Responder, ResponseError,
+};
+
+use crate::{
+    api_key::ApiKeyExtractor, client_ip::TrustedProxies, headers::RateLimitHeaders,
+    registry::RateLimiterRegistry, reload::ReloadableRegistry, RateLimiter, RateLimiterResponse,
+    RequestIdentifier, RequestThrottled, ThrottleReason,
+};
+
+/// Attribute wrapping an async handler with a rate limiter of the registry, by name, like
+/// `#[rate_limited(policy = "search")]`. Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use rate_limiter_rs_derive::rate_limited;
+
+/// The header carrying the remaining requests of the window, in the responses to allowed requests
+pub const RATE_LIMITER_REMAINING_REQUEST_HTTP_HEADER_NAME: &str = "X-Remaining-Request";
+
+/// The header carrying the seconds after which throttled requests should be retried
+pub const RATE_LIMITER_RETRY_AFTER_HTTP_HEADER_NAME: &str = crate::headers::RETRY_AFTER_HEADER;
+
+/// The lowercase name of the remaining requests header, as required by [HeaderName::from_static]
+const REMAINING_REQUEST_HEADER: HeaderName = HeaderName::from_static("x-remaining-request");
+
+/// The function extracting the request identifier of a request
+type KeyExtractor = Rc<dyn Fn(&ServiceRequest) -> Result<RequestIdentifier, ActixWebError>>;
+
+/// The function building the error failing a throttled request
+type ThrottleHandler = Rc<dyn Fn(&RequestThrottled) -> ActixWebError>;
+
+/// The responder of the handlers wrapped by the `#[rate_limited]` attribute: the response of the
+/// handler, along with the remaining requests header, or the response to the rejected request
+pub type RateLimited<R> = Either<CustomizeResponder<R>, HttpResponse>;
+
+/// Enum that represents the errors failing the requests rate limited by the middleware
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("Request throttled, retry after {}s", .0.retry_after.unwrap_or_default())]
+    RequestThrottled(RateLimitHeaders),
+    #[error("Service overloaded, retry after {}s", .0.retry_after.unwrap_or_default())]
+    ServiceOverloaded(RateLimitHeaders),
+    #[error("Missing IP address")]
+    MissingIpAddress,
+    #[error("Invalid IP address: {0}")]
+    InvalidIpAddress(String),
+    #[error("Missing API key")]
+    MissingApiKey,
+}
+
+impl From<&RequestThrottled> for RateLimitError {
+    fn from(throttled: &RequestThrottled) -> Self {
+        let headers = RateLimitHeaders::from(throttled);
+
+        match throttled.reason {
+            ThrottleReason::QuotaExceeded => RateLimitError::RequestThrottled(headers),
+            ThrottleReason::LoadShed => RateLimitError::ServiceOverloaded(headers),
+        }
+    }
+}
+
+impl ResponseError for RateLimitError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            RateLimitError::RequestThrottled(headers) => {
+                with_headers(&mut HttpResponse::build(self.status_code()), headers)
+                    .body("You've been throttled!")
+            }
+            RateLimitError::ServiceOverloaded(headers) => {
+                with_headers(&mut HttpResponse::build(self.status_code()), headers)
+                    .body("Service overloaded, please retry later")
+            }
+            _ => HttpResponse::build(self.status_code()).finish(),
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RateLimitError::RequestThrottled { .. } => StatusCode::TOO_MANY_REQUESTS,
+            RateLimitError::ServiceOverloaded { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            RateLimitError::MissingIpAddress => StatusCode::BAD_REQUEST,
+            RateLimitError::InvalidIpAddress(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            RateLimitError::MissingApiKey => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+/// Factory of the middleware, to be passed to the `wrap` method of apps, scopes and resources
+pub struct RateLimiterMiddlewareFactory {
+    rate_limiter: Arc<dyn RateLimiter + Send + Sync>,
+    key_extractor: KeyExtractor,
+    throttle_handler: ThrottleHandler,
+}
+
+impl RateLimiterMiddlewareFactory {
+    /// Creates a factory of middlewares checking the requests against the given rate limiter,
+    /// by the IP address of the client.
+    pub fn with_rate_limiter(
+        rate_limiter: Arc<dyn RateLimiter + Send + Sync>,
+    ) -> RateLimiterMiddlewareFactory {
+        RateLimiterMiddlewareFactory {
+            rate_limiter,
+            key_extractor: Rc::new(ip_address),
+            throttle_handler: Rc::new(|throttled| RateLimitError::from(throttled).into()),
+        }
+    }
+
+    /// Rate limits all requests under the given identifier, instead of the caller IP address.
+    /// Used for traffic that should be metered as a whole, like uptime probes.
+    pub fn with_request_identifier(self, request_identifier: RequestIdentifier) -> Self {
+        self.with_key_extractor(move |_| Ok(request_identifier.clone()))
+    }
+
+    /// Rate limits requests under the identifier extracted by the given function, like an API
+    /// key header. Requests are failed with the error returned by the function, if any.
+    pub fn with_key_extractor(
+        mut self,
+        key_extractor: impl Fn(&ServiceRequest) -> Result<RequestIdentifier, ActixWebError> + 'static,
+    ) -> Self {
+        self.key_extractor = Rc::new(key_extractor);
+        self
+    }
+
+    /// Rate limits requests by the API key extracted by the given extractor, or by the IP
+    /// address of the client if it falls back to it. Requests without an identifier are failed
+    /// with a `401 Unauthorized` status.
+    pub fn with_api_key(self, api_key_extractor: ApiKeyExtractor) -> Self {
+        self.with_key_extractor(move |req| {
+            let api_key = req
+                .headers()
+                .get(api_key_extractor.header())
+                .and_then(|api_key| api_key.to_str().ok());
+            let client_ip = client_ip_address(req.request()).ok();
+
+            Ok(api_key_extractor
+                .extract(api_key, client_ip)
+                .ok_or(RateLimitError::MissingApiKey)?)
+        })
+    }
+
+    /// Fails throttled requests with the error built by the given function, to customize the
+    /// response of throttled requests.
+    pub fn with_throttle_handler(
+        mut self,
+        throttle_handler: impl Fn(&RequestThrottled) -> ActixWebError + 'static,
+    ) -> Self {
+        self.throttle_handler = Rc::new(throttle_handler);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiterMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixWebError> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixWebError;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimiterMiddleware {
+            service: Rc::new(service),
+            rate_limiter: self.rate_limiter.clone(),
+            key_extractor: self.key_extractor.clone(),
+            throttle_handler: self.throttle_handler.clone(),
+        }))
+    }
+}
+
+/// Middleware failing the throttled requests on behalf of the service it wraps
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<S>,
+    rate_limiter: Arc<dyn RateLimiter + Send + Sync>,
+    key_extractor: KeyExtractor,
+    throttle_handler: ThrottleHandler,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixWebError> + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = ActixWebError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let rate_limiter = self.rate_limiter.clone();
+        let key_extractor = self.key_extractor.clone();
+        let throttle_handler = self.throttle_handler.clone();
+        Box::pin(async move {
+            let request_identifier = key_extractor(&req)?;
+            let key = rate_limiter.build_request_key(request_identifier.clone());
+
+            match rate_limiter.check_request(request_identifier) {
+                Ok(RateLimiterResponse::RequestAllowed(allowed)) => {
+                    let mut response = service.call(req).await?;
+                    response.headers_mut().insert(
+                        REMAINING_REQUEST_HEADER,
+                        HeaderValue::from(allowed.remaining_request_counter),
+                    );
+                    for (name, value) in RateLimitHeaders::from(&allowed).to_pairs() {
+                        if let (Ok(name), Ok(value)) =
+                            (HeaderName::from_str(name), HeaderValue::from_str(&value))
+                        {
+                            response.headers_mut().insert(name, value);
+                        }
+                    }
+                    Ok(response)
+                }
+                Ok(RateLimiterResponse::RequestThrottled(throttled)) => {
+                    log::warn!(
+                        "request throttled for key={} reason={:?}",
+                        key,
+                        throttled.reason
+                    );
+                    Err(throttle_handler(&throttled))
+                }
+                Err(e) => {
+                    log::warn!(
+                        "unable to check rate limit for key={}, skipping validation: {}",
+                        key,
+                        e
+                    );
+                    service.call(req).await
+                }
+            }
+        })
+    }
+}
+
+/// Checks the given request against the rate limiter registered under the given name, by the
+/// IP address of the client, and runs the given handler only if the request is allowed. Called
+/// by the handlers wrapped by the `#[rate_limited]` attribute.
+pub async fn run_rate_limited<R: Responder>(
+    req: HttpRequest,
+    policy: &str,
+    handler: impl Future<Output = R>,
+) -> RateLimited<R> {
+    let Some(rate_limiter) = req
+        .app_data::<web::Data<RateLimiterRegistry>>()
+        .and_then(|registry| registry.get(policy))
+        .or_else(|| {
+            req.app_data::<web::Data<ReloadableRegistry>>()
+                .and_then(|registry| registry.get(policy))
+        })
+    else {
+        log::warn!(
+            "no rate limiter registered for policy={}, skipping validation",
+            policy
+        );
+        return Either::Left(handler.await.customize());
+    };
+
+    let request_identifier = match client_ip_address(&req) {
+        Ok(ip_address) => RequestIdentifier::Ip(ip_address),
+        Err(e) => return Either::Right(e.error_response()),
+    };
+    let key = rate_limiter.build_request_key(request_identifier.clone());
+
+    match rate_limiter.check_request(request_identifier) {
+        Ok(RateLimiterResponse::RequestAllowed(allowed)) => {
+            let mut responder = handler.await.customize().insert_header((
+                REMAINING_REQUEST_HEADER,
+                HeaderValue::from(allowed.remaining_request_counter),
+            ));
+            for pair in RateLimitHeaders::from(&allowed).to_pairs() {
+                responder = responder.insert_header(pair);
+            }
+            Either::Left(responder)
+        }
+        Ok(RateLimiterResponse::RequestThrottled(throttled)) => {
+            log::warn!(
+                "request throttled for key={} policy={} reason={:?}",
+                key,
+                policy,
+                throttled.reason
+            );
+            Either::Right(RateLimitError::from(&throttled).error_response())
+        }
+        Err(e) => {
+            log::warn!(
+                "unable to check rate limit for key={}, skipping validation: {}",
+                key,
+                e
+            );
+            Either::Left(handler.await.customize())
+        }
+    }
+}
+
+/// Utility method that inserts the given rate limit headers into the given response builder.
+fn with_headers<'a>(
+    builder: &'a mut HttpResponseBuilder,
+    headers: &RateLimitHeaders,
+) -> &'a mut HttpResponseBuilder {
+    for pair in headers.to_pairs() {
+        builder.insert_header(pair);
+    }
+    builder
+}
+
+/// Utility method that returns the IP address of the client of the given request.
+fn ip_address(req: &ServiceRequest) -> Result<RequestIdentifier, ActixWebError> {
+    Ok(RequestIdentifier::Ip(client_ip_address(req.request())?))
+}
+
+/// Utility method that returns the IP address of the client of the given request: the peer of
+/// the connection, or the client listed by the forwarding headers if the peer is one of the
+/// [trusted proxies](TrustedProxies) registered as app data.
+fn client_ip_address(req: &HttpRequest) -> Result<IpAddr, RateLimitError> {
+    let peer_ip = req
+        .peer_addr()
+        .ok_or(RateLimitError::MissingIpAddress)?
+        .ip();
+
+    let Some(trusted_proxies) = req.app_data::<web::Data<TrustedProxies>>() else {
+        return Ok(peer_ip);
+    };
+    let header_values = |name: &str| -> Vec<&str> {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .collect()
+    };
+
+    Ok(trusted_proxies.client_ip(
+        peer_ip,
+        &header_values("forwarded"),
+        &header_values("x-forwarded-for"),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        future::Future,
+        net::SocketAddr,
+        sync::Arc,
+        task::{Context, Poll, Waker},
+        time::Duration,
+        time::SystemTime,
+    };
+
+    use actix_web::{http::StatusCode, test::TestRequest, web, Either, HttpRequest, ResponseError};
+    use rstest::rstest;
+
+    use super::{
+        ip_address, run_rate_limited, RateLimitError, RATE_LIMITER_RETRY_AFTER_HTTP_HEADER_NAME,
+    };
+    use crate::{
+        client_ip::TrustedProxies, factory::RateLimiterFactory, redis_mock::RedisMock,
+        registry::RateLimiterRegistry, RateLimitStatus, RequestIdentifier, RequestThrottled,
+        ThrottleReason,
+    };
+
+    fn poll_now<F: Future>(future: F) -> F::Output {
+        match std::pin::pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future is not ready"),
+        }
+    }
+
+    fn request_with_registry(redis_mock: &RedisMock) -> HttpRequest {
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let mut registry = RateLimiterRegistry::default();
+        registry.register("search", Arc::new(rate_limiter));
+
+        TestRequest::default()
+            .peer_addr("172.28.0.9:4242".parse().unwrap())
+            .app_data(web::Data::new(registry))
+            .to_http_request()
+    }
+
+    #[rstest]
+    #[case::quota_exceeded(ThrottleReason::QuotaExceeded, StatusCode::TOO_MANY_REQUESTS)]
+    #[case::load_shed(ThrottleReason::LoadShed, StatusCode::SERVICE_UNAVAILABLE)]
+    fn should_answer_throttled_requests(
+        #[case] reason: ThrottleReason,
+        #[case] expected_status: StatusCode,
+    ) {
+        let error = RateLimitError::from(&RequestThrottled {
+            retry_in: Duration::from_millis(1500),
+            reason,
+            status: RateLimitStatus {
+                limit: 5,
+                window_duration: Duration::from_secs(60),
+                used: 6,
+                reset_at: SystemTime::now(),
+            },
+        });
+
+        let response = error.error_response();
+
+        assert_eq!(response.status(), expected_status);
+        assert_eq!(
+            response
+                .headers()
+                .get(RATE_LIMITER_RETRY_AFTER_HTTP_HEADER_NAME)
+                .unwrap(),
+            "2"
+        );
+        assert_eq!(response.headers().get("RateLimit-Limit").unwrap(), "5");
+        assert_eq!(response.headers().get("RateLimit-Remaining").unwrap(), "0");
+    }
+
+    #[test]
+    fn should_extract_ip_address_of_client() {
+        let addr: SocketAddr = "172.28.0.6:4242".parse().unwrap();
+        let req = TestRequest::default().peer_addr(addr).to_srv_request();
+
+        let request_identifier = ip_address(&req).unwrap();
+
+        assert!(matches!(request_identifier, RequestIdentifier::Ip(ip) if ip == addr.ip()));
+    }
+
+    #[test]
+    fn should_prefer_forwarded_ip_address_of_client() {
+        let addr: SocketAddr = "172.28.0.6:4242".parse().unwrap();
+        let req = TestRequest::default()
+            .peer_addr(addr)
+            .insert_header(("X-Forwarded-For", "10.0.0.1"))
+            .app_data(web::Data::new(
+                TrustedProxies::new(["172.28.0.0/16"]).unwrap(),
+            ))
+            .to_srv_request();
+
+        let request_identifier = ip_address(&req).unwrap();
+
+        assert!(
+            matches!(request_identifier, RequestIdentifier::Ip(ip) if ip.to_string() == "10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn should_ignore_forwarded_ip_address_of_untrusted_peer() {
+        let addr: SocketAddr = "172.28.0.6:4242".parse().unwrap();
+        let req = TestRequest::default()
+            .peer_addr(addr)
+            .insert_header(("X-Forwarded-For", "10.0.0.1"))
+            .to_srv_request();
+
+        let request_identifier = ip_address(&req).unwrap();
+
+        assert!(matches!(request_identifier, RequestIdentifier::Ip(ip) if ip == addr.ip()));
+    }
+
+    #[test]
+    fn should_throttle_rate_limited_handler_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let req = request_with_registry(&redis_mock);
+
+        //act
+        let first = poll_now(run_rate_limited(req.clone(), "search", async { "ok" }));
+        let second = poll_now(run_rate_limited(req, "search", async { "ok" }));
+
+        //assert
+        assert!(matches!(first, Either::Left(_)));
+        assert!(
+            matches!(second, Either::Right(response) if response.status() == StatusCode::TOO_MANY_REQUESTS)
+        );
+    }
+
+    #[test]
+    fn should_not_rate_limit_handler_of_unknown_policy_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let req = request_with_registry(&redis_mock);
+
+        //act
+        let responses: Vec<_> = (0..3)
+            .map(|_| poll_now(run_rate_limited(req.clone(), "export", async { "ok" })))
+            .collect();
+
+        //assert
+        assert!(responses
+            .iter()
+            .all(|response| matches!(response, Either::Left(_))));
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn should_throttle_handler_wrapped_by_attribute_against_redis_mock() {
+        use super::rate_limited;
+
+        #[rate_limited(policy = "search")]
+        async fn search(query: String) -> String {
+            format!("results for {}", query)
+        }
+
+        //arrange
+        let redis_mock = RedisMock::start();
+        let req = request_with_registry(&redis_mock);
+
+        //act
+        let first = poll_now(search(req.clone(), "rust".to_string()));
+        let second = poll_now(search(req, "rust".to_string()));
+
+        //assert
+        assert!(matches!(first, Either::Left(_)));
+        assert!(matches!(second, Either::Right(_)));
+    }
+}
@@ -0,0 +1,279 @@
+//! Module that includes the optional adaptive limits, tightened while Redis is struggling and
+//! relaxed back once it recovers, so that the rate limiter sheds load before its backend is
+//! overloaded.
+//!
+//! ## Implementation details
+//!
+//! The latency of every check against Redis, including the time spent acquiring a connection,
+//! is smoothed with an exponentially weighted moving average. At most once per adjustment
+//! interval, the fraction of the configured limits granted to every request identifier is
+//! adjusted: while the smoothed latency is above the target, the fraction is cut by a fifth, down
+//! to the configured minimum; otherwise it grows back by 5% of the limits, up to the full limits.
+//! Tightening fast and relaxing slowly lets the backend recover before the traffic comes back.
+//!
+//! The fraction is held in process memory and shared by the clones of the rate limiter, so each
+//! node adapts to the latency it observes. While the limits are tightened, the throttled verdicts
+//! are cached in process memory, like with the [throttle cache](crate::throttle_cache), even if
+//! the rate limiter has none: the requests of the request identifiers over their tightened limit
+//! are throttled locally until they may retry, sparing Redis their checks. Once the limits are
+//! relaxed back to the full limits, every request is checked against Redis again.
+//!
+//! Requests throttled only because of the tightened limits, that is that would have been allowed
+//! with the configured ones, are throttled with the [LoadShed](crate::ThrottleReason::LoadShed)
+//! reason, like the ones shed by the [load shedding](crate::load_shedding).
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::throttle_cache::ThrottleCache;
+
+/// The weight of the latest latency in the smoothed latency
+const SMOOTHING_WEIGHT: f64 = 0.2;
+
+/// The factor the fraction of the limits is multiplied by when tightened
+const DECREASE_FACTOR: f64 = 0.8;
+
+/// The fraction of the limits added back when relaxed
+const INCREASE_STEP: f64 = 0.05;
+
+/// The maximum number of throttled verdicts cached while the limits are tightened
+const MAX_CACHED_VERDICTS: usize = 10_000;
+
+/// Represents how the limits adapt to the latency of the checks
+#[derive(Clone, Debug)]
+pub struct AdaptivePolicy {
+    /// The smoothed check latency above which Redis is considered struggling
+    pub target_latency: Duration,
+
+    /// The lowest fraction of the configured limits the limits are tightened to.
+    /// Expected to be in the `0.0..=1.0` range.
+    pub min_fraction: f64,
+
+    /// The minimum time between two adjustments of the limits
+    pub adjust_interval: Duration,
+}
+
+/// Represents the state of the adaptive limits
+#[derive(Debug)]
+struct AdaptiveState {
+    /// The smoothed latency of the checks, once any check completed
+    smoothed_latency: Option<Duration>,
+    /// The fraction of the configured limits currently granted
+    fraction: f64,
+    /// When the limits were last adjusted, or the first latency recorded
+    adjusted_at: Option<Instant>,
+}
+
+/// Represents the adaptive limits of a rate limiter, shared by its clones
+#[derive(Clone, Debug)]
+pub struct AdaptiveLimits {
+    /// The policy the limits adapt with
+    pub policy: AdaptivePolicy,
+
+    /// The current state of the limits
+    state: Arc<Mutex<AdaptiveState>>,
+
+    /// The throttled verdicts cached while the limits are tightened
+    pub(crate) throttle_cache: ThrottleCache,
+}
+
+impl AdaptiveLimits {
+    /// Creates adaptive limits granting the full configured limits, until adjusted.
+    pub fn new(policy: AdaptivePolicy) -> Self {
+        AdaptiveLimits {
+            policy,
+            state: Arc::new(Mutex::new(AdaptiveState {
+                smoothed_latency: None,
+                fraction: 1.0,
+                adjusted_at: None,
+            })),
+            throttle_cache: ThrottleCache::new(MAX_CACHED_VERDICTS),
+        }
+    }
+
+    /// Returns the fraction of the configured limits currently granted, between the minimum
+    /// fraction of the policy and 1.
+    pub fn fraction(&self) -> f64 {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .fraction
+    }
+
+    /// Computes the limit currently granted, out of the given configured one. At least one
+    /// request is always allowed, to not lock out request identifiers entirely.
+    pub fn effective_limit(&self, limit: u64) -> u64 {
+        ((limit as f64 * self.fraction()).floor() as u64).clamp(1, limit.max(1))
+    }
+
+    /// Returns the cache of throttled verdicts while the limits are tightened, so that the checks
+    /// of throttled keys are answered without a round trip to Redis.
+    pub(crate) fn throttle_cache(&self) -> Option<&ThrottleCache> {
+        (self.fraction() < 1.0).then_some(&self.throttle_cache)
+    }
+
+    /// Records the latency of a check completed at the given time, adjusting the limits if the
+    /// adjustment interval elapsed since the last adjustment.
+    pub fn record_latency(&self, latency: Duration, now: Instant) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let smoothed_latency = match state.smoothed_latency {
+            Some(smoothed) => {
+                smoothed.mul_f64(1.0 - SMOOTHING_WEIGHT) + latency.mul_f64(SMOOTHING_WEIGHT)
+            }
+            None => latency,
+        };
+        state.smoothed_latency = Some(smoothed_latency);
+
+        let Some(adjusted_at) = state.adjusted_at else {
+            state.adjusted_at = Some(now);
+            return;
+        };
+        if now.saturating_duration_since(adjusted_at) < self.policy.adjust_interval {
+            return;
+        }
+
+        let min_fraction = self.policy.min_fraction.clamp(0.0, 1.0);
+        state.fraction = if smoothed_latency > self.policy.target_latency {
+            (state.fraction * DECREASE_FACTOR).max(min_fraction)
+        } else {
+            (state.fraction + INCREASE_STEP).min(1.0)
+        };
+        state.adjusted_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        panic,
+        time::{Duration, Instant},
+    };
+
+    use super::{AdaptiveLimits, AdaptivePolicy};
+
+    fn adaptive_limits() -> AdaptiveLimits {
+        AdaptiveLimits::new(AdaptivePolicy {
+            target_latency: Duration::from_millis(10),
+            min_fraction: 0.5,
+            adjust_interval: Duration::from_secs(1),
+        })
+    }
+
+    #[test]
+    fn should_grant_full_limits_until_adjusted() {
+        let adaptive_limits = adaptive_limits();
+
+        adaptive_limits.record_latency(Duration::from_millis(100), Instant::now());
+
+        assert_eq!(adaptive_limits.fraction(), 1.0);
+        assert_eq!(adaptive_limits.effective_limit(100), 100);
+    }
+
+    #[test]
+    fn should_tighten_limits_down_to_min_fraction_while_latency_is_high() {
+        //arrange
+        let adaptive_limits = adaptive_limits();
+        let started_at = Instant::now();
+
+        //act
+        for second in 0..=10 {
+            adaptive_limits.record_latency(
+                Duration::from_millis(100),
+                started_at + Duration::from_secs(second),
+            );
+        }
+
+        //assert
+        assert_eq!(adaptive_limits.fraction(), 0.5);
+        assert_eq!(adaptive_limits.effective_limit(100), 50);
+        assert_eq!(adaptive_limits.effective_limit(1), 1);
+    }
+
+    #[test]
+    fn should_cache_throttled_verdicts_only_while_tightened() {
+        //arrange
+        let adaptive_limits = adaptive_limits();
+        let started_at = Instant::now();
+        let full_limits_cache = adaptive_limits.throttle_cache().is_some();
+
+        //act
+        for second in 0..=1 {
+            adaptive_limits.record_latency(
+                Duration::from_millis(100),
+                started_at + Duration::from_secs(second),
+            );
+        }
+
+        //assert
+        assert!(!full_limits_cache);
+        assert!(adaptive_limits.throttle_cache().is_some());
+    }
+
+    #[test]
+    fn should_adjust_limits_at_most_once_per_interval() {
+        //arrange
+        let adaptive_limits = adaptive_limits();
+        let started_at = Instant::now();
+
+        //act
+        for millis in (0..=1_500).step_by(100) {
+            adaptive_limits.record_latency(
+                Duration::from_millis(100),
+                started_at + Duration::from_millis(millis),
+            );
+        }
+
+        //assert
+        assert_eq!(adaptive_limits.effective_limit(100), 80);
+    }
+
+    #[test]
+    fn should_relax_limits_once_latency_recovers() {
+        //arrange
+        let adaptive_limits = adaptive_limits();
+        let started_at = Instant::now();
+        for second in 0..=2 {
+            adaptive_limits.record_latency(
+                Duration::from_millis(100),
+                started_at + Duration::from_secs(second),
+            );
+        }
+        let tightened_fraction = adaptive_limits.fraction();
+
+        //act
+        for second in 3..=60 {
+            adaptive_limits.record_latency(
+                Duration::from_millis(1),
+                started_at + Duration::from_secs(second),
+            );
+        }
+
+        //assert
+        assert!(tightened_fraction < 1.0);
+        assert_eq!(adaptive_limits.fraction(), 1.0);
+    }
+
+    #[test]
+    fn should_keep_adjusting_limits_once_poisoned() {
+        //arrange
+        let adaptive_limits = adaptive_limits();
+        let started_at = Instant::now();
+        let _ = panic::catch_unwind(|| {
+            let _state = adaptive_limits.state.lock().unwrap();
+            panic!("poisoning the adaptive state");
+        });
+
+        //act
+        for second in 0..=10 {
+            adaptive_limits.record_latency(
+                Duration::from_millis(100),
+                started_at + Duration::from_secs(second),
+            );
+        }
+
+        //assert
+        assert!(adaptive_limits.state.is_poisoned());
+        assert_eq!(adaptive_limits.fraction(), 0.5);
+    }
+}
@@ -0,0 +1,169 @@
+//! Module that includes the extraction of request identifiers from an API key header, like
+//! `X-Api-Key`, shared by the framework integrations, so that APIs handing out keys can rate
+//! limit requests by key, instead of by client IP address.
+//!
+//! ```
+//! use std::net::IpAddr;
+//!
+//! use rate_limiter_rs::{api_key::ApiKeyExtractor, RequestIdentifier};
+//!
+//! let key_extractor = ApiKeyExtractor::new("X-Api-Key")
+//!     .with_hashing(true)
+//!     .with_ip_fallback(true);
+//! let client_ip: IpAddr = "172.28.0.6".parse().unwrap();
+//!
+//! // requests carrying the header are rate limited by key
+//! let request_identifier = key_extractor.extract(Some("secret"), Some(client_ip));
+//! assert!(matches!(request_identifier, Some(RequestIdentifier::Custom { .. })));
+//!
+//! // other requests are rate limited by client IP address
+//! let request_identifier = key_extractor.extract(None, Some(client_ip));
+//! assert!(matches!(request_identifier, Some(RequestIdentifier::Ip(_))));
+//! ```
+//!
+//! ## Implementation details
+//!
+//! API keys are [custom identifiers](crate::RequestIdentifier::Custom) named `api_key`, like
+//! `rl:cst_api_key:secret`. With hashing enabled, the key is replaced by its 64 bit FNV-1a hash,
+//! in hex, so that API keys don't end up in Redis: the hash is stable across processes, but it
+//! isn't cryptographic, and isn't meant to protect low entropy keys.
+//!
+//! Requests without the header, or with an empty one, are rate limited by client IP address
+//! when the fallback is enabled, and not identified otherwise.
+use std::net::IpAddr;
+
+use crate::{stable_hash, RequestIdentifier};
+
+/// The name of the custom request identifiers holding API keys
+pub const API_KEY_IDENTIFIER: &str = "api_key";
+
+/// Extractor of request identifiers from an API key header
+#[derive(Debug, Clone)]
+pub struct ApiKeyExtractor {
+    header: String,
+    hashing: bool,
+    ip_fallback: bool,
+}
+
+impl ApiKeyExtractor {
+    /// Creates an extractor of the API keys carried by the given header, like `X-Api-Key`.
+    /// Keys are stored as they are, and requests without a key are not identified.
+    pub fn new(header: impl Into<String>) -> Self {
+        ApiKeyExtractor {
+            header: header.into(),
+            hashing: false,
+            ip_fallback: false,
+        }
+    }
+
+    /// Replaces API keys by their hash in the request identifiers.
+    pub fn with_hashing(mut self, hashing: bool) -> Self {
+        self.hashing = hashing;
+        self
+    }
+
+    /// Identifies the requests without an API key by the IP address of the client.
+    pub fn with_ip_fallback(mut self, ip_fallback: bool) -> Self {
+        self.ip_fallback = ip_fallback;
+        self
+    }
+
+    /// Returns the name of the header carrying the API keys.
+    pub fn header(&self) -> &str {
+        &self.header
+    }
+
+    /// Returns the request identifier of a request, given the value of its API key header and
+    /// the IP address of its client, if any.
+    pub fn extract(
+        &self,
+        api_key: Option<&str>,
+        client_ip: Option<IpAddr>,
+    ) -> Option<RequestIdentifier> {
+        match api_key.map(str::trim).filter(|api_key| !api_key.is_empty()) {
+            Some(api_key) => Some(RequestIdentifier::Custom {
+                key: API_KEY_IDENTIFIER.to_string(),
+                value: if self.hashing {
+                    format!("{:016x}", stable_hash(api_key))
+                } else {
+                    api_key.to_string()
+                },
+            }),
+            None if self.ip_fallback => client_ip.map(RequestIdentifier::Ip),
+            None => None,
+        }
+    }
+
+    /// Returns the request identifier of a request, given its [http](https://docs.rs/http)
+    /// headers and the IP address of its client, if any. Header values that aren't visible
+    /// ASCII are ignored. Requires the `http` feature.
+    #[cfg(feature = "http")]
+    pub fn extract_from_headers(
+        &self,
+        headers: &http::HeaderMap,
+        client_ip: Option<IpAddr>,
+    ) -> Option<RequestIdentifier> {
+        let api_key = headers
+            .get(self.header.as_str())
+            .and_then(|api_key| api_key.to_str().ok());
+
+        self.extract(api_key, client_ip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use rstest::rstest;
+
+    use super::ApiKeyExtractor;
+    use crate::RequestIdentifier;
+
+    const CLIENT_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(172, 28, 0, 6));
+
+    #[rstest]
+    #[case::plain("secret")]
+    #[case::trimmed(" secret ")]
+    fn should_identify_requests_by_api_key(#[case] api_key: &str) {
+        let request_identifier =
+            ApiKeyExtractor::new("X-Api-Key").extract(Some(api_key), Some(CLIENT_IP));
+
+        assert!(matches!(
+            request_identifier,
+            Some(RequestIdentifier::Custom { key, value }) if key == "api_key" && value == "secret"
+        ))
+    }
+
+    #[test]
+    fn should_hash_api_keys() {
+        let key_extractor = ApiKeyExtractor::new("X-Api-Key").with_hashing(true);
+
+        let request_identifier = key_extractor.extract(Some("secret"), None);
+
+        let Some(RequestIdentifier::Custom { value, .. }) = request_identifier else {
+            panic!("the request is not identified by api key")
+        };
+        assert_eq!(value.len(), 16);
+        assert_ne!(value, "secret");
+        assert!(matches!(
+            key_extractor.extract(Some("secret"), None),
+            Some(RequestIdentifier::Custom { value: same_value, .. }) if same_value == value
+        ))
+    }
+
+    #[rstest]
+    #[case::missing_header(None)]
+    #[case::empty_header(Some(""))]
+    fn should_fall_back_to_client_ip(#[case] api_key: Option<&str>) {
+        let key_extractor = ApiKeyExtractor::new("X-Api-Key");
+
+        assert!(key_extractor.extract(api_key, Some(CLIENT_IP)).is_none());
+        assert!(matches!(
+            key_extractor
+                .with_ip_fallback(true)
+                .extract(api_key, Some(CLIENT_IP)),
+            Some(RequestIdentifier::Ip(ip)) if ip == CLIENT_IP
+        ))
+    }
+}
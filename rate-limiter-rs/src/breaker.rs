@@ -0,0 +1,347 @@
+//! Module that includes the state machine deciding how checks are served when Redis is
+//! unavailable, combining a circuit breaker with a failure policy.
+//!
+//! ## Implementation details
+//!
+//! The breaker starts [closed](BreakerState::Closed), routing checks to Redis. After the
+//! configured number of consecutive failures it [opens](BreakerState::Open), and checks are
+//! served according to the [failure policy](FailurePolicy) without contacting Redis at all.
+//! Once the open duration has elapsed, the next check is routed to Redis as a probe, in the
+//! [half open](BreakerState::HalfOpen) state: a success closes the breaker again, while a failure
+//! opens it for another open duration. The failure policy is also applied to the checks that
+//! fail while the breaker is not open yet.
+//!
+//! All transitions happen in [Breaker::route] and [Breaker::record], which take the current
+//! time as input, so that every transition is explicit and can be tested deterministically:
+//!
+//! ```
+//! use std::time::{Duration, Instant};
+//!
+//! use rate_limiter_rs::breaker::{Breaker, BreakerPolicy, CheckOutcome, CheckRoute, FailurePolicy};
+//!
+//! let mut breaker = Breaker::new(BreakerPolicy {
+//!     failure_threshold: 3,
+//!     open_duration: Duration::from_secs(30),
+//!     failure_policy: FailurePolicy::Fallback,
+//! });
+//!
+//! let now = Instant::now();
+//! match breaker.route(now) {
+//!     CheckRoute::Redis => {
+//!         // check the request against Redis, then report the outcome
+//!         breaker.record(CheckOutcome::Failure, now);
+//!         // and serve the failed check with breaker.degraded_route()
+//!     }
+//!     CheckRoute::Allow | CheckRoute::Deny | CheckRoute::Fallback => {}
+//! }
+//! ```
+use std::time::{Duration, Instant};
+
+/// Represents how checks are served when Redis is unavailable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Requests are allowed, favouring availability over protection
+    FailOpen,
+    /// Requests are throttled, favouring protection over availability
+    FailClosed,
+    /// Requests are checked against a local, in-process rate limiter
+    Fallback,
+}
+
+/// Represents the configuration of the breaker
+#[derive(Debug, Clone)]
+pub struct BreakerPolicy {
+    /// The number of consecutive failures after which the breaker opens
+    pub failure_threshold: u32,
+
+    /// How long the breaker stays open before probing Redis again
+    pub open_duration: Duration,
+
+    /// How checks are served while Redis is unavailable
+    pub failure_policy: FailurePolicy,
+}
+
+/// Enum that represents the states of the breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Checks are routed to Redis
+    Closed {
+        /// the number of consecutive failed checks
+        consecutive_failures: u32,
+    },
+    /// Checks are served according to the failure policy, without contacting Redis
+    Open {
+        /// the point in time when the breaker opened
+        opened_at: Instant,
+    },
+    /// A probe check has been routed to Redis, to find out whether it recovered
+    HalfOpen,
+}
+
+/// Enum that represents the outcome of a check routed to Redis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// Redis answered the check
+    Success,
+    /// Redis could not be reached, or failed to answer the check
+    Failure,
+}
+
+/// Enum that represents how a check should be served
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckRoute {
+    /// Check the request against Redis, and record the outcome
+    Redis,
+    /// Allow the request without checking it
+    Allow,
+    /// Throttle the request without checking it
+    Deny,
+    /// Check the request against the local fallback rate limiter
+    Fallback,
+}
+
+/// State machine combining a circuit breaker with a failure policy
+#[derive(Debug, Clone)]
+pub struct Breaker {
+    policy: BreakerPolicy,
+    state: BreakerState,
+}
+
+impl Breaker {
+    /// Creates a new, closed breaker.
+    pub fn new(policy: BreakerPolicy) -> Self {
+        Breaker {
+            policy,
+            state: BreakerState::Closed {
+                consecutive_failures: 0,
+            },
+        }
+    }
+
+    /// Returns the current state of the breaker.
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Returns how the next check should be served, at the given point in time. An open breaker
+    /// turns half open once the open duration has elapsed, routing the check to Redis as a probe.
+    pub fn route(&mut self, now: Instant) -> CheckRoute {
+        match self.state {
+            BreakerState::Closed { .. } | BreakerState::HalfOpen => CheckRoute::Redis,
+            BreakerState::Open { opened_at } => {
+                if now.saturating_duration_since(opened_at) >= self.policy.open_duration {
+                    self.state = BreakerState::HalfOpen;
+                    CheckRoute::Redis
+                } else {
+                    self.degraded_route()
+                }
+            }
+        }
+    }
+
+    /// Records the outcome of a check routed to Redis, at the given point in time. Outcomes of
+    /// checks still in flight when the breaker opened are ignored.
+    pub fn record(&mut self, outcome: CheckOutcome, now: Instant) {
+        self.state = match (self.state, outcome) {
+            (BreakerState::Closed { .. } | BreakerState::HalfOpen, CheckOutcome::Success) => {
+                BreakerState::Closed {
+                    consecutive_failures: 0,
+                }
+            }
+            (
+                BreakerState::Closed {
+                    consecutive_failures,
+                },
+                CheckOutcome::Failure,
+            ) => {
+                let consecutive_failures = consecutive_failures.saturating_add(1);
+                if consecutive_failures >= self.policy.failure_threshold {
+                    BreakerState::Open { opened_at: now }
+                } else {
+                    BreakerState::Closed {
+                        consecutive_failures,
+                    }
+                }
+            }
+            (BreakerState::HalfOpen, CheckOutcome::Failure) => {
+                BreakerState::Open { opened_at: now }
+            }
+            (BreakerState::Open { opened_at }, _) => BreakerState::Open { opened_at },
+        }
+    }
+
+    /// Returns how checks are served when Redis is unavailable, according to the failure policy.
+    pub fn degraded_route(&self) -> CheckRoute {
+        match self.policy.failure_policy {
+            FailurePolicy::FailOpen => CheckRoute::Allow,
+            FailurePolicy::FailClosed => CheckRoute::Deny,
+            FailurePolicy::Fallback => CheckRoute::Fallback,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use rstest::rstest;
+
+    use super::{Breaker, BreakerPolicy, BreakerState, CheckOutcome, CheckRoute, FailurePolicy};
+
+    const OPEN_DURATION: Duration = Duration::from_secs(30);
+
+    fn breaker(failure_policy: FailurePolicy) -> Breaker {
+        Breaker::new(BreakerPolicy {
+            failure_threshold: 2,
+            open_duration: OPEN_DURATION,
+            failure_policy,
+        })
+    }
+
+    fn open_breaker(opened_at: Instant) -> Breaker {
+        let mut breaker = breaker(FailurePolicy::FailOpen);
+        breaker.record(CheckOutcome::Failure, opened_at);
+        breaker.record(CheckOutcome::Failure, opened_at);
+        breaker
+    }
+
+    #[test]
+    fn should_start_closed_and_route_to_redis() {
+        let mut breaker = breaker(FailurePolicy::FailOpen);
+
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Closed {
+                consecutive_failures: 0
+            }
+        );
+        assert_eq!(breaker.route(Instant::now()), CheckRoute::Redis);
+    }
+
+    #[test]
+    fn should_count_failures_below_threshold_when_closed() {
+        let mut breaker = breaker(FailurePolicy::FailOpen);
+
+        breaker.record(CheckOutcome::Failure, Instant::now());
+
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Closed {
+                consecutive_failures: 1
+            }
+        );
+    }
+
+    #[test]
+    fn should_reset_failures_on_success_when_closed() {
+        let mut breaker = breaker(FailurePolicy::FailOpen);
+        breaker.record(CheckOutcome::Failure, Instant::now());
+
+        breaker.record(CheckOutcome::Success, Instant::now());
+
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn should_open_when_failure_threshold_is_reached() {
+        let opened_at = Instant::now();
+
+        let breaker = open_breaker(opened_at);
+
+        assert_eq!(breaker.state(), BreakerState::Open { opened_at });
+    }
+
+    #[rstest]
+    #[case::fail_open(FailurePolicy::FailOpen, CheckRoute::Allow)]
+    #[case::fail_closed(FailurePolicy::FailClosed, CheckRoute::Deny)]
+    #[case::fallback(FailurePolicy::Fallback, CheckRoute::Fallback)]
+    fn should_apply_failure_policy_when_open(
+        #[case] failure_policy: FailurePolicy,
+        #[case] expected_route: CheckRoute,
+    ) {
+        let opened_at = Instant::now();
+        let mut breaker = breaker(failure_policy);
+        breaker.record(CheckOutcome::Failure, opened_at);
+        breaker.record(CheckOutcome::Failure, opened_at);
+
+        let route = breaker.route(opened_at + OPEN_DURATION / 2);
+
+        assert_eq!(route, expected_route);
+        assert_eq!(breaker.degraded_route(), expected_route);
+        assert_eq!(breaker.state(), BreakerState::Open { opened_at });
+    }
+
+    #[rstest]
+    #[case::success(CheckOutcome::Success)]
+    #[case::failure(CheckOutcome::Failure)]
+    fn should_ignore_outcomes_when_open(#[case] outcome: CheckOutcome) {
+        let opened_at = Instant::now();
+        let mut breaker = open_breaker(opened_at);
+
+        breaker.record(outcome, opened_at + Duration::from_secs(1));
+
+        assert_eq!(breaker.state(), BreakerState::Open { opened_at });
+    }
+
+    #[test]
+    fn should_turn_half_open_and_probe_redis_after_open_duration() {
+        let opened_at = Instant::now();
+        let mut breaker = open_breaker(opened_at);
+
+        let route = breaker.route(opened_at + OPEN_DURATION);
+
+        assert_eq!(route, CheckRoute::Redis);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    }
+
+    #[test]
+    fn should_close_on_successful_probe() {
+        let opened_at = Instant::now();
+        let mut breaker = open_breaker(opened_at);
+        breaker.route(opened_at + OPEN_DURATION);
+
+        breaker.record(CheckOutcome::Success, opened_at + OPEN_DURATION);
+
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn should_reopen_on_failed_probe() {
+        let opened_at = Instant::now();
+        let probed_at = opened_at + OPEN_DURATION;
+        let mut breaker = open_breaker(opened_at);
+        breaker.route(probed_at);
+
+        breaker.record(CheckOutcome::Failure, probed_at);
+
+        assert_eq!(
+            breaker.state(),
+            BreakerState::Open {
+                opened_at: probed_at
+            }
+        );
+        assert_eq!(breaker.route(probed_at), CheckRoute::Allow);
+    }
+
+    #[test]
+    fn should_keep_probing_redis_when_half_open() {
+        let opened_at = Instant::now();
+        let mut breaker = open_breaker(opened_at);
+        breaker.route(opened_at + OPEN_DURATION);
+
+        let route = breaker.route(opened_at + OPEN_DURATION);
+
+        assert_eq!(route, CheckRoute::Redis);
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+    }
+}
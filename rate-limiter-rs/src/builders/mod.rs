@@ -1,19 +1,272 @@
 //! Module that includes builders to construct instances of the 2 rate limiter types. Used internally.
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+
+#[cfg(feature = "tls")]
+use redis::TlsCertificates;
+use redis::{
+    Client as RedisClient, ConnectionAddr, ConnectionInfo, IntoConnectionInfo, RedisConnectionInfo,
+};
+
+use crate::{
+    connection::ConnectionPool,
+    errors::{ConfigError, RateLimiterError},
+    sharding::Shard,
+};
+
 pub mod fixed_window;
 pub mod sliding_window;
+pub mod typestate;
+
+pub(crate) const DEFAULT_REDIS_HOST: &str = "127.0.0.1";
+pub(crate) const DEFAULT_REDIS_PORT: u16 = 6379;
+pub(crate) const DEFAULT_WINDOW_SIZE: u64 = 5;
+pub(crate) const DEFAULT_WINDOW_DURATION: Duration = Duration::from_secs(15);
+pub(crate) const DEFAULT_QUORUM_TIMEOUT: Duration = Duration::from_secs(1);
 
-const DEFAULT_REDIS_HOST: &str = "127.0.0.1";
-const DEFAULT_REDIS_PORT: u16 = 6379;
-const DEFAULT_WINDOW_SIZE: u64 = 5;
-const DEFAULT_WINDOW_DURATION: Duration = Duration::from_secs(15);
+/// The resolution of the Redis expiries, below which windows can't be enforced
+const EXPIRY_RESOLUTION: Duration = Duration::from_millis(1);
 
-#[derive(Clone)]
+/// Checks the given window can be enforced: a window size of 0 would throttle every request, and
+/// a duration below the resolution of the Redis expiries would be silently rounded up.
+pub(crate) fn validate_window(
+    window_size: u64,
+    window_duration: Duration,
+) -> Result<(), ConfigError> {
+    if window_size == 0 {
+        return Err(ConfigError::ZeroWindowSize);
+    }
+    if window_duration.is_zero() {
+        return Err(ConfigError::ZeroWindowDuration);
+    }
+    if window_duration < EXPIRY_RESOLUTION {
+        return Err(ConfigError::SubResolutionWindowDuration(window_duration));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represent the Redis configuration object
 pub struct RedisSettings {
     /// The host of the Redis server used.
     pub host: String,
     /// The port of the Redis server used.
     pub port: u16,
+    /// The username used to authenticate, for servers with ACL users. Requires a password.
+    pub username: Option<String>,
+    /// The password used to authenticate, for servers with `requirepass` or ACL users.
+    pub password: Option<String>,
+    /// Whether the connection is encrypted with TLS. Requires the `tls` feature.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tls: bool,
+    /// The logical database holding the rate limiter keys, to isolate them from application data.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub db: u16,
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        RedisSettings {
+            host: DEFAULT_REDIS_HOST.to_string(),
+            port: DEFAULT_REDIS_PORT,
+            username: None,
+            password: None,
+            tls: false,
+            db: 0,
+        }
+    }
+}
+
+impl RedisSettings {
+    /// Returns the information needed to connect to the Redis server.
+    fn connection_info(&self) -> ConnectionInfo {
+        let addr = if self.tls {
+            ConnectionAddr::TcpTls {
+                host: self.host.clone(),
+                port: self.port,
+                insecure: false,
+                tls_params: None,
+            }
+        } else {
+            ConnectionAddr::Tcp(self.host.clone(), self.port)
+        };
+
+        ConnectionInfo {
+            addr,
+            redis: RedisConnectionInfo {
+                db: self.db.into(),
+                username: self.username.clone(),
+                password: self.password.clone(),
+                ..RedisConnectionInfo::default()
+            },
+        }
+    }
+}
+
+/// The options used to connect to the underlying Redis server, shared by all the builders
+#[derive(Default)]
+pub(crate) struct RedisConnectionOptions {
+    /// The pre-built client, taking precedence over all the other options
+    pub(crate) client: Option<RedisClient>,
+    /// The configuration of the Redis server
+    pub(crate) settings: Option<RedisSettings>,
+    /// The connection URL of the Redis server, taking precedence over the settings
+    pub(crate) url: Option<String>,
+    /// The username used to authenticate, taking precedence over the settings and URL
+    pub(crate) username: Option<String>,
+    /// The password used to authenticate, taking precedence over the settings and URL
+    pub(crate) password: Option<String>,
+    /// The custom root and client certificates used for TLS connections, if any
+    #[cfg(feature = "tls")]
+    pub(crate) tls_certificates: Option<TlsCertificates>,
+    /// The maximum number of pooled connections, if connections are pooled
+    #[cfg(feature = "pool")]
+    pub(crate) pool_size: Option<u32>,
+    /// The number of connections reused across checks, if connections are shared
+    pub(crate) shared_connections: Option<usize>,
+    /// The configuration of the independent Redis servers keys are sharded across, if any,
+    /// taking precedence over the client, URL and settings
+    pub(crate) shards: Vec<RedisSettings>,
+    /// Whether checks are applied against a quorum of the independent servers, instead of
+    /// sharding keys across them
+    pub(crate) quorum: bool,
+    /// The time each of the quorum masters has to answer, if set
+    pub(crate) quorum_timeout: Option<Duration>,
+    /// The configuration of the read-only replica serving the non-mutating operations, if any
+    pub(crate) read_replica: Option<RedisSettings>,
+}
+
+impl RedisConnectionOptions {
+    /// Checks the Redis servers the rate limiter connects to have a host, among the shards if
+    /// any, or the settings, unless they're overridden by a client or a URL, and the read replica.
+    /// A read replica can only mirror a single server.
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        if self.read_replica.is_some() && !self.shards.is_empty() {
+            return Err(ConfigError::ReadReplicaWithShards);
+        }
+
+        let settings = if self.shards.is_empty() {
+            match (&self.client, &self.url) {
+                (None, None) => self.settings.as_slice(),
+                _ => &[],
+            }
+        } else {
+            self.shards.as_slice()
+        };
+
+        if settings
+            .iter()
+            .chain(&self.read_replica)
+            .any(|rs| rs.host.trim().is_empty())
+        {
+            return Err(ConfigError::EmptyRedisHost);
+        }
+        Ok(())
+    }
+
+    /// Returns the pre-built client, if set. Otherwise opens a client to the Redis server with
+    /// the given connection URL, or settings, falling back to the default server if neither is set.
+    pub(crate) fn open_client(&self) -> Result<RedisClient, RateLimiterError> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+
+        let connection_info = match (&self.url, &self.settings) {
+            (Some(url), _) => url.as_str().into_connection_info()?,
+            (None, Some(rs)) => rs.connection_info(),
+            (None, None) => RedisSettings::default().connection_info(),
+        };
+
+        self.open_client_with(connection_info)
+    }
+
+    /// Opens a client to each of the Redis servers keys are sharded across, if configured, with
+    /// its own pool of connections. The connections to quorum masters time out, so that a master
+    /// that stopped answering fails its checks instead of blocking them.
+    pub(crate) fn open_shards(&self) -> Result<Vec<Shard>, RateLimiterError> {
+        self.shards
+            .iter()
+            .map(|rs| {
+                let redis_client = self.open_client_with(rs.connection_info())?;
+                let mut connection_pool = self.connection_pool(&redis_client);
+                if self.quorum {
+                    connection_pool = connection_pool.with_timeout(self.quorum_timeout());
+                }
+                Ok(Shard::new(redis_client, connection_pool))
+            })
+            .collect()
+    }
+
+    /// Returns the time each of the quorum masters has to answer.
+    pub(crate) fn quorum_timeout(&self) -> Duration {
+        self.quorum_timeout.unwrap_or(DEFAULT_QUORUM_TIMEOUT)
+    }
+
+    /// Opens a client to the read replica, if configured, with its own pool of connections.
+    pub(crate) fn open_read_replica(&self) -> Result<Option<Arc<Shard>>, RateLimiterError> {
+        self.read_replica
+            .as_ref()
+            .map(|rs| {
+                let redis_client = self.open_client_with(rs.connection_info())?;
+                let connection_pool = self.connection_pool(&redis_client);
+                Ok(Arc::new(Shard::new(redis_client, connection_pool)))
+            })
+            .transpose()
+    }
+
+    /// Returns the client and the pool of connections of the primary Redis server, that is the
+    /// first of the given shards, if any, or the one opened by [Self::open_client] otherwise.
+    pub(crate) fn primary(
+        &self,
+        shards: &[Shard],
+    ) -> Result<(RedisClient, ConnectionPool), RateLimiterError> {
+        if let Some(shard) = shards.first() {
+            return Ok((shard.redis_client.clone(), shard.connection_pool.clone()));
+        }
+
+        let redis_client = self.open_client()?;
+        let connection_pool = self.connection_pool(&redis_client);
+        Ok((redis_client, connection_pool))
+    }
+
+    /// Opens a client with the given connection information, overriding the credentials and
+    /// certificates, if set.
+    fn open_client_with(
+        &self,
+        mut connection_info: ConnectionInfo,
+    ) -> Result<RedisClient, RateLimiterError> {
+        if let Some(username) = &self.username {
+            connection_info.redis.username = Some(username.clone());
+        }
+        if let Some(password) = &self.password {
+            connection_info.redis.password = Some(password.clone());
+        }
+
+        #[cfg(feature = "tls")]
+        if let Some(tls_certificates) = &self.tls_certificates {
+            return Ok(RedisClient::build_with_tls(
+                connection_info,
+                tls_certificates.clone(),
+            )?);
+        }
+
+        Ok(RedisClient::open(connection_info)?)
+    }
+
+    /// Returns the pool of connections to the given client, or the shared connections, if
+    /// configured.
+    #[cfg_attr(not(feature = "pool"), allow(unused_variables))]
+    pub(crate) fn connection_pool(&self, redis_client: &RedisClient) -> ConnectionPool {
+        #[cfg(feature = "pool")]
+        if let Some(pool_size) = self.pool_size {
+            return ConnectionPool::new(redis_client, pool_size);
+        }
+
+        if let Some(count) = self.shared_connections {
+            return ConnectionPool::shared(count);
+        }
+
+        ConnectionPool::default()
+    }
 }
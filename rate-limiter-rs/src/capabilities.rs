@@ -0,0 +1,218 @@
+//! Module that includes the detection of the capabilities of the underlying Redis server, so
+//! that a single binary can pick the best implementation path across Redis 5 to 7 deployments.
+//!
+//! ## Implementation details
+//!
+//! Capabilities are detected with a single `INFO` command, the first time they are requested
+//! from a rate limiter, and then cached on the rate limiter and all of its clones. The version
+//! is read from the `redis_version` field of the `Server` section, and the loaded modules from
+//! the `Modules` section.
+//!
+//! The WATCH/MULTI/EXEC transactions and Lua scripts used by checks are supported by all the
+//! Redis versions above, while Redis Functions are only used when the server supports them.
+//! Transactions only set the expiry of counters with the `NX` option of `PEXPIRE` on servers
+//! supporting it, and otherwise only set it after reading that it's missing. Capabilities are
+//! also exposed for callers.
+use std::fmt::Display;
+#[cfg(feature = "redis")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{connection::RedisConnection, errors::RateLimiterError};
+
+/// Represents a Redis server version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RedisVersion {
+    /// the major version
+    pub major: u32,
+    /// the minor version
+    pub minor: u32,
+    /// the patch version
+    pub patch: u32,
+}
+
+impl RedisVersion {
+    /// Creates a new version.
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        RedisVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for RedisVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Represents the capabilities of a Redis server
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedisCapabilities {
+    /// the version of the server
+    pub version: RedisVersion,
+    /// the names of the modules loaded on the server, like `search` or `ReJSON`
+    pub modules: Vec<String>,
+}
+
+impl RedisCapabilities {
+    /// Whether the server supports Lua scripts, via `EVAL` and `EVALSHA`
+    pub fn supports_scripting(&self) -> bool {
+        self.version >= RedisVersion::new(2, 6, 0)
+    }
+
+    /// Whether the server supports the RESP3 protocol, and with it client side caching
+    pub fn supports_resp3(&self) -> bool {
+        self.version >= RedisVersion::new(6, 0, 0)
+    }
+
+    /// Whether the server supports Redis Functions, via `FUNCTION LOAD` and `FCALL`
+    pub fn supports_functions(&self) -> bool {
+        self.version >= RedisVersion::new(7, 0, 0)
+    }
+
+    /// Whether the server supports the `NX`, `XX`, `GT` and `LT` options of `EXPIRE` and `PEXPIRE`
+    pub fn supports_expire_options(&self) -> bool {
+        self.version >= RedisVersion::new(7, 0, 0)
+    }
+
+    /// Whether the server has the given module loaded
+    pub fn has_module(&self, name: &str) -> bool {
+        self.modules.iter().any(|m| m.eq_ignore_ascii_case(name))
+    }
+
+    /// Detects the capabilities of the server the given connection points to.
+    #[cfg(feature = "redis")]
+    pub(crate) fn detect(con: &mut Connection) -> Result<Self, RateLimiterError> {
+        let info: String = redis::cmd("INFO").query(con)?;
+
+        parse_info(&info).ok_or(RateLimiterError::ComputeError)
+    }
+}
+
+/// Returns the capabilities cached in the given cell, detecting them on first use with a
+/// connection from the given source.
+#[cfg(feature = "redis")]
+pub(crate) fn negotiate(
+    capabilities: &OnceLock<RedisCapabilities>,
+    connect: impl FnOnce() -> Result<RedisConnection, RateLimiterError>,
+) -> Result<RedisCapabilities, RateLimiterError> {
+    if let Some(capabilities) = capabilities.get() {
+        return Ok(capabilities.clone());
+    }
+
+    let mut con = connect()?;
+    negotiate_on(capabilities, &mut con)
+}
+
+/// Returns the capabilities cached in the given cell, detecting them on first use with the
+/// given connection.
+#[cfg(feature = "redis")]
+pub(crate) fn negotiate_on(
+    capabilities: &OnceLock<RedisCapabilities>,
+    con: &mut Connection,
+) -> Result<RedisCapabilities, RateLimiterError> {
+    if let Some(capabilities) = capabilities.get() {
+        return Ok(capabilities.clone());
+    }
+
+    let detected = RedisCapabilities::detect(con)?;
+    Ok(capabilities.get_or_init(|| detected).clone())
+}
+
+/// Utility method that parses the capabilities from the output of the `INFO` command.
+/// Returns `None` if the version can't be found.
+#[cfg(feature = "redis")]
+fn parse_info(info: &str) -> Option<RedisCapabilities> {
+    let mut version = None;
+    let mut modules = vec![];
+
+    for line in info.lines().map(str::trim) {
+        if let Some(raw_version) = line.strip_prefix("redis_version:") {
+            version = parse_version(raw_version);
+        } else if let Some(module) = line.strip_prefix("module:") {
+            if let Some(name) = module
+                .split(',')
+                .find_map(|attribute| attribute.strip_prefix("name="))
+            {
+                modules.push(name.to_string());
+            }
+        }
+    }
+
+    Some(RedisCapabilities {
+        version: version?,
+        modules,
+    })
+}
+
+/// Utility method that parses a version like `7.2.4`. Missing components default to zero.
+#[cfg(feature = "redis")]
+fn parse_version(raw_version: &str) -> Option<RedisVersion> {
+    let mut components = raw_version.split('.').map(str::parse::<u32>);
+    let major = components.next()?.ok()?;
+    let minor = components.next().unwrap_or(Ok(0)).ok()?;
+    let patch = components.next().unwrap_or(Ok(0)).ok()?;
+
+    Some(RedisVersion::new(major, minor, patch))
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use rstest::rstest;
+
+    use super::{parse_info, parse_version, RedisCapabilities, RedisVersion};
+
+    #[rstest]
+    #[case::full("7.2.4", Some(RedisVersion::new(7, 2, 4)))]
+    #[case::major_only("6", Some(RedisVersion::new(6, 0, 0)))]
+    #[case::invalid("seven", None)]
+    fn should_parse_version(#[case] raw_version: &str, #[case] expected: Option<RedisVersion>) {
+        assert_eq!(parse_version(raw_version), expected)
+    }
+
+    #[test]
+    fn should_parse_info() {
+        let info = "# Server\r\nredis_version:7.2.4\r\nredis_mode:standalone\r\n\r\n\
+            # Modules\r\nmodule:name=search,ver=20809,api=1,filters=0\r\n\
+            module:name=ReJSON,ver=20609,api=1,filters=0\r\n";
+
+        let capabilities = parse_info(info).unwrap();
+
+        assert_eq!(capabilities.version, RedisVersion::new(7, 2, 4));
+        assert!(capabilities.has_module("rejson"));
+        assert!(!capabilities.has_module("bf"));
+    }
+
+    #[test]
+    fn should_not_parse_info_without_version() {
+        assert!(parse_info("# Server\r\nredis_mode:standalone\r\n").is_none())
+    }
+
+    #[rstest]
+    #[case::redis_5(RedisVersion::new(5, 0, 14), true, false, false, false)]
+    #[case::redis_6(RedisVersion::new(6, 2, 0), true, true, false, false)]
+    #[case::redis_7(RedisVersion::new(7, 0, 0), true, true, true, true)]
+    fn should_negotiate_capabilities_by_version(
+        #[case] version: RedisVersion,
+        #[case] scripting: bool,
+        #[case] resp3: bool,
+        #[case] functions: bool,
+        #[case] expire_options: bool,
+    ) {
+        let capabilities = RedisCapabilities {
+            version,
+            modules: vec![],
+        };
+
+        assert_eq!(capabilities.supports_scripting(), scripting);
+        assert_eq!(capabilities.supports_resp3(), resp3);
+        assert_eq!(capabilities.supports_functions(), functions);
+        assert_eq!(capabilities.supports_expire_options(), expire_options);
+    }
+}
@@ -0,0 +1,283 @@
+//! Module that includes the resolution of the IP address of the client of a request, from the
+//! `Forwarded` and `X-Forwarded-For` headers set by a configured list of trusted proxies, so that
+//! clients can't pick the IP address they are rate limited by, just by setting the headers
+//! themselves.
+//!
+//! ```
+//! use std::net::IpAddr;
+//!
+//! use rate_limiter_rs::client_ip::TrustedProxies;
+//!
+//! let trusted_proxies = TrustedProxies::new(["10.0.0.0/8", "fd00::/8"]).unwrap();
+//! let peer_ip: IpAddr = "10.0.0.2".parse().unwrap();
+//!
+//! let client_ip = trusted_proxies.client_ip(peer_ip, &[], &["203.0.113.7, 10.0.0.1"]);
+//! assert_eq!(client_ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The headers are only read when the peer of the connection is a trusted proxy: the addresses
+//! they list are then walked from the closest hop, the last one, skipping the trusted proxies,
+//! and the first untrusted address is the client. Addresses to the left of it are ignored, as the
+//! client can put anything there. The `Forwarded` header is preferred over `X-Forwarded-For` when
+//! both are set.
+//!
+//! When every listed address is trusted, the farthest one is the client. When a hop can't be
+//! parsed, like the `unknown` or obfuscated identifiers allowed by `Forwarded`, the walk stops and
+//! the closest trusted proxy is the client, as nothing beyond it can be relied upon.
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+/// Error returned when a network can't be parsed
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid network: {0}")]
+pub struct InvalidNetwork(pub String);
+
+/// Represents a network, in CIDR notation like `10.0.0.0/8`. Single addresses are networks with
+/// the longest prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNetwork {
+    /// Returns whether the given address belongs to the network. IPv4 addresses mapped to IPv6
+    /// are compared as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => prefix_matches(
+                network.to_bits().into(),
+                ip.to_bits().into(),
+                u32::BITS,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(network.to_bits(), ip.to_bits(), u128::BITS, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(network: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(network.to_string());
+
+        let (address, prefix_len) = match network.trim().split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (network.trim(), None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let max_prefix_len = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|_| invalid())?,
+            None => max_prefix_len,
+        };
+        if prefix_len > max_prefix_len {
+            return Err(invalid());
+        }
+
+        Ok(IpNetwork {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+impl Display for IpNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+/// Represents the proxies trusted to set the `Forwarded` and `X-Forwarded-For` headers
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+}
+
+impl TrustedProxies {
+    /// Trusts the proxies in the given networks, in CIDR notation like `10.0.0.0/8`, or as
+    /// single addresses. Returns an error if any of the networks can't be parsed.
+    pub fn new<I, S>(networks: I) -> Result<Self, InvalidNetwork>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let networks = networks
+            .into_iter()
+            .map(|network| network.as_ref().parse())
+            .collect::<Result<_, _>>()?;
+
+        Ok(TrustedProxies { networks })
+    }
+
+    /// Returns whether the given address is a trusted proxy.
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Returns the IP address of the client of a request, given the address of the peer of the
+    /// connection, and the values of the `Forwarded` and `X-Forwarded-For` headers of the
+    /// request, in the order they were received.
+    pub fn client_ip(
+        &self,
+        peer_ip: IpAddr,
+        forwarded: &[&str],
+        x_forwarded_for: &[&str],
+    ) -> IpAddr {
+        if !self.is_trusted(peer_ip) {
+            return peer_ip;
+        }
+
+        let hops: Vec<&str> = if forwarded.iter().any(|value| !value.trim().is_empty()) {
+            forwarded
+                .iter()
+                .flat_map(|value| value.split(','))
+                .filter_map(forwarded_for)
+                .collect()
+        } else {
+            x_forwarded_for
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|hop| !hop.is_empty())
+                .collect()
+        };
+
+        let mut client_ip = peer_ip;
+        for hop in hops.iter().rev() {
+            match parse_node(hop) {
+                Some(ip) if self.is_trusted(ip) => client_ip = ip,
+                Some(ip) => return ip,
+                None => break,
+            }
+        }
+        client_ip
+    }
+}
+
+/// Utility method that returns whether the first bits of the given addresses, of the given
+/// width, are the same up to the given prefix length.
+fn prefix_matches(network: u128, ip: u128, width: u32, prefix_len: u8) -> bool {
+    let host_bits = width - prefix_len as u32;
+
+    // shifting a u128 by 128 bits overflows: a zero prefix length matches any IPv6 address
+    host_bits == u128::BITS || (network ^ ip) >> host_bits == 0
+}
+
+/// Utility method that returns the `for` parameter of the given element of a `Forwarded` header,
+/// if any, without quotes.
+fn forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Utility method that parses the IP address of the given node, as listed by the `Forwarded` and
+/// `X-Forwarded-For` headers, like `192.0.2.43`, `192.0.2.43:47011` or `[2001:db8::17]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Some(bracketed) = node.strip_prefix('[') {
+        let (ip, _port) = bracketed.split_once(']')?;
+        return ip.parse().ok();
+    }
+    let (ip, _port) = node.split_once(':')?;
+    ip.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::IpAddr;
+
+    use rstest::rstest;
+
+    use super::{parse_node, IpNetwork, TrustedProxies};
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[rstest]
+    #[case::ipv4_inside("10.0.0.0/8", "10.1.2.3", true)]
+    #[case::ipv4_outside("10.0.0.0/8", "11.0.0.1", false)]
+    #[case::ipv4_single_address("192.0.2.1", "192.0.2.1", true)]
+    #[case::ipv4_any("0.0.0.0/0", "203.0.113.7", true)]
+    #[case::ipv4_mapped("10.0.0.0/8", "::ffff:10.0.0.1", true)]
+    #[case::ipv6_inside("fd00::/8", "fd12::1", true)]
+    #[case::ipv6_outside("fd00::/8", "2001:db8::1", false)]
+    #[case::mixed_families("10.0.0.0/8", "fd00::1", false)]
+    fn should_match_networks(#[case] network: &str, #[case] address: &str, #[case] expected: bool) {
+        let network: IpNetwork = network.parse().unwrap();
+
+        assert_eq!(network.contains(ip(address)), expected)
+    }
+
+    #[rstest]
+    #[case::not_an_address("localhost/8")]
+    #[case::ipv4_prefix_too_long("10.0.0.0/33")]
+    #[case::ipv6_prefix_too_long("fd00::/129")]
+    #[case::invalid_prefix("10.0.0.0/a")]
+    fn should_reject_invalid_networks(#[case] network: &str) {
+        assert!(network.parse::<IpNetwork>().is_err())
+    }
+
+    #[rstest]
+    #[case::ipv4("192.0.2.43", Some("192.0.2.43"))]
+    #[case::ipv4_with_port("192.0.2.43:47011", Some("192.0.2.43"))]
+    #[case::ipv6("2001:db8::17", Some("2001:db8::17"))]
+    #[case::bracketed_ipv6_with_port("[2001:db8::17]:4711", Some("2001:db8::17"))]
+    #[case::unknown("unknown", None)]
+    #[case::obfuscated("_hidden", None)]
+    fn should_parse_nodes(#[case] node: &str, #[case] expected: Option<&str>) {
+        assert_eq!(parse_node(node), expected.map(ip))
+    }
+
+    #[rstest]
+    #[case::untrusted_peer("203.0.113.9", &[], &["198.51.100.1"], "203.0.113.9")]
+    #[case::trusted_peer_without_headers("10.0.0.2", &[], &[], "10.0.0.2")]
+    #[case::spoofed_hops("10.0.0.2", &[], &["198.51.100.1, 203.0.113.7, 10.0.0.1"], "203.0.113.7")]
+    #[case::several_headers("10.0.0.2", &[], &["198.51.100.1", "203.0.113.7"], "203.0.113.7")]
+    #[case::only_trusted_hops("10.0.0.2", &[], &["10.0.0.4, 10.0.0.3"], "10.0.0.4")]
+    #[case::unknown_hop("10.0.0.2", &[], &["203.0.113.7, unknown, 10.0.0.1"], "10.0.0.1")]
+    #[case::forwarded(
+        "10.0.0.2",
+        &["for=198.51.100.1;proto=https, for=\"[2001:db8::17]:4711\";by=10.0.0.1"],
+        &["192.0.2.1"],
+        "2001:db8::17"
+    )]
+    #[case::forwarded_case_insensitive("10.0.0.2", &["For=203.0.113.7"], &[], "203.0.113.7")]
+    fn should_resolve_client_ip(
+        #[case] peer_ip: &str,
+        #[case] forwarded: &[&str],
+        #[case] x_forwarded_for: &[&str],
+        #[case] expected: &str,
+    ) {
+        let trusted_proxies = TrustedProxies::new(["10.0.0.0/8"]).unwrap();
+
+        let client_ip = trusted_proxies.client_ip(ip(peer_ip), forwarded, x_forwarded_for);
+
+        assert_eq!(client_ip, ip(expected))
+    }
+
+    #[test]
+    fn should_not_trust_any_proxy_by_default() {
+        let client_ip = TrustedProxies::default().client_ip(ip("10.0.0.2"), &[], &["203.0.113.7"]);
+
+        assert_eq!(client_ip, ip("10.0.0.2"))
+    }
+}
@@ -0,0 +1,127 @@
+//! Module that includes the clocks the rate limiters read the current time from, so that the
+//! behavior of windows at their boundaries can be tested deterministically, by moving a
+//! [ManualClock] forward instead of sleeping.
+//!
+//! ```
+//! use std::time::{Duration, SystemTime};
+//!
+//! use rate_limiter_rs::clock::{Clock, ManualClock};
+//!
+//! let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+//! // clones share the same time, so a test can keep moving the clock of a rate limiter
+//! let rate_limiter_clock = clock.clone();
+//!
+//! clock.advance(Duration::from_secs(60));
+//! assert_eq!(
+//!     rate_limiter_clock.now(),
+//!     SystemTime::UNIX_EPOCH + Duration::from_secs(60)
+//! );
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The clock drives all the timestamps computed by the rate limiters: the boundaries of sliding
+//! windows and of clock-aligned windows, the reset times of the responses, the first seen
+//! timestamps, the decay of abuse scores and the usage periods. Expiries are still enforced by
+//! Redis, on its own clock, so the counters of plain fixed windows, which only rely on `PEXPIRE`,
+//! keep expiring in real time. Sliding windows reading the time of the Redis server ignore the
+//! clock, and latencies are always measured in real time.
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Trait implemented by the sources of the current time of a rate limiter
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// Shared clocks, so that the application can keep moving a clock it hands to a rate limiter.
+impl<T: Clock + ?Sized> Clock for Arc<T> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+/// The clock of the system, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to, for tests. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    /// Creates a clock stopped at the given time.
+    pub fn new(now: SystemTime) -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the clock to the given time, possibly backwards, like a system clock adjusted by NTP.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Moves the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap();
+        *now += duration;
+    }
+}
+
+/// A manual clock stopped at the current system time
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new(SystemTime::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use super::{Clock, ManualClock, SystemClock};
+
+    #[test]
+    fn should_move_manual_clock_only_when_told_to() {
+        //arrange
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_760_601_600);
+        let clock = ManualClock::new(start);
+        let shared_clock = clock.clone();
+
+        //act
+        clock.advance(Duration::from_millis(1500));
+
+        //assert
+        assert_eq!(shared_clock.now(), start + Duration::from_millis(1500));
+        clock.set(start);
+        assert_eq!(shared_clock.now(), start);
+    }
+
+    #[test]
+    fn should_read_system_time() {
+        let before = SystemTime::now();
+
+        let now = SystemClock.now();
+
+        assert!(now >= before && now <= SystemTime::now());
+    }
+}
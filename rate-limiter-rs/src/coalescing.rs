@@ -0,0 +1,402 @@
+//! Module that includes the coalescing of the concurrent checks of the same key, so that a hot key
+//! costs fewer Redis operations, and contends less with itself, than one transaction per request.
+//!
+//! ## Implementation details
+//!
+//! At most one Redis operation per key is in flight at once. The first check of an idle key runs
+//! its operation straight away; the checks of the same key arriving while it is in flight, or
+//! while a batch is about to run, join a batch, whose first check leads it: once the operation
+//! in flight completes, the leader runs a single operation on behalf of the whole batch, like
+//! incrementing a counter by the size of the batch, then hands every check of the batch its own
+//! result, in the order they joined.
+//!
+//! Checks are therefore never delayed by more than one operation in flight, and an idle key is
+//! checked as it would be without coalescing. When the operation of a batch fails, the leader
+//! gets its error, and every other check of the batch gets a copy of it. When it panics, the
+//! operation is completed anyway, so that the next batch runs, and every other check of the
+//! batch gets an error.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex, PoisonError},
+};
+
+use redis::RedisError;
+
+use crate::errors::RateLimiterError;
+
+/// Represents the results of a batch of checks, once its operation completed
+type BatchResults<T> = Vec<Option<Result<T, RateLimiterError>>>;
+
+/// Represents a batch of checks waiting for the operation in flight on their key to complete
+struct Batch<T> {
+    /// The results of the checks of the batch, by position, once available
+    results: Mutex<Option<BatchResults<T>>>,
+    /// Notified once the results of the batch are available
+    completed: Condvar,
+    /// Notified, along with the lock of the keys, once the operation in flight on the key of the
+    /// batch completes, so that its leader runs it
+    ready: Condvar,
+}
+
+/// Represents the checks of a key
+struct KeyState<T> {
+    /// Whether an operation is in flight on the key
+    in_flight: bool,
+    /// The batch of checks waiting for the operation in flight, with its size, if any
+    next: Option<(Arc<Batch<T>>, u64)>,
+}
+
+/// Represents an operation in flight on a key, completed once dropped, even if the operation
+/// panicked. The checks of the batch it runs on behalf of, if any, are handed an error unless
+/// their results were handed first.
+struct InFlight<'a, T> {
+    /// The coalescer of the checks of the key
+    coalescer: &'a RequestCoalescer<T>,
+    /// The key the operation is in flight on
+    key: &'a str,
+    /// The batch the operation runs on behalf of, if any
+    batch: Option<Arc<Batch<T>>>,
+}
+
+impl<T> Drop for InFlight<'_, T> {
+    fn drop(&mut self) {
+        if let Some(batch) = &self.batch {
+            batch
+                .results
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_or_insert_with(Vec::new);
+            batch.completed.notify_all();
+        }
+        self.coalescer.complete(self.key);
+    }
+}
+
+/// Coalesces the concurrent checks of the same key into a single operation per batch
+pub(crate) struct RequestCoalescer<T> {
+    /// The checks of the keys with an operation in flight
+    keys: Mutex<HashMap<String, KeyState<T>>>,
+}
+
+impl<T> Default for RequestCoalescer<T> {
+    fn default() -> Self {
+        RequestCoalescer {
+            keys: Mutex::default(),
+        }
+    }
+}
+
+impl<T> RequestCoalescer<T> {
+    /// Checks the given key, with the given operation run on behalf of the whole batch the check
+    /// ends up in. The operation is given the number of checks of the batch, and returns their
+    /// results in the same order.
+    pub(crate) fn check(
+        &self,
+        key: &str,
+        op: impl FnOnce(u64) -> Result<Vec<T>, RateLimiterError>,
+    ) -> Result<T, RateLimiterError> {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = keys.entry(key.to_string()).or_insert(KeyState {
+            in_flight: false,
+            next: None,
+        });
+        if !state.in_flight && state.next.is_none() {
+            state.in_flight = true;
+            drop(keys);
+            let _in_flight = InFlight {
+                coalescer: self,
+                key,
+                batch: None,
+            };
+            return op(1).and_then(|results| {
+                results
+                    .into_iter()
+                    .next()
+                    .ok_or(RateLimiterError::ComputeError)
+            });
+        }
+
+        // joins the batch waiting for the operation in flight, leading it if it's the first check
+        let (batch, position) = match &mut state.next {
+            Some((batch, size)) => {
+                *size += 1;
+                (batch.clone(), *size - 1)
+            }
+            None => {
+                let batch = Arc::new(Batch {
+                    results: Mutex::new(None),
+                    completed: Condvar::new(),
+                    ready: Condvar::new(),
+                });
+                state.next = Some((batch.clone(), 1));
+                (batch, 0)
+            }
+        };
+
+        if position > 0 {
+            drop(keys);
+            let mut results = batch.results.lock().unwrap_or_else(PoisonError::into_inner);
+            loop {
+                if let Some(results) = results.as_mut() {
+                    let result = results.get_mut(position as usize).and_then(Option::take);
+                    return result.unwrap_or(Err(RateLimiterError::ComputeError));
+                }
+                results = batch
+                    .completed
+                    .wait(results)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+
+        // waits for the operation in flight, then closes the batch and runs its operation
+        let size = loop {
+            keys = batch
+                .ready
+                .wait(keys)
+                .unwrap_or_else(PoisonError::into_inner);
+            let state = keys
+                .get_mut(key)
+                .expect("the key of a waiting batch can't be removed");
+            if !state.in_flight {
+                state.in_flight = true;
+                break state.next.take().map_or(1, |(_, size)| size);
+            }
+        };
+        drop(keys);
+        let in_flight = InFlight {
+            coalescer: self,
+            key,
+            batch: Some(batch.clone()),
+        };
+
+        let mut results = run_batch(op, size);
+        let result = results
+            .first_mut()
+            .and_then(Option::take)
+            .unwrap_or(Err(RateLimiterError::ComputeError));
+        *batch.results.lock().unwrap_or_else(PoisonError::into_inner) = Some(results);
+        drop(in_flight);
+        result
+    }
+
+    /// Marks the operation in flight on the given key as completed, waking up the leader of the
+    /// next batch, if any.
+    fn complete(&self, key: &str) {
+        let mut keys = self.keys.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(state) = keys.get_mut(key) {
+            state.in_flight = false;
+            match &state.next {
+                Some((batch, _)) => batch.ready.notify_one(),
+                None => {
+                    keys.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// Utility method that runs the given operation on behalf of a batch of the given size, returning
+/// the result of every check of the batch.
+fn run_batch<T>(
+    op: impl FnOnce(u64) -> Result<Vec<T>, RateLimiterError>,
+    size: u64,
+) -> BatchResults<T> {
+    match op(size) {
+        Ok(results) => results.into_iter().map(|result| Some(Ok(result))).collect(),
+        Err(error) => {
+            let mut results: BatchResults<T> =
+                (1..size).map(|_| Some(Err(copy_error(&error)))).collect();
+            results.insert(0, Some(Err(error)));
+            results
+        }
+    }
+}
+
+/// Utility method that copies the given error, for the checks of a batch whose operation failed
+fn copy_error(error: &RateLimiterError) -> RateLimiterError {
+    let copy_redis_error =
+        |e: &RedisError| RedisError::from((e.kind(), "coalesced check failed", e.to_string()));
+    match error {
+        RateLimiterError::InitError(e) => RateLimiterError::InitError(copy_redis_error(e)),
+        RateLimiterError::IoError(e) => RateLimiterError::IoError(copy_redis_error(e)),
+        RateLimiterError::PolicyError(policy) => RateLimiterError::PolicyError(policy.clone()),
+        RateLimiterError::ComputeError | RateLimiterError::InvalidConfig(_) => {
+            RateLimiterError::ComputeError
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{mpsc, Arc, Mutex},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    use super::RequestCoalescer;
+    use crate::errors::RateLimiterError;
+
+    /// Represents a check running on its own thread
+    type Check = JoinHandle<Result<u64, RateLimiterError>>;
+
+    #[test]
+    fn should_run_idle_key_check_straight_away() {
+        let coalescer = RequestCoalescer::default();
+
+        let result = coalescer.check("rl:a", |size| Ok(vec![size * 10]));
+
+        assert_eq!(result.unwrap(), 10);
+        assert!(coalescer.keys.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_coalesce_checks_arriving_while_in_flight() {
+        //arrange
+        let coalescer = Arc::new(RequestCoalescer::default());
+        let sizes = Arc::new(Mutex::new(vec![]));
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let in_flight = {
+            let (coalescer, sizes) = (coalescer.clone(), sizes.clone());
+            thread::spawn(move || {
+                coalescer.check("rl:a", |size| {
+                    sizes.lock().unwrap().push(size);
+                    started_tx.send(()).unwrap();
+                    release_rx.recv().unwrap();
+                    Ok(vec![0])
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+
+        //act
+        let batch: Vec<_> = (0..3)
+            .map(|_| {
+                let (coalescer, sizes) = (coalescer.clone(), sizes.clone());
+                thread::spawn(move || {
+                    coalescer.check("rl:a", |size| {
+                        sizes.lock().unwrap().push(size);
+                        Ok((1..=size).collect())
+                    })
+                })
+            })
+            .collect();
+        while batch_size(&coalescer, "rl:a") < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        release_tx.send(()).unwrap();
+
+        //assert
+        assert_eq!(in_flight.join().unwrap().unwrap(), 0);
+        let mut results: Vec<u64> = batch
+            .into_iter()
+            .map(|check| check.join().unwrap().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(results, vec![1, 2, 3]);
+        assert_eq!(*sizes.lock().unwrap(), vec![1, 3]);
+        assert!(coalescer.keys.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_copy_error_to_every_check_of_failed_batch() {
+        let results = super::run_batch::<u64>(|_| Err(RateLimiterError::ComputeError), 3);
+
+        assert_eq!(results.len(), 3);
+        assert!(results
+            .into_iter()
+            .all(|result| matches!(result, Some(Err(RateLimiterError::ComputeError)))));
+    }
+
+    #[test]
+    fn should_run_waiting_batch_after_panicking_operation() {
+        //arrange
+        let coalescer = Arc::new(RequestCoalescer::default());
+
+        //act
+        let (in_flight, batch) =
+            check_while_in_flight(&coalescer, true, |size| Ok((1..=size).collect()));
+
+        //assert
+        assert!(in_flight.join().is_err());
+        let mut results: Vec<u64> = batch
+            .into_iter()
+            .map(|check| check.join().unwrap().unwrap())
+            .collect();
+        results.sort();
+        assert_eq!(results, vec![1, 2, 3]);
+        assert!(coalescer.keys.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_fail_checks_of_batch_whose_operation_panicked() {
+        //arrange
+        let coalescer = Arc::new(RequestCoalescer::default());
+
+        //act
+        let (in_flight, batch) =
+            check_while_in_flight(&coalescer, false, |_| panic!("batch check panicked"));
+
+        //assert
+        assert_eq!(in_flight.join().unwrap().unwrap(), 0);
+        let results: Vec<_> = batch.into_iter().map(JoinHandle::join).collect();
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert_eq!(
+            results
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|result| matches!(result, Err(RateLimiterError::ComputeError)))
+                .count(),
+            2
+        );
+        assert!(coalescer.keys.lock().unwrap().is_empty());
+    }
+
+    /// Runs a check of a key, then a batch of 3 checks of the same key with the given operation
+    /// once the first check is in flight, and completes it, panicking if asked to.
+    fn check_while_in_flight(
+        coalescer: &Arc<RequestCoalescer<u64>>,
+        panicking: bool,
+        op: fn(u64) -> Result<Vec<u64>, RateLimiterError>,
+    ) -> (Check, Vec<Check>) {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let in_flight = {
+            let coalescer = coalescer.clone();
+            thread::spawn(move || {
+                coalescer.check("rl:a", |_| {
+                    started_tx.send(()).unwrap();
+                    release_rx.recv().unwrap();
+                    if panicking {
+                        panic!("check panicked");
+                    }
+                    Ok(vec![0])
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+
+        let batch = (0..3)
+            .map(|_| {
+                let coalescer = coalescer.clone();
+                thread::spawn(move || coalescer.check("rl:a", op))
+            })
+            .collect();
+        while batch_size(coalescer, "rl:a") < 3 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        release_tx.send(()).unwrap();
+        (in_flight, batch)
+    }
+
+    fn batch_size(coalescer: &RequestCoalescer<u64>, key: &str) -> u64 {
+        coalescer
+            .keys
+            .lock()
+            .unwrap()
+            .get(key)
+            .and_then(|state| state.next.as_ref().map(|(_, size)| *size))
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,297 @@
+//! Module that includes the source of the connections to the underlying Redis server.
+//!
+//! By default, a new connection is opened for every call. Rate limiters can instead reuse a small
+//! set of shared connections, or, with the `pool` feature, borrow connections from a pool, so that
+//! high traffic services reuse connections rather than exhausting ephemeral ports. Both are shared
+//! by a rate limiter and its clones, and connections are only opened when first needed.
+//!
+//! The shared connections are handed out round-robin, each held for the duration of a check and
+//! handed back once the check completes. A check waits for the connection it's handed out while
+//! a concurrent check holds it, so that the number of connections never grows past the size of
+//! the set. A connection that broke is dropped instead of being handed back, so that the next
+//! check reconnects. Replies that arrive after a command failed are skipped by the connection
+//! itself, so that it can be reused after a failed check.
+//!
+//! Connections can be given a timeout, bounding the time to connect, and to send every command
+//! and read its reply, so that an unresponsive server fails the check instead of blocking it.
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+use redis::{Client as RedisClient, Connection, ConnectionLike};
+
+use crate::errors::RateLimiterError;
+
+/// Represents the optional pool of connections to the underlying Redis server
+#[derive(Clone, Default)]
+pub(crate) struct ConnectionPool {
+    #[cfg(feature = "pool")]
+    pool: Option<r2d2::Pool<RedisClient>>,
+    shared: Option<Arc<SharedConnections>>,
+    /// The timeout of the connections, if any
+    timeout: Option<Duration>,
+}
+
+impl ConnectionPool {
+    /// Creates a pool holding up to the given number of connections.
+    #[cfg(feature = "pool")]
+    pub(crate) fn new(redis_client: &RedisClient, max_size: u32) -> Self {
+        ConnectionPool {
+            pool: Some(
+                r2d2::Pool::builder()
+                    .max_size(max_size)
+                    .build_unchecked(redis_client.clone()),
+            ),
+            shared: None,
+            timeout: None,
+        }
+    }
+
+    /// Creates a source reusing the given number of connections, at least one, across checks.
+    pub(crate) fn shared(count: usize) -> Self {
+        ConnectionPool {
+            #[cfg(feature = "pool")]
+            pool: None,
+            shared: Some(Arc::new(SharedConnections::new(count))),
+            timeout: None,
+        }
+    }
+
+    /// Bounds the time to connect, and to send every command and read its reply, by the given
+    /// timeout.
+    pub(crate) fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Returns a connection borrowed from the pool, if any, the next shared connection, if reused,
+    /// or a new connection otherwise.
+    pub(crate) fn get(
+        &self,
+        redis_client: &RedisClient,
+    ) -> Result<RedisConnection, RateLimiterError> {
+        #[cfg(feature = "pool")]
+        if let Some(pool) = &self.pool {
+            let con = pool.get().map_err(|e| {
+                redis::RedisError::from((
+                    redis::ErrorKind::IoError,
+                    "unable to get a pooled connection",
+                    e.to_string(),
+                ))
+            })?;
+            self.set_timeouts(&con)?;
+            return Ok(RedisConnection::Pooled(con));
+        }
+
+        if let Some(shared) = &self.shared {
+            let (slot, idle) = shared.hold_next();
+            // handed back on drop, even if unable to connect
+            let mut shared = SharedConnection {
+                con: None,
+                shared: shared.clone(),
+                slot,
+            };
+            shared.con = Some(match idle {
+                Some(con) => con,
+                None => self.connect(redis_client)?,
+            });
+            return Ok(RedisConnection::Shared(shared));
+        }
+
+        Ok(RedisConnection::Direct(self.connect(redis_client)?))
+    }
+
+    /// Opens a new connection with the given client, within the timeout, if any.
+    fn connect(&self, redis_client: &RedisClient) -> Result<Connection, RateLimiterError> {
+        let con = match self.timeout {
+            Some(timeout) => redis_client.get_connection_with_timeout(timeout)?,
+            None => redis_client.get_connection()?,
+        };
+        self.set_timeouts(&con)?;
+        Ok(con)
+    }
+
+    /// Bounds the time to send every command and read its reply on the given connection by the
+    /// timeout, if any.
+    fn set_timeouts(&self, con: &Connection) -> Result<(), RateLimiterError> {
+        if let Some(timeout) = self.timeout {
+            con.set_read_timeout(Some(timeout))?;
+            con.set_write_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the fixed set of shared connections, handed out round-robin
+struct SharedConnections {
+    slots: Box<[SharedSlot]>,
+    /// The index of the next slot handed out, wrapping around the slots
+    next: AtomicUsize,
+}
+
+impl SharedConnections {
+    fn new(count: usize) -> Self {
+        SharedConnections {
+            slots: (0..count.max(1)).map(|_| SharedSlot::default()).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Holds the next slot, waiting for the check holding it to hand it back, if any. Returns the
+    /// index of the slot, and its idle connection, unless none was opened yet or the last one
+    /// was dropped.
+    fn hold_next(&self) -> (usize, Option<Connection>) {
+        let slot = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        (slot, self.slots[slot].hold())
+    }
+
+    /// Hands the given slot back, with the given connection, if still usable.
+    fn hand_back(&self, slot: usize, con: Option<Connection>) {
+        self.slots[slot].hand_back(con)
+    }
+}
+
+/// Represents the slot of a shared connection, held by one check at a time
+#[derive(Default)]
+struct SharedSlot {
+    state: Mutex<SlotState>,
+    handed_back: Condvar,
+}
+
+/// Represents the state of the slot of a shared connection
+#[derive(Default)]
+struct SlotState {
+    /// The idle connection, if any
+    idle: Option<Connection>,
+    /// Whether a check holds the slot
+    held: bool,
+}
+
+impl SharedSlot {
+    /// Holds the slot, waiting for the check holding it to hand it back, if any. Returns the
+    /// idle connection, unless none was opened yet or the last one was dropped.
+    fn hold(&self) -> Option<Connection> {
+        let mut state = self
+            .handed_back
+            .wait_while(self.lock(), |state| state.held)
+            .unwrap_or_else(PoisonError::into_inner);
+        state.held = true;
+        state.idle.take()
+    }
+
+    /// Hands the slot back, with the given connection, if still usable, waking up a waiting check.
+    fn hand_back(&self, con: Option<Connection>) {
+        let mut state = self.lock();
+        state.idle = con;
+        state.held = false;
+        self.handed_back.notify_one();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SlotState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Represents a shared connection, held for the duration of a check
+pub(crate) struct SharedConnection {
+    con: Option<Connection>,
+    shared: Arc<SharedConnections>,
+    /// The index of the slot the connection is handed back to
+    slot: usize,
+}
+
+impl Drop for SharedConnection {
+    fn drop(&mut self) {
+        let con = self.con.take().filter(|con| con.is_open());
+        self.shared.hand_back(self.slot, con);
+    }
+}
+
+/// Represents a connection to the underlying Redis server, either owned, shared or borrowed from
+/// a pool
+pub(crate) enum RedisConnection {
+    Direct(Connection),
+    Shared(SharedConnection),
+    #[cfg(feature = "pool")]
+    Pooled(r2d2::PooledConnection<RedisClient>),
+}
+
+impl Deref for RedisConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            RedisConnection::Direct(con) => con,
+            RedisConnection::Shared(SharedConnection { con, .. }) => con
+                .as_ref()
+                .expect("shared connection is only handed back on drop"),
+            #[cfg(feature = "pool")]
+            RedisConnection::Pooled(con) => con,
+        }
+    }
+}
+
+impl DerefMut for RedisConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            RedisConnection::Direct(con) => con,
+            RedisConnection::Shared(SharedConnection { con, .. }) => con
+                .as_mut()
+                .expect("shared connection is only handed back on drop"),
+            #[cfg(feature = "pool")]
+            RedisConnection::Pooled(con) => con,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread, time::Duration};
+
+    use super::{SharedConnections, SharedSlot};
+
+    #[test]
+    fn should_hand_out_shared_connections_round_robin() {
+        //arrange
+        let shared = SharedConnections::new(3);
+
+        //act
+        let slots: Vec<usize> = (0..6)
+            .map(|_| {
+                let (slot, _) = shared.hold_next();
+                shared.hand_back(slot, None);
+                slot
+            })
+            .collect();
+
+        //assert
+        assert_eq!(slots, vec![0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn should_wait_for_shared_connection_handed_back() {
+        //arrange
+        let slot = SharedSlot::default();
+        assert!(slot.hold().is_none());
+
+        //act
+        let waited = thread::scope(|scope| {
+            let waiting = scope.spawn(|| {
+                slot.hold();
+                slot.lock().held
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiting.is_finished());
+            slot.hand_back(None);
+            waiting.join().unwrap()
+        });
+
+        //assert
+        assert!(waited);
+    }
+}
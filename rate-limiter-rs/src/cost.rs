@@ -0,0 +1,170 @@
+//! Module that includes the cost functions, computing how many units of the budget of a request
+//! identifier a request consumes, from the context provided by the caller, so that expensive
+//! requests, like searches or large uploads, count more than cheap ones.
+//!
+//! ```
+//! use std::collections::HashMap;
+//!
+//! use rate_limiter_rs::cost::{CostFunction, EndpointWeights, RequestContext};
+//!
+//! let cost_function = EndpointWeights {
+//!     weights: HashMap::from([("/search".to_string(), 5)]),
+//!     default_weight: 1,
+//! };
+//!
+//! assert_eq!(cost_function.cost(&RequestContext::endpoint("/search")), 5);
+//! assert_eq!(cost_function.cost(&RequestContext::endpoint("/health")), 1);
+//! ```
+//!
+//! ## Implementation details
+//!
+//! A cost function is given to a rate limiter by its builder, and applied on every check: with
+//! the context of the request, by [RateLimiter::check_request_with_context](crate::RateLimiter::check_request_with_context),
+//! or with an empty context, by [RateLimiter::check_request](crate::RateLimiter::check_request).
+//! Costs of 0 are counted as 1, so that every request is checked.
+//!
+//! A request is allowed if the whole of its cost fits in the budget left to its identifier, and is
+//! otherwise throttled, its cost being counted anyway, like every throttled request. The checks
+//! of requests costing more than one unit always run as a transaction, whichever the check mode
+//! negotiated with the Redis server, and are never coalesced. Costs are capped to one more than
+//! the window size, as a request costing more is throttled all the same: the fixed window
+//! increments its counter by the capped cost, and the sliding window stores a request as one
+//! member of its sorted set per unit of it.
+use std::collections::HashMap;
+
+/// Represents the context of a request, provided by the caller, that costs are computed from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    /// The endpoint, or operation, the request is for, like `/search` or `GetObject`
+    pub endpoint: Option<String>,
+
+    /// The size of the payload of the request, in bytes
+    pub payload_size: Option<u64>,
+}
+
+impl RequestContext {
+    /// Creates the context of a request for the given endpoint.
+    pub fn endpoint(endpoint: impl Into<String>) -> Self {
+        RequestContext {
+            endpoint: Some(endpoint.into()),
+            ..RequestContext::default()
+        }
+    }
+
+    /// Returns the same context, with the given payload size, in bytes.
+    pub fn with_payload_size(mut self, payload_size: u64) -> Self {
+        self.payload_size = Some(payload_size);
+        self
+    }
+}
+
+/// Trait implemented by the functions computing the cost of a request from its context. Any
+/// closure taking a [RequestContext] and returning a `u64` is a cost function.
+pub trait CostFunction: Send + Sync {
+    /// Returns the number of units of the budget the request with the given context consumes.
+    fn cost(&self, context: &RequestContext) -> u64;
+}
+
+impl<F> CostFunction for F
+where
+    F: Fn(&RequestContext) -> u64 + Send + Sync,
+{
+    fn cost(&self, context: &RequestContext) -> u64 {
+        self(context)
+    }
+}
+
+/// Cost function weighting requests by endpoint
+#[derive(Debug, Clone, Default)]
+pub struct EndpointWeights {
+    /// The cost of the requests for each endpoint
+    pub weights: HashMap<String, u64>,
+
+    /// The cost of the requests for any other endpoint, or without one
+    pub default_weight: u64,
+}
+
+impl CostFunction for EndpointWeights {
+    fn cost(&self, context: &RequestContext) -> u64 {
+        context
+            .endpoint
+            .as_ref()
+            .and_then(|endpoint| self.weights.get(endpoint))
+            .copied()
+            .unwrap_or(self.default_weight)
+    }
+}
+
+/// Cost function charging one unit per started chunk of the payload of requests, so that a
+/// request costs at least one unit
+#[derive(Debug, Clone)]
+pub struct PayloadSizeCost {
+    /// The size of a chunk of payload, in bytes
+    pub unit_bytes: u64,
+}
+
+impl CostFunction for PayloadSizeCost {
+    fn cost(&self, context: &RequestContext) -> u64 {
+        context
+            .payload_size
+            .unwrap_or_default()
+            .div_ceil(self.unit_bytes.max(1))
+            .max(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use rstest::rstest;
+
+    use super::{CostFunction, EndpointWeights, PayloadSizeCost, RequestContext};
+
+    #[rstest]
+    #[case::weighted_endpoint(RequestContext::endpoint("/search"), 5)]
+    #[case::other_endpoint(RequestContext::endpoint("/health"), 2)]
+    #[case::no_endpoint(RequestContext::default(), 2)]
+    fn should_weight_requests_by_endpoint(
+        #[case] context: RequestContext,
+        #[case] expected_cost: u64,
+    ) {
+        let cost_function = EndpointWeights {
+            weights: HashMap::from([("/search".to_string(), 5)]),
+            default_weight: 2,
+        };
+
+        assert_eq!(cost_function.cost(&context), expected_cost);
+    }
+
+    #[rstest]
+    #[case::no_payload(None, 1)]
+    #[case::empty_payload(Some(0), 1)]
+    #[case::exact_chunks(Some(2048), 2)]
+    #[case::started_chunk(Some(2049), 3)]
+    fn should_charge_requests_by_payload_size(
+        #[case] payload_size: Option<u64>,
+        #[case] expected_cost: u64,
+    ) {
+        let cost_function = PayloadSizeCost { unit_bytes: 1024 };
+        let context = RequestContext {
+            payload_size,
+            ..RequestContext::default()
+        };
+
+        assert_eq!(cost_function.cost(&context), expected_cost);
+    }
+
+    #[test]
+    fn should_use_closures_as_cost_functions() {
+        let cost_function = |context: &RequestContext| match context.endpoint.as_deref() {
+            Some("/export") => 10,
+            _ => 1,
+        };
+
+        assert_eq!(
+            cost_function.cost(&RequestContext::endpoint("/export").with_payload_size(12)),
+            10
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! Module that includes the utilities used to serve data-subject access and deletion requests,
+//! that is exporting or deleting all the state the rate limiter stored for a given request identifier.
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{
+    errors::RateLimiterError, onboarding::first_seen_key, overrides::override_key,
+    reputation::reputation_key,
+};
+
+/// Represents all the state stored in Redis for a given request identifier
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdentifierData {
+    /// the Redis entries holding the state of the request identifier
+    pub entries: Vec<StoredEntry>,
+}
+
+/// Represents a single Redis entry stored for a request identifier
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredEntry {
+    /// the Redis key of the entry
+    pub key: String,
+    /// the value of the entry
+    pub value: StoredValue,
+    /// the remaining time to live of the entry, if any
+    pub expire_in: Option<Duration>,
+}
+
+/// Enum that represents the possible values stored for a request identifier
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StoredValue {
+    /// A plain value, like a counter or a timestamp
+    Value(String),
+    /// The members of a sorted set, like the timestamps of the requests in a sliding window
+    Members(Vec<String>),
+    /// The fields of a hash, like the abuse score of a request identifier
+    Fields(Vec<(String, String)>),
+}
+
+/// Utility method that returns all the keys that might hold state for the given request key.
+#[cfg(feature = "redis")]
+pub(crate) fn identifier_keys(key: &str) -> Vec<String> {
+    vec![
+        key.to_string(),
+        first_seen_key(key),
+        reputation_key(key),
+        override_key(key),
+    ]
+}
+
+/// Reads the given keys, skipping the ones that don't exist.
+#[cfg(feature = "redis")]
+pub(crate) fn export_keys(
+    con: &mut Connection,
+    keys: &[String],
+) -> Result<IdentifierData, RateLimiterError> {
+    let mut entries = Vec::with_capacity(keys.len());
+
+    for key in keys {
+        let key_type: String = redis::cmd("TYPE").arg(key).query(con)?;
+        let value = match key_type.as_str() {
+            "none" => continue,
+            "string" => StoredValue::Value(redis::cmd("GET").arg(key).query(con)?),
+            "zset" => {
+                StoredValue::Members(redis::cmd("ZRANGE").arg(key).arg(0).arg(-1).query(con)?)
+            }
+            "hash" => StoredValue::Fields(redis::cmd("HGETALL").arg(key).query(con)?),
+            _ => return Err(RateLimiterError::ComputeError),
+        };
+        let expire_in_millis: i64 = redis::cmd("PTTL").arg(key).query(con)?;
+
+        entries.push(StoredEntry {
+            key: key.clone(),
+            value,
+            expire_in: u64::try_from(expire_in_millis)
+                .ok()
+                .map(Duration::from_millis),
+        });
+    }
+
+    Ok(IdentifierData { entries })
+}
+
+/// Deletes the given keys, returning the number of keys actually deleted.
+#[cfg(feature = "redis")]
+pub(crate) fn purge_keys(con: &mut Connection, keys: &[String]) -> Result<u64, RateLimiterError> {
+    let deleted_keys: u64 = redis::cmd("DEL").arg(keys).query(con)?;
+    Ok(deleted_keys)
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use super::identifier_keys;
+
+    #[test]
+    fn should_list_all_identifier_keys() {
+        assert_eq!(
+            identifier_keys("rl:ip_1.2.3.4"),
+            vec![
+                "rl:ip_1.2.3.4",
+                "rl:ip_1.2.3.4:first_seen",
+                "rl:ip_1.2.3.4:reputation",
+                "rl:override:ip_1.2.3.4"
+            ]
+        )
+    }
+}
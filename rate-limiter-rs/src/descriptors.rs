@@ -0,0 +1,371 @@
+//! Module that includes a descriptor based policy engine, inspired by the
+//! [Envoy rate limit service](https://www.envoyproxy.io/docs/envoy/latest/intro/arch_overview/other_features/global_rate_limiting).
+//!
+//! Requests are described by a set of [descriptors](Descriptor), that is key/value pairs like
+//! `ip=1.2.3.4`, `path=/search` or `api_key=abc`. [Rules](DescriptorRule) match descriptors to
+//! policies, so that complex setups like "per ip AND per path AND per api key" can be enforced
+//! with a single call:
+//!
+//! ```no_run
+//! use rate_limiter_rs::{
+//!     builders::RedisSettings,
+//!     descriptors::{Descriptor, DescriptorRule, PolicyEngine},
+//! };
+//!
+//! let engine = PolicyEngine::new(
+//!     vec![
+//!         DescriptorRule::new("per_ip", "100/min").unwrap().matching_key("ip"),
+//!         DescriptorRule::new("search_per_api_key", "10/s")
+//!             .unwrap()
+//!             .matching_value("path", "/search")
+//!             .matching_key("api_key"),
+//!     ],
+//!     RedisSettings::default(),
+//! )
+//! .unwrap();
+//!
+//! let response = engine.check(&[
+//!     Descriptor::new("ip", "1.2.3.4"),
+//!     Descriptor::new("path", "/search"),
+//!     Descriptor::new("api_key", "abc"),
+//! ]);
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Each rule is backed by its own sliding window rate limiter. All the rules matching a request
+//! are checked, and counted, in a single transaction, so that a request costs one round trip to
+//! Redis however many rules it matches. The most restrictive result is returned: the throttled
+//! response with the longest retry time if any, or the allowed response with the fewest
+//! remaining requests otherwise. Each distinct combination of the matched descriptor values is metered
+//! separately, under the `rl:cst_<rule name>:<key>=<value>:...` key.
+use crate::{
+    builders::RedisSettings, encode_key_component, errors::RateLimiterError,
+    factory::RateLimiterFactory, policy::Policy,
+    rate_limiters::sliding_window::SlidingWindowRateLimiter, RateLimiter, RateLimiterResponse,
+    RequestIdentifier,
+};
+
+/// Represents a key/value pair describing a request
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Descriptor {
+    /// the name of the descriptor, like `ip`
+    pub key: String,
+    /// the value of the descriptor, like `1.2.3.4`
+    pub value: String,
+}
+
+impl Descriptor {
+    /// Creates a new descriptor.
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Descriptor {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// Represents a rule that applies a policy to the requests carrying some descriptors
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescriptorRule {
+    /// the name of the rule, used as prefix of the Redis keys
+    pub name: String,
+    /// the descriptors a request must carry for the rule to apply. A `None` value matches any
+    /// value of the descriptor. A rule with no descriptors applies to all requests.
+    pub descriptors: Vec<(String, Option<String>)>,
+    /// the policy enforced on the matching requests
+    pub policy: Policy,
+}
+
+impl DescriptorRule {
+    /// Creates a new rule enforcing the given policy, like `100/min`, on all requests.
+    /// Returns an error if the policy can't be parsed.
+    pub fn new(name: impl Into<String>, policy: &str) -> Result<Self, RateLimiterError> {
+        Ok(DescriptorRule {
+            name: name.into(),
+            descriptors: vec![],
+            policy: policy.parse()?,
+        })
+    }
+
+    /// Restricts the rule to requests carrying the given descriptor, with any value. Each value
+    /// is metered separately.
+    pub fn matching_key(mut self, key: impl Into<String>) -> Self {
+        self.descriptors.push((key.into(), None));
+        self
+    }
+
+    /// Restricts the rule to requests carrying the given descriptor, with the given value.
+    pub fn matching_value(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.descriptors.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Returns the request identifier the given descriptors are metered under, if the rule
+    /// applies to them.
+    fn request_identifier(&self, descriptors: &[Descriptor]) -> Option<RequestIdentifier> {
+        let mut components = Vec::with_capacity(self.descriptors.len());
+        for (key, expected_value) in &self.descriptors {
+            let descriptor = descriptors.iter().find(|d| &d.key == key)?;
+            if expected_value
+                .as_ref()
+                .is_some_and(|value| value != &descriptor.value)
+            {
+                return None;
+            }
+            components.push(format!(
+                "{}={}",
+                encode_key_component(key),
+                encode_key_component(&descriptor.value)
+            ));
+        }
+
+        Some(RequestIdentifier::Custom {
+            key: encode_key_component(&self.name),
+            value: components.join(":"),
+        })
+    }
+}
+
+/// Policy engine that checks requests against all the rules matching their descriptors
+pub struct PolicyEngine {
+    rules: Vec<(DescriptorRule, SlidingWindowRateLimiter)>,
+}
+
+impl PolicyEngine {
+    /// Builds a policy engine enforcing the given rules, backed by the given Redis server.
+    pub fn new(
+        rules: Vec<DescriptorRule>,
+        redis_settings: RedisSettings,
+    ) -> Result<Self, RateLimiterError> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let rate_limiter = RateLimiterFactory::sliding_window()
+                    .with_window_size(rule.policy.window_size())
+                    .with_window_duration(rule.policy.window_duration())
+                    .with_redis_settings(redis_settings.clone())
+                    .build()?;
+                Ok((rule, rate_limiter))
+            })
+            .collect::<Result<_, RateLimiterError>>()?;
+
+        Ok(PolicyEngine { rules })
+    }
+
+    /// Checks the request described by the given descriptors against all the matching rules,
+    /// returning the most restrictive result. Returns `None` if no rule applies to the request.
+    pub fn check(
+        &self,
+        descriptors: &[Descriptor],
+    ) -> Result<Option<RateLimiterResponse>, RateLimiterError> {
+        let checks: Vec<(&SlidingWindowRateLimiter, String)> = self
+            .rules
+            .iter()
+            .filter_map(|(rule, rate_limiter)| {
+                let request_identifier = rule.request_identifier(descriptors)?;
+                Some((
+                    rate_limiter,
+                    rate_limiter.build_request_key(request_identifier),
+                ))
+            })
+            .collect();
+
+        Ok(SlidingWindowRateLimiter::check_many(&checks)?
+            .into_iter()
+            .reduce(more_restrictive))
+    }
+}
+
+/// Utility method that returns the most restrictive of two responses
+fn more_restrictive(a: RateLimiterResponse, b: RateLimiterResponse) -> RateLimiterResponse {
+    match (&a, &b) {
+        (RateLimiterResponse::RequestThrottled(_), RateLimiterResponse::RequestAllowed(_)) => a,
+        (RateLimiterResponse::RequestAllowed(_), RateLimiterResponse::RequestThrottled(_)) => b,
+        (
+            RateLimiterResponse::RequestThrottled(throttled_a),
+            RateLimiterResponse::RequestThrottled(throttled_b),
+        ) => {
+            if throttled_b.retry_in > throttled_a.retry_in {
+                b
+            } else {
+                a
+            }
+        }
+        (
+            RateLimiterResponse::RequestAllowed(allowed_a),
+            RateLimiterResponse::RequestAllowed(allowed_b),
+        ) => {
+            if allowed_b.remaining_request_counter < allowed_a.remaining_request_counter {
+                b
+            } else {
+                a
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rstest::rstest;
+
+    use super::{more_restrictive, Descriptor, DescriptorRule, PolicyEngine};
+    use crate::{
+        redis_mock::RedisMock, RateLimitStatus, RateLimiterResponse, RedisAdmin, RequestAllowed,
+        RequestIdentifier, RequestThrottled, ThrottleReason,
+    };
+
+    #[rstest]
+    #[case::any_value(DescriptorRule::new("r", "1/s").unwrap().matching_key("ip"), Some("ip=1.2.3.4"))]
+    #[case::matching_value(DescriptorRule::new("r", "1/s").unwrap().matching_value("path", "/search"), Some("path=/search"))]
+    #[case::other_value(DescriptorRule::new("r", "1/s").unwrap().matching_value("path", "/export"), None)]
+    #[case::missing_key(DescriptorRule::new("r", "1/s").unwrap().matching_key("api_key"), None)]
+    #[case::all_requests(DescriptorRule::new("r", "1/s").unwrap(), Some(""))]
+    #[case::many(DescriptorRule::new("r", "1/s").unwrap().matching_value("path", "/search").matching_key("ip"), Some("path=/search:ip=1.2.3.4"))]
+    fn should_match_descriptors(#[case] rule: DescriptorRule, #[case] expected: Option<&str>) {
+        let descriptors = [
+            Descriptor::new("ip", "1.2.3.4"),
+            Descriptor::new("path", "/search"),
+        ];
+
+        let value = rule
+            .request_identifier(&descriptors)
+            .map(|request_identifier| match request_identifier {
+                RequestIdentifier::Custom { value, .. } => value,
+                _ => panic!("not a custom identifier!"),
+            });
+
+        assert_eq!(value.as_deref(), expected)
+    }
+
+    #[test]
+    fn should_prefer_throttled_response_with_longest_retry() {
+        let allowed = allowed(3);
+        let throttled_short = throttled(Duration::from_secs(1));
+        let throttled_long = throttled(Duration::from_secs(10));
+
+        let res = more_restrictive(more_restrictive(allowed, throttled_long), throttled_short);
+
+        assert_eq!(res.as_throttled().retry_in, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn should_prefer_allowed_response_with_fewest_remaining_requests() {
+        let res = more_restrictive(allowed(3), allowed(1));
+
+        assert_eq!(res.as_allowed().remaining_request_counter, 1)
+    }
+
+    #[test]
+    fn should_enforce_most_restrictive_rule_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let engine = PolicyEngine::new(
+            vec![
+                DescriptorRule::new("per_ip", "5/min")
+                    .unwrap()
+                    .matching_key("ip"),
+                DescriptorRule::new("search_per_ip", "2/min")
+                    .unwrap()
+                    .matching_value("path", "/search")
+                    .matching_key("ip"),
+            ],
+            redis_mock.redis_settings(),
+        )
+        .unwrap();
+        let search = [
+            Descriptor::new("ip", "1.2.3.4"),
+            Descriptor::new("path", "/search"),
+        ];
+        let export = [
+            Descriptor::new("ip", "1.2.3.4"),
+            Descriptor::new("path", "/export"),
+        ];
+
+        //act & assert
+        for n in 1..=2 {
+            let allowed_res = engine.check(&search).unwrap().unwrap().as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 2 - n);
+        }
+        engine.check(&search).unwrap().unwrap().as_throttled();
+        let allowed_res = engine.check(&export).unwrap().unwrap().as_allowed();
+        assert_eq!(allowed_res.remaining_request_counter, 1);
+        assert!(engine
+            .check(&[Descriptor::new("api_key", "abc")])
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn should_count_request_in_every_matching_rule_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let engine = PolicyEngine::new(
+            vec![
+                DescriptorRule::new("per_ip", "5/min")
+                    .unwrap()
+                    .matching_key("ip"),
+                DescriptorRule::new("per_api_key", "1/min")
+                    .unwrap()
+                    .matching_key("api_key"),
+                DescriptorRule::new("per_path", "1/min")
+                    .unwrap()
+                    .matching_key("path"),
+            ],
+            redis_mock.redis_settings(),
+        )
+        .unwrap();
+        let descriptors = [
+            Descriptor::new("ip", "1.2.3.4"),
+            Descriptor::new("api_key", "abc"),
+        ];
+
+        //act
+        engine.check(&descriptors).unwrap().unwrap().as_allowed();
+        let throttled_res = engine.check(&descriptors).unwrap().unwrap().as_throttled();
+
+        //assert
+        assert_eq!(throttled_res.status.limit, 1);
+        let counts: Vec<u64> = engine
+            .rules
+            .iter()
+            .map(|(rule, rate_limiter)| {
+                let request_identifier =
+                    rule.request_identifier(&descriptors)
+                        .unwrap_or(RequestIdentifier::Custom {
+                            key: "unmatched".to_string(),
+                            value: "".to_string(),
+                        });
+                rate_limiter.inspect(request_identifier).unwrap().count
+            })
+            .collect();
+        assert_eq!(counts, vec![2, 2, 0]);
+    }
+
+    fn allowed(remaining_request_counter: u64) -> RateLimiterResponse {
+        RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter,
+            status: status(),
+        })
+    }
+
+    fn throttled(retry_in: Duration) -> RateLimiterResponse {
+        RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in,
+            reason: ThrottleReason::QuotaExceeded,
+            status: status(),
+        })
+    }
+
+    fn status() -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 5,
+            window_duration: Duration::from_secs(60),
+            used: 5,
+            reset_at: SystemTime::now(),
+        }
+    }
+}
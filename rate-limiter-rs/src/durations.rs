@@ -0,0 +1,177 @@
+//! Module that includes the parser of human friendly durations, like `500ms`, `2m` or `1h30m`,
+//! used by the configuration objects and the [policy](crate::policy) syntax, so that window
+//! lengths don't have to be expressed as raw numbers of seconds.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use rate_limiter_rs::durations::{format_duration, parse_duration};
+//!
+//! assert_eq!(parse_duration("1h 30m").unwrap(), Duration::from_secs(90 * 60));
+//! assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+//! assert_eq!(format_duration(Duration::from_secs(120)), "2m");
+//! ```
+//!
+//! ## Implementation details
+//!
+//! A duration is a sequence of numbers, each followed by a unit, optionally separated by
+//! whitespace. Supported units are `ns`, `us`/`µs`, `ms`, `s`/`sec`/`second`,
+//! `m`/`min`/`minute`, `h`/`hr`/`hour` and `d`/`day`, singular or plural. Numbers without a unit
+//! are rejected, as they are exactly the ambiguity the format is meant to remove.
+//!
+//! With the `serde` feature enabled, optional durations can be (de)serialized as strings with
+//! `#[serde(with = "rate_limiter_rs::durations::optional")]`.
+use std::time::Duration;
+
+/// Error returned when a duration can't be parsed
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid duration: {0}")]
+pub struct InvalidDuration(pub String);
+
+/// The units a duration can be formatted with, from the largest to the smallest
+const FORMAT_UNITS: [(&str, Duration); 7] = [
+    ("d", Duration::from_secs(24 * 60 * 60)),
+    ("h", Duration::from_secs(60 * 60)),
+    ("m", Duration::from_secs(60)),
+    ("s", Duration::from_secs(1)),
+    ("ms", Duration::from_millis(1)),
+    ("us", Duration::from_micros(1)),
+    ("ns", Duration::from_nanos(1)),
+];
+
+/// Parses a duration like `500ms`, `2m` or `1h 30m`.
+pub fn parse_duration(duration: &str) -> Result<Duration, InvalidDuration> {
+    let invalid = || InvalidDuration(duration.to_string());
+
+    let mut rest = duration.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut total_nanos: u128 = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let value: u128 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = rest[digits..].trim_start();
+
+        let letters = rest
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = unit_duration(&rest[..letters]).ok_or_else(invalid)?;
+        rest = rest[letters..].trim_start();
+
+        total_nanos = value
+            .checked_mul(unit.as_nanos())
+            .and_then(|nanos| total_nanos.checked_add(nanos))
+            .ok_or_else(invalid)?;
+    }
+
+    u64::try_from(total_nanos)
+        .map(Duration::from_nanos)
+        .map_err(|_| invalid())
+}
+
+/// Formats a duration with the largest unit it is a whole number of, like `2m` or `1500ms`, so
+/// that it can be parsed back by [parse_duration].
+pub fn format_duration(duration: Duration) -> String {
+    if duration.is_zero() {
+        return "0s".to_string();
+    }
+
+    let nanos = duration.as_nanos();
+    let (unit, unit_duration) = FORMAT_UNITS
+        .into_iter()
+        .find(|(_, unit_duration)| nanos.is_multiple_of(unit_duration.as_nanos()))
+        .unwrap_or(FORMAT_UNITS[FORMAT_UNITS.len() - 1]);
+
+    format!("{}{}", nanos / unit_duration.as_nanos(), unit)
+}
+
+/// Returns the duration of the given unit, like `ms` or `minutes`, if supported.
+pub(crate) fn unit_duration(unit: &str) -> Option<Duration> {
+    match unit {
+        "ns" | "nsec" | "nsecs" => Some(Duration::from_nanos(1)),
+        "us" | "µs" | "usec" | "usecs" => Some(Duration::from_micros(1)),
+        "ms" | "msec" | "msecs" | "millis" => Some(Duration::from_millis(1)),
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(Duration::from_secs(1)),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(Duration::from_secs(60)),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(Duration::from_secs(60 * 60)),
+        "d" | "day" | "days" => Some(Duration::from_secs(24 * 60 * 60)),
+        _ => None,
+    }
+}
+
+/// Serializes and deserializes optional durations as human friendly strings, like `500ms`.
+/// Requires the `serde` feature.
+#[cfg(feature = "serde")]
+pub mod optional {
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{format_duration, parse_duration};
+
+    /// Serializes the given duration, if any, as a string like `500ms`.
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        duration.map(format_duration).serialize(serializer)
+    }
+
+    /// Deserializes a duration, if any, from a string like `500ms`.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|duration| parse_duration(&duration).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{format_duration, parse_duration};
+
+    #[rstest]
+    #[case::millis("500ms", Duration::from_millis(500))]
+    #[case::minutes("2m", Duration::from_secs(120))]
+    #[case::hours("1h", Duration::from_secs(3600))]
+    #[case::long_unit("3 seconds", Duration::from_secs(3))]
+    #[case::combined("1h30m", Duration::from_secs(5400))]
+    #[case::combined_with_spaces(" 1m 500ms ", Duration::from_millis(60_500))]
+    #[case::micros("250µs", Duration::from_micros(250))]
+    fn should_parse_durations(#[case] duration: &str, #[case] expected: Duration) {
+        assert_eq!(parse_duration(duration).unwrap(), expected)
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::missing_unit("60")]
+    #[case::missing_number("ms")]
+    #[case::unknown_unit("2 fortnights")]
+    #[case::negative("-1s")]
+    #[case::overflow("99999999999999999999d")]
+    fn should_reject_invalid_durations(#[case] duration: &str) {
+        assert!(parse_duration(duration).is_err())
+    }
+
+    #[rstest]
+    #[case::zero(Duration::ZERO, "0s")]
+    #[case::minutes(Duration::from_secs(120), "2m")]
+    #[case::seconds(Duration::from_secs(90), "90s")]
+    #[case::millis(Duration::from_millis(1500), "1500ms")]
+    #[case::nanos(Duration::from_nanos(7), "7ns")]
+    fn should_format_durations(#[case] duration: Duration, #[case] expected: &str) {
+        let formatted = format_duration(duration);
+
+        assert_eq!(formatted, expected);
+        assert_eq!(parse_duration(&formatted).unwrap(), duration);
+    }
+}
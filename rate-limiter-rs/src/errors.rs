@@ -1,18 +1,54 @@
 //! Module that includes the library custom errors.
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
 use redis::RedisError;
 
 /// Enum that represent the error potentially returned by the rate limiter component
 #[derive(thiserror::Error, Debug)]
 pub enum RateLimiterError {
+    #[cfg(feature = "redis")]
     #[error("Init error")]
     InitError(#[source] RedisError),
     #[error("Compute error")]
     ComputeError,
+    #[cfg(feature = "redis")]
     #[error("Connect error: {0}")]
     IoError(#[source] RedisError),
+    #[error("Invalid policy: {0}")]
+    PolicyError(String),
+    #[error("Invalid config: {0}")]
+    InvalidConfig(#[source] ConfigError),
+}
+
+/// Enum that represents the configurations rejected when building a rate limiter, that would
+/// otherwise throttle every request, or never let the counters expire
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ConfigError {
+    #[error("the window size must be greater than 0")]
+    ZeroWindowSize,
+    #[error("the window duration must be greater than 0")]
+    ZeroWindowDuration,
+    #[error("the window duration must be at least 1ms, the resolution of Redis expiries: {0:?}")]
+    SubResolutionWindowDuration(Duration),
+    #[error("the Redis host must not be empty")]
+    EmptyRedisHost,
+    #[error("a read replica can't be combined with sharded or quorum Redis servers")]
+    ReadReplicaWithShards,
+    #[error("invalid value of the environment variable {0}")]
+    InvalidEnvVar(String),
+    #[error("unable to read the policy file {0}")]
+    UnreadablePolicyFile(String),
+}
+
+impl From<ConfigError> for RateLimiterError {
+    fn from(config_error: ConfigError) -> Self {
+        RateLimiterError::InvalidConfig(config_error)
+    }
 }
 
 // Converts from RedisError to our custom errors
+#[cfg(feature = "redis")]
 impl From<RedisError> for RateLimiterError {
     fn from(redis_error: RedisError) -> Self {
         match redis_error.kind() {
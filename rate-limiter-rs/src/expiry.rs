@@ -0,0 +1,139 @@
+//! Module that includes the listener of the expiry of rate limiter keys, surfacing them as window
+//! resets, so that connected clients can be told as soon as their quota is restored.
+//!
+//! ```no_run
+//! use rate_limiter_rs::{expiry::ExpiryListener, factory::RateLimiterFactory};
+//!
+//! let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+//! let listener = ExpiryListener::new(rate_limiter.redis_client.clone());
+//! listener.enable_notifications().unwrap();
+//!
+//! listener.spawn(|reset| println!("quota restored for {}", reset.key));
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The listener subscribes to the `__keyevent@<db>__:expired` channel of the
+//! [keyspace notifications](https://redis.io/docs/latest/develop/use/keyspace-notifications/),
+//! which Redis only publishes when the `notify-keyspace-events` setting includes the `E` and `x`
+//! flags. They can be enabled with [ExpiryListener::enable_notifications], or in the Redis
+//! configuration.
+//!
+//! Only the expiry of the keys holding the counters of request identifiers is surfaced: the end
+//! of a fixed window, or the expiry of the last request of a sliding window. Counters stored in
+//! hashes or per region are not surfaced. Redis publishes expiries when expired keys are evicted,
+//! which might happen a while after their time to live elapsed, and doesn't retry publishing to
+//! disconnected listeners, so resets are best-effort signals. With sharded keys, one listener per
+//! shard is needed.
+use std::thread::{self, JoinHandle};
+
+use redis::Client as RedisClient;
+
+use crate::{errors::RateLimiterError, listing::is_request_key};
+
+/// Represents the reset of the window of a request identifier, whose quota is fully restored
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowReset {
+    /// The request key of the identifier, like `rl:ip_172.28.0.6`
+    pub key: String,
+}
+
+/// Listener of the expiry notifications of the keys of a Redis server
+#[derive(Clone, Debug)]
+pub struct ExpiryListener {
+    redis_client: RedisClient,
+}
+
+impl ExpiryListener {
+    /// Creates a listener of the Redis server, and logical database, of the given client.
+    pub fn new(redis_client: RedisClient) -> Self {
+        ExpiryListener { redis_client }
+    }
+
+    /// Enables the expiry notifications on the Redis server, keeping any other notification
+    /// already enabled. Requires the permission to run `CONFIG SET`.
+    pub fn enable_notifications(&self) -> Result<(), RateLimiterError> {
+        let mut con = self.redis_client.get_connection()?;
+
+        let (_, flags): (String, String) = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query(&mut con)?;
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg(with_expiry_events(&flags))
+            .query::<()>(&mut con)?;
+        Ok(())
+    }
+
+    /// Calls the given function with every window reset, blocking the current thread until the
+    /// connection to Redis fails.
+    pub fn listen(&self, mut on_reset: impl FnMut(WindowReset)) -> Result<(), RateLimiterError> {
+        let mut con = self.redis_client.get_connection()?;
+        let mut pubsub = con.as_pubsub();
+        pubsub.subscribe(expired_channel(
+            self.redis_client.get_connection_info().redis.db,
+        ))?;
+
+        loop {
+            let key: String = pubsub.get_message()?.get_payload()?;
+            if is_request_key(&key) {
+                on_reset(WindowReset { key });
+            }
+        }
+    }
+
+    /// Calls the given function with every window reset from a background thread, until the
+    /// connection to Redis fails.
+    pub fn spawn(
+        self,
+        on_reset: impl FnMut(WindowReset) + Send + 'static,
+    ) -> JoinHandle<Result<(), RateLimiterError>> {
+        thread::Builder::new()
+            .name("rate-limiter-expiry-listener".to_string())
+            .spawn(move || self.listen(on_reset))
+            .expect("failed to spawn the expiry listener thread")
+    }
+}
+
+/// Utility method that returns the channel the expiries of the given logical database are
+/// published to.
+fn expired_channel(db: i64) -> String {
+    format!("__keyevent@{}__:expired", db)
+}
+
+/// Utility method that adds the flags enabling the expiry notifications to the given
+/// `notify-keyspace-events` flags, if missing. The `A` flag already includes `x`.
+fn with_expiry_events(flags: &str) -> String {
+    let mut flags = flags.to_string();
+    if !flags.contains('E') {
+        flags.push('E');
+    }
+    if !flags.contains('x') && !flags.contains('A') {
+        flags.push('x');
+    }
+    flags
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{expired_channel, with_expiry_events};
+
+    #[test]
+    fn should_build_expired_channel() {
+        assert_eq!(expired_channel(2), "__keyevent@2__:expired")
+    }
+
+    #[rstest]
+    #[case::disabled("", "Ex")]
+    #[case::keyspace_only("Kx", "KxE")]
+    #[case::all_events("KEA", "KEA")]
+    #[case::already_enabled("Ex", "Ex")]
+    fn should_add_expiry_events_to_flags(#[case] flags: &str, #[case] expected: &str) {
+        assert_eq!(with_expiry_events(flags), expected)
+    }
+}
@@ -1,6 +1,15 @@
 //! Factory pattern for rate limiters. Used by the consumers of this crate.
-use crate::builders::{
-    fixed_window::FixedWindowRateLimiterBuilder, sliding_window::SlidingWindowRateLimiterBuilder,
+use std::time::Duration;
+
+use crate::{
+    builders::{
+        fixed_window::FixedWindowRateLimiterBuilder,
+        sliding_window::SlidingWindowRateLimiterBuilder, typestate::TypedRateLimiterBuilder,
+    },
+    config::RateLimiterConfig,
+    errors::RateLimiterError,
+    policy::Policy,
+    RateLimiter,
 };
 
 /// A factory used as entrypoint for building rate limiter variants
@@ -18,4 +27,185 @@ impl RateLimiterFactory {
     pub fn sliding_window() -> SlidingWindowRateLimiterBuilder {
         SlidingWindowRateLimiterBuilder::default()
     }
+
+    /// Provides a [typestate](../builders/typestate/index.html) builder for a fixed window rate
+    /// limiter, that only builds once the window size and duration are set.
+    pub fn typed_fixed_window() -> TypedRateLimiterBuilder<FixedWindowRateLimiterBuilder> {
+        TypedRateLimiterBuilder::default()
+    }
+
+    /// Provides a [typestate](../builders/typestate/index.html) builder for a sliding window rate
+    /// limiter, that only builds once the window size and duration are set.
+    pub fn typed_sliding_window() -> TypedRateLimiterBuilder<SlidingWindowRateLimiterBuilder> {
+        TypedRateLimiterBuilder::default()
+    }
+
+    /// Provides a builder for a rate limiter allowing `limit` requests per second.
+    /// A fixed window is used: with such a short window, bursts across two adjacent
+    /// windows are negligible and a single counter is the cheapest option on Redis.
+    pub fn per_second(limit: u64) -> FixedWindowRateLimiterBuilder {
+        Self::fixed_window()
+            .with_window_size(limit)
+            .with_window_duration(Duration::from_secs(1))
+    }
+
+    /// Provides a builder for a rate limiter allowing `limit` requests per minute.
+    /// A sliding window is used, to avoid letting twice the limit through at window boundaries.
+    pub fn per_minute(limit: u64) -> SlidingWindowRateLimiterBuilder {
+        Self::sliding_window()
+            .with_window_size(limit)
+            .with_window_duration(Duration::from_secs(60))
+    }
+
+    /// Provides a builder for a rate limiter allowing `limit` requests per hour.
+    /// A sliding window is used, to avoid letting twice the limit through at window boundaries.
+    pub fn per_hour(limit: u64) -> SlidingWindowRateLimiterBuilder {
+        Self::sliding_window()
+            .with_window_size(limit)
+            .with_window_duration(Duration::from_secs(60 * 60))
+    }
+
+    /// Provides a builder for a rate limiter configured from a policy string, like `100/min`
+    /// or `10 per second burst 20`. See the [policy](../policy/index.html) module for the
+    /// supported syntax. A sliding window is used, to enforce the policy regardless of window
+    /// boundaries. Returns an error if the policy can't be parsed.
+    pub fn from_policy(policy: &str) -> Result<SlidingWindowRateLimiterBuilder, RateLimiterError> {
+        let policy: Policy = policy.parse()?;
+
+        Ok(Self::sliding_window()
+            .with_window_size(policy.window_size())
+            .with_window_duration(policy.window_duration()))
+    }
+
+    /// Builds the rate limiter described by the given configuration, as a trait object.
+    /// Useful for applications that let the algorithm be chosen via configuration.
+    pub fn from_config(
+        config: &RateLimiterConfig,
+    ) -> Result<Box<dyn RateLimiter + Send + Sync>, RateLimiterError> {
+        match config {
+            RateLimiterConfig::FixedWindow(window_config) => Self::fixed_window()
+                .with_window_config(window_config)
+                .build_boxed(),
+            RateLimiterConfig::SlidingWindow(window_config) => Self::sliding_window()
+                .with_window_config(window_config)
+                .build_boxed(),
+        }
+    }
+
+    /// Builds the rate limiter configured by the environment variables with the given prefix,
+    /// like `RATE_LIMITER`, as a trait object. See the [config](crate::config) module for the
+    /// variables read.
+    pub fn from_env(prefix: &str) -> Result<Box<dyn RateLimiter + Send + Sync>, RateLimiterError> {
+        Self::from_config(&RateLimiterConfig::from_env(prefix)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::RateLimiterFactory;
+    use crate::{
+        builders::RedisSettings,
+        config::{RateLimiterConfig, WindowConfig},
+        errors::RateLimiterError,
+        RateLimiter, RequestIdentifier,
+    };
+
+    #[test]
+    fn should_build_per_second_rate_limiter() {
+        let rate_limiter = RateLimiterFactory::per_second(10).build().unwrap();
+
+        assert_eq!(rate_limiter.window_size(), 10);
+        assert_eq!(rate_limiter.window_validity(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn should_build_per_minute_rate_limiter() {
+        let rate_limiter = RateLimiterFactory::per_minute(100).build().unwrap();
+
+        assert_eq!(rate_limiter.window_size(), 100);
+        assert_eq!(rate_limiter.window_duration(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn should_build_per_hour_rate_limiter() {
+        let rate_limiter = RateLimiterFactory::per_hour(1000).build().unwrap();
+
+        assert_eq!(rate_limiter.window_size(), 1000);
+        assert_eq!(rate_limiter.window_duration(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn should_build_rate_limiter_from_policy() {
+        let rate_limiter = RateLimiterFactory::from_policy("10 per second burst 20")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(rate_limiter.window_size(), 20);
+        assert_eq!(rate_limiter.window_duration(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn should_build_rate_limiter_from_config() {
+        let config = RateLimiterConfig::SlidingWindow(WindowConfig {
+            window_size: Some(10),
+            window_duration_seconds: Some(60),
+            window_duration: None,
+            redis: Some(RedisSettings {
+                host: "127.0.0.1".to_string(),
+                port: 1234,
+                ..RedisSettings::default()
+            }),
+            redis_url: None,
+        });
+
+        let rate_limiter = RateLimiterFactory::from_config(&config).unwrap();
+
+        assert_eq!(
+            rate_limiter.build_request_key(RequestIdentifier::Internal("billing".to_string())),
+            "rl:int_billing"
+        );
+        assert!(matches!(
+            rate_limiter.check_request(RequestIdentifier::Internal("billing".to_string())),
+            Err(RateLimiterError::IoError(_))
+        ))
+    }
+
+    #[test]
+    fn should_build_boxed_rate_limiters() {
+        let rate_limiters: Vec<Box<dyn RateLimiter + Send + Sync>> = vec![
+            RateLimiterFactory::per_second(10).build_boxed().unwrap(),
+            RateLimiterFactory::per_minute(100).build_boxed().unwrap(),
+        ];
+
+        for rate_limiter in rate_limiters {
+            assert_eq!(
+                rate_limiter.build_request_key(RequestIdentifier::Internal("billing".to_string())),
+                "rl:int_billing"
+            );
+        }
+    }
+
+    #[test]
+    fn should_not_build_rate_limiter_from_config_with_invalid_redis_url() {
+        let config = RateLimiterConfig::FixedWindow(WindowConfig {
+            redis_url: Some("memcached://127.0.0.1:11211".to_string()),
+            ..WindowConfig::default()
+        });
+
+        assert!(matches!(
+            RateLimiterFactory::from_config(&config),
+            Err(RateLimiterError::InitError(_))
+        ))
+    }
+
+    #[test]
+    fn should_not_build_rate_limiter_from_invalid_policy() {
+        assert!(matches!(
+            RateLimiterFactory::from_policy("lots/min"),
+            Err(RateLimiterError::PolicyError(_))
+        ))
+    }
 }
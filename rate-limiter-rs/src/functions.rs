@@ -0,0 +1,97 @@
+//! Module that includes the [Redis Functions](https://redis.io/docs/latest/develop/interact/programmability/functions-intro/)
+//! library used by checks on Redis 7 and above.
+//!
+//! ## Implementation details
+//!
+//! The library registers one function per rate limiting algorithm, wrapping the same Lua code used
+//! by scripted checks. Functions are called with `FCALL`, and the library is loaded with
+//! `FUNCTION LOAD` whenever Redis answers that a function is not found: on the first check
+//! against a server, and again after a restart or a `FUNCTION FLUSH`.
+use std::sync::LazyLock;
+
+use redis::{Connection, ErrorKind, FromRedisValue, RedisError, ToRedisArgs};
+
+use crate::{
+    errors::RateLimiterError,
+    rate_limiters::{fixed_window, sliding_window},
+};
+
+/// The name of the library holding the functions
+pub(crate) const LIBRARY_NAME: &str = "rate_limiter_rs";
+
+/// The function checking requests against a fixed window
+pub(crate) const FIXED_WINDOW_CHECK: &str = "rate_limiter_rs_fixed_window_check";
+
+/// The function checking requests against a sliding window
+pub(crate) const SLIDING_WINDOW_CHECK: &str = "rate_limiter_rs_sliding_window_check";
+
+/// The source of the library, registering the Lua code of each algorithm as a function
+pub(crate) static LIBRARY: LazyLock<String> = LazyLock::new(|| {
+    format!(
+        "#!lua name={LIBRARY_NAME}\n{}\n{}",
+        register_function(FIXED_WINDOW_CHECK, fixed_window::CHECK_SCRIPT_SOURCE),
+        register_function(SLIDING_WINDOW_CHECK, sliding_window::CHECK_SCRIPT_SOURCE),
+    )
+});
+
+/// Calls the given function with the given key and arguments, loading the library if needed.
+pub(crate) fn fcall<T: FromRedisValue>(
+    con: &mut Connection,
+    function: &str,
+    key: &str,
+    args: impl ToRedisArgs,
+) -> Result<T, RateLimiterError> {
+    let call = |con: &mut Connection| {
+        redis::cmd("FCALL")
+            .arg(function)
+            .arg(1)
+            .arg(key)
+            .arg(&args)
+            .query(con)
+    };
+
+    match call(con) {
+        Err(e) if is_function_not_found(&e) => {
+            redis::cmd("FUNCTION")
+                .arg("LOAD")
+                .arg("REPLACE")
+                .arg(LIBRARY.as_str())
+                .query::<()>(con)?;
+            Ok(call(con)?)
+        }
+        res => Ok(res?),
+    }
+}
+
+/// Utility method that wraps the given Lua code in a function registration. The code reads its
+/// keys and arguments from `KEYS` and `ARGV`, like a script, which the function parameters shadow.
+fn register_function(name: &str, source: &str) -> String {
+    format!("redis.register_function('{name}', function(KEYS, ARGV)\n{source}\nend)")
+}
+
+/// Utility method that returns whether the given error is about a function that is not loaded.
+fn is_function_not_found(e: &RedisError) -> bool {
+    e.kind() == ErrorKind::ResponseError && e.detail() == Some("Function not found")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{register_function, FIXED_WINDOW_CHECK, LIBRARY, SLIDING_WINDOW_CHECK};
+
+    #[test]
+    fn should_register_function_with_script_parameters() {
+        let function = register_function("check", "return KEYS[1]");
+
+        assert_eq!(
+            function,
+            "redis.register_function('check', function(KEYS, ARGV)\nreturn KEYS[1]\nend)"
+        );
+    }
+
+    #[test]
+    fn should_register_a_function_per_algorithm() {
+        assert!(LIBRARY.starts_with("#!lua name=rate_limiter_rs\n"));
+        assert!(LIBRARY.contains(&format!("'{FIXED_WINDOW_CHECK}'")));
+        assert!(LIBRARY.contains(&format!("'{SLIDING_WINDOW_CHECK}'")));
+    }
+}
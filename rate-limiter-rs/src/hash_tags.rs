@@ -0,0 +1,128 @@
+//! Module that includes the [hash tags](https://redis.io/docs/latest/operate/oss_and_stack/reference/cluster-spec/#hash-tags)
+//! optionally wrapped around a portion of the request keys, to pin related keys to the same
+//! Redis Cluster slot.
+//!
+//! ## Implementation details
+//!
+//! Redis Cluster only hashes the portion of a key between the first `{` and the following `}`,
+//! if any, to pick its slot. The keys holding the state of a request identifier, like its first
+//! seen timestamp, abuse score and limit override, are all derived from its request key, keeping
+//! the tag, so they land on the same slot as its counters and can be operated on atomically:
+//!
+//! ```text
+//! rl:{ip_172.28.0.6}
+//! rl:{ip_172.28.0.6}:first_seen
+//! rl:{ip_172.28.0.6}:reputation
+//! rl:override:{ip_172.28.0.6}
+//! ```
+use crate::RequestIdentifier;
+
+/// Represents the portion of the request keys wrapped in a hash tag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum HashTag {
+    /// The whole request identifier, like `rl:{ip_172.28.0.6}`, so that all the keys of the same
+    /// identifier share a slot
+    Identifier,
+    /// The name of custom request identifiers, like `rl:cst_{tenant}:acme`, so that all the keys
+    /// of the same custom key share a slot, whatever the value. Other request identifiers are
+    /// tagged whole.
+    CustomKeyName,
+}
+
+/// Utility method that builds the request key of the given request identifier, wrapping the
+/// portion selected by the given hash tag, if any.
+pub(crate) fn request_key(
+    request_identifier: RequestIdentifier,
+    hash_tag: Option<HashTag>,
+) -> String {
+    match (request_identifier, hash_tag) {
+        (RequestIdentifier::Ip(ip), None) => format!("rl:ip_{}", ip),
+        (RequestIdentifier::Ip(ip), Some(_)) => format!("rl:{{ip_{}}}", ip),
+        (RequestIdentifier::Custom { key, value }, None) => format!("rl:cst_{0}:{1}", key, value),
+        (RequestIdentifier::Custom { key, value }, Some(HashTag::Identifier)) => {
+            format!("rl:{{cst_{0}:{1}}}", key, value)
+        }
+        (RequestIdentifier::Custom { key, value }, Some(HashTag::CustomKeyName)) => {
+            format!("rl:cst_{{{0}}}:{1}", key, value)
+        }
+        (RequestIdentifier::Internal(service_name), None) => format!("rl:int_{}", service_name),
+        (RequestIdentifier::Internal(service_name), Some(_)) => {
+            format!("rl:{{int_{}}}", service_name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use rstest::rstest;
+
+    use crate::RequestIdentifier;
+    #[cfg(feature = "redis")]
+    use crate::{
+        data_subject::identifier_keys, onboarding::first_seen_key, overrides::override_key,
+        reputation::reputation_key,
+    };
+
+    use super::{request_key, HashTag};
+
+    fn custom_identifier() -> RequestIdentifier {
+        RequestIdentifier::Custom {
+            key: "tenant".to_string(),
+            value: "acme".to_string(),
+        }
+    }
+
+    #[rstest]
+    #[case::untagged_ip(
+        RequestIdentifier::Ip(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
+        None,
+        "rl:ip_1.2.3.4"
+    )]
+    #[case::tagged_ip(
+        RequestIdentifier::Ip(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
+        Some(HashTag::Identifier),
+        "rl:{ip_1.2.3.4}"
+    )]
+    #[case::ip_tagged_by_custom_key_name(
+        RequestIdentifier::Ip(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
+        Some(HashTag::CustomKeyName),
+        "rl:{ip_1.2.3.4}"
+    )]
+    #[case::untagged_custom(custom_identifier(), None, "rl:cst_tenant:acme")]
+    #[case::tagged_custom(custom_identifier(), Some(HashTag::Identifier), "rl:{cst_tenant:acme}")]
+    #[case::custom_tagged_by_key_name(
+        custom_identifier(),
+        Some(HashTag::CustomKeyName),
+        "rl:cst_{tenant}:acme"
+    )]
+    #[case::untagged_internal(RequestIdentifier::Internal("billing".to_string()), None, "rl:int_billing")]
+    #[case::tagged_internal(
+        RequestIdentifier::Internal("billing".to_string()),
+        Some(HashTag::Identifier),
+        "rl:{int_billing}"
+    )]
+    fn should_build_request_key(
+        #[case] request_identifier: RequestIdentifier,
+        #[case] hash_tag: Option<HashTag>,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(request_key(request_identifier, hash_tag), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "redis")]
+    fn should_keep_hash_tag_in_derived_keys() {
+        let key = request_key(custom_identifier(), Some(HashTag::Identifier));
+
+        assert_eq!(first_seen_key(&key), "rl:{cst_tenant:acme}:first_seen");
+        assert_eq!(reputation_key(&key), "rl:{cst_tenant:acme}:reputation");
+        assert_eq!(override_key(&key), "rl:override:{cst_tenant:acme}");
+        assert!(identifier_keys(&key)
+            .iter()
+            .all(|k| k.contains("{cst_tenant:acme}")));
+    }
+}
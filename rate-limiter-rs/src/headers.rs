@@ -0,0 +1,214 @@
+//! Module that includes the builder of the rate limit HTTP header fields, as defined by the
+//! [RateLimit header fields for HTTP](https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/)
+//! draft, along with the `Retry-After` header of throttled requests, so that every HTTP
+//! integration emits the same headers for the same response.
+//!
+//! ```
+//! use std::time::{Duration, SystemTime};
+//!
+//! use rate_limiter_rs::{headers::RateLimitHeaders, RateLimitStatus};
+//!
+//! let headers = RateLimitHeaders::from_status(&RateLimitStatus {
+//!     limit: 100,
+//!     window_duration: Duration::from_secs(60),
+//!     used: 40,
+//!     reset_at: SystemTime::now() + Duration::from_secs(30),
+//! });
+//!
+//! for (name, value) in headers.to_pairs() {
+//!     println!("{}: {}", name, value);
+//! }
+//! ```
+//!
+//! ## Implementation details
+//!
+//! All values are expressed in whole seconds, rounded up, so that clients never retry before the
+//! window has been restored. `RateLimit-Reset` is the number of seconds until the budget of the
+//! current window is restored, not a timestamp, as required by the draft.
+use std::time::{Duration, SystemTime};
+
+use crate::{RateLimitStatus, RateLimiterResponse, RequestAllowed, RequestThrottled};
+
+/// The header carrying the maximum number of requests allowed in a single window
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "RateLimit-Limit";
+
+/// The header carrying the number of requests left in the current window
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "RateLimit-Remaining";
+
+/// The header carrying the seconds until the budget of the current window is restored
+pub const RATE_LIMIT_RESET_HEADER: &str = "RateLimit-Reset";
+
+/// The header carrying the seconds after which throttled requests should be retried
+pub const RETRY_AFTER_HEADER: &str = "Retry-After";
+
+/// Struct that holds the values of the rate limit headers of a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitHeaders {
+    /// the value of the `RateLimit-Limit` header
+    pub limit: u64,
+    /// the value of the `RateLimit-Remaining` header
+    pub remaining: u64,
+    /// the value of the `RateLimit-Reset` header
+    pub reset: u64,
+    /// the value of the `Retry-After` header, only set for throttled requests
+    pub retry_after: Option<u64>,
+}
+
+impl RateLimitHeaders {
+    /// Builds the headers describing the given status, as of now.
+    pub fn from_status(status: &RateLimitStatus) -> Self {
+        Self::from_status_at(status, SystemTime::now())
+    }
+
+    /// Returns the name and value of every header, in the order defined by the draft, followed
+    /// by the `Retry-After` header, if any.
+    pub fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![
+            (RATE_LIMIT_LIMIT_HEADER, self.limit.to_string()),
+            (RATE_LIMIT_REMAINING_HEADER, self.remaining.to_string()),
+            (RATE_LIMIT_RESET_HEADER, self.reset.to_string()),
+        ];
+        if let Some(retry_after) = self.retry_after {
+            pairs.push((RETRY_AFTER_HEADER, retry_after.to_string()));
+        }
+        pairs
+    }
+
+    /// Utility method that builds the headers describing the given status, as of the given time.
+    fn from_status_at(status: &RateLimitStatus, now: SystemTime) -> Self {
+        RateLimitHeaders {
+            limit: status.limit,
+            remaining: status.limit.saturating_sub(status.used),
+            reset: ceil_secs(status.reset_at.duration_since(now).unwrap_or_default()),
+            retry_after: None,
+        }
+    }
+}
+
+impl From<&RequestAllowed> for RateLimitHeaders {
+    fn from(allowed: &RequestAllowed) -> Self {
+        RateLimitHeaders {
+            remaining: allowed.remaining_request_counter,
+            ..RateLimitHeaders::from_status(&allowed.status)
+        }
+    }
+}
+
+impl From<&RequestThrottled> for RateLimitHeaders {
+    fn from(throttled: &RequestThrottled) -> Self {
+        RateLimitHeaders {
+            remaining: 0,
+            retry_after: Some(ceil_secs(throttled.retry_in)),
+            ..RateLimitHeaders::from_status(&throttled.status)
+        }
+    }
+}
+
+impl From<&RateLimiterResponse> for RateLimitHeaders {
+    fn from(response: &RateLimiterResponse) -> Self {
+        match response {
+            RateLimiterResponse::RequestAllowed(allowed) => allowed.into(),
+            RateLimiterResponse::RequestThrottled(throttled) => throttled.into(),
+        }
+    }
+}
+
+/// Utility method that returns the given duration in whole seconds, rounded up.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_millis().div_ceil(1000) as u64
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rstest::rstest;
+
+    use super::{ceil_secs, RateLimitHeaders};
+    use crate::{RateLimitStatus, RequestAllowed, RequestThrottled, ThrottleReason};
+
+    fn status(used: u64, reset_in: Duration) -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 10,
+            window_duration: Duration::from_secs(60),
+            used,
+            reset_at: SystemTime::now() + reset_in,
+        }
+    }
+
+    #[rstest]
+    #[case::whole_seconds(Duration::from_secs(2), 2)]
+    #[case::rounded_up(Duration::from_millis(1001), 2)]
+    #[case::below_one_second(Duration::from_millis(1), 1)]
+    #[case::zero(Duration::ZERO, 0)]
+    fn should_round_seconds_up(#[case] duration: Duration, #[case] expected: u64) {
+        assert_eq!(ceil_secs(duration), expected)
+    }
+
+    #[test]
+    fn should_describe_status() {
+        let now = SystemTime::now();
+        let status = RateLimitStatus {
+            reset_at: now + Duration::from_millis(29_500),
+            ..status(4, Duration::ZERO)
+        };
+
+        let headers = RateLimitHeaders::from_status_at(&status, now);
+
+        assert_eq!(
+            headers,
+            RateLimitHeaders {
+                limit: 10,
+                remaining: 6,
+                reset: 30,
+                retry_after: None,
+            }
+        )
+    }
+
+    #[test]
+    fn should_not_reset_in_the_past() {
+        let now = SystemTime::now();
+        let status = RateLimitStatus {
+            reset_at: now - Duration::from_secs(1),
+            ..status(12, Duration::ZERO)
+        };
+
+        let headers = RateLimitHeaders::from_status_at(&status, now);
+
+        assert_eq!(headers.remaining, 0);
+        assert_eq!(headers.reset, 0);
+    }
+
+    #[test]
+    fn should_describe_allowed_request() {
+        let headers = RateLimitHeaders::from(&RequestAllowed {
+            remaining_request_counter: 7,
+            status: status(3, Duration::from_secs(60)),
+        });
+
+        let pairs = headers.to_pairs();
+
+        assert_eq!(
+            pairs.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            vec!["RateLimit-Limit", "RateLimit-Remaining", "RateLimit-Reset"]
+        );
+        assert_eq!(pairs[1].1, "7")
+    }
+
+    #[test]
+    fn should_describe_throttled_request() {
+        let headers = RateLimitHeaders::from(&RequestThrottled {
+            retry_in: Duration::from_millis(1500),
+            reason: ThrottleReason::QuotaExceeded,
+            status: status(11, Duration::from_secs(2)),
+        });
+
+        assert_eq!(headers.remaining, 0);
+        assert_eq!(headers.retry_after, Some(2));
+        assert_eq!(
+            headers.to_pairs().last().unwrap(),
+            &("Retry-After", "2".to_string())
+        )
+    }
+}
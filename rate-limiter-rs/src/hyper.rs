@@ -0,0 +1,173 @@
+//! Module that includes the [hyper](https://docs.rs/hyper) service wrapper of the rate limiter,
+//! for raw hyper servers not built on tower. Requires the `hyper` feature.
+//!
+//! ```no_run
+//! use std::{convert::Infallible, net::IpAddr, sync::Arc};
+//!
+//! use http::{Request, Response};
+//! use hyper::service::service_fn;
+//! use rate_limiter_rs::{factory::RateLimiterFactory, hyper::RateLimitService, RequestIdentifier};
+//!
+//! let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+//! let client_ip: IpAddr = "172.28.0.6".parse().unwrap();
+//! let service = RateLimitService::new(
+//!     service_fn(|_request: Request<String>| async {
+//!         Ok::<_, Infallible>(Response::new(String::from("ok")))
+//!     }),
+//!     Arc::new(rate_limiter),
+//!     move |_request: &Request<String>| Some(RequestIdentifier::Ip(client_ip)),
+//! );
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Hyper creates a service per connection, so the key extractor usually captures the address of
+//! the client of the connection. Requests without an identifier are not rate limited, and
+//! throttled requests are answered with the [throttled response](crate::responses::throttled_response),
+//! without reaching the inner service. The responses to allowed requests carry the
+//! [rate limit headers](crate::headers).
+//!
+//! Checks are performed on the task calling the service, blocking it for the duration of the
+//! Redis round trip. Requests are let through when the check fails: [circuit
+//! breakers](../breaker/index.html) should be configured on the rate limiter to serve checks
+//! differently.
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+    sync::Arc,
+};
+
+use http::{Request, Response};
+use hyper::service::Service;
+
+use crate::{
+    responses::{check_http_request, insert_headers, CheckOutcome},
+    RateLimiter, RequestIdentifier,
+};
+
+/// Service answering the throttled requests on behalf of the service it wraps
+pub struct RateLimitService<S, R: ?Sized, F> {
+    inner: S,
+    rate_limiter: Arc<R>,
+    key_extractor: F,
+}
+
+impl<S, R: ?Sized, F> RateLimitService<S, R, F> {
+    /// Wraps the given service, checking the requests against the given rate limiter, with the
+    /// request identifier extracted by the given closure. Requests without an identifier are not
+    /// rate limited.
+    pub fn new(inner: S, rate_limiter: Arc<R>, key_extractor: F) -> Self {
+        RateLimitService {
+            inner,
+            rate_limiter,
+            key_extractor,
+        }
+    }
+}
+
+impl<S: Clone, R: ?Sized, F: Clone> Clone for RateLimitService<S, R, F> {
+    fn clone(&self) -> Self {
+        RateLimitService {
+            inner: self.inner.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            key_extractor: self.key_extractor.clone(),
+        }
+    }
+}
+
+/// The future returned by the [RateLimitService]
+pub type ResponseFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl<S, R, F, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimitService<S, R, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    R: RateLimiter + ?Sized,
+    F: Fn(&Request<ReqBody>) -> Option<RequestIdentifier>,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<Self::Response, Self::Error>;
+
+    fn call(&self, request: Request<ReqBody>) -> Self::Future {
+        let request_identifier = (self.key_extractor)(&request);
+        match check_http_request(&*self.rate_limiter, request_identifier) {
+            CheckOutcome::Throttled(response) => Box::pin(ready(Ok(response))),
+            CheckOutcome::Allowed(rate_limit_headers) => {
+                let response = self.inner.call(request);
+                Box::pin(async move {
+                    let mut response = response.await?;
+                    insert_headers(response.headers_mut(), &rate_limit_headers);
+                    Ok(response)
+                })
+            }
+            CheckOutcome::Unchecked => Box::pin(self.inner.call(request)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use std::{
+        convert::Infallible,
+        future::{ready, Future, Ready},
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use http::{Request, Response, StatusCode};
+    use hyper::service::Service;
+
+    use super::RateLimitService;
+    use crate::{factory::RateLimiterFactory, redis_mock::RedisMock, RequestIdentifier};
+
+    /// Service answering every request with a `200 OK` status
+    struct Ok200;
+
+    impl Service<Request<()>> for Ok200 {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn call(&self, _request: Request<()>) -> Self::Future {
+            ready(Ok(Response::new("ok".to_string())))
+        }
+    }
+
+    fn poll_now<F: Future>(future: F) -> F::Output {
+        match std::pin::pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future is not ready"),
+        }
+    }
+
+    #[test]
+    fn should_throttle_requests_through_service_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let service =
+            RateLimitService::new(Ok200, Arc::new(rate_limiter), move |_: &Request<()>| {
+                Some(RequestIdentifier::Ip(ip))
+            });
+
+        //act
+        let first = poll_now(service.call(Request::new(()))).unwrap();
+        let second = poll_now(service.call(Request::new(()))).unwrap();
+
+        //assert
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(second.headers().contains_key("retry-after"));
+    }
+}
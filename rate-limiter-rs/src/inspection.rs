@@ -0,0 +1,127 @@
+//! Module that includes the inspection of the state of a request identifier, so that support
+//! engineers can quickly answer why a given client is throttled.
+//!
+//! Inspecting a request identifier only reads its state: it doesn't count as a request, and
+//! doesn't store the first seen timestamp of identifiers never checked before.
+use std::time::Duration;
+#[cfg(feature = "redis")]
+use std::time::SystemTime;
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{
+    errors::RateLimiterError,
+    onboarding::OnboardingRamp,
+    overrides::{read_override, LimitOverride},
+};
+
+/// Represents the state of a request identifier, at the time it was inspected
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyInspection {
+    /// The request key of the identifier, like `rl:ip_172.28.0.6`
+    pub key: String,
+
+    /// The number of requests counted in the current window
+    pub count: u64,
+
+    /// The maximum number of requests allowed in a window, including any limit override and
+    /// onboarding ramp
+    pub limit: u64,
+
+    /// The duration of the window, including any limit override
+    pub window_duration: Duration,
+
+    /// Whether the limits of the identifier are overridden
+    pub overridden: bool,
+
+    /// The remaining time to live of the counter, if any
+    pub expire_in: Option<Duration>,
+}
+
+impl KeyInspection {
+    /// Returns whether the next request of the identifier would be throttled.
+    pub fn is_throttled(&self) -> bool {
+        self.count >= self.limit
+    }
+}
+
+/// Returns the limits applying to the given request key at the given time, out of the configured
+/// ones, without storing anything.
+#[cfg(feature = "redis")]
+pub(crate) fn inspect_limits(
+    con: &mut Connection,
+    key: &str,
+    window_size: u64,
+    window_duration: Duration,
+    limit_overrides: bool,
+    onboarding_ramp: Option<&OnboardingRamp>,
+    now: SystemTime,
+) -> Result<(u64, Duration, bool), RateLimiterError> {
+    let limit_override = if limit_overrides {
+        read_override(con, key)?
+    } else {
+        LimitOverride::default()
+    };
+    let overridden = limit_override != LimitOverride::default();
+    let window_size = limit_override.window_size.unwrap_or(window_size);
+    let window_duration = limit_override.window_duration.unwrap_or(window_duration);
+
+    let window_size = match onboarding_ramp {
+        Some(onboarding_ramp) => onboarding_ramp.effective_limit(
+            window_size,
+            onboarding_ramp.peek_elapsed_since_first_seen(con, key, now)?,
+        ),
+        None => window_size,
+    };
+
+    Ok((window_size, window_duration, overridden))
+}
+
+/// Utility method that returns the remaining time to live of a key out of its `PTTL`, which is
+/// negative for keys without expiry, or missing.
+#[cfg(feature = "redis")]
+pub(crate) fn as_expire_in(expire_in_millis: i64) -> Option<Duration> {
+    u64::try_from(expire_in_millis)
+        .ok()
+        .map(Duration::from_millis)
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{as_expire_in, KeyInspection};
+
+    #[rstest]
+    #[case::expiring(1500, Some(Duration::from_millis(1500)))]
+    #[case::persistent(-1, None)]
+    #[case::missing(-2, None)]
+    fn should_convert_pttl_to_expire_in(
+        #[case] expire_in_millis: i64,
+        #[case] expected: Option<Duration>,
+    ) {
+        assert_eq!(as_expire_in(expire_in_millis), expected)
+    }
+
+    #[rstest]
+    #[case::below_limit(2, false)]
+    #[case::at_limit(3, true)]
+    #[case::above_limit(4, true)]
+    fn should_tell_whether_next_request_is_throttled(#[case] count: u64, #[case] expected: bool) {
+        let inspection = KeyInspection {
+            key: "rl:ip_1.2.3.4".to_string(),
+            count,
+            limit: 3,
+            window_duration: Duration::from_secs(60),
+            overridden: false,
+            expire_in: None,
+        };
+
+        assert_eq!(inspection.is_throttled(), expected)
+    }
+}
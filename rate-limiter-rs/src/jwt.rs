@@ -0,0 +1,184 @@
+//! Module that includes the extraction of request identifiers from the claims of
+//! [JSON Web Tokens](https://datatracker.ietf.org/doc/html/rfc7519), so that authenticated APIs
+//! can rate limit requests by principal, instead of by client IP address. Requires the `jwt`
+//! feature.
+//!
+//! ```no_run
+//! use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+//! use rate_limiter_rs::jwt::JwtKeyExtractor;
+//!
+//! let key_extractor = JwtKeyExtractor::new(
+//!     "sub",
+//!     DecodingKey::from_secret(b"secret"),
+//!     Validation::new(Algorithm::HS256),
+//! );
+//!
+//! // the value of the Authorization header of the request
+//! let request_identifier = key_extractor
+//!     .extract_from_header("Bearer eyJhbGciOiJIUzI1NiJ9...")
+//!     .unwrap();
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Tokens are decoded with [jsonwebtoken](https://docs.rs/jsonwebtoken), and their signature is
+//! always verified against the given key: an unverified claim could be forged to exhaust the
+//! budget of another principal, or to get a fresh budget on every request. The claims checked by
+//! the given [Validation], like the expiration time, are validated as well.
+//!
+//! The request identifier is a [custom identifier](crate::RequestIdentifier::Custom) named after
+//! the claim, like `rl:cst_sub:dili91`. Only string and number claims are supported.
+use std::collections::HashMap;
+
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde_json::Value;
+
+use crate::RequestIdentifier;
+
+/// The authentication scheme of bearer tokens, compared case insensitively
+const BEARER_SCHEME: &str = "Bearer";
+
+/// Enum that represents the errors returned when a request identifier can't be extracted from a
+/// token
+#[derive(Debug, thiserror::Error)]
+pub enum JwtError {
+    #[error("Missing bearer token")]
+    MissingBearerToken,
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[source] jsonwebtoken::errors::Error),
+    #[error("Missing claim: {0}")]
+    MissingClaim(String),
+    #[error("Unsupported value of claim: {0}")]
+    UnsupportedClaim(String),
+}
+
+/// Extractor of request identifiers from a claim of verified tokens
+pub struct JwtKeyExtractor {
+    claim: String,
+    decoding_key: DecodingKey,
+    validation: Validation,
+}
+
+impl JwtKeyExtractor {
+    /// Creates an extractor of the given claim, like `sub` or `client_id`, from the tokens
+    /// verified with the given key and validation.
+    pub fn new(
+        claim: impl Into<String>,
+        decoding_key: DecodingKey,
+        validation: Validation,
+    ) -> Self {
+        JwtKeyExtractor {
+            claim: claim.into(),
+            decoding_key,
+            validation,
+        }
+    }
+
+    /// Returns the request identifier of the bearer token carried by the given value of an
+    /// `Authorization` header.
+    pub fn extract_from_header(&self, authorization: &str) -> Result<RequestIdentifier, JwtError> {
+        self.extract(bearer_token(authorization).ok_or(JwtError::MissingBearerToken)?)
+    }
+
+    /// Returns the request identifier of the given token.
+    pub fn extract(&self, token: &str) -> Result<RequestIdentifier, JwtError> {
+        let claims = decode::<HashMap<String, Value>>(token, &self.decoding_key, &self.validation)
+            .map_err(JwtError::InvalidToken)?
+            .claims;
+
+        let value = match claims.get(&self.claim) {
+            Some(Value::String(value)) if !value.is_empty() => value.clone(),
+            Some(Value::Number(value)) => value.to_string(),
+            Some(_) => return Err(JwtError::UnsupportedClaim(self.claim.clone())),
+            None => return Err(JwtError::MissingClaim(self.claim.clone())),
+        };
+
+        Ok(RequestIdentifier::Custom {
+            key: self.claim.clone(),
+            value,
+        })
+    }
+}
+
+/// Utility method that returns the token of the given `Authorization` header value, if it uses
+/// the bearer scheme.
+fn bearer_token(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.trim().split_once(' ')?;
+    let token = token.trim();
+
+    (scheme.eq_ignore_ascii_case(BEARER_SCHEME) && !token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod test {
+    use jsonwebtoken::{encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+    use rstest::rstest;
+    use serde_json::json;
+
+    use super::{bearer_token, JwtError, JwtKeyExtractor};
+    use crate::RequestIdentifier;
+
+    const SECRET: &[u8] = b"secret";
+
+    fn key_extractor(claim: &str) -> JwtKeyExtractor {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_required_spec_claims::<&str>(&[]);
+        JwtKeyExtractor::new(claim, DecodingKey::from_secret(SECRET), validation)
+    }
+
+    fn token(secret: &[u8]) -> String {
+        encode(
+            &Header::default(),
+            &json!({ "sub": "dili91", "client_id": 42, "scopes": ["read"] }),
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::bearer("Bearer abc", Some("abc"))]
+    #[case::lowercase_scheme("bearer abc", Some("abc"))]
+    #[case::padded(" Bearer  abc ", Some("abc"))]
+    #[case::basic("Basic abc", None)]
+    #[case::missing_token("Bearer ", None)]
+    #[case::missing_scheme("abc", None)]
+    fn should_extract_bearer_token(#[case] authorization: &str, #[case] expected: Option<&str>) {
+        assert_eq!(bearer_token(authorization), expected)
+    }
+
+    #[rstest]
+    #[case::string_claim("sub", "dili91")]
+    #[case::number_claim("client_id", "42")]
+    fn should_extract_request_identifier_from_claim(
+        #[case] claim: &str,
+        #[case] expected_value: &str,
+    ) {
+        let request_identifier = key_extractor(claim)
+            .extract_from_header(&format!("Bearer {}", token(SECRET)))
+            .unwrap();
+
+        assert!(matches!(
+            request_identifier,
+            RequestIdentifier::Custom { key, value } if key == claim && value == expected_value
+        ))
+    }
+
+    #[test]
+    fn should_reject_token_with_invalid_signature() {
+        let result = key_extractor("sub").extract(&token(b"another secret"));
+
+        assert!(matches!(result, Err(JwtError::InvalidToken(_))))
+    }
+
+    #[rstest]
+    #[case::missing_claim("tenant")]
+    #[case::unsupported_claim("scopes")]
+    fn should_reject_token_without_usable_claim(#[case] claim: &str) {
+        let result = key_extractor(claim).extract(&token(SECRET));
+
+        assert!(matches!(
+            result,
+            Err(JwtError::MissingClaim(_)) | Err(JwtError::UnsupportedClaim(_))
+        ))
+    }
+}
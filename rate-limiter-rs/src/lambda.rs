@@ -0,0 +1,342 @@
+//! Module that includes the helper of the rate limiter for serverless deployments, answering the
+//! requests of [API Gateway Lambda authorizers](https://docs.aws.amazon.com/apigateway/latest/developerguide/apigateway-use-lambda-authorizer.html),
+//! so that throttled requests are denied before reaching the backend. Requires the `lambda`
+//! feature.
+//!
+//! ```no_run
+//! use rate_limiter_rs::{
+//!     factory::RateLimiterFactory,
+//!     lambda::{authorize, AuthorizerKey, AuthorizerRequest},
+//! };
+//!
+//! let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+//!
+//! // the event received by the Lambda function, usually deserialized by the Lambda runtime
+//! let request: AuthorizerRequest = serde_json::from_str(
+//!     r#"{
+//!         "methodArn": "arn:aws:execute-api:eu-west-1:123456789012:abcdef/prod/GET/intensity",
+//!         "requestContext": { "identity": { "sourceIp": "172.28.0.6" } }
+//!     }"#,
+//! )
+//! .unwrap();
+//!
+//! let response = authorize(&rate_limiter, &request, AuthorizerKey::SourceIp).unwrap();
+//! println!("{}", serde_json::to_string(&response).unwrap());
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The helper supports the `REQUEST` authorizers of REST APIs, receiving the version `1.0` payload.
+//! Requests are rate limited by the source IP address of the client, or by its API key, as a
+//! [custom identifier](crate::RequestIdentifier::Custom) named `api_key`. Requests without the
+//! configured key are denied.
+//!
+//! The principal of the returned policy is the request key of the client, like
+//! `rl:ip_172.28.0.6`. Denied requests are answered by API Gateway with a `403 Forbidden` status,
+//! which can be mapped to a `429 Too Many Requests` status with a gateway response, using the
+//! `retryAfterSeconds` value of the authorizer context. Only Redis backed rate limiters are
+//! supported.
+use std::{collections::HashMap, net::IpAddr};
+
+use crate::{
+    api_key::API_KEY_IDENTIFIER, errors::RateLimiterError, RateLimiter, RateLimiterResponse,
+    RequestIdentifier,
+};
+
+/// The version of the IAM policy language used by the returned policies
+const POLICY_VERSION: &str = "2012-10-17";
+
+/// The action allowed or denied by the returned policies
+const INVOKE_ACTION: &str = "execute-api:Invoke";
+
+/// Represents the event received by a `REQUEST` Lambda authorizer, with the fields needed to
+/// rate limit the request
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerRequest {
+    /// The ARN of the method invoked by the request
+    pub method_arn: String,
+
+    /// The context of the request
+    #[serde(default)]
+    pub request_context: AuthorizerRequestContext,
+}
+
+/// Represents the context of the request received by a Lambda authorizer
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerRequestContext {
+    /// The identity of the client of the request
+    #[serde(default)]
+    pub identity: AuthorizerIdentity,
+}
+
+/// Represents the identity of the client of the request received by a Lambda authorizer
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerIdentity {
+    /// The source IP address of the client
+    pub source_ip: Option<String>,
+
+    /// The API key of the client, for methods requiring one
+    pub api_key: Option<String>,
+}
+
+/// Enum that represents the keys requests can be rate limited by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthorizerKey {
+    /// The source IP address of the client
+    SourceIp,
+    /// The API key of the client
+    ApiKey,
+}
+
+/// Represents the response of a Lambda authorizer
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerResponse {
+    /// The principal the policy applies to
+    pub principal_id: String,
+
+    /// The policy allowing or denying the request
+    pub policy_document: PolicyDocument,
+
+    /// The values passed to the backend, or to the gateway responses of denied requests
+    pub context: HashMap<String, String>,
+}
+
+impl AuthorizerResponse {
+    /// Returns whether the request is allowed.
+    pub fn is_allowed(&self) -> bool {
+        self.policy_document
+            .statement
+            .iter()
+            .all(|statement| statement.effect == PolicyEffect::Allow)
+    }
+}
+
+/// Represents the IAM policy returned by a Lambda authorizer
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyDocument {
+    /// The version of the policy language
+    pub version: String,
+
+    /// The statements of the policy
+    pub statement: Vec<PolicyStatement>,
+}
+
+/// Represents a statement of the IAM policy returned by a Lambda authorizer
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyStatement {
+    /// The action the statement applies to
+    pub action: String,
+
+    /// Whether the action is allowed or denied
+    pub effect: PolicyEffect,
+
+    /// The ARN of the method the statement applies to
+    pub resource: String,
+}
+
+/// Enum that represents the effects of a policy statement
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PolicyEffect {
+    Allow,
+    Deny,
+}
+
+/// Checks the given authorizer request against the given rate limiter, by the given key, and
+/// returns the policy allowing or denying it. Fails if the rate limiter can't check the request.
+pub fn authorize<R: RateLimiter + ?Sized>(
+    rate_limiter: &R,
+    request: &AuthorizerRequest,
+    key: AuthorizerKey,
+) -> Result<AuthorizerResponse, RateLimiterError> {
+    let Some(request_identifier) = request_identifier(request, key) else {
+        return Ok(policy(
+            "anonymous".to_string(),
+            PolicyEffect::Deny,
+            &request.method_arn,
+            HashMap::new(),
+        ));
+    };
+    let principal_id = rate_limiter.build_request_key(request_identifier.clone());
+
+    Ok(match rate_limiter.check_request(request_identifier)? {
+        RateLimiterResponse::RequestAllowed(allowed) => policy(
+            principal_id,
+            PolicyEffect::Allow,
+            &request.method_arn,
+            HashMap::from([(
+                "remainingRequests".to_string(),
+                allowed.remaining_request_counter.to_string(),
+            )]),
+        ),
+        RateLimiterResponse::RequestThrottled(throttled) => policy(
+            principal_id,
+            PolicyEffect::Deny,
+            &request.method_arn,
+            HashMap::from([(
+                "retryAfterSeconds".to_string(),
+                throttled.retry_in.as_millis().div_ceil(1000).to_string(),
+            )]),
+        ),
+    })
+}
+
+/// Utility method that returns the request identifier of the given authorizer request, by the
+/// given key, if the request has one.
+fn request_identifier(
+    request: &AuthorizerRequest,
+    key: AuthorizerKey,
+) -> Option<RequestIdentifier> {
+    let identity = &request.request_context.identity;
+    match key {
+        AuthorizerKey::SourceIp => identity
+            .source_ip
+            .as_deref()
+            .and_then(|source_ip| source_ip.parse::<IpAddr>().ok())
+            .map(RequestIdentifier::Ip),
+        AuthorizerKey::ApiKey => identity
+            .api_key
+            .as_ref()
+            .filter(|api_key| !api_key.is_empty())
+            .map(|api_key| RequestIdentifier::Custom {
+                key: API_KEY_IDENTIFIER.to_string(),
+                value: api_key.clone(),
+            }),
+    }
+}
+
+/// Utility method that builds a policy with a single statement, with the given effect on the
+/// given method.
+fn policy(
+    principal_id: String,
+    effect: PolicyEffect,
+    method_arn: &str,
+    context: HashMap<String, String>,
+) -> AuthorizerResponse {
+    AuthorizerResponse {
+        principal_id,
+        policy_document: PolicyDocument {
+            version: POLICY_VERSION.to_string(),
+            statement: vec![PolicyStatement {
+                action: INVOKE_ACTION.to_string(),
+                effect,
+                resource: method_arn.to_string(),
+            }],
+        },
+        context,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "redis")]
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    #[cfg(feature = "redis")]
+    use super::authorize;
+    use super::{
+        request_identifier, AuthorizerIdentity, AuthorizerKey, AuthorizerRequest,
+        AuthorizerRequestContext,
+    };
+    use crate::RequestIdentifier;
+    #[cfg(feature = "redis")]
+    use crate::{factory::RateLimiterFactory, redis_mock::RedisMock};
+
+    const METHOD_ARN: &str = "arn:aws:execute-api:eu-west-1:123456789012:abcdef/prod/GET/intensity";
+
+    fn request(source_ip: Option<&str>, api_key: Option<&str>) -> AuthorizerRequest {
+        AuthorizerRequest {
+            method_arn: METHOD_ARN.to_string(),
+            request_context: AuthorizerRequestContext {
+                identity: AuthorizerIdentity {
+                    source_ip: source_ip.map(String::from),
+                    api_key: api_key.map(String::from),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn should_extract_source_ip() {
+        let request_identifier =
+            request_identifier(&request(Some("172.28.0.6"), None), AuthorizerKey::SourceIp);
+
+        assert!(
+            matches!(request_identifier, Some(RequestIdentifier::Ip(ip)) if ip.to_string() == "172.28.0.6")
+        )
+    }
+
+    #[test]
+    fn should_extract_api_key() {
+        let request_identifier =
+            request_identifier(&request(None, Some("secret")), AuthorizerKey::ApiKey);
+
+        assert!(matches!(
+            request_identifier,
+            Some(RequestIdentifier::Custom { key, value }) if key == "api_key" && value == "secret"
+        ))
+    }
+
+    #[rstest]
+    #[case::missing_source_ip(request(None, Some("secret")), AuthorizerKey::SourceIp)]
+    #[case::invalid_source_ip(request(Some("unknown"), None), AuthorizerKey::SourceIp)]
+    #[case::missing_api_key(request(Some("172.28.0.6"), None), AuthorizerKey::ApiKey)]
+    #[case::empty_api_key(request(None, Some("")), AuthorizerKey::ApiKey)]
+    fn should_not_extract_missing_key(
+        #[case] request: AuthorizerRequest,
+        #[case] key: AuthorizerKey,
+    ) {
+        assert!(request_identifier(&request, key).is_none())
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn should_deny_throttled_requests_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request = request(Some("172.28.0.7"), None);
+
+        //act
+        let first = authorize(&rate_limiter, &request, AuthorizerKey::SourceIp).unwrap();
+        let second = authorize(&rate_limiter, &request, AuthorizerKey::SourceIp).unwrap();
+
+        //assert
+        assert!(first.is_allowed());
+        assert_eq!(first.principal_id, "rl:ip_172.28.0.7");
+        assert_eq!(first.policy_document.statement[0].resource, METHOD_ARN);
+        assert!(!second.is_allowed());
+        assert!(second.context.contains_key("retryAfterSeconds"));
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn should_deny_requests_without_key_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+
+        //act
+        let response =
+            authorize(&rate_limiter, &request(None, None), AuthorizerKey::ApiKey).unwrap();
+
+        //assert
+        assert!(!response.is_allowed());
+        assert_eq!(response.principal_id, "anonymous");
+    }
+}
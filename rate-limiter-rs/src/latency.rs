@@ -0,0 +1,63 @@
+//! Module that includes the utilities used to track the latency of the rate limiter checks.
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use crate::stable_hash;
+
+/// Represents the time spent by a rate limiter check against Redis, split by phase
+#[derive(Clone, Copy, Debug)]
+pub struct CheckLatency {
+    /// Time spent acquiring a connection to Redis
+    pub connect: Duration,
+    /// Time spent running the Redis commands of the check
+    pub commands: Duration,
+}
+
+impl CheckLatency {
+    /// The overall Redis round-trip time of the check
+    pub fn total(&self) -> Duration {
+        self.connect + self.commands
+    }
+}
+
+/// Emits a structured log event if the given check took longer than the threshold, along with the
+/// names of the commands it ran. The commands of a check run in a single round trip, so their
+/// latency is measured as a whole. The request key is hashed, so that identifiers like IP
+/// addresses don't end up in logs.
+#[cfg(feature = "redis")]
+pub(crate) fn report_slow_check(
+    threshold: Duration,
+    key: &str,
+    latency: &CheckLatency,
+    command_names: &[&str],
+) {
+    if latency.total() < threshold {
+        return;
+    }
+
+    log::warn!(
+        "slow rate limiter check: key_hash={:016x} total_ms={} connect_ms={} commands_ms={} command_names={}",
+        stable_hash(key),
+        latency.total().as_millis(),
+        latency.connect.as_millis(),
+        latency.commands.as_millis(),
+        command_names.join(",")
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::CheckLatency;
+
+    #[test]
+    fn should_compute_total_latency() {
+        let latency = CheckLatency {
+            connect: Duration::from_millis(3),
+            commands: Duration::from_millis(7),
+        };
+
+        assert_eq!(latency.total(), Duration::from_millis(10))
+    }
+}
@@ -4,23 +4,122 @@
 //!
 //! Both implementations are meant to work in a distributed environment and they are based on Redis
 //! for their remote state management.
-use std::{net::IpAddr, time::Duration};
+//!
+//! Redis support is behind the default `redis` feature: with `default-features = false`, only the
+//! [RateLimiter] trait, the request identifiers, the [policy] syntax and the key extractors are
+//! compiled, so that other backends can be built without pulling in the Redis dependency tree.
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime},
+};
 
+use capabilities::RedisCapabilities;
+use cost::RequestContext;
+use data_subject::IdentifierData;
 use errors::RateLimiterError;
+use inspection::KeyInspection;
+use listing::{KeyListing, ListCursor};
+use offenders::Offender;
+use overrides::LimitOverride;
+use snapshot::StateSnapshot;
+use usage::UsagePeriod;
 
+#[cfg(feature = "actix")]
+pub mod actix;
+#[cfg(feature = "redis")]
+pub mod adaptive;
+pub mod api_key;
+pub mod breaker;
+#[cfg(feature = "redis")]
 pub mod builders;
+pub mod capabilities;
+pub mod client_ip;
+pub mod clock;
+#[cfg(feature = "redis")]
+mod coalescing;
+#[cfg(feature = "redis")]
+pub mod config;
+#[cfg(feature = "redis")]
+mod connection;
+pub mod cost;
+pub mod data_subject;
+#[cfg(feature = "redis")]
+pub mod descriptors;
+pub mod durations;
 pub mod errors;
+#[cfg(feature = "redis")]
+pub mod expiry;
+#[cfg(feature = "redis")]
 pub mod factory;
+#[cfg(feature = "redis")]
+mod functions;
+pub mod hash_tags;
+pub mod headers;
+#[cfg(feature = "hyper")]
+pub mod hyper;
+pub mod inspection;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(feature = "lambda")]
+pub mod lambda;
+pub mod latency;
+pub mod listing;
+#[cfg(feature = "redis")]
+pub mod load_shedding;
+pub mod notifier;
+pub mod observer;
+pub mod offenders;
+#[cfg(feature = "redis")]
+pub mod onboarding;
+pub mod overrides;
+pub mod policy;
+#[cfg(feature = "redis")]
+mod quorum;
+#[cfg(feature = "redis")]
 pub mod rate_limiters;
+#[cfg(all(test, feature = "redis"))]
+mod redis_mock;
+#[cfg(feature = "redis")]
+pub mod regions;
+#[cfg(feature = "redis")]
+pub mod registry;
+#[cfg(feature = "redis")]
+pub mod reload;
+pub mod replay;
+#[cfg(feature = "redis")]
+pub mod reputation;
+#[cfg(feature = "http")]
+pub mod responses;
+#[cfg(feature = "redis")]
+mod sharding;
+pub mod snapshot;
+#[cfg(feature = "redis")]
+mod spans;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "redis")]
+pub mod throttle_cache;
+pub mod throttle_log;
+#[cfg(feature = "tonic")]
+pub mod tonic;
+#[cfg(feature = "tower")]
+pub mod tower;
+pub mod usage;
+
+/// Derive macro that implements [ToRequestIdentifier] for custom key structs.
+/// Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use rate_limiter_rs_derive::RateLimitKey;
+
+// Allows the derive macro to refer to this crate by name from within its own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as rate_limiter_rs;
 
 /// Trait representing the capabilities offered by the rate limiter
 pub trait RateLimiter {
     /// Method that builds a request key based on the different input
     fn build_request_key(&self, request_identifier: RequestIdentifier) -> String {
-        match request_identifier {
-            RequestIdentifier::Ip(ip) => format!("rl:ip_{}", ip),
-            RequestIdentifier::Custom { key, value } => format!("rl:cst_{0}:{1}", key, value),
-        }
+        hash_tags::request_key(request_identifier, None)
     }
 
     /// Method that checks whether a request is allowed or should be throttled instead.
@@ -30,25 +129,172 @@ pub trait RateLimiter {
         &self,
         request_identifier: RequestIdentifier,
     ) -> Result<RateLimiterResponse, RateLimiterError>;
+
+    /// Convenience method that checks whether a request is allowed, accepting any type that can be
+    /// converted into a [RequestIdentifier]. Not available on trait objects, which should rely on
+    /// [RateLimiter::check_request] instead.
+    fn check<T: ToRequestIdentifier>(
+        &self,
+        request_identifier: T,
+    ) -> Result<RateLimiterResponse, RateLimiterError>
+    where
+        Self: Sized,
+    {
+        self.check_request(request_identifier.to_request_identifier())
+    }
+
+    /// Method that checks whether a request is allowed, counting the cost computed from the given
+    /// context by the [cost function](cost::CostFunction) of the rate limiter, if any. By default,
+    /// the context is ignored, and the request checked as by [RateLimiter::check_request], like
+    /// rate limiters without costs do.
+    fn check_request_with_context(
+        &self,
+        request_identifier: RequestIdentifier,
+        _context: &RequestContext,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        self.check_request(request_identifier)
+    }
+}
+
+/// Extension trait representing the administrative operations offered by the rate limiters
+/// storing their state in Redis, like inspecting, exporting or purging the state of request
+/// identifiers. Kept apart from [RateLimiter], so that other backends only have to build keys
+/// and check requests.
+pub trait RedisAdmin: RateLimiter {
+    /// Method that exports all the state stored for the given request identifier,
+    /// to serve data-subject access requests.
+    fn export_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<IdentifierData, RateLimiterError>;
+
+    /// Method that deletes all the state stored for the given request identifier,
+    /// to serve data-subject deletion requests. Returns the number of deleted Redis keys.
+    fn purge_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<u64, RateLimiterError>;
+
+    /// Method that returns the current abuse score of the given request identifier, according to
+    /// the configured [reputation policy](./reputation/index.html). Always zero when no
+    /// reputation policy is configured.
+    fn reputation(&self, request_identifier: RequestIdentifier) -> Result<f64, RateLimiterError>;
+
+    /// Method that returns the given number of most throttled request identifiers over the rolling
+    /// period of the configured [offender tracking](./offenders/index.html), most throttled first.
+    /// Always empty when offender tracking is not configured.
+    fn top_offenders(&self, n: usize) -> Result<Vec<Offender>, RateLimiterError>;
+
+    /// Method that returns the number of requests of the given request identifier allowed in the
+    /// given period, as aggregated by the [usage reporting](./usage/index.html). Always zero when
+    /// usage reporting is not enabled.
+    fn usage(
+        &self,
+        request_identifier: RequestIdentifier,
+        period: UsagePeriod,
+    ) -> Result<u64, RateLimiterError>;
+
+    /// Method that returns the current state of the given request identifier, like its count and
+    /// limit, without counting a request nor otherwise mutating it.
+    fn inspect(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<KeyInspection, RateLimiterError>;
+
+    /// Method that lists a page of the request keys matching the given pattern, like `ip_*`, with
+    /// their current counts, starting from the given cursor. The [default cursor](ListCursor::default)
+    /// starts a new [listing](./listing/index.html), continued with the cursor of each page until
+    /// none is returned.
+    fn list_keys(&self, pattern: &str, cursor: ListCursor) -> Result<KeyListing, RateLimiterError>;
+
+    /// Method that takes a [snapshot](./snapshot/index.html) of all the state stored by the rate
+    /// limiter, to migrate it to another Redis deployment with [RedisAdmin::import_state].
+    fn export_state(&self) -> Result<StateSnapshot, RateLimiterError>;
+
+    /// Method that restores the given snapshot, replacing the state of the keys it holds.
+    /// Returns the number of restored keys, skipping the ones expired since the snapshot was taken.
+    fn import_state(&self, snapshot: &StateSnapshot) -> Result<u64, RateLimiterError>;
+
+    /// Method that updates the window size and duration of a live rate limiter, without
+    /// rebuilding it and dropping its Redis client. The new limits are shared by all the clones
+    /// of the rate limiter, and apply to the checks performed from now on.
+    fn update_limits(&self, window_size: u64, window_duration: Duration);
+
+    /// Method that grants bespoke limits to the given request identifier, replacing any previous
+    /// override. Only consulted by rate limiters with limit overrides enabled.
+    fn set_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+        limit_override: &LimitOverride,
+    ) -> Result<(), RateLimiterError>;
+
+    /// Method that removes the bespoke limits of the given request identifier, if any.
+    /// Returns whether an override was set.
+    fn remove_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<bool, RateLimiterError>;
+
+    /// Method that returns the capabilities of the underlying Redis server. They are detected
+    /// on the first call and then cached, for the rate limiter and all of its clones.
+    fn capabilities(&self) -> Result<RedisCapabilities, RateLimiterError>;
+}
+
+/// Struct that describes the state of the rate limiter for a given request identifier,
+/// at the time the request was checked. Shared by allowed and throttled responses, so that
+/// callers can emit standard rate limit headers without duplicating the limiter configuration.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimitStatus {
+    /// the maximum number of requests allowed in a single window
+    pub limit: u64,
+    /// the duration of the window
+    pub window_duration: Duration,
+    /// the number of requests counted in the current window, including the current one
+    pub used: u64,
+    /// the point in time when the budget of the current window is restored
+    pub reset_at: SystemTime,
 }
 
 /// Struct for requests that are allowed by the rate limiter
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RequestAllowed {
     /// the updated counter of available requests for the given ip/custom request id
     pub remaining_request_counter: u64,
+    /// the status of the rate limiter for the given ip/custom request id
+    pub status: RateLimitStatus,
 }
 
 /// Struct for requests that are throttled by the rate limiter
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RequestThrottled {
     /// a duration representing when the user should retry the request
     pub retry_in: Duration,
+    /// the reason why the request was throttled
+    pub reason: ThrottleReason,
+    /// the status of the rate limiter for the given ip/custom request id
+    pub status: RateLimitStatus,
+}
+
+/// Enum that represents the reasons why a request might be throttled, so that callers can
+/// tell a client exceeding its own quota apart from the service protecting itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ThrottleReason {
+    /// The request identifier exceeded the requests allowed in the current window.
+    /// Usually mapped to a `429 Too Many Requests` HTTP status.
+    QuotaExceeded,
+    /// The request was shed to protect the service from overload, regardless of the quota
+    /// left to the request identifier. Usually mapped to a `503 Service Unavailable` HTTP status.
+    LoadShed,
 }
 
 /// Wrapper enum that describes the list of possible responses returned by the rate limiter
 /// with each specific inner detail according to the scenario
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RateLimiterResponse {
     /// variant for requests that are allowed
     RequestAllowed(RequestAllowed),
@@ -58,6 +304,7 @@ pub enum RateLimiterResponse {
 
 /// Enum that represents the possible input types for our rate limiter
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RequestIdentifier {
     /// An Ip address. Used when we want to rate limit requests based on the Ip address
     /// from which the request was fired
@@ -65,6 +312,88 @@ pub enum RequestIdentifier {
     /// A custom identifier in a string format. Used when we want to rate limit based on
     /// custom criteria, like a client identifier.
     Custom { key: String, value: String },
+    /// The name of an internal service. Used when we want to meter service-to-service
+    /// calls separately from end-user traffic, as they are stored under a dedicated key prefix.
+    Internal(String),
+}
+
+/// Trait for types that can be converted into a [RequestIdentifier], so that application code
+/// doesn't have to construct the enum manually.
+pub trait ToRequestIdentifier {
+    /// Method that converts the value into a request identifier
+    fn to_request_identifier(&self) -> RequestIdentifier;
+}
+
+impl ToRequestIdentifier for RequestIdentifier {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        self.clone()
+    }
+}
+
+impl ToRequestIdentifier for IpAddr {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        RequestIdentifier::Ip(*self)
+    }
+}
+
+/// Only the Ip address is taken into account, so that requests coming from different
+/// ports of the same host share the same budget.
+impl ToRequestIdentifier for SocketAddr {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        RequestIdentifier::Ip(self.ip())
+    }
+}
+
+/// Plain strings are considered custom identifiers, stored under the `id` key.
+impl ToRequestIdentifier for &str {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        RequestIdentifier::Custom {
+            key: "id".to_string(),
+            value: self.to_string(),
+        }
+    }
+}
+
+/// Plain strings are considered custom identifiers, stored under the `id` key.
+impl ToRequestIdentifier for String {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        self.as_str().to_request_identifier()
+    }
+}
+
+/// Tuples are considered custom identifiers, in the `(key, value)` form.
+impl<K: ToString, V: ToString> ToRequestIdentifier for (K, V) {
+    fn to_request_identifier(&self) -> RequestIdentifier {
+        RequestIdentifier::Custom {
+            key: self.0.to_string(),
+            value: self.1.to_string(),
+        }
+    }
+}
+
+/// Utility method that escapes the `%`, `:` and `=` characters of a value used in a custom
+/// request key, so that values containing separators can't produce colliding keys.
+/// Used by the `RateLimitKey` derive macro.
+pub fn encode_key_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '%' | ':' | '=' => encoded.push_str(&format!("%{:02X}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+
+    encoded
+}
+
+/// Utility method that returns a hash of the given key, using 64 bit FNV-1a. Unlike the hashers of
+/// the standard library, the hash is stable across processes and releases, so that all the nodes
+/// sharing a Redis server derive the same values from the same key.
+pub(crate) fn stable_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 /// Utility method used in tests only
@@ -88,12 +417,16 @@ impl RateLimiterResponse {
 
 #[cfg(test)]
 mod test {
-    use std::net::{IpAddr, Ipv4Addr};
+    #[cfg(feature = "redis")]
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
     use rstest::rstest;
 
-    use crate::{factory::RateLimiterFactory, RateLimiter, RequestIdentifier};
+    use crate::{encode_key_component, stable_hash};
+    #[cfg(feature = "redis")]
+    use crate::{factory::RateLimiterFactory, RateLimiter, RequestIdentifier, ToRequestIdentifier};
 
+    #[cfg(feature = "redis")]
     #[rstest]
     #[case::ip(
         RequestIdentifier::Ip(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
@@ -103,6 +436,10 @@ mod test {
         RequestIdentifier::Custom { key: "client_id".to_string(), value: "dili91".to_string() },
         "rl:cst_client_id:dili91"
     )]
+    #[case::internal(
+        RequestIdentifier::Internal("billing-service".to_string()),
+        "rl:int_billing-service"
+    )]
     fn should_build_request_identifier(
         #[case] request_identifier: RequestIdentifier,
         #[case] expected_key: &str,
@@ -114,4 +451,124 @@ mod test {
             expected_key
         )
     }
+
+    #[cfg(feature = "redis")]
+    #[rstest]
+    #[case::request_identifier(
+        RequestIdentifier::Internal("billing-service".to_string()),
+        "rl:int_billing-service"
+    )]
+    #[case::ip_addr(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), "rl:ip_1.2.3.4")]
+    #[case::socket_addr(
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), 8080),
+        "rl:ip_1.2.3.4"
+    )]
+    #[case::str("dili91", "rl:cst_id:dili91")]
+    #[case::string("dili91".to_string(), "rl:cst_id:dili91")]
+    #[case::tuple(("client_id", "dili91"), "rl:cst_client_id:dili91")]
+    fn should_convert_to_request_identifier<T: ToRequestIdentifier>(
+        #[case] value: T,
+        #[case] expected_key: &str,
+    ) {
+        let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+
+        assert_eq!(
+            rate_limiter.build_request_key(value.to_request_identifier()),
+            expected_key
+        )
+    }
+
+    #[test]
+    fn stable_hash_should_match_fnv1a() {
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_eq!(stable_hash("a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[rstest]
+    #[case::plain("dili91", "dili91")]
+    #[case::separators("a:b=c", "a%3Ab%3Dc")]
+    #[case::escape_char("100%", "100%25")]
+    fn should_encode_key_component(#[case] value: &str, #[case] expected_component: &str) {
+        assert_eq!(encode_key_component(value), expected_component)
+    }
+
+    #[cfg(all(feature = "derive", feature = "redis"))]
+    #[test]
+    fn should_derive_request_identifier() {
+        use crate::RateLimitKey;
+
+        #[derive(RateLimitKey)]
+        struct ClientKey {
+            tenant: String,
+            user: String,
+            #[rate_limit_key(skip)]
+            _trace_id: u64,
+        }
+
+        #[derive(RateLimitKey)]
+        #[rate_limit_key(name = "plan")]
+        struct PlanKey(&'static str, u8);
+
+        let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+        let client_key = ClientKey {
+            tenant: "acme:eu".to_string(),
+            user: "dili91".to_string(),
+            _trace_id: 42,
+        };
+
+        assert_eq!(
+            rate_limiter.build_request_key(client_key.to_request_identifier()),
+            "rl:cst_client_key:tenant=acme%3Aeu:user=dili91"
+        );
+        assert_eq!(
+            rate_limiter.build_request_key(PlanKey("pro", 2).to_request_identifier()),
+            "rl:cst_plan:0=pro:1=2"
+        )
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn should_serialize_and_deserialize_rate_limiter_response() {
+        use std::time::{Duration, SystemTime};
+
+        use crate::{RateLimitStatus, RateLimiterResponse, RequestThrottled, ThrottleReason};
+
+        let response = RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in: Duration::from_millis(1500),
+            reason: ThrottleReason::QuotaExceeded,
+            status: RateLimitStatus {
+                limit: 5,
+                window_duration: Duration::from_secs(60),
+                used: 6,
+                reset_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            },
+        });
+
+        let json = serde_json::to_string(&response).unwrap();
+        let deserialized = serde_json::from_str::<RateLimiterResponse>(&json)
+            .unwrap()
+            .as_throttled();
+
+        assert_eq!(deserialized.retry_in, Duration::from_millis(1500));
+        assert_eq!(deserialized.reason, ThrottleReason::QuotaExceeded);
+        assert_eq!(deserialized.status.used, 6);
+    }
+
+    #[cfg(all(feature = "serde", feature = "redis"))]
+    #[test]
+    fn should_serialize_and_deserialize_request_identifier() {
+        let request_identifier = RequestIdentifier::Custom {
+            key: "client_id".to_string(),
+            value: "dili91".to_string(),
+        };
+
+        let json = serde_json::to_string(&request_identifier).unwrap();
+        let deserialized = serde_json::from_str::<RequestIdentifier>(&json).unwrap();
+
+        let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+        assert_eq!(
+            rate_limiter.build_request_key(deserialized),
+            "rl:cst_client_id:dili91"
+        )
+    }
 }
@@ -0,0 +1,182 @@
+//! Module that includes the administrative listing of the request keys stored by a rate limiter,
+//! with their current counts, to build ops tooling on top of the rate limiter.
+//!
+//! ## Implementation details
+//!
+//! Keys are listed incrementally with the `SCAN` command, matching the given pattern prefixed
+//! with `rl:`, so that a listing never blocks Redis, even with millions of keys. Like `SCAN`, a
+//! page might be empty while more keys are still to be listed, and keys created or deleted while
+//! listing might be missed. Keys sharded across several Redis servers are listed one server
+//! after the other.
+//!
+//! Only the keys holding the counters of request identifiers are listed, skipping the keys
+//! derived from them, like the first seen timestamps or the abuse scores. Counters stored in
+//! hashes or per region are not listed.
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{connection::RedisConnection, errors::RateLimiterError};
+
+/// The prefix of all the keys of the rate limiter
+#[cfg(feature = "redis")]
+const KEY_PREFIX: &str = "rl:";
+
+/// The number of keys each `SCAN` call is hinted to go through
+#[cfg(feature = "redis")]
+const SCAN_COUNT: u64 = 100;
+
+/// The prefixes of the request keys, after the `rl:` prefix and the opening brace of a hash tag
+#[cfg(feature = "redis")]
+const REQUEST_KEY_PREFIXES: &[&str] = &["ip_", "cst_", "int_"];
+
+/// The suffixes of the keys derived from request keys, like `rl:ip_172.28.0.6:reputation`
+#[cfg(feature = "redis")]
+const DERIVED_KEY_MARKERS: &[&str] = &[":first_seen", ":reputation", ":usage:", ":region:"];
+
+/// Represents the position of a key listing, across the Redis servers of a rate limiter.
+/// The default cursor starts a new listing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListCursor {
+    /// The index of the Redis server being listed
+    pub server: usize,
+
+    /// The `SCAN` cursor within the Redis server being listed
+    pub cursor: u64,
+}
+
+/// Represents a page of listed request keys
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyListing {
+    /// The request keys listed in this page, with their current counts
+    pub keys: Vec<ListedKey>,
+
+    /// The cursor to list the next page from, if any keys are left to list
+    pub next_cursor: Option<ListCursor>,
+}
+
+/// Represents a listed request key
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListedKey {
+    /// The request key, like `rl:ip_172.28.0.6`
+    pub key: String,
+
+    /// The number of requests counted in the current window
+    pub count: u64,
+}
+
+/// Lists a page of the request keys matching the given pattern on the Redis server connected by
+/// the given function, out of the given number of servers, counting their requests with the
+/// given function.
+#[cfg(feature = "redis")]
+pub(crate) fn list_keys(
+    pattern: &str,
+    cursor: ListCursor,
+    servers: usize,
+    connect: impl FnOnce(usize) -> Result<RedisConnection, RateLimiterError>,
+    count: impl FnOnce(&mut Connection, &[String]) -> Result<Vec<u64>, RateLimiterError>,
+) -> Result<KeyListing, RateLimiterError> {
+    if cursor.server >= servers {
+        return Ok(KeyListing::default());
+    }
+
+    let mut con = connect(cursor.server)?;
+    let (scan_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(cursor.cursor)
+        .arg("MATCH")
+        .arg(format!("{}{}", KEY_PREFIX, pattern))
+        .arg("COUNT")
+        .arg(SCAN_COUNT)
+        .query(&mut *con)?;
+
+    let keys: Vec<String> = keys.into_iter().filter(|k| is_request_key(k)).collect();
+    let counts = if keys.is_empty() {
+        vec![]
+    } else {
+        count(&mut con, &keys)?
+    };
+
+    Ok(KeyListing {
+        keys: keys
+            .into_iter()
+            .zip(counts)
+            .map(|(key, count)| ListedKey { key, count })
+            .collect(),
+        next_cursor: next_cursor(cursor, scan_cursor, servers),
+    })
+}
+
+/// Utility method that returns the cursor following a `SCAN` on the server of the given cursor,
+/// moving on to the next server once the current one is fully listed.
+#[cfg(feature = "redis")]
+fn next_cursor(cursor: ListCursor, scan_cursor: u64, servers: usize) -> Option<ListCursor> {
+    if scan_cursor != 0 {
+        return Some(ListCursor {
+            server: cursor.server,
+            cursor: scan_cursor,
+        });
+    }
+
+    (cursor.server + 1 < servers).then_some(ListCursor {
+        server: cursor.server + 1,
+        cursor: 0,
+    })
+}
+
+/// Utility method that returns whether the given key holds the counter of a request identifier.
+#[cfg(feature = "redis")]
+pub(crate) fn is_request_key(key: &str) -> bool {
+    let Some(name) = key.strip_prefix(KEY_PREFIX) else {
+        return false;
+    };
+    let name = name.strip_prefix('{').unwrap_or(name);
+
+    REQUEST_KEY_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        && !DERIVED_KEY_MARKERS
+            .iter()
+            .any(|marker| key.contains(marker))
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use rstest::rstest;
+
+    use super::{is_request_key, next_cursor, ListCursor};
+
+    #[rstest]
+    #[case::ip("rl:ip_1.2.3.4", true)]
+    #[case::tagged_ip("rl:{ip_1.2.3.4}", true)]
+    #[case::custom("rl:cst_tenant:acme", true)]
+    #[case::tagged_custom_key_name("rl:cst_{tenant}:acme", true)]
+    #[case::internal("rl:int_billing", true)]
+    #[case::first_seen("rl:ip_1.2.3.4:first_seen", false)]
+    #[case::reputation("rl:{ip_1.2.3.4}:reputation", false)]
+    #[case::usage("rl:int_billing:usage:2026-10", false)]
+    #[case::regional_counter("rl:ip_1.2.3.4:region:eu-west-1:42", false)]
+    #[case::limit_override("rl:override:ip_1.2.3.4", false)]
+    #[case::offenders("rl:offenders:42", false)]
+    #[case::foreign("session:42", false)]
+    fn should_tell_request_keys_apart(#[case] key: &str, #[case] expected: bool) {
+        assert_eq!(is_request_key(key), expected)
+    }
+
+    #[rstest]
+    #[case::same_server(7, 2, Some(ListCursor { server: 0, cursor: 7 }))]
+    #[case::next_server(0, 2, Some(ListCursor { server: 1, cursor: 0 }))]
+    #[case::last_server(0, 1, None)]
+    fn should_compute_next_cursor(
+        #[case] scan_cursor: u64,
+        #[case] servers: usize,
+        #[case] expected: Option<ListCursor>,
+    ) {
+        assert_eq!(
+            next_cursor(ListCursor::default(), scan_cursor, servers),
+            expected
+        )
+    }
+}
@@ -0,0 +1,161 @@
+//! Module that includes the optional load shedding, scaling the budgets of every request
+//! identifier by a factor derived from a load signal fed by the application, like its CPU usage,
+//! the depth of its queues or its error rate.
+//!
+//! ```
+//! use rate_limiter_rs::load_shedding::{LoadShedder, LoadSheddingPolicy};
+//!
+//! let load_shedder = LoadShedder::new(LoadSheddingPolicy {
+//!     shed_above: 0.8,
+//!     min_fraction: 0.2,
+//! });
+//!
+//! // e.g. from a task sampling the CPU usage of the service
+//! load_shedder.report_load(0.9);
+//!
+//! assert_eq!(load_shedder.effective_limit(100), 60);
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The load is a number between 0, idle, and 1, saturated, and the last one reported is held in
+//! process memory. Up to the load the policy sheds above, the budgets are left untouched; past
+//! it, they shrink linearly with the load, down to the minimum fraction of the policy once the
+//! service is saturated. At least one request per window is always allowed.
+//!
+//! The same load shedder can be given to several rate limiters, so that a single signal scales
+//! all of their budgets at once. Requests throttled only because of the scaled budget, that is
+//! that would have been allowed with the configured one, are throttled with the
+//! [LoadShed](crate::ThrottleReason::LoadShed) reason, so that callers can tell them apart from
+//! the clients exceeding their own quota.
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::ThrottleReason;
+
+/// Represents how the budgets are scaled with the load
+#[derive(Clone, Debug)]
+pub struct LoadSheddingPolicy {
+    /// The load above which the budgets start to be scaled down, between 0 and 1
+    pub shed_above: f64,
+
+    /// The fraction of the budgets still granted once the service is saturated, between 0 and 1
+    pub min_fraction: f64,
+}
+
+/// Represents the load shedding of one or more rate limiters, fed with the load of the service.
+/// Clones share the same load.
+#[derive(Clone, Debug)]
+pub struct LoadShedder {
+    /// The policy the budgets are scaled with
+    pub policy: LoadSheddingPolicy,
+
+    /// The bits of the last load reported
+    load: Arc<AtomicU64>,
+}
+
+impl LoadShedder {
+    /// Creates a load shedder for an idle service, until a load is reported.
+    pub fn new(policy: LoadSheddingPolicy) -> Self {
+        LoadShedder {
+            policy,
+            load: Arc::new(AtomicU64::new(0f64.to_bits())),
+        }
+    }
+
+    /// Reports the current load of the service, between 0, idle, and 1, saturated. Loads out of
+    /// that range are clamped to it, while loads that are not a number are ignored.
+    pub fn report_load(&self, load: f64) {
+        if !load.is_nan() {
+            self.load
+                .store(load.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the last load reported.
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Returns the fraction of the budgets currently granted, between the minimum fraction of the
+    /// policy and 1.
+    pub fn fraction(&self) -> f64 {
+        let load = self.load();
+        let shed_above = self.policy.shed_above.clamp(0.0, 1.0);
+        let min_fraction = self.policy.min_fraction.clamp(0.0, 1.0);
+        if load <= shed_above {
+            return 1.0;
+        }
+
+        1.0 - (1.0 - min_fraction) * (load - shed_above) / (1.0 - shed_above)
+    }
+
+    /// Computes the budget currently granted, out of the given configured one.
+    pub fn effective_limit(&self, limit: u64) -> u64 {
+        ((limit as f64 * self.fraction()).round() as u64).clamp(1, limit.max(1))
+    }
+}
+
+/// Utility method that returns the reason why a request is throttled, given the requests counted
+/// in its window and the budget of the window before being scaled by the adaptive limits and the
+/// load shedding.
+pub(crate) fn throttle_reason(used: u64, unscaled_limit: u64) -> ThrottleReason {
+    if used <= unscaled_limit {
+        ThrottleReason::LoadShed
+    } else {
+        ThrottleReason::QuotaExceeded
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{throttle_reason, LoadShedder, LoadSheddingPolicy};
+    use crate::ThrottleReason;
+
+    fn load_shedder() -> LoadShedder {
+        LoadShedder::new(LoadSheddingPolicy {
+            shed_above: 0.5,
+            min_fraction: 0.1,
+        })
+    }
+
+    #[rstest]
+    #[case::idle(0.0, 100)]
+    #[case::at_threshold(0.5, 100)]
+    #[case::halfway(0.75, 55)]
+    #[case::saturated(1.0, 10)]
+    #[case::above_range(3.0, 10)]
+    fn should_scale_budgets_with_load(#[case] load: f64, #[case] expected_limit: u64) {
+        let load_shedder = load_shedder();
+
+        load_shedder.report_load(load);
+
+        assert_eq!(load_shedder.effective_limit(100), expected_limit);
+    }
+
+    #[test]
+    fn should_share_load_between_clones_and_ignore_invalid_loads() {
+        //arrange
+        let load_shedder = load_shedder();
+        let clone = load_shedder.clone();
+
+        //act
+        clone.report_load(1.0);
+        clone.report_load(f64::NAN);
+
+        //assert
+        assert_eq!(load_shedder.load(), 1.0);
+        assert_eq!(load_shedder.effective_limit(1), 1);
+    }
+
+    #[rstest]
+    #[case::within_configured_budget(5, ThrottleReason::LoadShed)]
+    #[case::over_configured_budget(11, ThrottleReason::QuotaExceeded)]
+    fn should_tell_shed_requests_apart(#[case] used: u64, #[case] expected_reason: ThrottleReason) {
+        assert_eq!(throttle_reason(used, 10), expected_reason);
+    }
+}
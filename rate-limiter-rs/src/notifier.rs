@@ -0,0 +1,458 @@
+//! Module that includes the notifier of throttle events, so that abuse-detection systems can
+//! react to the decisions of a rate limiter in near-real time.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use rate_limiter_rs::notifier::{NotifierSettings, ThrottleEvent, ThrottleNotifier};
+//!
+//! let notifier = ThrottleNotifier::new(
+//!     |events: &[ThrottleEvent]| {
+//!         println!("{} requests throttled", events.len());
+//!         Ok(())
+//!     },
+//!     NotifierSettings {
+//!         flush_interval: Duration::from_millis(500),
+//!         ..NotifierSettings::default()
+//!     },
+//! );
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The [ThrottleNotifier] is an [observer](crate::observer::RateLimiterObserver) that queues an
+//! event for every throttled request, without blocking the check. A background thread delivers
+//! the queued events to the configured [sink](NotificationSink) in batches, as soon as a batch is
+//! full or the oldest queued event has waited for the flush interval. Failed deliveries are
+//! retried with an exponential backoff, and the batch is dropped once all the attempts failed.
+//!
+//! The queue is bounded, so that a slow sink can't exhaust the memory of the application during
+//! an attack: events are dropped while the queue is full, and counted by
+//! [ThrottleNotifier::dropped_events]. Events still queued when the notifier is dropped are
+//! delivered before the background thread exits.
+//!
+//! With the `webhook` feature, the `WebhookSink` posts every batch to a URL, as a JSON array:
+//!
+//! ```text
+//! [{"key":"rl:ip_172.28.0.6","reason":"QuotaExceeded","limit":100,"used":101,"retry_in_ms":5320,"throttled_at_ms":1760601600000}]
+//! ```
+use std::{
+    error::Error,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{observer::RateLimiterObserver, RequestThrottled, ThrottleReason};
+
+/// The error of a failed delivery
+pub type DeliveryError = Box<dyn Error + Send + Sync>;
+
+/// Represents a throttled request
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrottleEvent {
+    /// The request key of the throttled identifier, like `rl:ip_172.28.0.6`
+    pub key: String,
+
+    /// Why the request was throttled
+    pub reason: ThrottleReason,
+
+    /// The maximum number of requests allowed in the window
+    pub limit: u64,
+
+    /// The number of requests counted in the window, including the throttled one
+    pub used: u64,
+
+    /// How long until a request of the identifier is allowed again
+    pub retry_in: Duration,
+
+    /// When the request was throttled
+    pub throttled_at: SystemTime,
+}
+
+impl ThrottleEvent {
+    /// Returns the given events as a JSON array, as posted by the `WebhookSink`.
+    pub fn to_json(events: &[ThrottleEvent]) -> String {
+        let mut json = String::from("[");
+        for (i, event) in events.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let throttled_at_ms = event
+                .throttled_at
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let _ = write!(
+                json,
+                r#"{{"key":"{}","reason":"{:?}","limit":{},"used":{},"retry_in_ms":{},"throttled_at_ms":{}}}"#,
+                escape_json(&event.key),
+                event.reason,
+                event.limit,
+                event.used,
+                event.retry_in.as_millis(),
+                throttled_at_ms
+            );
+        }
+        json.push(']');
+        json
+    }
+}
+
+/// Trait implemented by the destinations of throttle events, called with every batch of events
+/// from the background thread of the notifier. Implemented by closures with the same signature.
+pub trait NotificationSink: Send + 'static {
+    /// Delivers the given batch of events. Failed deliveries are retried with the same batch.
+    fn deliver(&self, events: &[ThrottleEvent]) -> Result<(), DeliveryError>;
+}
+
+impl<F> NotificationSink for F
+where
+    F: Fn(&[ThrottleEvent]) -> Result<(), DeliveryError> + Send + 'static,
+{
+    fn deliver(&self, events: &[ThrottleEvent]) -> Result<(), DeliveryError> {
+        self(events)
+    }
+}
+
+/// Represents how throttle events are batched and retried
+#[derive(Clone, Debug)]
+pub struct NotifierSettings {
+    /// The maximum number of events delivered in a batch
+    pub max_batch_size: usize,
+
+    /// The maximum time an event is queued for before its batch is delivered
+    pub flush_interval: Duration,
+
+    /// The maximum number of events queued for delivery, beyond which events are dropped
+    pub queue_capacity: usize,
+
+    /// The maximum number of delivery attempts of a batch, including the first one
+    pub max_attempts: u32,
+
+    /// The time waited before the first retry, doubled after every failed retry
+    pub retry_backoff: Duration,
+}
+
+impl Default for NotifierSettings {
+    fn default() -> Self {
+        NotifierSettings {
+            max_batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+            queue_capacity: 10_000,
+            max_attempts: 3,
+            retry_backoff: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Observer that delivers an event for every throttled request to a sink, in batches, from a
+/// background thread.
+#[derive(Debug)]
+pub struct ThrottleNotifier {
+    sender: SyncSender<ThrottleEvent>,
+    dropped_events: Arc<AtomicU64>,
+}
+
+impl ThrottleNotifier {
+    /// Creates a notifier delivering events to the given sink, spawning its background thread.
+    pub fn new(sink: impl NotificationSink, settings: NotifierSettings) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(settings.queue_capacity.max(1));
+        let dropped_events = Arc::new(AtomicU64::new(0));
+
+        let dropped = dropped_events.clone();
+        thread::Builder::new()
+            .name("rate-limiter-notifier".to_string())
+            .spawn(move || deliver_events(receiver, sink, settings, dropped))
+            .expect("failed to spawn the notifier thread");
+
+        ThrottleNotifier {
+            sender,
+            dropped_events,
+        }
+    }
+
+    /// Returns the number of events dropped so far, either because the queue was full, or
+    /// because all the attempts to deliver their batch failed.
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped_events.load(Ordering::Relaxed)
+    }
+}
+
+impl RateLimiterObserver for ThrottleNotifier {
+    fn on_throttled(&self, key: &str, throttled: &RequestThrottled, _latency: Duration) {
+        let event = ThrottleEvent {
+            key: key.to_string(),
+            reason: throttled.reason,
+            limit: throttled.status.limit,
+            used: throttled.status.used,
+            retry_in: throttled.retry_in,
+            throttled_at: SystemTime::now(),
+        };
+
+        if self.sender.try_send(event).is_err() {
+            self.dropped_events.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Sink that posts every batch of events to a webhook, as a JSON array. Requires the `webhook`
+/// feature.
+#[cfg(feature = "webhook")]
+#[derive(Clone, Debug)]
+pub struct WebhookSink {
+    url: String,
+    timeout: Duration,
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookSink {
+    /// Creates a sink posting to the given URL, timing out after 5 seconds.
+    pub fn new(url: impl Into<String>) -> Self {
+        WebhookSink {
+            url: url.into(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Setter for the time after which a post is considered failed.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl NotificationSink for WebhookSink {
+    fn deliver(&self, events: &[ThrottleEvent]) -> Result<(), DeliveryError> {
+        ureq::post(&self.url)
+            .timeout(self.timeout)
+            .set("Content-Type", "application/json")
+            .send_string(&ThrottleEvent::to_json(events))?;
+        Ok(())
+    }
+}
+
+/// Delivers the events received from the given queue in batches, until the notifier is dropped.
+fn deliver_events(
+    receiver: Receiver<ThrottleEvent>,
+    sink: impl NotificationSink,
+    settings: NotifierSettings,
+    dropped_events: Arc<AtomicU64>,
+) {
+    let max_batch_size = settings.max_batch_size.max(1);
+    let mut batch = Vec::with_capacity(max_batch_size);
+    let mut flush_at: Option<Instant> = None;
+
+    loop {
+        let received = match flush_at {
+            Some(flush_at) => {
+                receiver.recv_timeout(flush_at.saturating_duration_since(Instant::now()))
+            }
+            None => receiver.recv().map_err(|_e| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(event) => {
+                flush_at.get_or_insert_with(|| Instant::now() + settings.flush_interval);
+                batch.push(event);
+                if batch.len() < max_batch_size {
+                    continue;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if !batch.is_empty() {
+                    deliver_batch(&sink, &batch, &settings, &dropped_events);
+                }
+                return;
+            }
+        }
+
+        deliver_batch(&sink, &batch, &settings, &dropped_events);
+        batch.clear();
+        flush_at = None;
+    }
+}
+
+/// Delivers the given batch, retrying failed attempts with an exponential backoff.
+fn deliver_batch(
+    sink: &impl NotificationSink,
+    batch: &[ThrottleEvent],
+    settings: &NotifierSettings,
+    dropped_events: &AtomicU64,
+) {
+    let max_attempts = settings.max_attempts.max(1);
+
+    for attempt in 1..=max_attempts {
+        match sink.deliver(batch) {
+            Ok(()) => return,
+            Err(e) if attempt < max_attempts => {
+                log::warn!(
+                    "failed to deliver {} throttle events, attempt {} of {}: {}",
+                    batch.len(),
+                    attempt,
+                    max_attempts,
+                    e
+                );
+                thread::sleep(
+                    settings
+                        .retry_backoff
+                        .saturating_mul(1 << (attempt - 1).min(16)),
+                );
+            }
+            Err(e) => log::error!(
+                "dropping {} throttle events after {} failed attempts: {}",
+                batch.len(),
+                max_attempts,
+                e
+            ),
+        }
+    }
+
+    dropped_events.fetch_add(batch.len() as u64, Ordering::Relaxed);
+}
+
+/// Utility method that escapes the given string to be embedded in a JSON string.
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use crate::{observer::RateLimiterObserver, RateLimitStatus, RequestThrottled, ThrottleReason};
+
+    use super::{NotifierSettings, ThrottleEvent, ThrottleNotifier};
+
+    fn throttled() -> RequestThrottled {
+        RequestThrottled {
+            retry_in: Duration::from_millis(5320),
+            reason: ThrottleReason::QuotaExceeded,
+            status: RateLimitStatus {
+                limit: 100,
+                window_duration: Duration::from_secs(60),
+                used: 101,
+                reset_at: SystemTime::now(),
+            },
+        }
+    }
+
+    /// Waits up to a second for the given condition to hold.
+    fn wait_for(condition: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while !condition() {
+            if Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        true
+    }
+
+    #[test]
+    fn should_encode_events_as_json() {
+        let event = ThrottleEvent {
+            key: "rl:cst_user:\"x\"".to_string(),
+            reason: ThrottleReason::QuotaExceeded,
+            limit: 100,
+            used: 101,
+            retry_in: Duration::from_millis(5320),
+            throttled_at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_760_601_600_000),
+        };
+
+        assert_eq!(
+            ThrottleEvent::to_json(&[event.clone(), event]),
+            r#"[{"key":"rl:cst_user:\"x\"","reason":"QuotaExceeded","limit":100,"used":101,"retry_in_ms":5320,"throttled_at_ms":1760601600000},{"key":"rl:cst_user:\"x\"","reason":"QuotaExceeded","limit":100,"used":101,"retry_in_ms":5320,"throttled_at_ms":1760601600000}]"#
+        );
+    }
+
+    #[test]
+    fn should_deliver_events_in_batches() {
+        let batches = Arc::new(Mutex::new(vec![]));
+        let delivered = batches.clone();
+        let notifier = ThrottleNotifier::new(
+            move |events: &[ThrottleEvent]| {
+                delivered.lock().unwrap().push(events.len());
+                Ok(())
+            },
+            NotifierSettings {
+                max_batch_size: 2,
+                flush_interval: Duration::from_millis(20),
+                ..NotifierSettings::default()
+            },
+        );
+
+        for _ in 0..3 {
+            notifier.on_throttled("rl:ip_1.2.3.4", &throttled(), Duration::ZERO);
+        }
+
+        assert!(wait_for(|| batches.lock().unwrap().len() == 2));
+        assert_eq!(*batches.lock().unwrap(), vec![2, 1]);
+        assert_eq!(notifier.dropped_events(), 0);
+    }
+
+    #[test]
+    fn should_retry_failed_deliveries() {
+        let attempts = Arc::new(Mutex::new(0));
+        let attempted = attempts.clone();
+        let notifier = ThrottleNotifier::new(
+            move |_events: &[ThrottleEvent]| {
+                let mut attempts = attempted.lock().unwrap();
+                *attempts += 1;
+                if *attempts < 3 {
+                    return Err("unavailable".into());
+                }
+                Ok(())
+            },
+            NotifierSettings {
+                flush_interval: Duration::ZERO,
+                retry_backoff: Duration::from_millis(1),
+                ..NotifierSettings::default()
+            },
+        );
+
+        notifier.on_throttled("rl:ip_1.2.3.4", &throttled(), Duration::ZERO);
+
+        assert!(wait_for(|| *attempts.lock().unwrap() == 3));
+        assert_eq!(notifier.dropped_events(), 0);
+    }
+
+    #[test]
+    fn should_count_events_dropped_after_all_attempts_failed() {
+        let notifier = ThrottleNotifier::new(
+            |_events: &[ThrottleEvent]| Err("unavailable".into()),
+            NotifierSettings {
+                flush_interval: Duration::ZERO,
+                max_attempts: 2,
+                retry_backoff: Duration::from_millis(1),
+                ..NotifierSettings::default()
+            },
+        );
+
+        notifier.on_throttled("rl:ip_1.2.3.4", &throttled(), Duration::ZERO);
+
+        assert!(wait_for(|| notifier.dropped_events() == 1));
+    }
+}
@@ -0,0 +1,297 @@
+//! Module that includes the observers notified of the outcome of every check, providing a single
+//! integration point for metrics, logging and alerting without wrapping every call site.
+//!
+//! ```
+//! use std::{
+//!     sync::atomic::{AtomicU64, Ordering},
+//!     time::Duration,
+//! };
+//!
+//! use rate_limiter_rs::{observer::RateLimiterObserver, RequestThrottled};
+//!
+//! #[derive(Default)]
+//! struct ThrottleCounter(AtomicU64);
+//!
+//! impl RateLimiterObserver for ThrottleCounter {
+//!     fn on_throttled(&self, _key: &str, _throttled: &RequestThrottled, _latency: Duration) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//! }
+//! ```
+use std::{sync::Arc, time::Duration};
+
+#[cfg(feature = "redis")]
+use crate::RateLimiterResponse;
+use crate::{errors::RateLimiterError, latency::CheckLatency, RequestAllowed, RequestThrottled};
+
+/// Trait implemented by the observers of a rate limiter, called on every check with the request
+/// key of the checked identifier, the decision and the overall latency of the check. All methods
+/// do nothing by default, so that observers only implement the ones they need.
+///
+/// Observers are called synchronously, on the thread running the check, and should not block.
+pub trait RateLimiterObserver: Send + Sync {
+    /// Called when a request is allowed.
+    fn on_allowed(&self, _key: &str, _allowed: &RequestAllowed, _latency: Duration) {}
+
+    /// Called when a request is throttled.
+    fn on_throttled(&self, _key: &str, _throttled: &RequestThrottled, _latency: Duration) {}
+
+    /// Called when a request could not be checked.
+    fn on_error(&self, _key: &str, _error: &RateLimiterError, _latency: Duration) {}
+
+    /// Called with the time spent against Redis by every check that reached it, split between
+    /// acquiring a connection and running the commands, so that a slow Redis can be told apart
+    /// from a slow application. Called once per master in quorum mode.
+    fn on_redis_latency(&self, _key: &str, _latency: &CheckLatency) {}
+}
+
+/// Shared observers, so that the application can keep reading the state of an observer it hands
+/// to a rate limiter.
+impl<T: RateLimiterObserver + ?Sized> RateLimiterObserver for Arc<T> {
+    fn on_allowed(&self, key: &str, allowed: &RequestAllowed, latency: Duration) {
+        (**self).on_allowed(key, allowed, latency)
+    }
+
+    fn on_throttled(&self, key: &str, throttled: &RequestThrottled, latency: Duration) {
+        (**self).on_throttled(key, throttled, latency)
+    }
+
+    fn on_error(&self, key: &str, error: &RateLimiterError, latency: Duration) {
+        (**self).on_error(key, error, latency)
+    }
+
+    fn on_redis_latency(&self, key: &str, latency: &CheckLatency) {
+        (**self).on_redis_latency(key, latency)
+    }
+}
+
+/// Pairs of observers, both notified of every check, so that several integrations can observe
+/// the same rate limiter, like `(MetricsObserver::new("api"), ThrottleLogger::new(..))`.
+impl<A: RateLimiterObserver, B: RateLimiterObserver> RateLimiterObserver for (A, B) {
+    fn on_allowed(&self, key: &str, allowed: &RequestAllowed, latency: Duration) {
+        self.0.on_allowed(key, allowed, latency);
+        self.1.on_allowed(key, allowed, latency);
+    }
+
+    fn on_throttled(&self, key: &str, throttled: &RequestThrottled, latency: Duration) {
+        self.0.on_throttled(key, throttled, latency);
+        self.1.on_throttled(key, throttled, latency);
+    }
+
+    fn on_error(&self, key: &str, error: &RateLimiterError, latency: Duration) {
+        self.0.on_error(key, error, latency);
+        self.1.on_error(key, error, latency);
+    }
+
+    fn on_redis_latency(&self, key: &str, latency: &CheckLatency) {
+        self.0.on_redis_latency(key, latency);
+        self.1.on_redis_latency(key, latency);
+    }
+}
+
+/// Observer that emits the outcome and latency of every check through the
+/// [metrics](https://docs.rs/metrics) facade, so that they are recorded by whichever exporter the
+/// application installed, like Prometheus, StatsD or OTLP. Requires the `metrics` feature.
+///
+/// The following metrics are emitted, all labelled by the `rate_limiter` name. Request keys are
+/// not used as labels, to keep the cardinality of the metrics bounded:
+/// - `rate_limiter_checks_total`, a counter of checks, also labelled by `decision`, one of
+///   `allowed`, `throttled` or `error`;
+/// - `rate_limiter_check_duration_seconds`, a histogram of the latency of checks;
+/// - `rate_limiter_redis_duration_seconds`, a histogram of the time spent against Redis by checks,
+///   also labelled by `phase`, one of `connect`, `commands` or `total`.
+#[cfg(feature = "metrics")]
+#[derive(Clone, Debug)]
+pub struct MetricsObserver {
+    name: String,
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsObserver {
+    /// Creates an observer labelling the metrics with the given rate limiter name.
+    pub fn new(name: impl Into<String>) -> Self {
+        MetricsObserver { name: name.into() }
+    }
+
+    /// Records a check with the given decision and latency.
+    fn record(&self, decision: &'static str, latency: Duration) {
+        metrics::counter!(
+            "rate_limiter_checks_total",
+            "rate_limiter" => self.name.clone(),
+            "decision" => decision
+        )
+        .increment(1);
+        metrics::histogram!(
+            "rate_limiter_check_duration_seconds",
+            "rate_limiter" => self.name.clone()
+        )
+        .record(latency.as_secs_f64());
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl RateLimiterObserver for MetricsObserver {
+    fn on_allowed(&self, _key: &str, _allowed: &RequestAllowed, latency: Duration) {
+        self.record("allowed", latency)
+    }
+
+    fn on_throttled(&self, _key: &str, _throttled: &RequestThrottled, latency: Duration) {
+        self.record("throttled", latency)
+    }
+
+    fn on_error(&self, _key: &str, _error: &RateLimiterError, latency: Duration) {
+        self.record("error", latency)
+    }
+
+    fn on_redis_latency(&self, _key: &str, latency: &CheckLatency) {
+        for (phase, duration) in [
+            ("connect", latency.connect),
+            ("commands", latency.commands),
+            ("total", latency.total()),
+        ] {
+            metrics::histogram!(
+                "rate_limiter_redis_duration_seconds",
+                "rate_limiter" => self.name.clone(),
+                "phase" => phase
+            )
+            .record(duration.as_secs_f64());
+        }
+    }
+}
+
+/// Notifies the given observer of the outcome of the check of the given key.
+#[cfg(feature = "redis")]
+pub(crate) fn notify(
+    observer: &dyn RateLimiterObserver,
+    key: &str,
+    result: &Result<RateLimiterResponse, RateLimiterError>,
+    latency: Duration,
+) {
+    match result {
+        Ok(RateLimiterResponse::RequestAllowed(allowed)) => {
+            observer.on_allowed(key, allowed, latency)
+        }
+        Ok(RateLimiterResponse::RequestThrottled(throttled)) => {
+            observer.on_throttled(key, throttled, latency)
+        }
+        Err(error) => observer.on_error(key, error, latency),
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+pub(crate) mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        errors::RateLimiterError, latency::CheckLatency, RateLimitStatus, RateLimiterResponse,
+        RequestAllowed, RequestThrottled, ThrottleReason,
+    };
+
+    use super::{notify, RateLimiterObserver};
+
+    /// Observer recording the decisions it's notified of, as `allowed`, `throttled` or `error`,
+    /// and the Redis latencies
+    #[derive(Default)]
+    pub(crate) struct RecordingObserver {
+        pub(crate) decisions: Mutex<Vec<(String, &'static str)>>,
+        pub(crate) redis_latencies: Mutex<Vec<CheckLatency>>,
+    }
+
+    impl RateLimiterObserver for RecordingObserver {
+        fn on_allowed(&self, key: &str, _allowed: &RequestAllowed, _latency: Duration) {
+            self.decisions
+                .lock()
+                .unwrap()
+                .push((key.to_string(), "allowed"));
+        }
+
+        fn on_throttled(&self, key: &str, _throttled: &RequestThrottled, _latency: Duration) {
+            self.decisions
+                .lock()
+                .unwrap()
+                .push((key.to_string(), "throttled"));
+        }
+
+        fn on_error(&self, key: &str, _error: &RateLimiterError, _latency: Duration) {
+            self.decisions
+                .lock()
+                .unwrap()
+                .push((key.to_string(), "error"));
+        }
+
+        fn on_redis_latency(&self, _key: &str, latency: &CheckLatency) {
+            self.redis_latencies.lock().unwrap().push(*latency);
+        }
+    }
+
+    #[test]
+    fn should_notify_observer_of_each_outcome() {
+        let observer = RecordingObserver::default();
+        let status = || RateLimitStatus {
+            limit: 1,
+            window_duration: Duration::from_secs(60),
+            used: 1,
+            reset_at: SystemTime::now(),
+        };
+
+        notify(
+            &observer,
+            "rl:a",
+            &Ok(RateLimiterResponse::RequestAllowed(RequestAllowed {
+                remaining_request_counter: 0,
+                status: status(),
+            })),
+            Duration::ZERO,
+        );
+        notify(
+            &observer,
+            "rl:b",
+            &Ok(RateLimiterResponse::RequestThrottled(RequestThrottled {
+                retry_in: Duration::from_secs(1),
+                reason: ThrottleReason::QuotaExceeded,
+                status: status(),
+            })),
+            Duration::ZERO,
+        );
+        notify(
+            &observer,
+            "rl:c",
+            &Err(RateLimiterError::ComputeError),
+            Duration::ZERO,
+        );
+
+        assert_eq!(
+            *observer.decisions.lock().unwrap(),
+            vec![
+                ("rl:a".to_string(), "allowed"),
+                ("rl:b".to_string(), "throttled"),
+                ("rl:c".to_string(), "error"),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_notify_both_observers_of_a_pair() {
+        let observers = (
+            Arc::new(RecordingObserver::default()),
+            Arc::new(RecordingObserver::default()),
+        );
+
+        notify(
+            &observers,
+            "rl:a",
+            &Err(RateLimiterError::ComputeError),
+            Duration::ZERO,
+        );
+
+        for observer in [&observers.0, &observers.1] {
+            assert_eq!(
+                *observer.decisions.lock().unwrap(),
+                vec![("rl:a".to_string(), "error")]
+            );
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Module that includes the optional tracking of the most throttled request identifiers, so that
+//! operators can quickly see which clients are hammering the service.
+//!
+//! ## Implementation details
+//!
+//! Every throttled request increments the score of its request key in a sorted set holding the
+//! throttles of the current period, aligned to the clock:
+//!
+//! ```text
+//! rl:offenders:480793
+//! ```
+//!
+//! Each sorted set expires one period after its end, so that the previous period can still be
+//! read. The throttles over the last rolling period are then approximated by adding the
+//! throttles of the current period to the ones of the previous period, weighted by the portion
+//! of the previous period still within the rolling one. Only the top offenders of each period
+//! are read, so an identifier just out of the top of both periods might be missed.
+#[cfg(feature = "redis")]
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{
+    errors::RateLimiterError,
+    rate_limiters::{as_expiry_millis, AlignedWindow},
+};
+
+/// The prefix of the sorted sets holding the throttles of every period
+#[cfg(feature = "redis")]
+const OFFENDERS_PREFIX: &str = "rl:offenders";
+
+/// The request keys of a sorted set of throttles, with their scores
+#[cfg(feature = "redis")]
+type ScoredKeys = Vec<(String, f64)>;
+
+/// Represents the tracking of the most throttled request identifiers over a rolling period
+#[cfg(feature = "redis")]
+#[derive(Clone, Debug)]
+pub struct OffenderTracking {
+    /// The rolling period throttles are counted over
+    pub period: Duration,
+}
+
+/// Represents a request identifier among the most throttled ones
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Offender {
+    /// The request key of the identifier, like `rl:ip_172.28.0.6`
+    pub key: String,
+
+    /// The approximate number of requests throttled over the rolling period
+    pub throttled_requests: u64,
+}
+
+#[cfg(feature = "redis")]
+impl OffenderTracking {
+    /// Counts a throttled request of the given key in the period including the given time.
+    pub(crate) fn record_throttle(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<(), RateLimiterError> {
+        let window = AlignedWindow::current(self.period, now)?;
+        let offenders_key = offenders_key(window.index);
+
+        redis::pipe()
+            .cmd("ZINCRBY")
+            .arg(&offenders_key)
+            .arg(1)
+            .arg(key)
+            .ignore()
+            .cmd("PEXPIREAT")
+            .arg(&offenders_key)
+            .arg(window.end_millis + as_expiry_millis(self.period))
+            .ignore()
+            .query::<()>(con)?;
+        Ok(())
+    }
+
+    /// Returns the throttles over the rolling period ending at the given time of the top given
+    /// number of offenders of the current and previous periods.
+    pub(crate) fn throttles(
+        &self,
+        con: &mut Connection,
+        n: usize,
+        now: SystemTime,
+    ) -> Result<HashMap<String, f64>, RateLimiterError> {
+        if n == 0 {
+            return Ok(HashMap::new());
+        }
+
+        let window = AlignedWindow::current(self.period, now)?;
+        let (current, previous): (ScoredKeys, ScoredKeys) = redis::pipe()
+            .cmd("ZREVRANGE")
+            .arg(offenders_key(window.index))
+            .arg(0)
+            .arg(n - 1)
+            .arg("WITHSCORES")
+            .cmd("ZREVRANGE")
+            .arg(offenders_key(window.index.saturating_sub(1)))
+            .arg(0)
+            .arg(n - 1)
+            .arg("WITHSCORES")
+            .query(con)?;
+
+        let previous_weight = window.expire_in_millis as f64 / as_expiry_millis(self.period) as f64;
+        let mut throttles: HashMap<String, f64> = current.into_iter().collect();
+        for (key, score) in previous {
+            *throttles.entry(key).or_default() += score * previous_weight;
+        }
+        Ok(throttles)
+    }
+
+    /// Forgets the throttles of the given key, in the current and previous periods.
+    pub(crate) fn forget(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<(), RateLimiterError> {
+        let window = AlignedWindow::current(self.period, now)?;
+
+        redis::pipe()
+            .cmd("ZREM")
+            .arg(offenders_key(window.index))
+            .arg(key)
+            .ignore()
+            .cmd("ZREM")
+            .arg(offenders_key(window.index.saturating_sub(1)))
+            .arg(key)
+            .ignore()
+            .query::<()>(con)?;
+        Ok(())
+    }
+}
+
+/// Utility method that returns the top given number of offenders, out of the throttles read from
+/// each Redis server. Throttles of the same key read from several servers, like a quorum of
+/// masters, are not added up, and the highest is kept instead.
+#[cfg(feature = "redis")]
+pub(crate) fn top_offenders(throttles: Vec<HashMap<String, f64>>, n: usize) -> Vec<Offender> {
+    let mut merged: HashMap<String, f64> = HashMap::new();
+    for (key, score) in throttles.into_iter().flatten() {
+        let merged_score = merged.entry(key).or_default();
+        *merged_score = merged_score.max(score);
+    }
+
+    let mut offenders: Vec<(String, f64)> = merged.into_iter().collect();
+    offenders.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    offenders
+        .into_iter()
+        .take(n)
+        .map(|(key, score)| Offender {
+            key,
+            throttled_requests: score.round() as u64,
+        })
+        .collect()
+}
+
+/// Utility method that returns the key of the sorted set holding the throttles of the given period.
+#[cfg(feature = "redis")]
+fn offenders_key(window_index: u64) -> String {
+    format!("{}:{}", OFFENDERS_PREFIX, window_index)
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{offenders_key, top_offenders, Offender};
+
+    #[test]
+    fn should_build_offenders_key() {
+        assert_eq!(offenders_key(42), "rl:offenders:42")
+    }
+
+    #[test]
+    fn should_rank_offenders_keeping_highest_throttles_per_key() {
+        let throttles = vec![
+            HashMap::from([("rl:ip_1".to_string(), 3.0), ("rl:ip_2".to_string(), 5.4)]),
+            HashMap::from([("rl:ip_1".to_string(), 4.0), ("rl:ip_3".to_string(), 1.0)]),
+        ];
+
+        assert_eq!(
+            top_offenders(throttles, 2),
+            vec![
+                Offender {
+                    key: "rl:ip_2".to_string(),
+                    throttled_requests: 5
+                },
+                Offender {
+                    key: "rl:ip_1".to_string(),
+                    throttled_requests: 4
+                },
+            ]
+        )
+    }
+}
@@ -0,0 +1,158 @@
+//! Module that includes the onboarding policy applied to newly seen request identifiers.
+//!
+//! ## Implementation details
+//!
+//! When a request identifier is checked for the first time, the current timestamp is stored
+//! in Redis under a dedicated `<request key>:first_seen` key. From that moment on, the limit
+//! granted to the identifier starts at a fraction of the configured one, and linearly grows
+//! to 100% over the configured ramp duration. This protects the platform from brand-new API
+//! keys instantly maxing out their plan.
+//!
+//! The first seen key expires after the ramp duration plus the window duration, and its expiry
+//! is pushed back on every check, so that the keys of the identifiers that stopped sending
+//! requests, like the IP addresses of one-off clients, are eventually removed, while the ramp
+//! never restarts for the identifiers still active. An identifier idle for longer than that is
+//! onboarded again when it comes back.
+use std::time::{Duration, SystemTime};
+
+use redis::Connection;
+
+use crate::{errors::RateLimiterError, rate_limiters::as_expiry_millis};
+
+/// The names of the Redis commands run to track the first seen time of a request identifier
+pub(crate) const ONBOARDING_COMMAND_NAMES: &[&str] = &["SET", "PEXPIRE", "GET"];
+
+/// Represents the onboarding ramp applied to newly seen request identifiers
+#[derive(Clone, Debug)]
+pub struct OnboardingRamp {
+    /// The fraction of the configured limit granted to a request identifier when first seen.
+    /// Expected to be in the `0.0..=1.0` range.
+    pub initial_fraction: f64,
+
+    /// How long it takes for a newly seen request identifier to be granted the full limit
+    pub ramp_duration: Duration,
+}
+
+impl OnboardingRamp {
+    /// Computes the limit granted to a request identifier first seen `elapsed` time ago.
+    /// At least one request is always allowed, to not lock out new identifiers entirely.
+    pub fn effective_limit(&self, limit: u64, elapsed: Duration) -> u64 {
+        if self.ramp_duration.is_zero() || elapsed >= self.ramp_duration {
+            return limit;
+        }
+
+        let initial_fraction = self.initial_fraction.clamp(0.0, 1.0);
+        let progress = elapsed.as_secs_f64() / self.ramp_duration.as_secs_f64();
+        let fraction = initial_fraction + (1.0 - initial_fraction) * progress;
+
+        ((limit as f64 * fraction).floor() as u64).clamp(1, limit.max(1))
+    }
+
+    /// Returns how long before the given time the given request key was first seen, storing the
+    /// given time as first seen time if the key was never seen before. The first seen time is
+    /// kept for the ramp duration plus the given window duration since the last check.
+    pub(crate) fn elapsed_since_first_seen(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        window_duration: Duration,
+        now: SystemTime,
+    ) -> Result<Duration, RateLimiterError> {
+        let first_seen_key = first_seen_key(key);
+        let now_epoch_millis = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_e| RateLimiterError::ComputeError)?
+            .as_millis() as u64;
+
+        let (first_seen_epoch_millis,): (u64,) = redis::pipe()
+            .cmd("SET")
+            .arg(&first_seen_key)
+            .arg(now_epoch_millis)
+            .arg("NX")
+            .ignore()
+            .cmd("PEXPIRE")
+            .arg(&first_seen_key)
+            .arg(as_expiry_millis(
+                self.ramp_duration.saturating_add(window_duration),
+            ))
+            .ignore()
+            .cmd("GET")
+            .arg(&first_seen_key)
+            .query(con)?;
+
+        Ok(Duration::from_millis(
+            now_epoch_millis.saturating_sub(first_seen_epoch_millis),
+        ))
+    }
+
+    /// Returns how long before the given time the given request key was first seen, without
+    /// storing anything. Request keys never seen are considered just seen, as they will be on
+    /// their first check.
+    pub(crate) fn peek_elapsed_since_first_seen(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<Duration, RateLimiterError> {
+        let now_epoch_millis = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_e| RateLimiterError::ComputeError)?
+            .as_millis() as u64;
+
+        let first_seen_epoch_millis: Option<u64> =
+            redis::cmd("GET").arg(first_seen_key(key)).query(con)?;
+
+        Ok(Duration::from_millis(
+            first_seen_epoch_millis
+                .map_or(0, |first_seen| now_epoch_millis.saturating_sub(first_seen)),
+        ))
+    }
+}
+
+/// Utility method that returns the key holding the first seen timestamp of the given request key.
+pub(crate) fn first_seen_key(key: &str) -> String {
+    format!("{}:first_seen", key)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{first_seen_key, OnboardingRamp};
+
+    const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[rstest]
+    #[case::first_seen(Duration::ZERO, 10)]
+    #[case::half_way(ONE_DAY * 5, 55)]
+    #[case::completed(ONE_DAY * 10, 100)]
+    #[case::onboarded(ONE_DAY * 30, 100)]
+    fn should_ramp_limit_linearly(#[case] elapsed: Duration, #[case] expected_limit: u64) {
+        let onboarding_ramp = OnboardingRamp {
+            initial_fraction: 0.1,
+            ramp_duration: ONE_DAY * 10,
+        };
+
+        assert_eq!(
+            onboarding_ramp.effective_limit(100, elapsed),
+            expected_limit
+        )
+    }
+
+    #[test]
+    fn should_always_allow_at_least_one_request() {
+        let onboarding_ramp = OnboardingRamp {
+            initial_fraction: 0.0,
+            ramp_duration: ONE_DAY,
+        };
+
+        assert_eq!(onboarding_ramp.effective_limit(5, Duration::ZERO), 1)
+    }
+
+    #[test]
+    fn should_build_first_seen_key() {
+        assert_eq!(first_seen_key("rl:ip_1.2.3.4"), "rl:ip_1.2.3.4:first_seen")
+    }
+}
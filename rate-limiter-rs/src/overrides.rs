@@ -0,0 +1,108 @@
+//! Module that includes the per-key limit overrides, that let operators grant bespoke limits to
+//! specific request identifiers at runtime, without redeploying.
+//!
+//! ## Implementation details
+//!
+//! Overrides are stored in Redis, in a `rl:override:<request key>` hash with the optional
+//! `window_size` and `window_duration_millis` fields. When limit overrides are enabled on a
+//! rate limiter, the hash is read on every check and its fields take precedence over the
+//! configured limits. Missing fields fall back to the configured limits.
+//!
+//! Overrides can be managed with [RedisAdmin::set_limit_override](../trait.RedisAdmin.html#tymethod.set_limit_override)
+//! or directly in Redis, like:
+//!
+//! ```text
+//! HSET rl:override:ip_1.2.3.4 window_size 1000
+//! ```
+use std::time::Duration;
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::errors::RateLimiterError;
+
+/// The names of the Redis commands run to read the limit override of a request identifier
+#[cfg(feature = "redis")]
+pub(crate) const OVERRIDE_COMMAND_NAMES: &[&str] = &["HMGET"];
+
+/// Represents the limits granted to a specific request identifier, overriding the configured ones
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimitOverride {
+    /// The window size granted to the request identifier, if overridden
+    pub window_size: Option<u64>,
+    /// The window duration granted to the request identifier, if overridden
+    pub window_duration: Option<Duration>,
+}
+
+/// Reads the limit override of the given request key. Returns an empty override if none is set.
+#[cfg(feature = "redis")]
+pub(crate) fn read_override(
+    con: &mut Connection,
+    key: &str,
+) -> Result<LimitOverride, RateLimiterError> {
+    let (window_size, window_duration_millis): (Option<u64>, Option<u64>) = redis::cmd("HMGET")
+        .arg(override_key(key))
+        .arg("window_size")
+        .arg("window_duration_millis")
+        .query(con)?;
+
+    Ok(LimitOverride {
+        window_size,
+        window_duration: window_duration_millis.map(Duration::from_millis),
+    })
+}
+
+/// Stores the given limit override for the given request key, replacing any previous one.
+#[cfg(feature = "redis")]
+pub(crate) fn write_override(
+    con: &mut Connection,
+    key: &str,
+    limit_override: &LimitOverride,
+) -> Result<(), RateLimiterError> {
+    let override_key = override_key(key);
+    let mut pipe = redis::pipe();
+    pipe.atomic().cmd("DEL").arg(&override_key).ignore();
+
+    let mut fields: Vec<(&str, u64)> = vec![];
+    if let Some(window_size) = limit_override.window_size {
+        fields.push(("window_size", window_size));
+    }
+    if let Some(window_duration) = limit_override.window_duration {
+        fields.push(("window_duration_millis", window_duration.as_millis() as u64));
+    }
+    if !fields.is_empty() {
+        pipe.cmd("HSET").arg(&override_key).arg(fields).ignore();
+    }
+
+    pipe.query::<()>(con)?;
+    Ok(())
+}
+
+/// Deletes the limit override of the given request key. Returns whether an override was set.
+#[cfg(feature = "redis")]
+pub(crate) fn delete_override(con: &mut Connection, key: &str) -> Result<bool, RateLimiterError> {
+    let deleted_keys: u64 = redis::cmd("DEL").arg(override_key(key)).query(con)?;
+    Ok(deleted_keys > 0)
+}
+
+/// Utility method that returns the key holding the limit override of the given request key.
+#[cfg(feature = "redis")]
+pub(crate) fn override_key(key: &str) -> String {
+    format!("rl:override:{}", key.strip_prefix("rl:").unwrap_or(key))
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use super::override_key;
+
+    #[test]
+    fn should_build_override_key() {
+        assert_eq!(override_key("rl:ip_1.2.3.4"), "rl:override:ip_1.2.3.4");
+        assert_eq!(
+            override_key("rl:cst_id:dili91"),
+            "rl:override:cst_id:dili91"
+        )
+    }
+}
@@ -0,0 +1,225 @@
+//! Module that includes the parser of rate limiting policies expressed as strings, so that limits
+//! can be easily defined in configuration files and environment variables.
+//!
+//! ## Syntax
+//!
+//! A policy is made of a limit and a period, separated either by `/` or by `per`, optionally
+//! followed by a burst and by a scope:
+//!
+//! ```text
+//! 100/min
+//! 10 per second burst 20
+//! 100/minute per ip
+//! 20/500ms
+//! 1000 per 1h30m
+//! ```
+//!
+//! Supported periods are `s`/`sec`/`second`, `m`/`min`/`minute`, `h`/`hour` and `d`/`day`,
+//! singular or plural, or any [duration](crate::durations) like `500ms` or `2m`.
+//!
+//! The burst is the maximum number of requests allowed at once: a policy like
+//! `10 per second burst 20` grants 20 requests every 2 seconds, keeping the same average rate
+//! while tolerating spikes. Without a burst, the limit itself is used.
+//!
+//! The scope is informational only, as the request identifier is chosen when checking a request.
+use std::{str::FromStr, time::Duration};
+
+use crate::{
+    durations::{parse_duration, unit_duration},
+    errors::RateLimiterError,
+};
+
+/// Represents a rate limiting policy parsed from a string
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    /// the number of requests allowed in a period, on average
+    pub limit: u64,
+    /// the period the limit refers to
+    pub period: Duration,
+    /// the maximum number of requests allowed at once, if different from the limit
+    pub burst: Option<u64>,
+    /// the scope of the policy, like `ip`
+    pub scope: Option<String>,
+}
+
+impl Policy {
+    /// Returns the number of requests allowed in a single window
+    pub fn window_size(&self) -> u64 {
+        self.burst.unwrap_or(self.limit)
+    }
+
+    /// Returns the duration of a single window, stretched or shrunk according to the burst
+    /// so that the average rate always matches the limit. Windows too long to be represented,
+    /// which parsed policies are checked against, are capped to the longest one.
+    pub fn window_duration(&self) -> Duration {
+        scaled_window(self.period, self.window_size(), self.limit)
+            .unwrap_or(Duration::from_nanos(u64::MAX))
+    }
+}
+
+/// Utility method that returns the given period scaled by the given window size over the given
+/// limit, if the result fits in a duration of up to `u64::MAX` nanoseconds.
+fn scaled_window(period: Duration, window_size: u64, limit: u64) -> Option<Duration> {
+    let window_nanos = period
+        .as_nanos()
+        .checked_mul(u128::from(window_size))?
+        .checked_div(u128::from(limit))?;
+
+    u64::try_from(window_nanos).ok().map(Duration::from_nanos)
+}
+
+impl FromStr for Policy {
+    type Err = RateLimiterError;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        let normalized_policy = policy.to_lowercase().replace('/', " / ");
+        let mut tokens = normalized_policy.split_whitespace();
+
+        let limit = parse_count(tokens.next(), policy)?;
+        match tokens.next() {
+            Some("/") | Some("per") => {}
+            _ => {
+                return Err(invalid_policy(
+                    policy,
+                    "expected '/' or 'per' after the limit",
+                ))
+            }
+        }
+        let period = parse_period(tokens.next(), policy)?;
+
+        let mut burst = None;
+        let mut scope = None;
+        while let Some(token) = tokens.next() {
+            match token {
+                "burst" if burst.is_none() => burst = Some(parse_count(tokens.next(), policy)?),
+                "per" if scope.is_none() => {
+                    scope = Some(
+                        tokens
+                            .next()
+                            .ok_or_else(|| invalid_policy(policy, "missing scope after 'per'"))?
+                            .to_string(),
+                    )
+                }
+                _ => return Err(invalid_policy(policy, "unexpected token")),
+            }
+        }
+
+        if scaled_window(period, burst.unwrap_or(limit), limit).is_none() {
+            return Err(invalid_policy(
+                policy,
+                "the window of the burst is too long",
+            ));
+        }
+
+        Ok(Policy {
+            limit,
+            period,
+            burst,
+            scope,
+        })
+    }
+}
+
+/// Utility method that parses a strictly positive number of requests
+fn parse_count(token: Option<&str>, policy: &str) -> Result<u64, RateLimiterError> {
+    match token.map(str::parse::<u64>) {
+        Some(Ok(count)) if count > 0 => Ok(count),
+        _ => Err(invalid_policy(
+            policy,
+            "expected a positive number of requests",
+        )),
+    }
+}
+
+/// Utility method that parses a period, either a unit like `min`, or a duration like `500ms`
+fn parse_period(token: Option<&str>, policy: &str) -> Result<Duration, RateLimiterError> {
+    match token.and_then(|token| unit_duration(token).or_else(|| parse_duration(token).ok())) {
+        Some(period) if !period.is_zero() => Ok(period),
+        _ => Err(invalid_policy(
+            policy,
+            "expected a period like 'second', 'min' or '500ms'",
+        )),
+    }
+}
+
+/// Utility method that builds the error returned for an invalid policy
+fn invalid_policy(policy: &str, reason: &str) -> RateLimiterError {
+    RateLimiterError::PolicyError(format!("{}: {}", reason, policy))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::Policy;
+    use crate::errors::RateLimiterError;
+
+    #[rstest]
+    #[case::slash("100/min", 100, Duration::from_secs(60), None)]
+    #[case::per("10 per second", 10, Duration::from_secs(1), None)]
+    #[case::burst("10 per second burst 20", 10, Duration::from_secs(1), Some(20))]
+    #[case::scope("100/minute per ip", 100, Duration::from_secs(60), None)]
+    #[case::uppercase("5000 / Hours", 5000, Duration::from_secs(3600), None)]
+    #[case::duration("20/500ms", 20, Duration::from_millis(500), None)]
+    #[case::combined_duration("1000 per 1h30m", 1000, Duration::from_secs(5400), None)]
+    fn should_parse_policy(
+        #[case] policy: &str,
+        #[case] expected_limit: u64,
+        #[case] expected_period: Duration,
+        #[case] expected_burst: Option<u64>,
+    ) {
+        let policy: Policy = policy.parse().unwrap();
+
+        assert_eq!(policy.limit, expected_limit);
+        assert_eq!(policy.period, expected_period);
+        assert_eq!(policy.burst, expected_burst);
+    }
+
+    #[test]
+    fn should_parse_policy_scope() {
+        let policy: Policy = "100/minute burst 200 per ip".parse().unwrap();
+
+        assert_eq!(policy.scope.as_deref(), Some("ip"))
+    }
+
+    #[rstest]
+    #[case::empty("")]
+    #[case::zero_limit("0/min")]
+    #[case::missing_period("100/")]
+    #[case::unknown_period("100/fortnight")]
+    #[case::zero_period("100/0s")]
+    #[case::missing_separator("100 min")]
+    #[case::missing_burst("10 per second burst")]
+    #[case::trailing_garbage("10 per second please")]
+    fn should_reject_invalid_policy(#[case] policy: &str) {
+        let err = policy.parse::<Policy>().unwrap_err();
+
+        assert!(matches!(err, RateLimiterError::PolicyError(_)))
+    }
+
+    #[rstest]
+    #[case::largest_burst("1/100000d burst 18446744073709551615")]
+    #[case::large_burst("1/1d burst 1000000")]
+    fn should_reject_policy_with_too_long_window(#[case] policy: &str) {
+        let err = policy.parse::<Policy>().unwrap_err();
+
+        assert!(matches!(err, RateLimiterError::PolicyError(reason) if reason.contains("too long")))
+    }
+
+    #[rstest]
+    #[case::no_burst("100/min", 100, Duration::from_secs(60))]
+    #[case::burst("10 per second burst 20", 20, Duration::from_secs(2))]
+    #[case::small_burst("60/min burst 1", 1, Duration::from_secs(1))]
+    fn should_compute_window(
+        #[case] policy: &str,
+        #[case] expected_window_size: u64,
+        #[case] expected_window_duration: Duration,
+    ) {
+        let policy: Policy = policy.parse().unwrap();
+
+        assert_eq!(policy.window_size(), expected_window_size);
+        assert_eq!(policy.window_duration(), expected_window_duration);
+    }
+}
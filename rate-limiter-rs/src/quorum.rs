@@ -0,0 +1,616 @@
+//! Module that includes the checks applied against a quorum of independent Redis masters, in the
+//! style of [Redlock](https://redis.io/docs/latest/develop/use/patterns/distributed-locks/), so that
+//! rate limiting survives the loss of any minority of the masters without Sentinel or Cluster.
+//!
+//! ## Implementation details
+//!
+//! Every operation is run against all the masters, and succeeds as long as a majority of them,
+//! the quorum, answers. A request is allowed only if a quorum of the masters allows it, so that
+//! the decision never depends on a single master. As each master keeps its own counters, a master
+//! that was unreachable, or restarted, catches up within a window.
+//!
+//! Checks run against all the masters in parallel, on a fixed pool of worker threads per master,
+//! spawned once by the builder and shared by the clones of the rate limiter, so that no thread is
+//! spawned per check. The workers of a master run up to [WORKERS_PER_MASTER] checks against it at
+//! once, and queue up to [QUEUED_CHECKS_PER_MASTER] more. Once its queue is full, checks fail that
+//! master right away instead of waiting for it, so that a master that stopped answering only
+//! holds up its own workers, never the checks against the other masters. The calling thread only
+//! waits for the results, so that the timeout bounds the whole check. The administrative
+//! operations, which are rare, run against every master in turn on the calling thread.
+//!
+//! A check returns as soon as the decision of a quorum of the masters is known, without waiting
+//! for the other ones: as soon as a quorum allowed the request, or as soon as a quorum answered
+//! and enough masters throttled it that no quorum can allow it. While the early responses
+//! disagree, the check waits for the other masters, and gives up on the ones that didn't answer
+//! within the timeout, counting them as failed. A request that no quorum of the masters that
+//! answered in time allowed is throttled. The connections to the masters time out too, so that
+//! the workers running the checks against an unresponsive master are eventually freed.
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use redis::{ErrorKind, RedisError};
+
+use crate::{errors::RateLimiterError, sharding::Shard, RateLimiterResponse};
+
+/// The number of workers per master, bounding the number of checks run at once against each of
+/// them
+pub(crate) const WORKERS_PER_MASTER: usize = 8;
+
+/// The number of checks queued per master while all its workers are busy, beyond which checks
+/// fail that master right away
+pub(crate) const QUEUED_CHECKS_PER_MASTER: usize = 64;
+
+/// Represents a job run by the workers of a master
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Represents the fixed pools of worker threads running the checks against each of the masters.
+/// Clones share the same workers, which stop once all the clones are dropped.
+#[derive(Clone, Default)]
+pub(crate) struct QuorumWorkers {
+    /// The bounded queue of the jobs of the workers of each master, if any was spawned
+    queues: Arc<[Option<mpsc::SyncSender<Job>>]>,
+    /// The time the masters have to answer
+    timeout: Duration,
+}
+
+impl QuorumWorkers {
+    /// Spawns the workers checking requests against the given number of masters, which have the
+    /// given time to answer.
+    pub(crate) fn new(masters: usize, timeout: Duration) -> Self {
+        QuorumWorkers {
+            queues: (0..masters).map(spawn_workers).collect(),
+            timeout,
+        }
+    }
+
+    /// Runs the given job on a worker of the given master, or on the calling thread if none
+    /// could be spawned. Returns the job back if the queue of the master is full.
+    fn run(&self, master: usize, job: Job) -> Result<(), Job> {
+        match self.queues.get(master) {
+            Some(Some(queue)) => match queue.try_send(job) {
+                Ok(()) => Ok(()),
+                Err(mpsc::TrySendError::Full(job)) => Err(job),
+                Err(mpsc::TrySendError::Disconnected(job)) => {
+                    job();
+                    Ok(())
+                }
+            },
+            _ => {
+                job();
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks a request against all the given masters in parallel, until the decision of a
+    /// quorum of them is known or the timeout elapsed. Returns as soon as a quorum allowed the
+    /// request, or as soon as a quorum answered and enough of them throttled it that no quorum can
+    /// allow it, waiting for the other masters while the early responses disagree. Returns the
+    /// combined responses, or the error of one of the failed masters if fewer than a quorum
+    /// answered.
+    pub(crate) fn check_on_quorum(
+        &self,
+        masters: &Arc<[Shard]>,
+        op: impl Fn(&Shard) -> Result<RateLimiterResponse, RateLimiterError> + Send + Sync + 'static,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        let (count, quorum) = (masters.len(), quorum(masters.len()));
+        let responses = self.on_masters_until(masters, op, |results| {
+            let succeeded = results.iter().filter(|result| result.is_ok()).count();
+            let allowed = results
+                .iter()
+                .filter(|result| matches!(result, Ok(RateLimiterResponse::RequestAllowed(_))))
+                .count();
+            let pending = count - results.len();
+            // a throttled request still needs a quorum of answers, unless it can't be reached
+            allowed >= quorum
+                || (allowed + pending < quorum && succeeded >= quorum)
+                || succeeded + pending < quorum
+        })?;
+        combine_responses(responses, count)
+    }
+
+    /// Runs the given operation against all the given masters in parallel, on their workers,
+    /// until the results received so far settle the outcome, every master answered, or the
+    /// timeout elapsed. Masters whose queue is full fail right away.
+    fn on_masters_until<T: Send + 'static>(
+        &self,
+        masters: &Arc<[Shard]>,
+        op: impl Fn(&Shard) -> Result<T, RateLimiterError> + Send + Sync + 'static,
+        settled: impl Fn(&[Result<T, RateLimiterError>]) -> bool,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        let deadline = Instant::now() + self.timeout;
+        let op = Arc::new(op);
+        let (sender, receiver) = mpsc::channel();
+        for index in 0..masters.len() {
+            let (op, masters, results) = (op.clone(), masters.clone(), sender.clone());
+            let job = Box::new(move || {
+                // the check is dropped along with the sender if it panics, failing its master
+                let _ = results.send(op(&masters[index]));
+            });
+            if self.run(index, job).is_err() {
+                let _ = sender.send(Err(RedisError::from((
+                    ErrorKind::IoError,
+                    "too many checks queued for the quorum master",
+                ))
+                .into()));
+            }
+        }
+        drop(sender);
+
+        let mut results = vec![];
+        while !settled(&results) {
+            match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(result) => results.push(result),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    results.push(Err(RedisError::from((
+                        ErrorKind::IoError,
+                        "quorum masters didn't answer in time",
+                    ))
+                    .into()));
+                    break;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        quorum_results(masters.len(), results)
+    }
+}
+
+/// Spawns the workers checking requests against the master with the given index. Returns their
+/// bounded queue, unless none of them could be spawned.
+fn spawn_workers(master: usize) -> Option<mpsc::SyncSender<Job>> {
+    let (jobs, queue) = mpsc::sync_channel::<Job>(QUEUED_CHECKS_PER_MASTER);
+    let queue = Arc::new(Mutex::new(queue));
+    let mut spawned = 0;
+    for _ in 0..WORKERS_PER_MASTER {
+        let queue = queue.clone();
+        let worker = thread::Builder::new()
+            .name(format!("rate-limiter-quorum-{}", master))
+            .spawn(move || loop {
+                let job = queue.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                match job {
+                    // a panicking check only fails its own master
+                    Ok(job) => drop(panic::catch_unwind(AssertUnwindSafe(job))),
+                    Err(_) => return,
+                }
+            });
+        spawned += usize::from(worker.is_ok());
+    }
+
+    (spawned > 0).then_some(jobs)
+}
+
+/// Utility method that returns the minimum number of masters that must agree, out of the given ones.
+pub(crate) fn quorum(masters: usize) -> usize {
+    masters / 2 + 1
+}
+
+/// Runs the given operation against all the given masters, in turn. Returns the results of the
+/// masters that succeeded, or the error of one of the failed masters if fewer than a quorum did.
+pub(crate) fn on_quorum<T>(
+    masters: &[Shard],
+    op: impl Fn(&Shard) -> Result<T, RateLimiterError>,
+) -> Result<Vec<T>, RateLimiterError> {
+    quorum_results(masters.len(), masters.iter().map(op).collect())
+}
+
+/// Utility method that returns the results of the masters that succeeded, out of the results of
+/// the given number of masters, or the error of one of the failed masters if fewer than a quorum
+/// did. Masters without a result, like the ones whose operation panicked, count as failed.
+fn quorum_results<T>(
+    masters: usize,
+    results: Vec<Result<T, RateLimiterError>>,
+) -> Result<Vec<T>, RateLimiterError> {
+    let (succeeded, failed): (Vec<_>, Vec<_>) = results.into_iter().partition(Result::is_ok);
+    if succeeded.len() < quorum(masters) {
+        return Err(failed
+            .into_iter()
+            .find_map(Result::err)
+            .unwrap_or(RateLimiterError::ComputeError));
+    }
+    Ok(succeeded.into_iter().filter_map(Result::ok).collect())
+}
+
+/// Utility method that combines the responses of a quorum of masters to the same check. The
+/// request is allowed if a quorum of the given masters allowed it, reporting the fewest remaining
+/// requests. Otherwise it's throttled, reporting the longest time to wait before retrying.
+/// Returns an error if there is no response to combine.
+pub(crate) fn combine_responses(
+    responses: Vec<RateLimiterResponse>,
+    masters: usize,
+) -> Result<RateLimiterResponse, RateLimiterError> {
+    let (allowed, throttled): (Vec<_>, Vec<_>) = responses
+        .into_iter()
+        .partition(|response| matches!(response, RateLimiterResponse::RequestAllowed(_)));
+
+    let combined = if allowed.len() >= quorum(masters) {
+        allowed.into_iter().min_by_key(|response| match response {
+            RateLimiterResponse::RequestAllowed(allowed) => allowed.remaining_request_counter,
+            RateLimiterResponse::RequestThrottled(_) => u64::MAX,
+        })
+    } else {
+        throttled.into_iter().max_by_key(|response| match response {
+            RateLimiterResponse::RequestThrottled(throttled) => throttled.retry_in,
+            RateLimiterResponse::RequestAllowed(_) => Default::default(),
+        })
+    };
+
+    combined.ok_or(RateLimiterError::ComputeError)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        sync::{mpsc, Arc, Mutex},
+        thread::{self, ThreadId},
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use redis::Client as RedisClient;
+    use rstest::rstest;
+
+    use crate::{
+        connection::ConnectionPool, errors::RateLimiterError, sharding::Shard, RateLimitStatus,
+        RateLimiterResponse, RequestAllowed, RequestThrottled, ThrottleReason,
+    };
+
+    use super::{
+        combine_responses, on_quorum, quorum, QuorumWorkers, QUEUED_CHECKS_PER_MASTER,
+        WORKERS_PER_MASTER,
+    };
+
+    fn on_quorum_of<T: Send + 'static>(
+        quorum_workers: &QuorumWorkers,
+        masters: &Arc<[Shard]>,
+        op: impl Fn(&Shard) -> Result<T, RateLimiterError> + Send + Sync + 'static,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        let quorum = quorum(masters.len());
+        quorum_workers.on_masters_until(masters, op, move |results| {
+            results.iter().filter(|result| result.is_ok()).count() >= quorum
+        })
+    }
+
+    fn addr(master: &Shard) -> String {
+        master.redis_client.get_connection_info().addr.to_string()
+    }
+
+    fn masters(count: u16) -> Arc<[Shard]> {
+        (0..count)
+            .map(|i| {
+                let redis_client = RedisClient::open(format!("redis://127.0.0.1:{}", 6380 + i))
+                    .expect("a valid url");
+                Shard::new(redis_client, ConnectionPool::shared(1))
+            })
+            .collect()
+    }
+
+    fn status() -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 3,
+            window_duration: Duration::from_secs(60),
+            used: 3,
+            reset_at: SystemTime::now(),
+        }
+    }
+
+    fn allowed(remaining_request_counter: u64) -> RateLimiterResponse {
+        RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter,
+            status: status(),
+        })
+    }
+
+    fn throttled(retry_in_secs: u64) -> RateLimiterResponse {
+        RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in: Duration::from_secs(retry_in_secs),
+            reason: ThrottleReason::QuotaExceeded,
+            status: status(),
+        })
+    }
+
+    #[test]
+    fn should_require_a_majority_of_masters() {
+        assert_eq!(quorum(1), 1);
+        assert_eq!(quorum(2), 2);
+        assert_eq!(quorum(3), 2);
+        assert_eq!(quorum(5), 3);
+    }
+
+    #[test]
+    fn should_allow_requests_allowed_by_a_quorum() {
+        let response = combine_responses(vec![allowed(2), throttled(10), allowed(1)], 3).unwrap();
+
+        assert_eq!(response.as_allowed().remaining_request_counter, 1);
+    }
+
+    #[test]
+    fn should_throttle_requests_not_allowed_by_a_quorum() {
+        let response =
+            combine_responses(vec![allowed(2), throttled(10), throttled(20)], 3).unwrap();
+
+        assert_eq!(response.as_throttled().retry_in, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn should_throttle_requests_allowed_by_a_minority_of_masters() {
+        let response = combine_responses(vec![allowed(2), throttled(10)], 3).unwrap();
+
+        assert_eq!(response.as_throttled().retry_in, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn should_fail_to_combine_no_responses() {
+        assert!(matches!(
+            combine_responses(vec![], 3),
+            Err(RateLimiterError::ComputeError)
+        ));
+    }
+
+    #[test]
+    fn should_reuse_the_same_workers_across_checks() {
+        //arrange
+        let masters = masters(3);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+        let check =
+            || on_quorum_of(&quorum_workers, &masters, |_| Ok(thread::current().id())).unwrap();
+
+        //act
+        let threads: HashSet<ThreadId> = (0..100).flat_map(|_| check()).collect();
+
+        //assert
+        assert!(!threads.contains(&thread::current().id()));
+        assert!(threads.len() <= 3 * WORKERS_PER_MASTER);
+    }
+
+    #[test]
+    fn should_fail_masters_whose_operation_panicked() {
+        //arrange
+        let masters = masters(3);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+
+        //act
+        let failed = on_quorum_of(&quorum_workers, &masters, |master| {
+            if addr(master) == "127.0.0.1:6380" {
+                Ok(())
+            } else {
+                panic!("check panicked")
+            }
+        });
+        let recovered = on_quorum_of(&quorum_workers, &masters, |_| Ok(()));
+
+        //assert
+        assert!(matches!(failed, Err(RateLimiterError::ComputeError)));
+        assert_eq!(recovered.unwrap().len(), quorum(3));
+    }
+
+    #[test]
+    fn should_drop_the_slowest_master_once_a_quorum_answered() {
+        //arrange
+        let masters = masters(3);
+        let delay = Duration::from_millis(300);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+
+        //act
+        let started_at = Instant::now();
+        let addrs = on_quorum_of(&quorum_workers, &masters, move |master| {
+            let addr = addr(master);
+            if addr == "127.0.0.1:6381" {
+                thread::sleep(delay);
+            }
+            Ok(addr)
+        })
+        .unwrap();
+
+        //assert
+        assert!(started_at.elapsed() < delay);
+        assert_eq!(addrs.len(), quorum(3));
+        assert!(!addrs.contains(&"127.0.0.1:6381".to_string()));
+    }
+
+    #[rstest]
+    #[case::one_unresponsive_master(&["127.0.0.1:6382"], true)]
+    #[case::unresponsive_first_master(&["127.0.0.1:6380"], true)]
+    #[case::unresponsive_majority(&["127.0.0.1:6381", "127.0.0.1:6382"], false)]
+    fn should_not_wait_for_unresponsive_masters(
+        #[case] unresponsive: &'static [&'static str],
+        #[case] reached_quorum: bool,
+    ) {
+        //arrange
+        let masters = masters(3);
+        let timeout = Duration::from_millis(200);
+        let quorum_workers = QuorumWorkers::new(masters.len(), timeout);
+        let (answer, never_answered) = mpsc::channel::<()>();
+        let never_answered = Arc::new(Mutex::new(never_answered));
+
+        //act
+        let started_at = Instant::now();
+        let result = on_quorum_of(&quorum_workers, &masters, move |master| {
+            let addr = addr(master);
+            if unresponsive.contains(&addr.as_str()) {
+                let _ = never_answered.lock().unwrap().recv();
+            }
+            Ok(addr)
+        });
+        let elapsed = started_at.elapsed();
+        drop(answer);
+
+        //assert
+        assert_eq!(result.is_ok(), reached_quorum);
+        if let Ok(addrs) = result {
+            assert_eq!(addrs.len(), 2);
+            assert!(elapsed < timeout);
+        } else {
+            assert!(matches!(result, Err(RateLimiterError::IoError(_))));
+            assert!(elapsed >= timeout && elapsed < 10 * timeout);
+        }
+    }
+
+    #[test]
+    fn should_keep_checking_the_other_masters_under_load_while_a_master_hangs() {
+        //arrange
+        let masters = masters(3);
+        let timeout = Duration::from_secs(1);
+        let quorum_workers = QuorumWorkers::new(masters.len(), timeout);
+        let (answer, never_answered) = mpsc::channel::<()>();
+        let never_answered = Arc::new(Mutex::new(never_answered));
+        let check = || {
+            let never_answered = never_answered.clone();
+            on_quorum_of(&quorum_workers, &masters, move |master| {
+                let addr = addr(master);
+                if addr == "127.0.0.1:6382" {
+                    let _ = never_answered.lock().unwrap().recv();
+                }
+                Ok(addr)
+            })
+        };
+
+        //act
+        let started_at = Instant::now();
+        let results: Vec<_> = thread::scope(|scope| {
+            let checks: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..2 * (WORKERS_PER_MASTER + QUEUED_CHECKS_PER_MASTER))
+                            .map(|_| check())
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            checks
+                .into_iter()
+                .flat_map(|checks| checks.join().unwrap())
+                .collect()
+        });
+        let elapsed = started_at.elapsed();
+        drop(answer);
+
+        //assert
+        assert!(results.iter().all(|result| result
+            .as_ref()
+            .is_ok_and(|addrs| !addrs.contains(&"127.0.0.1:6382".to_string()))));
+        assert!(elapsed < timeout);
+    }
+
+    #[rstest]
+    #[case::allowed_by_the_slow_master(&["127.0.0.1:6380"], true)]
+    #[case::throttled_by_the_slow_master(&["127.0.0.1:6380", "127.0.0.1:6382"], false)]
+    fn should_wait_for_the_other_masters_while_responses_disagree(
+        #[case] throttling: &'static [&'static str],
+        #[case] expected_allowed: bool,
+    ) {
+        //arrange
+        let masters = masters(3);
+        let delay = Duration::from_millis(100);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+
+        //act
+        let started_at = Instant::now();
+        let response = quorum_workers
+            .check_on_quorum(&masters, move |master| {
+                let addr = addr(master);
+                if addr == "127.0.0.1:6382" {
+                    thread::sleep(delay);
+                }
+                Ok(match throttling.contains(&addr.as_str()) {
+                    true => throttled(10),
+                    false => allowed(1),
+                })
+            })
+            .unwrap();
+
+        //assert
+        assert!(started_at.elapsed() >= delay);
+        assert_eq!(
+            matches!(response, RateLimiterResponse::RequestAllowed(_)),
+            expected_allowed
+        );
+    }
+
+    #[test]
+    fn should_throttle_without_waiting_once_a_quorum_throttled() {
+        //arrange
+        let masters = masters(3);
+        let delay = Duration::from_millis(300);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+
+        //act
+        let started_at = Instant::now();
+        let response = quorum_workers
+            .check_on_quorum(&masters, move |master| match addr(master).as_str() {
+                "127.0.0.1:6382" => {
+                    thread::sleep(delay);
+                    Ok(allowed(1))
+                }
+                _ => Ok(throttled(10)),
+            })
+            .unwrap();
+
+        //assert
+        assert!(started_at.elapsed() < delay);
+        assert_eq!(response.as_throttled().retry_in, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn should_wait_for_a_quorum_of_answers_after_a_failed_master() {
+        //arrange
+        let masters = masters(3);
+        let quorum_workers = QuorumWorkers::new(masters.len(), Duration::from_secs(1));
+
+        //act
+        let response =
+            quorum_workers.check_on_quorum(&masters, move |master| match addr(master).as_str() {
+                "127.0.0.1:6381" => Err(RateLimiterError::ComputeError),
+                "127.0.0.1:6382" => {
+                    thread::sleep(Duration::from_millis(100));
+                    Ok(throttled(20))
+                }
+                _ => Ok(throttled(10)),
+            });
+
+        //assert
+        assert_eq!(
+            response.unwrap().as_throttled().retry_in,
+            Duration::from_secs(20)
+        );
+    }
+
+    #[test]
+    fn should_throttle_requests_no_quorum_allowed_in_time() {
+        //arrange
+        let masters = masters(3);
+        let timeout = Duration::from_millis(200);
+        let quorum_workers = QuorumWorkers::new(masters.len(), timeout);
+        let (answer, never_answered) = mpsc::channel::<()>();
+        let never_answered = Arc::new(Mutex::new(never_answered));
+
+        //act
+        let response =
+            quorum_workers.check_on_quorum(&masters, move |master| match addr(master).as_str() {
+                "127.0.0.1:6380" => Ok(throttled(10)),
+                "127.0.0.1:6381" => Ok(allowed(1)),
+                _ => {
+                    let _ = never_answered.lock().unwrap().recv();
+                    Ok(allowed(1))
+                }
+            });
+        drop(answer);
+
+        //assert
+        assert_eq!(
+            response.unwrap().as_throttled().retry_in,
+            Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn should_run_operations_in_turn_on_the_calling_thread() {
+        let results = on_quorum(&masters(3), |_| Ok(thread::current().id())).unwrap();
+
+        assert_eq!(results, vec![thread::current().id(); 3]);
+    }
+}
@@ -17,7 +17,8 @@
 //! let rate_limiter = RateLimiterFactory::fixed_window()
 //!     .with_redis_settings(RedisSettings{
 //!         host: "127.0.0.1".to_string(),
-//!         port: 7379
+//!         port: 7379,
+//!         ..RedisSettings::default()
 //!     })
 //!     .build()
 //!     .unwrap();
@@ -27,40 +28,645 @@
 //! let rate_limiter_response = rate_limiter.check_request(request_id).unwrap();
 //!
 //! match rate_limiter_response {
-//!     RateLimiterResponse::RequestAllowed(RequestAllowed {remaining_request_counter}) => {
+//!     RateLimiterResponse::RequestAllowed(RequestAllowed {remaining_request_counter, ..}) => {
 //!         println!("Request allowed! Remaining request counter is {0}.", remaining_request_counter);
 //!     },
-//!     RateLimiterResponse::RequestThrottled(RequestThrottled {retry_in}) => {
+//!     RateLimiterResponse::RequestThrottled(RequestThrottled {retry_in, ..}) => {
 //!         println!("Request throttled! Retry in {0} seconds.", retry_in.as_secs());
 //!     },
 //! }
 //! ```
-use std::time::Duration;
+use std::{
+    sync::{Arc, LazyLock, OnceLock},
+    time::{Duration, Instant, SystemTime},
+};
 
-use redis::Client as RedisClient;
+use redis::{Client as RedisClient, Connection, Script};
 
+use super::{as_jittered_expiry_millis, AlignedWindow, CheckMode, WindowLimits};
 use crate::{
-    errors::RateLimiterError, RateLimiter, RateLimiterResponse, RequestAllowed, RequestIdentifier,
-    RequestThrottled,
+    adaptive::AdaptiveLimits,
+    capabilities::{negotiate, negotiate_on, RedisCapabilities},
+    clock::Clock,
+    coalescing::RequestCoalescer,
+    connection::{ConnectionPool, RedisConnection},
+    cost::{CostFunction, RequestContext},
+    data_subject::{export_keys, identifier_keys, purge_keys, IdentifierData},
+    errors::RateLimiterError,
+    functions::{fcall, FIXED_WINDOW_CHECK},
+    hash_tags::{request_key, HashTag},
+    inspection::{as_expire_in, inspect_limits, KeyInspection},
+    latency::{report_slow_check, CheckLatency},
+    listing::{list_keys, KeyListing, ListCursor},
+    load_shedding::{throttle_reason, LoadShedder},
+    observer::{notify, RateLimiterObserver},
+    offenders::{top_offenders, Offender, OffenderTracking},
+    onboarding::{OnboardingRamp, ONBOARDING_COMMAND_NAMES},
+    overrides::{
+        delete_override, read_override, write_override, LimitOverride, OVERRIDE_COMMAND_NAMES,
+    },
+    quorum::{on_quorum, QuorumWorkers},
+    regions::{RegionalCounters, REGIONAL_CHECK_COMMAND_NAMES},
+    reputation::ReputationPolicy,
+    sharding::{shard_for, Shard},
+    snapshot::{export_state, import_entries, SnapshotEntry, StateSnapshot},
+    spans::{record_latency, CheckSpan},
+    stable_hash,
+    throttle_cache::ThrottleCache,
+    usage::{read_usage, record_usage, retained_usage_keys, UsagePeriod},
+    RateLimitStatus, RateLimiter, RateLimiterResponse, RedisAdmin, RequestAllowed,
+    RequestIdentifier, RequestThrottled,
 };
 
 /// Represents a distributed fixed windowå rate limiter
 /// based on [Redis](https://redis.io/)
 #[derive(Clone)]
 pub struct FixedWindowRateLimiter {
-    /// The size of the window, that is the maximum number
-    /// of requests that the rate limiter will allow for a time equal to the _window_duration_
-    pub window_size: u64,
-
-    /// Represents how long the window should be considered valid.
-    /// This can be considered as the equivalent of the _refill rate_
-    pub window_validity: Duration,
+    /// The size of the window, that is the maximum number of requests that the rate limiter
+    /// will allow for a time equal to the _window_validity_, and how long the window should be
+    /// considered valid. The latter can be considered as the equivalent of the _refill rate_.
+    /// Both can be updated at runtime with [RedisAdmin::update_limits].
+    pub(crate) limits: Arc<WindowLimits>,
 
     /// The internal client that will be used to fire requests against Redis
     pub redis_client: RedisClient,
+
+    /// The pool the connections to Redis are borrowed from, if configured
+    pub(crate) connection_pool: ConnectionPool,
+
+    /// The optional onboarding ramp applied to newly seen request identifiers
+    pub onboarding_ramp: Option<OnboardingRamp>,
+
+    /// The optional threshold above which checks are reported as slow
+    pub slow_check_threshold: Option<Duration>,
+
+    /// The optional reputation policy applied to request identifiers
+    pub reputation: Option<ReputationPolicy>,
+
+    /// The optional tracking of the most throttled request identifiers
+    pub offender_tracking: Option<OffenderTracking>,
+
+    /// Whether the requests allowed are aggregated per day and per month
+    pub usage_reporting: bool,
+
+    /// Whether the per-key limit overrides stored in Redis are consulted on every check
+    pub limit_overrides: bool,
+
+    /// Whether checks run as a cached Lua script, instead of a transaction
+    pub scripted_checks: bool,
+
+    /// Whether checks run as Redis Functions, on servers supporting them
+    pub redis_functions: bool,
+
+    /// The optional number of hashes the counters are grouped into, instead of a key each
+    pub hash_buckets: Option<u32>,
+
+    /// The optional regions whose counters are summed, in active-active deployments
+    pub regional_counters: Option<RegionalCounters>,
+
+    /// The capabilities of the underlying Redis server, once detected
+    pub(crate) capabilities: Arc<OnceLock<RedisCapabilities>>,
+
+    /// The independent Redis servers keys are sharded across, if configured
+    pub(crate) shards: Arc<[Shard]>,
+
+    /// The read-only replica serving the non-mutating operations, if configured
+    pub(crate) read_replica: Option<Arc<Shard>>,
+
+    /// The optional portion of the request keys wrapped in a hash tag
+    pub hash_tag: Option<HashTag>,
+
+    /// Whether checks are applied against a quorum of independent Redis masters, instead of
+    /// sharding keys across them
+    pub quorum: bool,
+    /// The pools of workers checking requests against each of the masters, in quorum mode
+    pub(crate) quorum_workers: QuorumWorkers,
+
+    /// The optional coalescing of the concurrent checks of the same key
+    pub(crate) request_coalescer: Option<Arc<RequestCoalescer<RateLimiterResponse>>>,
+
+    /// The optional maximum random jitter added to the expiry of the keys, lengthening the windows
+    /// of the counters stored in their own keys by up to the jitter
+    pub expiry_jitter: Option<Duration>,
+
+    /// The optional local cache of throttled verdicts, answering the checks of throttled keys
+    pub throttle_cache: Option<ThrottleCache>,
+
+    /// The optional adaptive limits, tightened while the checks against Redis are slow
+    pub adaptive_limits: Option<AdaptiveLimits>,
+
+    /// The optional load shedding, scaling the budgets with the load reported by the application
+    pub load_shedder: Option<LoadShedder>,
+
+    /// The optional function computing the cost of the requests from their context
+    pub cost_function: Option<Arc<dyn CostFunction>>,
+
+    /// The optional observer notified of the outcome of every check
+    pub(crate) observer: Option<Arc<dyn RateLimiterObserver>>,
+
+    /// The clock the current time is read from
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+/// The name of the algorithm, used when tracing checks
+const ALGORITHM: &str = "fixed_window";
+
+/// The names of the commands run by every check, logged with slow checks
+const CHECK_COMMAND_NAMES: &[&str] = &[
+    "WATCH", "MULTI", "INCR", "PEXPIRE", "PTTL", "EXEC", "UNWATCH",
+];
+
+/// The names of the commands run by every scripted check, logged with slow checks
+const SCRIPTED_CHECK_COMMAND_NAMES: &[&str] = &["EVALSHA"];
+
+/// The names of the commands run by every check calling a Redis Function, logged with slow
+/// checks
+const FUNCTION_CHECK_COMMAND_NAMES: &[&str] = &["FCALL"];
+
+/// The names of the commands run by every check with counters stored in hashes, logged with
+/// slow checks
+const HASHED_CHECK_COMMAND_NAMES: &[&str] = &["MULTI", "HINCRBY", "PEXPIREAT", "EXEC"];
+
+/// The prefix of the hashes holding the counters, when stored in hashes
+const HASHED_COUNTERS_PREFIX: &str = "rl:hashed";
+
+/// The Lua script run by scripted checks. The expiry is set when missing, like `PEXPIRE` with
+/// the `NX` option, which is only available from Redis 7.
+pub(crate) const CHECK_SCRIPT_SOURCE: &str = r"
+local counter = redis.call('INCR', KEYS[1])
+local expire_in = redis.call('PTTL', KEYS[1])
+if expire_in < 0 then
+    redis.call('PEXPIRE', KEYS[1], ARGV[1])
+    expire_in = tonumber(ARGV[1])
+end
+return {counter, expire_in}
+";
+
+/// The Lua script run by scripted checks
+static CHECK_SCRIPT: LazyLock<Script> = LazyLock::new(|| Script::new(CHECK_SCRIPT_SOURCE));
+
+impl FixedWindowRateLimiter {
+    /// Returns the size of the window, that is the maximum number of requests allowed
+    /// in a single window.
+    pub fn window_size(&self) -> u64 {
+        self.limits.window_size()
+    }
+
+    /// Returns how long the window should be considered valid.
+    pub fn window_validity(&self) -> Duration {
+        self.limits.window_duration()
+    }
+
+    /// Returns a connection to the Redis server owning the given key, that is the shard it's
+    /// hashed to, if keys are sharded, or the single underlying server otherwise.
+    fn connection(&self, key: &str) -> Result<RedisConnection, RateLimiterError> {
+        match shard_for(&self.shards, key) {
+            Some(shard) => shard.connection(),
+            None => self.connection_pool.get(&self.redis_client),
+        }
+    }
+
+    /// Checks the request of the given key, against the Redis server owning it or, in quorum mode,
+    /// against all the masters.
+    fn check(&self, key: &str, cost: u64) -> Result<RateLimiterResponse, RateLimiterError> {
+        let check_mode = CheckMode::negotiate(self.scripted_checks, self.redis_functions, || {
+            self.capabilities()
+        })?;
+        // the scripts and functions count a single request at once
+        let check_mode = if cost > 1 {
+            CheckMode::Transaction
+        } else {
+            check_mode
+        };
+
+        if self.quorum {
+            let rate_limiter = self.clone();
+            let key = key.to_string();
+            return self
+                .quorum_workers
+                .check_on_quorum(&self.shards, move |master| {
+                    rate_limiter.check_key(&key, cost, check_mode, || master.connection())
+                });
+        }
+
+        match &self.request_coalescer {
+            // only plain counters incremented in a transaction can be incremented by a whole batch
+            Some(request_coalescer)
+                if check_mode == CheckMode::Transaction
+                    && cost == 1
+                    && self.regional_counters.is_none()
+                    && self.hash_buckets.is_none() =>
+            {
+                request_coalescer.check(key, |requests| {
+                    self.check_requests(key, requests, 1, check_mode, || self.connection(key))
+                })
+            }
+            _ => self.check_key(key, cost, check_mode, || self.connection(key)),
+        }
+    }
+
+    /// Checks the request of the given key, with the given cost, against the Redis server connected
+    /// by the given function.
+    fn check_key(
+        &self,
+        key: &str,
+        cost: u64,
+        check_mode: CheckMode,
+        connect: impl FnOnce() -> Result<RedisConnection, RateLimiterError>,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        self.check_requests(key, 1, cost, check_mode, connect)?
+            .pop()
+            .ok_or(RateLimiterError::ComputeError)
+    }
+
+    /// Checks the given number of concurrent requests of the given key, each with the given cost,
+    /// against the Redis server connected by the given function, returning their responses in the
+    /// order they're counted. Batches of more than one request are only supported by plain
+    /// counters, and costs of more than one unit by counters incremented in a transaction.
+    fn check_requests(
+        &self,
+        key: &str,
+        requests: u64,
+        cost: u64,
+        check_mode: CheckMode,
+        connect: impl FnOnce() -> Result<RedisConnection, RateLimiterError>,
+    ) -> Result<Vec<RateLimiterResponse>, RateLimiterError> {
+        let check_started_at = Instant::now();
+        let mut con = connect()?;
+        let connect_latency = check_started_at.elapsed();
+
+        let limit_override = if self.limit_overrides {
+            read_override(&mut con, key)?
+        } else {
+            LimitOverride::default()
+        };
+        let window_size = limit_override
+            .window_size
+            .unwrap_or_else(|| self.window_size());
+        let window_validity = limit_override
+            .window_duration
+            .unwrap_or_else(|| self.window_validity());
+
+        let now = self.clock.now();
+        let window_size = match &self.onboarding_ramp {
+            Some(onboarding_ramp) => onboarding_ramp.effective_limit(
+                window_size,
+                onboarding_ramp.elapsed_since_first_seen(&mut con, key, window_validity, now)?,
+            ),
+            None => window_size,
+        };
+        // requests throttled only because of the adaptive limits or the load shedding are shed
+        let unscaled_window_size = window_size;
+        let window_size = match &self.adaptive_limits {
+            Some(adaptive_limits) => adaptive_limits.effective_limit(window_size),
+            None => window_size,
+        };
+        let window_size = match &self.load_shedder {
+            Some(load_shedder) => load_shedder.effective_limit(window_size),
+            None => window_size,
+        };
+
+        let expiry_millis = as_jittered_expiry_millis(window_validity, self.expiry_jitter);
+        // a cost over the window size is throttled all the same, and would overflow the counter
+        let cost = cost.min(window_size.saturating_add(1));
+        let increment = requests.saturating_mul(cost);
+        let (executed_request_counter, expire_in_millis): (u64, u64) =
+            match (&self.regional_counters, self.hash_buckets, check_mode) {
+                (Some(regional_counters), _, _) => regional_counters.increment(
+                    &mut con,
+                    key,
+                    &AlignedWindow::current(window_validity, now)?,
+                    increment,
+                )?,
+                (None, Some(hash_buckets), _) => increment_hashed_counter(
+                    &mut con,
+                    key,
+                    hash_buckets,
+                    increment,
+                    window_validity,
+                    now,
+                )?,
+                (None, None, CheckMode::Function) => {
+                    fcall(&mut con, FIXED_WINDOW_CHECK, key, expiry_millis)?
+                }
+                (None, None, CheckMode::Script) => {
+                    CHECK_SCRIPT.key(key).arg(expiry_millis).invoke(&mut *con)?
+                }
+                (None, None, CheckMode::Transaction) => {
+                    let expire_options =
+                        negotiate_on(&self.capabilities, &mut con)?.supports_expire_options();
+                    let (counter, expire_in_millis): (u64, i64) =
+                        redis::transaction(&mut *con, &[key], |con, pipe| {
+                            if increment == 1 {
+                                pipe.cmd("INCR").arg(key);
+                            } else {
+                                pipe.cmd("INCRBY").arg(key).arg(increment);
+                            }
+                            if expire_options {
+                                pipe.cmd("PEXPIRE")
+                                    .arg(key)
+                                    .arg(expiry_millis)
+                                    .arg("NX")
+                                    .ignore();
+                            }
+                            pipe.cmd("PTTL").arg(key).query(con)
+                        })?;
+                    // servers before Redis 7 lack the NX option, the expiry is then set once read
+                    // as missing, by the check that created the counter or the next one
+                    if expire_in_millis < 0 {
+                        redis::cmd("PEXPIRE")
+                            .arg(key)
+                            .arg(expiry_millis)
+                            .query::<()>(&mut *con)?;
+                        (counter, expiry_millis)
+                    } else {
+                        (counter, expire_in_millis as u64)
+                    }
+                }
+            };
+
+        let latency = CheckLatency {
+            connect: connect_latency,
+            commands: check_started_at.elapsed() - connect_latency,
+        };
+        record_latency(&latency);
+        if let Some(adaptive_limits) = &self.adaptive_limits {
+            adaptive_limits.record_latency(latency.total(), Instant::now());
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_redis_latency(key, &latency);
+        }
+        if let Some(slow_check_threshold) = self.slow_check_threshold {
+            report_slow_check(
+                slow_check_threshold,
+                key,
+                &latency,
+                &self.check_command_names(check_mode),
+            );
+        }
+
+        let expire_in = Duration::from_millis(expire_in_millis);
+        // the windows of counters in their own keys, jittered or not, end when their keys expire,
+        // that is their time to live after the reply, while aligned windows end on the clock
+        let reset_at = match (&self.regional_counters, self.hash_buckets) {
+            (None, None) => self.clock.now() + expire_in,
+            _ => now + expire_in,
+        };
+        // the requests of a batch are counted one after the other, up to the incremented counter
+        let first_request_counter = (executed_request_counter + cost).saturating_sub(increment);
+        let mut responses = Vec::with_capacity(requests as usize);
+        for request_counter in
+            (first_request_counter..=executed_request_counter).step_by(cost as usize)
+        {
+            let status = RateLimitStatus {
+                limit: window_size,
+                window_duration: window_validity,
+                used: request_counter,
+                reset_at,
+            };
+
+            let response = if request_counter <= window_size {
+                RateLimiterResponse::RequestAllowed(RequestAllowed {
+                    remaining_request_counter: window_size - request_counter,
+                    status,
+                })
+            } else {
+                RateLimiterResponse::RequestThrottled(RequestThrottled {
+                    retry_in: expire_in,
+                    reason: throttle_reason(request_counter, unscaled_window_size),
+                    status,
+                })
+            };
+
+            if let (RateLimiterResponse::RequestThrottled(_), Some(reputation)) =
+                (&response, &self.reputation)
+            {
+                reputation.record_throttle(&mut con, key, now)?;
+            }
+            if let (RateLimiterResponse::RequestThrottled(_), Some(offender_tracking)) =
+                (&response, &self.offender_tracking)
+            {
+                offender_tracking.record_throttle(&mut con, key, now)?;
+            }
+            if let (RateLimiterResponse::RequestAllowed(_), true) =
+                (&response, self.usage_reporting)
+            {
+                record_usage(&mut con, key, now)?;
+            }
+            responses.push(response);
+        }
+
+        Ok(responses)
+    }
+
+    /// Returns the state of the given key, without mutating it.
+    fn inspect_key(
+        &self,
+        con: &mut Connection,
+        key: &str,
+    ) -> Result<KeyInspection, RateLimiterError> {
+        let now = self.clock.now();
+        let (limit, window_duration, overridden) = inspect_limits(
+            con,
+            key,
+            self.window_size(),
+            self.window_validity(),
+            self.limit_overrides,
+            self.onboarding_ramp.as_ref(),
+            now,
+        )?;
+
+        let (count, expire_in_millis): (Option<u64>, i64) =
+            match (&self.regional_counters, self.hash_buckets) {
+                (Some(regional_counters), _) => {
+                    let window = AlignedWindow::current(window_duration, now)?;
+                    let count = regional_counters.read(con, key, &window)?;
+                    (
+                        Some(count).filter(|count| *count > 0),
+                        window.expire_in_millis as i64,
+                    )
+                }
+                (None, Some(hash_buckets)) => {
+                    let window = AlignedWindow::current(window_duration, now)?;
+                    let hash_key = hashed_counters_key(key, hash_buckets, window.index);
+                    redis::pipe()
+                        .cmd("HGET")
+                        .arg(&hash_key)
+                        .arg(key)
+                        .cmd("PTTL")
+                        .arg(&hash_key)
+                        .query(con)?
+                }
+                (None, None) => redis::pipe()
+                    .cmd("GET")
+                    .arg(key)
+                    .cmd("PTTL")
+                    .arg(key)
+                    .query(con)?,
+            };
+
+        Ok(KeyInspection {
+            key: key.to_string(),
+            count: count.unwrap_or(0),
+            limit,
+            window_duration,
+            overridden,
+            expire_in: count.and(as_expire_in(expire_in_millis)),
+        })
+    }
+
+    /// Returns the requests counted in the current window for each of the given keys.
+    fn current_counts(
+        &self,
+        con: &mut Connection,
+        keys: &[String],
+    ) -> Result<Vec<u64>, RateLimiterError> {
+        let counts: Vec<Option<u64>> = redis::cmd("MGET").arg(keys).query(con)?;
+        Ok(counts.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    /// Returns the number of Redis servers holding distinct keys: all the shards, if keys are
+    /// sharded, or a single one otherwise, as the masters of a quorum hold the same keys.
+    fn distinct_servers(&self) -> usize {
+        if self.quorum {
+            1
+        } else {
+            self.shards.len().max(1)
+        }
+    }
+
+    /// Returns a connection to the Redis server of the given index, out of the distinct ones.
+    fn server_connection(&self, server: usize) -> Result<RedisConnection, RateLimiterError> {
+        match self.shards.get(server) {
+            Some(shard) => shard.connection(),
+            None => self.connection_pool.get(&self.redis_client),
+        }
+    }
+
+    /// Returns a connection to the read replica, if configured, or to the Redis server of the
+    /// given index, out of the distinct ones, otherwise.
+    fn read_server_connection(&self, server: usize) -> Result<RedisConnection, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => read_replica.connection(),
+            None => self.server_connection(server),
+        }
+    }
+
+    /// Returns all the keys that might hold state for the given request key.
+    fn stored_keys(&self, key: &str) -> Vec<String> {
+        let mut keys = identifier_keys(key);
+        if self.usage_reporting {
+            keys.extend(retained_usage_keys(key, self.clock.now()));
+        }
+        keys
+    }
+
+    /// Returns the caches of throttled verdicts of the rate limiter: the throttle cache, if
+    /// configured, and the one of the adaptive limits, if any.
+    fn throttle_caches(&self) -> impl Iterator<Item = &ThrottleCache> {
+        let adaptive_cache = self
+            .adaptive_limits
+            .as_ref()
+            .map(|adaptive_limits| &adaptive_limits.throttle_cache);
+        self.throttle_cache.iter().chain(adaptive_cache)
+    }
+
+    /// Drops the cached throttled verdict of the given key, if any.
+    fn forget_throttled_verdict(&self, key: &str) {
+        for throttle_cache in self.throttle_caches() {
+            throttle_cache.forget(key);
+        }
+    }
+
+    /// Runs the given operation against the Redis server owning the given key or, in quorum mode,
+    /// against all the masters. Returns the results of the servers that succeeded.
+    fn run<T: Send>(
+        &self,
+        key: &str,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        if self.quorum {
+            return on_quorum(&self.shards, |master| op(&mut master.connection()?));
+        }
+        Ok(vec![op(&mut self.connection(key)?)?])
+    }
+
+    /// Runs the given non-mutating operation against the read replica, if configured, or like
+    /// [Self::run] otherwise.
+    fn run_read<T: Send>(
+        &self,
+        key: &str,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => Ok(vec![op(&mut read_replica.connection()?)?]),
+            None => self.run(key, op),
+        }
+    }
+
+    /// Runs the given non-mutating operation against the read replica, if configured, or like
+    /// [Self::run_everywhere] otherwise.
+    fn run_read_everywhere<T: Send>(
+        &self,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => Ok(vec![op(&mut read_replica.connection()?)?]),
+            None => self.run_everywhere(op),
+        }
+    }
+
+    /// Runs the given operation against every Redis server: all the shards, if keys are sharded,
+    /// a quorum of the masters in quorum mode, or the single underlying server otherwise.
+    fn run_everywhere<T: Send>(
+        &self,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        if self.quorum {
+            return on_quorum(&self.shards, |master| op(&mut master.connection()?));
+        }
+        if self.shards.is_empty() {
+            return Ok(vec![op(&mut self
+                .connection_pool
+                .get(&self.redis_client)?)?]);
+        }
+        self.shards
+            .iter()
+            .map(|shard| op(&mut shard.connection()?))
+            .collect()
+    }
+
+    /// Returns the Redis commands run by a check, according to the rate limiter configuration.
+    fn check_command_names(&self, check_mode: CheckMode) -> Vec<&'static str> {
+        let mut commands = vec![];
+        if self.limit_overrides {
+            commands.extend_from_slice(OVERRIDE_COMMAND_NAMES);
+        }
+        if self.onboarding_ramp.is_some() {
+            commands.extend_from_slice(ONBOARDING_COMMAND_NAMES);
+        }
+        if self.regional_counters.is_some() {
+            commands.extend_from_slice(REGIONAL_CHECK_COMMAND_NAMES);
+            return commands;
+        }
+        if self.hash_buckets.is_some() {
+            commands.extend_from_slice(HASHED_CHECK_COMMAND_NAMES);
+            return commands;
+        }
+        commands.extend_from_slice(match check_mode {
+            CheckMode::Transaction => CHECK_COMMAND_NAMES,
+            CheckMode::Script => SCRIPTED_CHECK_COMMAND_NAMES,
+            CheckMode::Function => FUNCTION_CHECK_COMMAND_NAMES,
+        });
+        commands
+    }
 }
 
 impl RateLimiter for FixedWindowRateLimiter {
+    fn build_request_key(&self, request_identifier: RequestIdentifier) -> String {
+        request_key(request_identifier, self.hash_tag)
+    }
+
     /// Function that returns the result of the rate limiter checks. Yields an error in case of troubles
     /// connecting to the underlying redis instance.
     ///
@@ -68,66 +674,286 @@ impl RateLimiter for FixedWindowRateLimiter {
     /// The implementation of this method heavily relies on Redis commands. It atomically runs a set commands to:
     ///
     /// 1. Increase by 1 the value of a key, if existing. Otherwise set it to 0.
-    /// 2. Set the configured expiration on it, if not set already;
-    /// 3. Get the updated expiry of the rate limiter.
+    /// 2. Set the configured expiration on it, in milliseconds, if not set already;
+    /// 3. Get the updated expiry of the rate limiter, in milliseconds.
     ///
     /// The above four commands are wrapped into a Redis [transaction](https://redis.io/docs/manual/transactions/) with the helper provided by the underlying redis crate used.
     /// The combination of `WATCH`, `MULTI` and `EXEC` commands here protect this piece of code from race conditions when multiple
     /// clients are modifying the same key simultaneously.
     ///
+    /// The expiration is set with the `NX` option of `PEXPIRE`, which is only available from Redis 7. On older servers, as
+    /// detected from their [capabilities](crate::capabilities::RedisCapabilities), the transaction only increases the value and
+    /// reads its expiry, and a plain `PEXPIRE` follows whenever the expiry is missing.
+    ///
+    /// With [scripted checks](crate::builders::fixed_window::FixedWindowRateLimiterBuilder::with_scripted_checks),
+    /// the same commands run in a Lua script instead, invoked with `EVALSHA` in a single round trip. Scripts are atomic, so no
+    /// retries are needed when multiple clients are modifying the same key. The script is loaded on first use, and again
+    /// whenever Redis answers with a `NOSCRIPT` error, like after a restart. With [Redis Functions](crate::builders::fixed_window::FixedWindowRateLimiterBuilder::with_redis_functions),
+    /// the same script is registered as a function and called with `FCALL` on Redis 7 and above.
+    ///
+    /// With [hash storage](crate::builders::fixed_window::FixedWindowRateLimiterBuilder::with_hash_storage), the counter is
+    /// instead a field of one of a fixed number of hashes, picked by hashing the key, and named after the current window. The field
+    /// is incremented with `HINCRBY`, and the hash set to expire at the end of the window with `PEXPIREAT`.
+    ///
+    /// With a [cost function](crate::builders::fixed_window::FixedWindowRateLimiterBuilder::with_cost_function), the counter is
+    /// increased by the cost of the request instead, with `INCRBY`, in a transaction whichever the check mode.
+    ///
     /// Below the output of a MONITOR command on a Redis instance when the `is_request_allowed` function is invoked:
     ///
     /// ```ignore
     /// 1675511728.833664 [0 172.28.0.5:48922] "WATCH" "rl:ip_172.28.0.6"
     /// 1675511728.834677 [0 172.28.0.5:48922] "MULTI"
     /// 1675511728.835237 [0 172.28.0.5:48922] "INCR" "rl:ip_172.28.0.6"
-    /// 1675511728.835358 [0 172.28.0.5:48922] "EXPIRE" "rl:ip_172.28.0.6" "60" "NX"
-    /// 1675511728.835526 [0 172.28.0.5:48922] "TTL" "rl:ip_172.28.0.6"
+    /// 1675511728.835358 [0 172.28.0.5:48922] "PEXPIRE" "rl:ip_172.28.0.6" "60000" "NX"
+    /// 1675511728.835526 [0 172.28.0.5:48922] "PTTL" "rl:ip_172.28.0.6"
     /// 1675511728.835626 [0 172.28.0.5:48922] "EXEC"
     /// 1675511728.836371 [0 172.28.0.5:48922] "UNWATCH"
     /// ```
     fn check_request(
         &self,
         request_identifier: RequestIdentifier,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        self.check_request_with_context(request_identifier, &RequestContext::default())
+    }
+
+    fn check_request_with_context(
+        &self,
+        request_identifier: RequestIdentifier,
+        context: &RequestContext,
     ) -> Result<RateLimiterResponse, RateLimiterError> {
         let key = &self.build_request_key(request_identifier);
+        let cost = self
+            .cost_function
+            .as_ref()
+            .map_or(1, |cost_function| cost_function.cost(context).max(1));
+
+        let check_started_at = Instant::now();
+        // while the adaptive limits are tightened, throttled verdicts are cached anyway
+        let throttle_cache = self.throttle_cache.as_ref().or_else(|| {
+            self.adaptive_limits
+                .as_ref()
+                .and_then(AdaptiveLimits::throttle_cache)
+        });
+        let res = CheckSpan::new(ALGORITHM, key).in_scope(|| match throttle_cache {
+            Some(throttle_cache) => {
+                throttle_cache.check(key, self.clock.now(), || self.check(key, cost))
+            }
+            None => self.check(key, cost),
+        });
+        if let Some(observer) = &self.observer {
+            notify(observer.as_ref(), key, &res, check_started_at.elapsed());
+        }
 
-        let mut con = self.redis_client.get_connection()?;
+        res
+    }
+}
 
-        let (executed_request_counter, expire_in_seconds): (u64, u64) =
-            redis::transaction(&mut con, &[key], |con, pipe| {
-                pipe.cmd("INCR")
-                    .arg(key)
-                    .cmd("EXPIRE")
-                    .arg(key)
-                    .arg(self.window_validity.as_secs())
-                    .arg("NX")
-                    .ignore()
-                    .cmd("TTL")
-                    .arg(key)
-                    .query(con)
-            })?;
+impl RedisAdmin for FixedWindowRateLimiter {
+    fn export_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<IdentifierData, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.run(&key, |con| export_keys(con, &self.stored_keys(&key)))?
+            .into_iter()
+            .next()
+            .ok_or(RateLimiterError::ComputeError)
+    }
 
-        let response = if executed_request_counter <= self.window_size {
-            RateLimiterResponse::RequestAllowed(RequestAllowed {
-                remaining_request_counter: self.window_size - executed_request_counter,
-            })
-        } else {
-            RateLimiterResponse::RequestThrottled(RequestThrottled {
-                retry_in: Duration::from_secs(expire_in_seconds),
-            })
-        };
+    fn purge_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<u64, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        let deleted_keys = self.run(&key, |con| {
+            if let Some(offender_tracking) = &self.offender_tracking {
+                offender_tracking.forget(con, &key, self.clock.now())?;
+            }
+            purge_keys(con, &self.stored_keys(&key))
+        })?;
+        Ok(deleted_keys.into_iter().max().unwrap_or(0))
+    }
+
+    fn reputation(&self, request_identifier: RequestIdentifier) -> Result<f64, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+
+        match &self.reputation {
+            Some(reputation) => {
+                let now = self.clock.now();
+                let scores = self.run(&key, |con| reputation.score(con, &key, now))?;
+                Ok(scores.into_iter().fold(0.0, f64::max))
+            }
+            None => Ok(0.0),
+        }
+    }
+
+    fn top_offenders(&self, n: usize) -> Result<Vec<Offender>, RateLimiterError> {
+        match &self.offender_tracking {
+            Some(offender_tracking) => {
+                let now = self.clock.now();
+                let throttles =
+                    self.run_read_everywhere(|con| offender_tracking.throttles(con, n, now))?;
+                Ok(top_offenders(throttles, n))
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    fn usage(
+        &self,
+        request_identifier: RequestIdentifier,
+        period: UsagePeriod,
+    ) -> Result<u64, RateLimiterError> {
+        if !self.usage_reporting {
+            return Ok(0);
+        }
+
+        let key = self.build_request_key(request_identifier);
+        let usages = self.run_read(&key, |con| read_usage(con, &key, period))?;
+        Ok(usages.into_iter().max().unwrap_or(0))
+    }
+
+    fn inspect(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<KeyInspection, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.run_read(&key, |con| self.inspect_key(con, &key))?
+            .into_iter()
+            .max_by_key(|inspection| inspection.count)
+            .ok_or(RateLimiterError::ComputeError)
+    }
+
+    fn list_keys(&self, pattern: &str, cursor: ListCursor) -> Result<KeyListing, RateLimiterError> {
+        list_keys(
+            pattern,
+            cursor,
+            self.distinct_servers(),
+            |server| self.read_server_connection(server),
+            |con, keys| self.current_counts(con, keys),
+        )
+    }
+
+    fn export_state(&self) -> Result<StateSnapshot, RateLimiterError> {
+        export_state(self.distinct_servers(), |server| {
+            self.server_connection(server)
+        })
+    }
+
+    fn import_state(&self, snapshot: &StateSnapshot) -> Result<u64, RateLimiterError> {
+        let servers = self.distinct_servers();
+        if snapshot.entries.iter().any(|entry| entry.server >= servers) {
+            return Err(RateLimiterError::ComputeError);
+        }
+
+        let mut restored = 0;
+        for server in 0..servers {
+            let entries: Vec<&SnapshotEntry> = snapshot
+                .entries
+                .iter()
+                .filter(|entry| entry.server == server)
+                .collect();
+            restored += if self.quorum {
+                self.run_everywhere(|con| import_entries(con, &entries, snapshot.taken_at))?
+                    .into_iter()
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                import_entries(
+                    &mut *self.server_connection(server)?,
+                    &entries,
+                    snapshot.taken_at,
+                )?
+            };
+        }
+        Ok(restored)
+    }
+
+    fn update_limits(&self, window_size: u64, window_duration: Duration) {
+        self.limits.update(window_size, window_duration);
+        for throttle_cache in self.throttle_caches() {
+            throttle_cache.clear();
+        }
+    }
+
+    fn set_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+        limit_override: &LimitOverride,
+    ) -> Result<(), RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        self.run(&key, |con| write_override(con, &key, limit_override))?;
+        Ok(())
+    }
+
+    fn remove_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<bool, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        let deleted = self.run(&key, |con| delete_override(con, &key))?;
+        Ok(deleted.into_iter().any(|deleted| deleted))
+    }
 
-        Ok(response)
+    fn capabilities(&self) -> Result<RedisCapabilities, RateLimiterError> {
+        negotiate(&self.capabilities, || {
+            self.connection_pool.get(&self.redis_client)
+        })
     }
 }
 
+/// Utility method that increments the counter of the given key by the given amount, stored as a
+/// field of the hash of its bucket for the current window. Returns the updated counter, and the
+/// expiry of the window in milliseconds. As each hash only holds the counters of one window, and
+/// expires at its end, all its fields expire together.
+fn increment_hashed_counter(
+    con: &mut Connection,
+    key: &str,
+    buckets: u32,
+    increment: u64,
+    window_validity: Duration,
+    now: SystemTime,
+) -> Result<(u64, u64), RateLimiterError> {
+    let window = AlignedWindow::current(window_validity, now)?;
+    let hash_key = hashed_counters_key(key, buckets, window.index);
+
+    let (counter,): (u64,) = redis::pipe()
+        .atomic()
+        .cmd("HINCRBY")
+        .arg(&hash_key)
+        .arg(key)
+        .arg(increment)
+        .cmd("PEXPIREAT")
+        .arg(&hash_key)
+        .arg(window.end_millis)
+        .ignore()
+        .query(con)?;
+
+    Ok((counter, window.expire_in_millis))
+}
+
+/// Utility method that returns the hash holding the counter of the given key, in the given window.
+fn hashed_counters_key(key: &str, buckets: u32, window_index: u64) -> String {
+    format!(
+        "{}:{}:{}",
+        HASHED_COUNTERS_PREFIX,
+        stable_hash(key) % u64::from(buckets.max(1)),
+        window_index
+    )
+}
+
 #[cfg(test)]
 mod test {
     use std::{
         cmp,
+        collections::HashMap,
         net::{IpAddr, Ipv4Addr},
-        time::Duration,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant, SystemTime},
     };
 
     use rand::Rng;
@@ -135,10 +961,31 @@ mod test {
     use uuid::Uuid;
 
     use crate::{
-        builders::RedisSettings, errors::RateLimiterError, factory::RateLimiterFactory,
-        RateLimiter, RequestIdentifier,
+        adaptive::AdaptivePolicy,
+        builders::RedisSettings,
+        capabilities::RedisVersion,
+        clock::{Clock, ManualClock},
+        cost::{EndpointWeights, PayloadSizeCost, RequestContext},
+        data_subject::StoredValue,
+        errors::RateLimiterError,
+        factory::RateLimiterFactory,
+        listing::{ListCursor, ListedKey},
+        load_shedding::{LoadShedder, LoadSheddingPolicy},
+        observer::test::RecordingObserver,
+        offenders::Offender,
+        onboarding::OnboardingRamp,
+        overrides::LimitOverride,
+        rate_limiters::CheckMode,
+        redis_mock::RedisMock,
+        regions::RegionalCounters,
+        reputation::ReputationPolicy,
+        testing::RedisContainer,
+        usage::UsagePeriod,
+        RateLimiter, RateLimiterResponse, RedisAdmin, RequestIdentifier, ThrottleReason,
     };
 
+    use super::hashed_counters_key;
+
     #[rstest]
     #[case::ip(RequestIdentifier::Ip(generate_random_ip()))]
     #[case::custom_id(
@@ -150,6 +997,7 @@ mod test {
             .with_redis_settings(RedisSettings {
                 host: "whatever".to_string(),
                 port: 1,
+                ..RedisSettings::default()
             })
             .build()
             .unwrap();
@@ -171,14 +1019,13 @@ mod test {
     #[case::custom_id(
         RequestIdentifier::Custom { key: "a_custom_id".to_string(), value: Uuid::new_v4().to_string() },
     )]
+    #[case::internal(RequestIdentifier::Internal(Uuid::new_v4().to_string()))]
     fn should_check_request_eligibility(#[case] request_identifier: RequestIdentifier) {
         //arrange
         let window_size = 5;
         let window_duration = Duration::from_secs(60);
-        let redis_settings = RedisSettings {
-            host: "127.0.0.1".to_string(),
-            port: 7379,
-        };
+        let redis = RedisContainer::start().unwrap();
+        let redis_settings = redis.redis_settings();
         let rate_limiter = RateLimiterFactory::fixed_window()
             .with_window_size(window_size)
             .with_window_duration(window_duration)
@@ -197,10 +1044,15 @@ mod test {
                 assert_eq!(
                     allowed_res.remaining_request_counter,
                     cmp::max(0, window_size as i64 - n as i64) as u64
-                )
+                );
+                assert_eq!(allowed_res.status.limit, window_size);
+                assert_eq!(allowed_res.status.window_duration, window_duration);
+                assert_eq!(allowed_res.status.used, n);
+                assert!(allowed_res.status.reset_at > SystemTime::now());
             } else {
                 let tolerance_secs = window_duration.as_secs() * 5 / 100;
                 let throttled_res = res.as_throttled();
+                assert_eq!(throttled_res.status.used, n);
                 let retry_in_secs = throttled_res.retry_in.as_secs();
                 assert!(
                     retry_in_secs > 0 && retry_in_secs <= window_duration.as_secs(),
@@ -215,8 +1067,1306 @@ mod test {
         }
     }
 
-    fn generate_random_ip() -> IpAddr {
-        let mut rng = rand::thread_rng();
-        IpAddr::V4(Ipv4Addr::new(rng.gen(), rng.gen(), rng.gen(), rng.gen()))
+    #[test]
+    fn should_support_sub_second_windows() {
+        //arrange
+        let redis = RedisContainer::start().unwrap();
+        let window_size = 2;
+        let window_duration = Duration::from_millis(200);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(window_size)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for _ in 0..window_size {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        assert!(
+            throttled_res.retry_in > Duration::ZERO && throttled_res.retry_in <= window_duration,
+            "retry in is not in valid range"
+        );
+
+        thread::sleep(window_duration + Duration::from_millis(50));
+        let allowed_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_allowed();
+        assert_eq!(allowed_res.remaining_request_counter, window_size - 1);
+    }
+
+    #[test]
+    fn should_apply_onboarding_ramp_to_new_request_identifiers() {
+        //arrange
+        let redis = RedisContainer::start().unwrap();
+        let window_size = 10;
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(window_size)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis.redis_settings())
+            .with_onboarding_ramp(OnboardingRamp {
+                initial_fraction: 0.2,
+                ramp_duration: Duration::from_secs(24 * 60 * 60),
+            })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Custom {
+            key: "api_key".to_string(),
+            value: Uuid::new_v4().to_string(),
+        };
+
+        //act & assert
+        for n in 1..=2 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.status.limit, 2);
+            assert_eq!(allowed_res.remaining_request_counter, 2 - n);
+        }
+        rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+    }
+
+    #[test]
+    fn should_export_and_purge_request_identifier_state() {
+        //arrange
+        let redis = RedisContainer::start().unwrap();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap();
+
+        //act
+        let exported = rate_limiter
+            .export_identifier(request_identifier.clone())
+            .unwrap();
+        let deleted_keys = rate_limiter
+            .purge_identifier(request_identifier.clone())
+            .unwrap();
+
+        //assert
+        assert_eq!(exported.entries.len(), 1);
+        assert_eq!(
+            exported.entries[0].value,
+            StoredValue::Value("1".to_string())
+        );
+        assert!(exported.entries[0].expire_in.is_some());
+        assert_eq!(deleted_keys, 1);
+        assert!(rate_limiter
+            .export_identifier(request_identifier)
+            .unwrap()
+            .entries
+            .is_empty());
+    }
+
+    #[rstest]
+    #[case::transaction(false, false)]
+    #[case::scripted_checks(true, false)]
+    #[case::redis_functions(false, true)]
+    fn should_check_request_eligibility_against_redis_mock(
+        #[case] scripted_checks: bool,
+        #[case] redis_functions: bool,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let window_size = 3;
+        let window_duration = Duration::from_secs(60);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(window_size)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_scripted_checks(scripted_checks)
+            .with_redis_functions(redis_functions)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=window_size {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, window_size - n);
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+        assert!(
+            throttled_res.retry_in > Duration::ZERO && throttled_res.retry_in <= window_duration,
+            "retry in is not in valid range"
+        );
+        assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+    }
+
+    #[test]
+    fn should_shed_requests_within_quota_while_overloaded_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let load_shedder = LoadShedder::new(LoadSheddingPolicy {
+            shed_above: 0.5,
+            min_fraction: 0.5,
+        });
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(4)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_load_shedding(load_shedder.clone())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        load_shedder.report_load(1.0);
+        let responses: Vec<RateLimiterResponse> = (0..5)
+            .map(|_| {
+                rate_limiter
+                    .check_request(request_identifier.clone())
+                    .unwrap()
+            })
+            .collect();
+
+        //assert
+        let reasons: Vec<Option<ThrottleReason>> = responses
+            .into_iter()
+            .map(|response| match response {
+                RateLimiterResponse::RequestAllowed(allowed) => {
+                    assert_eq!(allowed.status.limit, 2);
+                    None
+                }
+                RateLimiterResponse::RequestThrottled(throttled) => Some(throttled.reason),
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                None,
+                None,
+                Some(ThrottleReason::LoadShed),
+                Some(ThrottleReason::LoadShed),
+                Some(ThrottleReason::QuotaExceeded),
+            ]
+        );
+    }
+
+    #[test]
+    fn should_throttle_locally_while_adaptive_limits_are_tightened_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(10)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_adaptive_limits(AdaptivePolicy {
+                target_latency: Duration::from_secs(1),
+                min_fraction: 0.5,
+                adjust_interval: Duration::from_secs(1),
+            })
+            .build()
+            .unwrap();
+        let adaptive_limits = rate_limiter.adaptive_limits.as_ref().unwrap();
+        let started_at = Instant::now();
+        for second in [0, 3_600] {
+            adaptive_limits.record_latency(
+                Duration::from_secs(10),
+                started_at + Duration::from_secs(second),
+            );
+        }
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let responses: Vec<RateLimiterResponse> = (0..12)
+            .map(|_| {
+                rate_limiter
+                    .check_request(request_identifier.clone())
+                    .unwrap()
+            })
+            .collect();
+
+        //assert
+        let allowed = responses
+            .iter()
+            .filter(|response| matches!(response, RateLimiterResponse::RequestAllowed(_)))
+            .count();
+        assert_eq!(allowed, 8);
+        // the requests over the tightened limit are still within the configured one
+        for response in responses.into_iter().skip(allowed) {
+            assert_eq!(response.as_throttled().reason, ThrottleReason::LoadShed);
+        }
+        // only the first throttled request was checked against Redis
+        assert_eq!(rate_limiter.inspect(request_identifier).unwrap().count, 9);
+    }
+
+    #[test]
+    fn should_expire_first_seen_key_after_onboarding_ramp_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_onboarding_ramp(OnboardingRamp {
+                initial_fraction: 0.5,
+                ramp_duration: Duration::from_secs(3600),
+            })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap();
+
+        //assert
+        let exported = rate_limiter.export_identifier(request_identifier).unwrap();
+        let first_seen = exported
+            .entries
+            .iter()
+            .find(|entry| entry.key.ends_with(":first_seen"))
+            .unwrap();
+        assert!(first_seen
+            .expire_in
+            .is_some_and(|expire_in| expire_in <= Duration::from_secs(3660)
+                && expire_in > Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn should_count_cost_of_requests_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(10)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_cost_function(EndpointWeights {
+                weights: HashMap::from([("/search".to_string(), 4)]),
+                default_weight: 1,
+            })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let search = RequestContext::endpoint("/search");
+
+        //act & assert
+        for expected_remaining in [6, 2] {
+            let allowed_res = rate_limiter
+                .check_request_with_context(request_identifier.clone(), &search)
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, expected_remaining);
+        }
+        let throttled_res = rate_limiter
+            .check_request_with_context(request_identifier.clone(), &search)
+            .unwrap()
+            .as_throttled();
+        assert_eq!(throttled_res.status.used, 12);
+        rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+    }
+
+    #[test]
+    fn should_throttle_requests_of_huge_cost_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(10)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_cost_function(PayloadSizeCost { unit_bytes: 1 })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let huge_upload = RequestContext::default().with_payload_size(u64::MAX);
+
+        //act
+        let responses: Vec<RateLimiterResponse> = (0..2)
+            .map(|_| {
+                rate_limiter
+                    .check_request_with_context(request_identifier.clone(), &huge_upload)
+                    .unwrap()
+            })
+            .collect();
+        let next_res = rate_limiter.check_request(request_identifier).unwrap();
+
+        //assert
+        for response in responses {
+            assert_eq!(
+                response.as_throttled().reason,
+                ThrottleReason::QuotaExceeded
+            );
+        }
+        assert_eq!(next_res.as_throttled().status.used, 23);
+    }
+
+    #[test]
+    fn should_track_reputation_of_throttled_request_identifiers_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_reputation(ReputationPolicy {
+                half_life: Duration::from_secs(24 * 60 * 60),
+                throttle_penalty: 1.0,
+            })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        for _ in 0..3 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap();
+        }
+
+        //assert
+        let score = rate_limiter.reputation(request_identifier).unwrap();
+        assert!(
+            score > 1.99 && score <= 2.0,
+            "two throttled requests should yield a score of about 2"
+        );
+    }
+
+    #[test]
+    fn should_apply_updated_limits_to_all_clones_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let cloned_rate_limiter = rate_limiter.clone();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        cloned_rate_limiter.update_limits(3, Duration::from_secs(30));
+
+        //assert
+        assert_eq!(rate_limiter.window_size(), 3);
+        assert_eq!(rate_limiter.window_validity(), Duration::from_secs(30));
+        for n in 1..=3 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 3 - n);
+            assert_eq!(allowed_res.status.window_duration, Duration::from_secs(30));
+        }
+        rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+    }
+
+    #[test]
+    fn should_apply_limit_overrides_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_limit_overrides(true)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        rate_limiter
+            .set_limit_override(
+                request_identifier.clone(),
+                &LimitOverride {
+                    window_size: Some(3),
+                    window_duration: None,
+                },
+            )
+            .unwrap();
+
+        //act & assert
+        for n in 1..=3 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 3 - n);
+            assert_eq!(allowed_res.status.limit, 3);
+        }
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        assert!(rate_limiter
+            .remove_limit_override(request_identifier.clone())
+            .unwrap());
+        assert!(!rate_limiter
+            .remove_limit_override(request_identifier)
+            .unwrap());
+    }
+
+    fn generate_random_ip() -> IpAddr {
+        let mut rng = rand::thread_rng();
+        IpAddr::V4(Ipv4Addr::new(rng.gen(), rng.gen(), rng.gen(), rng.gen()))
+    }
+
+    #[test]
+    fn should_detect_redis_capabilities_once_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let cloned_rate_limiter = rate_limiter.clone();
+
+        //act
+        let capabilities = rate_limiter.capabilities().unwrap();
+
+        //assert
+        assert_eq!(capabilities.version, RedisVersion::new(7, 2, 0));
+        assert!(capabilities.supports_functions());
+        assert_eq!(cloned_rate_limiter.capabilities.get(), Some(&capabilities));
+    }
+
+    #[rstest]
+    #[case::redis_5(RedisVersion::new(5, 0, 14), vec![vec!["PEXPIRE", "60000"]])]
+    #[case::redis_6(RedisVersion::new(6, 2, 14), vec![vec!["PEXPIRE", "60000"]])]
+    #[case::redis_7(RedisVersion::new(7, 2, 0), vec![vec!["PEXPIRE", "60000", "NX"]; 3])]
+    fn should_set_expiry_supported_by_redis_version_against_redis_mock(
+        #[case] version: RedisVersion,
+        #[case] expected_expiries: Vec<Vec<&str>>,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start_with_version(version);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let key = rate_limiter.build_request_key(request_identifier.clone());
+
+        //act
+        for _ in 0..2 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+
+        //assert
+        let expiries: Vec<Vec<String>> = redis_mock
+            .commands()
+            .into_iter()
+            .filter(|command| command[0] == "PEXPIRE")
+            .collect();
+        let expected_expiries: Vec<Vec<String>> = expected_expiries
+            .into_iter()
+            .map(|expiry| {
+                let mut command = vec![expiry[0].to_string(), key.clone()];
+                command.extend(expiry[1..].iter().map(|arg| arg.to_string()));
+                command
+            })
+            .collect();
+        assert_eq!(expiries, expected_expiries);
+        assert!(throttled_res.retry_in > Duration::ZERO);
+        assert!(throttled_res.retry_in <= Duration::from_secs(60));
+    }
+
+    #[rstest]
+    #[case::redis_5(RedisVersion::new(5, 0, 14), "EVALSHA")]
+    #[case::redis_6(RedisVersion::new(6, 2, 14), "EVALSHA")]
+    #[case::redis_7(RedisVersion::new(7, 2, 0), "FCALL")]
+    fn should_call_functions_only_if_supported_by_redis_version_against_redis_mock(
+        #[case] version: RedisVersion,
+        #[case] expected_command: &str,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start_with_version(version);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_redis_functions(true)
+            .build()
+            .unwrap();
+
+        //act
+        rate_limiter
+            .check_request(RequestIdentifier::Ip(generate_random_ip()))
+            .unwrap()
+            .as_allowed();
+
+        //assert
+        let commands: Vec<String> = redis_mock
+            .commands()
+            .into_iter()
+            .map(|command| command[0].clone())
+            .collect();
+        assert!(commands.contains(&expected_command.to_string()));
+        assert!(!commands.contains(&"FCALL".to_string()) || expected_command == "FCALL");
+    }
+
+    #[rstest]
+    #[case::redis_5(RedisVersion::new(5, 0, 14))]
+    #[case::redis_6(RedisVersion::new(6, 2, 14))]
+    fn should_check_costly_requests_before_redis_7_against_redis_mock(
+        #[case] version: RedisVersion,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start_with_version(version);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(8)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_cost_function(EndpointWeights {
+                weights: HashMap::from([("/search".to_string(), 4)]),
+                default_weight: 1,
+            })
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let search = RequestContext::endpoint("/search");
+
+        //act & assert
+        for expected_remaining in [4, 0] {
+            let allowed_res = rate_limiter
+                .check_request_with_context(request_identifier.clone(), &search)
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, expected_remaining);
+        }
+        rate_limiter
+            .check_request_with_context(request_identifier, &search)
+            .unwrap()
+            .as_throttled();
+    }
+
+    #[test]
+    fn should_group_hashed_counters_by_bucket_and_window() {
+        let key = "rl:ip_1.2.3.4";
+
+        let hash_key = hashed_counters_key(key, 16, 42);
+
+        assert!(hash_key.starts_with("rl:hashed:"));
+        assert!(hash_key.ends_with(":42"));
+        assert_eq!(hash_key, hashed_counters_key(key, 16, 42));
+        assert_eq!(hashed_counters_key(key, 1, 42), "rl:hashed:0:42");
+        assert_eq!(hashed_counters_key(key, 0, 42), "rl:hashed:0:42");
+    }
+
+    #[test]
+    fn should_check_requests_with_hash_storage_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_hash_storage(1)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let other_request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=2 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 2 - n);
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+        assert!(throttled_res.retry_in <= Duration::from_secs(60));
+        rate_limiter
+            .check_request(other_request_identifier)
+            .unwrap()
+            .as_allowed();
+    }
+
+    #[test]
+    fn should_start_new_aligned_window_with_clock_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        // start of a minute far enough in the future for Redis not to expire the hashes right away
+        let clock = ManualClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(60 * 60 * 24 * 365 * 100),
+        );
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_hash_storage(1)
+            .with_clock(clock.clone())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let allowed_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_allowed();
+        clock.advance(Duration::from_secs(59));
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        clock.advance(Duration::from_secs(1));
+        let next_window_res = rate_limiter.check_request(request_identifier).unwrap();
+
+        //assert
+        assert_eq!(allowed_res.status.reset_at, clock.now());
+        assert_eq!(throttled_res.retry_in, Duration::from_secs(1));
+        next_window_res.as_allowed();
+    }
+
+    #[rstest]
+    #[case::transaction(false, false)]
+    #[case::scripted_checks(true, false)]
+    #[case::redis_functions(false, true)]
+    fn should_reset_jittered_window_when_its_key_expires_against_redis_mock(
+        #[case] scripted_checks: bool,
+        #[case] redis_functions: bool,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let clock = ManualClock::default();
+        let window_duration = Duration::from_secs(60);
+        let max_jitter = Duration::from_secs(5);
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_scripted_checks(scripted_checks)
+            .with_redis_functions(redis_functions)
+            .with_expiry_jitter(max_jitter)
+            .with_clock(clock.clone())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let allowed_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_allowed();
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        let expire_in = rate_limiter
+            .inspect(request_identifier)
+            .unwrap()
+            .expire_in
+            .unwrap();
+
+        //assert
+        let tolerance = Duration::from_millis(100);
+        let key_expires_at = clock.now() + expire_in;
+        assert!(allowed_res.status.reset_at >= key_expires_at);
+        assert!(allowed_res.status.reset_at <= key_expires_at + tolerance);
+        assert!(throttled_res.status.reset_at >= key_expires_at);
+        assert!(
+            throttled_res.retry_in >= expire_in && throttled_res.retry_in <= expire_in + tolerance
+        );
+        assert!(expire_in <= window_duration + max_jitter);
+    }
+
+    #[rstest]
+    #[case::transaction(CheckMode::Transaction, vec!["WATCH", "MULTI", "INCR", "PEXPIRE", "PTTL", "EXEC", "UNWATCH"])]
+    #[case::script(CheckMode::Script, vec!["EVALSHA"])]
+    #[case::function(CheckMode::Function, vec!["FCALL"])]
+    fn should_list_check_command_names(#[case] check_mode: CheckMode, #[case] expected: Vec<&str>) {
+        let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+
+        assert_eq!(rate_limiter.check_command_names(check_mode), expected);
+    }
+
+    #[test]
+    fn should_check_requests_with_shared_connection_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_shared_connections(1)
+            .build()
+            .unwrap();
+        let clone = rate_limiter.clone();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for rate_limiter in [&rate_limiter, &clone] {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        clone
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+        assert_eq!(redis_mock.connections(), 1);
+    }
+
+    #[test]
+    fn should_wait_for_shared_connections_held_by_concurrent_checks_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(100)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_shared_connections(2)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..5 {
+                        rate_limiter
+                            .check_request(request_identifier.clone())
+                            .unwrap()
+                            .as_allowed();
+                    }
+                });
+            }
+        });
+
+        //assert
+        assert_eq!(redis_mock.connections(), 2);
+    }
+
+    #[test]
+    fn should_reuse_shared_connection_after_failed_check_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_shared_connections(1)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        // a sorted set can't be incremented like a counter
+        RateLimiterFactory::sliding_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap()
+            .check_request(request_identifier.clone())
+            .unwrap();
+        let connections = redis_mock.connections();
+
+        //act
+        let first_res = rate_limiter.check_request(request_identifier.clone());
+        let second_res = rate_limiter.check_request(request_identifier);
+
+        //assert
+        assert!(first_res.is_err());
+        assert!(second_res.is_err());
+        assert_eq!(redis_mock.connections() - connections, 1);
+    }
+
+    #[test]
+    fn should_count_each_coalesced_request_once_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(20)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_request_coalescing(true)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let responses: Vec<RateLimiterResponse> = thread::scope(|scope| {
+            let checks: Vec<_> = (0..30)
+                .map(|_| scope.spawn(|| rate_limiter.check_request(request_identifier.clone())))
+                .collect();
+            checks
+                .into_iter()
+                .map(|check| check.join().unwrap().unwrap())
+                .collect()
+        });
+
+        //assert
+        let mut used: Vec<u64> = responses
+            .iter()
+            .map(|response| match response {
+                RateLimiterResponse::RequestAllowed(allowed) => allowed.status.used,
+                RateLimiterResponse::RequestThrottled(throttled) => throttled.status.used,
+            })
+            .collect();
+        used.sort();
+        assert_eq!(used, (1..=30).collect::<Vec<u64>>());
+        let allowed = responses
+            .iter()
+            .filter(|response| matches!(response, RateLimiterResponse::RequestAllowed(_)))
+            .count();
+        assert_eq!(allowed, 20);
+    }
+
+    #[cfg(feature = "pool")]
+    #[test]
+    fn should_check_requests_with_pooled_connections_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_connection_pool(1)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=2 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 2 - n);
+        }
+        rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+    }
+
+    #[test]
+    fn should_shard_request_identifiers_across_redis_mocks() {
+        //arrange
+        let redis_mocks = [RedisMock::start(), RedisMock::start()];
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_shards(redis_mocks.iter().map(RedisMock::redis_settings).collect())
+            .build()
+            .unwrap();
+        let single_server_rate_limiters: Vec<_> = redis_mocks
+            .iter()
+            .map(|redis_mock| {
+                RateLimiterFactory::fixed_window()
+                    .with_redis_settings(redis_mock.redis_settings())
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        let mut keys_per_server = [0; 2];
+
+        for _ in 0..20 {
+            let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+            //act
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            let throttled_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_throttled();
+
+            //assert
+            assert_eq!(allowed_res.remaining_request_counter, 0);
+            assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+            let owners: Vec<usize> = single_server_rate_limiters
+                .iter()
+                .enumerate()
+                .filter(|(_, single_server_rate_limiter)| {
+                    !single_server_rate_limiter
+                        .export_identifier(request_identifier.clone())
+                        .unwrap()
+                        .entries
+                        .is_empty()
+                })
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(owners.len(), 1, "key stored on {:?}", owners);
+            keys_per_server[owners[0]] += 1;
+        }
+        assert!(keys_per_server.iter().all(|&count| count > 0));
+    }
+
+    #[test]
+    fn should_check_requests_against_a_quorum_of_redis_mocks() {
+        //arrange
+        let redis_mocks = [RedisMock::start(), RedisMock::start()];
+        let unreachable_master = RedisSettings {
+            host: "127.0.0.1".to_string(),
+            port: 1,
+            ..RedisSettings::default()
+        };
+        let masters: Vec<RedisSettings> = redis_mocks
+            .iter()
+            .map(RedisMock::redis_settings)
+            .chain([unreachable_master.clone()])
+            .collect();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_quorum(masters)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=2 {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, 2 - n);
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+        assert!(rate_limiter.purge_identifier(request_identifier).unwrap() > 0);
+    }
+
+    #[test]
+    fn should_yield_an_error_without_a_quorum_of_redis_mocks() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let unreachable_master = RedisSettings {
+            host: "127.0.0.1".to_string(),
+            port: 1,
+            ..RedisSettings::default()
+        };
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_quorum(vec![
+                redis_mock.redis_settings(),
+                unreachable_master.clone(),
+                unreachable_master,
+            ])
+            .build()
+            .unwrap();
+
+        //act
+        let res = rate_limiter.check_request(RequestIdentifier::Ip(generate_random_ip()));
+
+        //assert
+        assert!(matches!(res, Err(RateLimiterError::IoError(_))));
+    }
+
+    #[test]
+    fn should_sum_regional_counters_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let regions = vec!["eu-west-1".to_string(), "us-east-1".to_string()];
+        let regional_rate_limiter = |local_region: &str| {
+            RateLimiterFactory::fixed_window()
+                .with_window_size(3)
+                .with_window_duration(Duration::from_secs(60))
+                .with_redis_settings(redis_mock.redis_settings())
+                .with_regional_counters(RegionalCounters {
+                    local_region: local_region.to_string(),
+                    regions: regions.clone(),
+                })
+                .build()
+                .unwrap()
+        };
+        let eu_rate_limiter = regional_rate_limiter("eu-west-1");
+        let us_rate_limiter = regional_rate_limiter("us-east-1");
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for (rate_limiter, remaining_request_counter) in [
+            (&eu_rate_limiter, 2),
+            (&us_rate_limiter, 1),
+            (&eu_rate_limiter, 0),
+        ] {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(
+                allowed_res.remaining_request_counter,
+                remaining_request_counter
+            );
+        }
+        let throttled_res = us_rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+        assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+        assert!(throttled_res.retry_in <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn should_notify_observer_of_each_check_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let observer = Arc::new(RecordingObserver::default());
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_observer(observer.clone())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let key = rate_limiter.build_request_key(request_identifier.clone());
+
+        //act
+        for _ in 0..2 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap();
+        }
+
+        //assert
+        assert_eq!(
+            *observer.decisions.lock().unwrap(),
+            vec![(key.clone(), "allowed"), (key, "throttled")]
+        );
+    }
+
+    #[test]
+    fn should_report_redis_latency_of_each_check_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let observer = Arc::new(RecordingObserver::default());
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_observer(observer.clone())
+            .build()
+            .unwrap();
+
+        //act
+        for _ in 0..3 {
+            rate_limiter
+                .check_request(RequestIdentifier::Ip(generate_random_ip()))
+                .unwrap();
+        }
+
+        //assert
+        let redis_latencies = observer.redis_latencies.lock().unwrap();
+        assert_eq!(redis_latencies.len(), 3);
+        assert!(redis_latencies
+            .iter()
+            .all(|latency| latency.total() == latency.connect + latency.commands));
+    }
+
+    #[test]
+    fn should_track_top_offenders_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_offender_tracking(Duration::from_secs(24 * 60 * 60))
+            .build()
+            .unwrap();
+        let worst_offender = RequestIdentifier::Ip(generate_random_ip());
+        let other_offender = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        for _ in 0..4 {
+            rate_limiter.check_request(worst_offender.clone()).unwrap();
+        }
+        for _ in 0..2 {
+            rate_limiter.check_request(other_offender.clone()).unwrap();
+        }
+
+        //assert
+        assert_eq!(
+            rate_limiter.top_offenders(1).unwrap(),
+            vec![Offender {
+                key: rate_limiter.build_request_key(worst_offender.clone()),
+                throttled_requests: 3
+            }]
+        );
+        rate_limiter.purge_identifier(worst_offender).unwrap();
+        assert_eq!(
+            rate_limiter.top_offenders(10).unwrap(),
+            vec![Offender {
+                key: rate_limiter.build_request_key(other_offender),
+                throttled_requests: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn should_report_usage_of_allowed_requests_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_usage_reporting(true)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        for _ in 0..3 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap();
+        }
+
+        //assert
+        let now = SystemTime::now();
+        for period in [UsagePeriod::day_of(now), UsagePeriod::month_of(now)] {
+            assert_eq!(
+                rate_limiter
+                    .usage(request_identifier.clone(), period)
+                    .unwrap(),
+                2
+            );
+        }
+        rate_limiter
+            .purge_identifier(request_identifier.clone())
+            .unwrap();
+        assert_eq!(
+            rate_limiter
+                .usage(request_identifier, UsagePeriod::month_of(now))
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn should_list_request_keys_with_counts_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_reputation(ReputationPolicy {
+                half_life: Duration::from_secs(60),
+                throttle_penalty: 1.0,
+            })
+            .with_window_size(1)
+            .build()
+            .unwrap();
+        let ip = RequestIdentifier::Ip(generate_random_ip());
+        for _ in 0..2 {
+            rate_limiter.check_request(ip.clone()).unwrap();
+        }
+        rate_limiter
+            .check_request(RequestIdentifier::Internal("billing".to_string()))
+            .unwrap();
+
+        //act
+        let mut listed_keys = vec![];
+        let mut cursor = Some(ListCursor::default());
+        while let Some(current_cursor) = cursor {
+            let listing = rate_limiter.list_keys("ip_*", current_cursor).unwrap();
+            listed_keys.extend(listing.keys);
+            cursor = listing.next_cursor;
+        }
+
+        //assert
+        assert_eq!(
+            listed_keys,
+            vec![ListedKey {
+                key: rate_limiter.build_request_key(ip),
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn should_inspect_request_identifier_without_counting_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_limit_overrides(true)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        rate_limiter
+            .set_limit_override(
+                request_identifier.clone(),
+                &LimitOverride {
+                    window_size: Some(1),
+                    window_duration: None,
+                },
+            )
+            .unwrap();
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap();
+
+        //act
+        let inspection = rate_limiter.inspect(request_identifier.clone()).unwrap();
+
+        //assert
+        assert_eq!(inspection.count, 1);
+        assert_eq!(inspection.limit, 1);
+        assert_eq!(inspection.window_duration, Duration::from_secs(60));
+        assert!(inspection.overridden);
+        assert!(inspection.is_throttled());
+        assert!(inspection.expire_in.unwrap() <= Duration::from_secs(60));
+        assert_eq!(rate_limiter.inspect(request_identifier).unwrap().count, 1);
+    }
+
+    #[test]
+    fn should_migrate_state_between_redis_mocks() {
+        //arrange
+        let source_mock = RedisMock::start();
+        let target_mock = RedisMock::start();
+        let build_rate_limiter = |redis_mock: &RedisMock| {
+            RateLimiterFactory::fixed_window()
+                .with_window_size(3)
+                .with_window_duration(Duration::from_secs(60))
+                .with_redis_settings(redis_mock.redis_settings())
+                .build()
+                .unwrap()
+        };
+        let source = build_rate_limiter(&source_mock);
+        let target = build_rate_limiter(&target_mock);
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        for _ in 0..2 {
+            source.check_request(request_identifier.clone()).unwrap();
+        }
+
+        //act
+        let snapshot = source.export_state().unwrap();
+        let restored = target.import_state(&snapshot).unwrap();
+
+        //assert
+        assert_eq!(restored, 1);
+        let inspection = target.inspect(request_identifier).unwrap();
+        assert_eq!(inspection.count, 2);
+        assert!(inspection.expire_in.unwrap() <= Duration::from_secs(60));
     }
 }
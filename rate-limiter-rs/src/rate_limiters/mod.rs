@@ -1,3 +1,222 @@
 //! Module that holds the rate limiter implementation of this crate.
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use crate::{capabilities::RedisCapabilities, errors::RateLimiterError};
+
 pub mod fixed_window;
 pub mod sliding_window;
+
+/// Represents the limits of a window based rate limiter. Limits are held in atomics, so that
+/// they can be updated on a live rate limiter without rebuilding it, and shared by all its clones.
+#[derive(Debug)]
+pub(crate) struct WindowLimits {
+    window_size: AtomicU64,
+    window_duration_nanos: AtomicU64,
+}
+
+impl WindowLimits {
+    pub(crate) fn new(window_size: u64, window_duration: Duration) -> Self {
+        WindowLimits {
+            window_size: AtomicU64::new(window_size),
+            window_duration_nanos: AtomicU64::new(as_nanos(window_duration)),
+        }
+    }
+
+    pub(crate) fn window_size(&self) -> u64 {
+        self.window_size.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn window_duration(&self) -> Duration {
+        Duration::from_nanos(self.window_duration_nanos.load(Ordering::Relaxed))
+    }
+
+    /// Updates both limits. The two values are not updated atomically as a pair: checks running
+    /// concurrently with an update might briefly observe the new size with the old duration.
+    pub(crate) fn update(&self, window_size: u64, window_duration: Duration) {
+        self.window_size.store(window_size, Ordering::Relaxed);
+        self.window_duration_nanos
+            .store(as_nanos(window_duration), Ordering::Relaxed);
+    }
+}
+
+/// Enum that represents how the Redis commands of a check are run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CheckMode {
+    /// In a `WATCH`/`MULTI`/`EXEC` transaction
+    Transaction,
+    /// In a cached Lua script, invoked with `EVALSHA`
+    Script,
+    /// In a Redis Function, invoked with `FCALL`
+    Function,
+}
+
+impl CheckMode {
+    /// Returns how checks are run, according to the rate limiter configuration. Redis Functions
+    /// are only used on servers supporting them, falling back to scripts on older servers.
+    pub(crate) fn negotiate(
+        scripted_checks: bool,
+        redis_functions: bool,
+        capabilities: impl FnOnce() -> Result<RedisCapabilities, RateLimiterError>,
+    ) -> Result<Self, RateLimiterError> {
+        if redis_functions {
+            if capabilities()?.supports_functions() {
+                return Ok(CheckMode::Function);
+            }
+            return Ok(CheckMode::Script);
+        }
+
+        if scripted_checks {
+            Ok(CheckMode::Script)
+        } else {
+            Ok(CheckMode::Transaction)
+        }
+    }
+}
+
+/// Utility method that returns the given duration in nanoseconds, saturating at about 584 years.
+fn as_nanos(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// Utility method that returns the given duration as a Redis expiry, in milliseconds.
+/// Durations shorter than one millisecond are rounded up, as a `PEXPIRE` of 0 would
+/// immediately delete the key instead of letting it live for the configured window.
+pub(crate) fn as_expiry_millis(duration: Duration) -> u64 {
+    duration.as_millis().max(1) as u64
+}
+
+/// Utility method that returns the given duration as a Redis expiry, in milliseconds, lengthened
+/// by a random jitter of up to the given one, if any, so that the keys created at the same moment
+/// don't all expire, and get recreated, at the same instant.
+pub(crate) fn as_jittered_expiry_millis(duration: Duration, jitter: Option<Duration>) -> u64 {
+    let jitter_millis = jitter.map_or(0, |jitter| jitter.as_millis() as u64);
+    if jitter_millis == 0 {
+        return as_expiry_millis(duration);
+    }
+
+    let random = RandomState::new().build_hasher().finish();
+    as_expiry_millis(duration) + random % (jitter_millis + 1)
+}
+
+/// Represents the current window of the given duration, aligned to the clock, so that all the
+/// nodes incrementing a counter in the same window agree on its boundaries.
+#[derive(Debug, PartialEq)]
+pub(crate) struct AlignedWindow {
+    /// The index of the window since the epoch
+    pub(crate) index: u64,
+    /// The end of the window, as milliseconds since the epoch
+    pub(crate) end_millis: u64,
+    /// How long until the end of the window, in milliseconds
+    pub(crate) expire_in_millis: u64,
+}
+
+impl AlignedWindow {
+    /// Returns the window of the given duration including the given current time.
+    pub(crate) fn current(
+        window_duration: Duration,
+        now: SystemTime,
+    ) -> Result<Self, RateLimiterError> {
+        let now_millis = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_e| RateLimiterError::ComputeError)?
+            .as_millis() as u64;
+        Ok(Self::at(now_millis, window_duration))
+    }
+
+    /// Returns the window of the given duration including the given time, in milliseconds.
+    fn at(now_millis: u64, window_duration: Duration) -> Self {
+        let window_millis = as_expiry_millis(window_duration);
+        let index = now_millis / window_millis;
+        let end_millis = (index + 1) * window_millis;
+        AlignedWindow {
+            index,
+            end_millis,
+            expire_in_millis: end_millis - now_millis,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{
+        as_expiry_millis, as_jittered_expiry_millis, AlignedWindow, CheckMode, WindowLimits,
+    };
+    use crate::capabilities::{RedisCapabilities, RedisVersion};
+
+    #[test]
+    fn as_expiry_millis_should_keep_millisecond_precision() {
+        assert_eq!(as_expiry_millis(Duration::from_millis(200)), 200);
+        assert_eq!(as_expiry_millis(Duration::from_secs(60)), 60_000);
+    }
+
+    #[test]
+    fn as_expiry_millis_should_round_up_sub_millisecond_durations() {
+        assert_eq!(as_expiry_millis(Duration::from_micros(10)), 1);
+    }
+
+    #[test]
+    fn as_jittered_expiry_millis_should_lengthen_expiry_by_up_to_jitter() {
+        assert_eq!(
+            as_jittered_expiry_millis(Duration::from_secs(60), None),
+            60_000
+        );
+        for _ in 0..100 {
+            let expiry_millis =
+                as_jittered_expiry_millis(Duration::from_secs(60), Some(Duration::from_secs(5)));
+            assert!((60_000..=65_000).contains(&expiry_millis));
+        }
+    }
+
+    #[test]
+    fn should_align_windows_to_the_clock() {
+        assert_eq!(
+            AlignedWindow::at(125_000, Duration::from_secs(60)),
+            AlignedWindow {
+                index: 2,
+                end_millis: 180_000,
+                expire_in_millis: 55_000,
+            }
+        );
+    }
+
+    #[test]
+    fn should_update_window_limits() {
+        let limits = WindowLimits::new(5, Duration::from_secs(60));
+
+        limits.update(2, Duration::from_millis(1500));
+
+        assert_eq!(limits.window_size(), 2);
+        assert_eq!(limits.window_duration(), Duration::from_millis(1500));
+    }
+
+    #[rstest]
+    #[case::transaction(false, false, RedisVersion::new(7, 2, 0), CheckMode::Transaction)]
+    #[case::script(true, false, RedisVersion::new(7, 2, 0), CheckMode::Script)]
+    #[case::function(false, true, RedisVersion::new(7, 2, 0), CheckMode::Function)]
+    #[case::function_on_redis_6(false, true, RedisVersion::new(6, 2, 0), CheckMode::Script)]
+    fn should_negotiate_check_mode(
+        #[case] scripted_checks: bool,
+        #[case] redis_functions: bool,
+        #[case] version: RedisVersion,
+        #[case] expected: CheckMode,
+    ) {
+        let check_mode = CheckMode::negotiate(scripted_checks, redis_functions, || {
+            Ok(RedisCapabilities {
+                version,
+                modules: vec![],
+            })
+        })
+        .unwrap();
+
+        assert_eq!(check_mode, expected);
+    }
+}
@@ -16,7 +16,8 @@
 //! let rate_limiter = RateLimiterFactory::sliding_window()
 //!     .with_redis_settings(RedisSettings{
 //!         host: "127.0.0.1".to_string(),
-//!         port: 7379
+//!         port: 7379,
+//!         ..RedisSettings::default()
 //!     })
 //!     .build()
 //!     .unwrap();
@@ -26,38 +27,781 @@
 //! let rate_limiter_response = rate_limiter.check_request(request_id).unwrap();
 //!
 //! match rate_limiter_response {
-//!     RateLimiterResponse::RequestAllowed(RequestAllowed {remaining_request_counter}) => {
+//!     RateLimiterResponse::RequestAllowed(RequestAllowed {remaining_request_counter, ..}) => {
 //!         println!("Request allowed! Remaining request counter is {0}.", remaining_request_counter);
 //!     },
-//!     RateLimiterResponse::RequestThrottled(RequestThrottled {retry_in}) => {
+//!     RateLimiterResponse::RequestThrottled(RequestThrottled {retry_in, ..}) => {
 //!         println!("Request throttled! Retry in {0} seconds.", retry_in.as_secs());
 //!     },
 //! }
 //! ```
-use redis::Client as RedisClient;
-use std::time::{Duration, SystemTime};
+use redis::{Client as RedisClient, Connection, Script};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, LazyLock, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
+use super::{as_jittered_expiry_millis, CheckMode, WindowLimits};
 use crate::{
-    errors::RateLimiterError, RateLimiter, RateLimiterResponse, RequestAllowed, RequestThrottled,
+    adaptive::AdaptiveLimits,
+    capabilities::{negotiate, RedisCapabilities},
+    clock::Clock,
+    connection::{ConnectionPool, RedisConnection},
+    cost::{CostFunction, RequestContext},
+    data_subject::{export_keys, identifier_keys, purge_keys, IdentifierData},
+    errors::RateLimiterError,
+    functions::{fcall, SLIDING_WINDOW_CHECK},
+    hash_tags::{request_key, HashTag},
+    inspection::{as_expire_in, inspect_limits, KeyInspection},
+    latency::{report_slow_check, CheckLatency},
+    listing::{list_keys, KeyListing, ListCursor},
+    load_shedding::{throttle_reason, LoadShedder},
+    observer::{notify, RateLimiterObserver},
+    offenders::{top_offenders, Offender, OffenderTracking},
+    onboarding::{OnboardingRamp, ONBOARDING_COMMAND_NAMES},
+    overrides::{
+        delete_override, read_override, write_override, LimitOverride, OVERRIDE_COMMAND_NAMES,
+    },
+    quorum::{on_quorum, QuorumWorkers},
+    reputation::ReputationPolicy,
+    sharding::{shard_for, Shard},
+    snapshot::{export_state, import_entries, SnapshotEntry, StateSnapshot},
+    spans::{record_latency, CheckSpan},
+    throttle_cache::ThrottleCache,
+    usage::{read_usage, record_usage, retained_usage_keys, UsagePeriod},
+    RateLimitStatus, RateLimiter, RateLimiterResponse, RedisAdmin, RequestAllowed,
+    RequestIdentifier, RequestThrottled,
 };
 
 /// Represents a distributed sliding window rate limiter
 /// based on [Redis](https://redis.io/)
 #[derive(Clone)]
 pub struct SlidingWindowRateLimiter {
-    /// The size of the sliding window, that is the maximum number of
-    /// requests allowed in a single window
-    pub window_size: u64,
-
-    /// The duration of the sliding window that the rate limiter takes
-    /// into account when deciding whether to allow or throttle a request
-    pub window_duration: Duration,
+    /// The size of the sliding window, that is the maximum number of requests allowed in a single
+    /// window, and the duration of the sliding window that the rate limiter takes into account
+    /// when deciding whether to allow or throttle a request.
+    /// Both can be updated at runtime with [RedisAdmin::update_limits].
+    pub(crate) limits: Arc<WindowLimits>,
 
     /// The internal client that will be used to fire requests against Redis
     pub redis_client: RedisClient,
+
+    /// The pool the connections to Redis are borrowed from, if configured
+    pub(crate) connection_pool: ConnectionPool,
+
+    /// The optional onboarding ramp applied to newly seen request identifiers
+    pub onboarding_ramp: Option<OnboardingRamp>,
+
+    /// The optional threshold above which checks are reported as slow
+    pub slow_check_threshold: Option<Duration>,
+
+    /// The optional reputation policy applied to request identifiers
+    pub reputation: Option<ReputationPolicy>,
+
+    /// The optional tracking of the most throttled request identifiers
+    pub offender_tracking: Option<OffenderTracking>,
+
+    /// Whether the requests allowed are aggregated per day and per month
+    pub usage_reporting: bool,
+
+    /// Whether the per-key limit overrides stored in Redis are consulted on every check
+    pub limit_overrides: bool,
+
+    /// Whether checks run as a cached Lua script, instead of a transaction
+    pub scripted_checks: bool,
+
+    /// Whether checks run as Redis Functions, on servers supporting them
+    pub redis_functions: bool,
+
+    /// Whether the timestamps of requests are read from the Redis server clock
+    pub redis_time: bool,
+
+    /// The optional maximum number of members retained in the sorted set of a key
+    pub max_members: Option<u64>,
+
+    /// The capabilities of the underlying Redis server, once detected
+    pub(crate) capabilities: Arc<OnceLock<RedisCapabilities>>,
+
+    /// The independent Redis servers keys are sharded across, if configured
+    pub(crate) shards: Arc<[Shard]>,
+
+    /// The read-only replica serving the non-mutating operations, if configured
+    pub(crate) read_replica: Option<Arc<Shard>>,
+
+    /// The optional portion of the request keys wrapped in a hash tag
+    pub hash_tag: Option<HashTag>,
+
+    /// Whether checks are applied against a quorum of independent Redis masters, instead of
+    /// sharding keys across them
+    pub quorum: bool,
+    /// The pools of workers checking requests against each of the masters, in quorum mode
+    pub(crate) quorum_workers: QuorumWorkers,
+
+    /// The optional maximum random jitter added to the expiry of the keys
+    pub expiry_jitter: Option<Duration>,
+
+    /// The optional local cache of throttled verdicts, answering the checks of throttled keys
+    pub throttle_cache: Option<ThrottleCache>,
+
+    /// The optional adaptive limits, tightened while the checks against Redis are slow
+    pub adaptive_limits: Option<AdaptiveLimits>,
+
+    /// The optional load shedding, scaling the budgets with the load reported by the application
+    pub load_shedder: Option<LoadShedder>,
+
+    /// The optional function computing the cost of the requests from their context
+    pub cost_function: Option<Arc<dyn CostFunction>>,
+
+    /// The optional observer notified of the outcome of every check
+    pub(crate) observer: Option<Arc<dyn RateLimiterObserver>>,
+
+    /// The clock the current time is read from
+    pub(crate) clock: Arc<dyn Clock>,
+}
+
+/// The name of the algorithm, used when tracing checks
+const ALGORITHM: &str = "sliding_window";
+
+/// The names of the commands run by every check, logged with slow checks
+const CHECK_COMMAND_NAMES: &[&str] = &[
+    "WATCH",
+    "MULTI",
+    "ZREMRANGEBYSCORE",
+    "ZADD",
+    "ZCOUNT",
+    "ZREVRANGEBYSCORE",
+    "ZRANGE",
+    "PEXPIRE",
+    "EXEC",
+    "UNWATCH",
+];
+
+/// The names of the commands run by every scripted check, logged with slow checks
+const SCRIPTED_CHECK_COMMAND_NAMES: &[&str] = &["EVALSHA"];
+
+/// The names of the commands run by every check calling a Redis Function, logged with slow
+/// checks
+const FUNCTION_CHECK_COMMAND_NAMES: &[&str] = &["FCALL"];
+
+/// The Lua script run by scripted checks. Timestamps are passed as strings, as they don't fit
+/// the double precision numbers used by Lua.
+pub(crate) const CHECK_SCRIPT_SOURCE: &str = r"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', '(' .. ARGV[1])
+redis.call('ZADD', KEYS[1], 'NX', ARGV[2], ARGV[3])
+if tonumber(ARGV[5]) > 0 then
+    redis.call('ZREMRANGEBYRANK', KEYS[1], 0, -tonumber(ARGV[5]) - 1)
+end
+local request_count = redis.call('ZCOUNT', KEYS[1], '-inf', '+inf')
+local quota_freeing_requests = redis.call('ZREVRANGEBYSCORE', KEYS[1], '+inf', '-inf', 'LIMIT', ARGV[6], 1)
+local oldest_requests = redis.call('ZRANGE', KEYS[1], 0, 0)
+redis.call('PEXPIRE', KEYS[1], ARGV[4])
+return {request_count, quota_freeing_requests, oldest_requests}
+";
+
+/// The Lua script run by scripted checks
+static CHECK_SCRIPT: LazyLock<Script> = LazyLock::new(|| Script::new(CHECK_SCRIPT_SOURCE));
+
+/// The sequence appended to the sorted set members, so that requests received by this process
+/// in the same nanosecond are counted separately
+static MEMBER_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// The random identifier of this process appended to the sorted set members, so that requests
+/// received by different nodes in the same nanosecond are counted separately
+static MEMBER_NONCE: LazyLock<u64> = LazyLock::new(|| RandomState::new().build_hasher().finish());
+
+impl SlidingWindowRateLimiter {
+    /// Returns the size of the sliding window, that is the maximum number of requests allowed
+    /// in a single window.
+    pub fn window_size(&self) -> u64 {
+        self.limits.window_size()
+    }
+
+    /// Returns the duration of the sliding window.
+    pub fn window_duration(&self) -> Duration {
+        self.limits.window_duration()
+    }
+
+    /// Returns a connection to the Redis server owning the given key, that is the shard it's
+    /// hashed to, if keys are sharded, or the single underlying server otherwise.
+    fn connection(&self, key: &str) -> Result<RedisConnection, RateLimiterError> {
+        match shard_for(&self.shards, key) {
+            Some(shard) => shard.connection(),
+            None => self.connection_pool.get(&self.redis_client),
+        }
+    }
+
+    /// Checks the request of the given key, with the given cost, answering from the throttle
+    /// cache, if any, and notifying the observer, if any.
+    fn check_request_key(
+        &self,
+        key: &str,
+        cost: u64,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        let check_started_at = Instant::now();
+        // while the adaptive limits are tightened, throttled verdicts are cached anyway
+        let throttle_cache = self.throttle_cache.as_ref().or_else(|| {
+            self.adaptive_limits
+                .as_ref()
+                .and_then(AdaptiveLimits::throttle_cache)
+        });
+        let res = CheckSpan::new(ALGORITHM, key).in_scope(|| match throttle_cache {
+            Some(throttle_cache) => {
+                throttle_cache.check(key, self.clock.now(), || self.check(key, cost))
+            }
+            None => self.check(key, cost),
+        });
+        if let Some(observer) = &self.observer {
+            notify(observer.as_ref(), key, &res, check_started_at.elapsed());
+        }
+
+        res
+    }
+
+    /// Checks the request of the given key, against the Redis server owning it or, in quorum mode,
+    /// against all the masters.
+    fn check(&self, key: &str, cost: u64) -> Result<RateLimiterResponse, RateLimiterError> {
+        let check_mode = CheckMode::negotiate(self.scripted_checks, self.redis_functions, || {
+            self.capabilities()
+        })?;
+        // the scripts and functions count a single request at once
+        let check_mode = if cost > 1 {
+            CheckMode::Transaction
+        } else {
+            check_mode
+        };
+
+        if self.quorum {
+            let rate_limiter = self.clone();
+            let key = key.to_string();
+            return self
+                .quorum_workers
+                .check_on_quorum(&self.shards, move |master| {
+                    rate_limiter.check_key(&key, cost, check_mode, || master.connection())
+                });
+        }
+
+        self.check_key(key, cost, check_mode, || self.connection(key))
+    }
+
+    /// Checks the request of the given key, with the given cost, against the Redis server connected
+    /// by the given function. Costs of more than one unit are only supported by transactions.
+    fn check_key(
+        &self,
+        key: &str,
+        cost: u64,
+        check_mode: CheckMode,
+        connect: impl FnOnce() -> Result<RedisConnection, RateLimiterError>,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        let check_started_at = Instant::now();
+        let mut con = connect()?;
+        let connect_latency = check_started_at.elapsed();
+
+        let limit_override = if self.limit_overrides {
+            read_override(&mut con, key)?
+        } else {
+            LimitOverride::default()
+        };
+        let window_size = limit_override
+            .window_size
+            .unwrap_or_else(|| self.window_size());
+        let window_duration = limit_override
+            .window_duration
+            .unwrap_or_else(|| self.window_duration());
+
+        let now = self.clock.now();
+        let window_size = match &self.onboarding_ramp {
+            Some(onboarding_ramp) => onboarding_ramp.effective_limit(
+                window_size,
+                onboarding_ramp.elapsed_since_first_seen(&mut con, key, window_duration, now)?,
+            ),
+            None => window_size,
+        };
+        // requests throttled only because of the adaptive limits or the load shedding are shed
+        let unscaled_window_size = window_size;
+        let window_size = match &self.adaptive_limits {
+            Some(adaptive_limits) => adaptive_limits.effective_limit(window_size),
+            None => window_size,
+        };
+        let window_size = match &self.load_shedder {
+            Some(load_shedder) => load_shedder.effective_limit(window_size),
+            None => window_size,
+        };
+
+        // Beware that this is NOT monotonic, whichever the clock!
+        let current_ts = if self.redis_time {
+            server_time(&mut con)?
+        } else {
+            now
+        };
+
+        let current_ts_epoch_time = as_epoch_time(current_ts)?;
+
+        let window_start_ts = current_ts
+            .checked_sub(window_duration)
+            .ok_or(RateLimiterError::ComputeError)?;
+
+        let window_start_epoch_time = as_epoch_time(window_start_ts)?;
+
+        // a request is stored as one member per unit of its cost, up to one more than the window
+        // size, which is already enough to throttle it until its members leave the window
+        let cost = cost.min(window_size.saturating_add(1));
+        let members: Vec<String> = (0..cost)
+            .map(|_| request_member(current_ts_epoch_time))
+            .collect();
+        let member = &members[0];
+
+        let max_members = self
+            .max_members
+            .map(|max_members| max_members.max(window_size.saturating_add(cost)));
+
+        let expiry_millis = as_jittered_expiry_millis(window_duration, self.expiry_jitter);
+
+        // The request whose expiry frees quota for a new request is preceded by window_size - cost newer ones
+        let quota_freeing_offset = window_size.saturating_sub(cost);
+
+        let (request_count, quota_freeing_requests, oldest_requests): (
+            u64,
+            Vec<String>,
+            Vec<String>,
+        ) = match check_mode {
+            CheckMode::Function => fcall(
+                &mut con,
+                SLIDING_WINDOW_CHECK,
+                key,
+                (
+                    window_start_epoch_time as u64,
+                    current_ts_epoch_time as u64,
+                    member,
+                    expiry_millis,
+                    max_members.unwrap_or(0),
+                    quota_freeing_offset,
+                ),
+            )?,
+            CheckMode::Script => CHECK_SCRIPT
+                .key(key)
+                .arg(window_start_epoch_time as u64)
+                .arg(current_ts_epoch_time as u64)
+                .arg(member)
+                .arg(expiry_millis)
+                .arg(max_members.unwrap_or(0))
+                .arg(quota_freeing_offset)
+                .invoke(&mut *con)?,
+            CheckMode::Transaction => redis::transaction(&mut *con, &[key], |con, pipe| {
+                pipe.cmd("ZREMRANGEBYSCORE")
+                    .arg(key)
+                    .arg("-inf")
+                    .arg(format!("({}", window_start_epoch_time))
+                    .ignore()
+                    .cmd("ZADD")
+                    .arg(key)
+                    .arg("NX");
+                for member in &members {
+                    pipe.arg(current_ts_epoch_time as u64).arg(member);
+                }
+                pipe.ignore();
+                if let Some(max_members) = max_members {
+                    pipe.cmd("ZREMRANGEBYRANK")
+                        .arg(key)
+                        .arg(0)
+                        .arg(-(max_members as i64) - 1)
+                        .ignore();
+                }
+                pipe.zcount(key, "-inf", "+inf")
+                    .cmd("ZREVRANGEBYSCORE")
+                    .arg(key)
+                    .arg("+inf")
+                    .arg("-inf")
+                    .arg("LIMIT")
+                    .arg(quota_freeing_offset)
+                    .arg(1)
+                    .cmd("ZRANGE")
+                    .arg(key)
+                    .arg(0)
+                    .arg(0)
+                    .cmd("PEXPIRE")
+                    .arg(key)
+                    .arg(expiry_millis)
+                    .ignore()
+                    .query(con)
+            })?,
+        };
+
+        let latency = CheckLatency {
+            connect: connect_latency,
+            commands: check_started_at.elapsed() - connect_latency,
+        };
+        record_latency(&latency);
+        if let Some(adaptive_limits) = &self.adaptive_limits {
+            adaptive_limits.record_latency(latency.total(), Instant::now());
+        }
+        if let Some(observer) = &self.observer {
+            observer.on_redis_latency(key, &latency);
+        }
+        if let Some(slow_check_threshold) = self.slow_check_threshold {
+            report_slow_check(
+                slow_check_threshold,
+                key,
+                &latency,
+                &self.check_command_names(check_mode),
+            );
+        }
+
+        let response = window_response(
+            request_count,
+            &quota_freeing_requests,
+            &oldest_requests,
+            current_ts,
+            window_size,
+            unscaled_window_size,
+            window_duration,
+        )?;
+
+        if let (RateLimiterResponse::RequestThrottled(_), Some(reputation)) =
+            (&response, &self.reputation)
+        {
+            reputation.record_throttle(&mut con, key, now)?;
+        }
+        if let (RateLimiterResponse::RequestThrottled(_), Some(offender_tracking)) =
+            (&response, &self.offender_tracking)
+        {
+            offender_tracking.record_throttle(&mut con, key, now)?;
+        }
+        if let (RateLimiterResponse::RequestAllowed(_), true) = (&response, self.usage_reporting) {
+            record_usage(&mut con, key, now)?;
+        }
+
+        Ok(response)
+    }
+
+    /// Checks the requests of the given keys, each against the window of its own rate limiter.
+    /// The checks are pipelined in a single transaction, so that a request subject to many
+    /// limits costs one round trip, as long as they can all be
+    /// [pipelined together](Self::pipelines_checks_with). Otherwise every key is checked on its
+    /// own, like by [RateLimiter::check_request]. Returns the responses in the same order as the
+    /// keys.
+    pub(crate) fn check_many(
+        checks: &[(&SlidingWindowRateLimiter, String)],
+    ) -> Result<Vec<RateLimiterResponse>, RateLimiterError> {
+        let Some((first, _)) = checks.first() else {
+            return Ok(vec![]);
+        };
+        if !checks
+            .iter()
+            .all(|(rate_limiter, _)| rate_limiter.pipelines_checks_with(first))
+        {
+            return checks
+                .iter()
+                .map(|(rate_limiter, key)| rate_limiter.check_request_key(key, 1))
+                .collect();
+        }
+        let mut con = first.connection_pool.get(&first.redis_client)?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let mut windows = Vec::with_capacity(checks.len());
+        for (rate_limiter, key) in checks {
+            let window_size = rate_limiter.window_size();
+            let window_duration = rate_limiter.window_duration();
+            let current_ts = rate_limiter.clock.now();
+            let current_ts_epoch_time = as_epoch_time(current_ts)?;
+            let window_start_epoch_time = as_epoch_time(
+                current_ts
+                    .checked_sub(window_duration)
+                    .ok_or(RateLimiterError::ComputeError)?,
+            )?;
+
+            pipe.cmd("ZREMRANGEBYSCORE")
+                .arg(key)
+                .arg("-inf")
+                .arg(format!("({}", window_start_epoch_time))
+                .ignore()
+                .cmd("ZADD")
+                .arg(key)
+                .arg("NX")
+                .arg(current_ts_epoch_time as u64)
+                .arg(request_member(current_ts_epoch_time))
+                .ignore()
+                .zcount(key, "-inf", "+inf")
+                .cmd("ZREVRANGEBYSCORE")
+                .arg(key)
+                .arg("+inf")
+                .arg("-inf")
+                .arg("LIMIT")
+                .arg(window_size.saturating_sub(1))
+                .arg(1)
+                .cmd("ZRANGE")
+                .arg(key)
+                .arg(0)
+                .arg(0)
+                .cmd("PEXPIRE")
+                .arg(key)
+                .arg(as_jittered_expiry_millis(
+                    window_duration,
+                    rate_limiter.expiry_jitter,
+                ))
+                .ignore();
+            windows.push((current_ts, window_size, window_duration));
+        }
+
+        // every check replies with its request count, quota freeing and oldest requests
+        let replies: Vec<redis::Value> = pipe.query(&mut *con)?;
+        if replies.len() != windows.len() * 3 {
+            return Err(RateLimiterError::ComputeError);
+        }
+
+        replies
+            .chunks(3)
+            .zip(windows)
+            .map(|(reply, (current_ts, window_size, window_duration))| {
+                window_response(
+                    redis::from_redis_value(&reply[0])?,
+                    &redis::from_redis_value::<Vec<String>>(&reply[1])?,
+                    &redis::from_redis_value::<Vec<String>>(&reply[2])?,
+                    current_ts,
+                    window_size,
+                    // pipelined checks never scale their window
+                    window_size,
+                    window_duration,
+                )
+            })
+            .collect()
+    }
+
+    /// Returns whether the checks of this rate limiter can be pipelined along with the ones of
+    /// the given rate limiter, that is whether both use the same single Redis server, without
+    /// sharding nor quorum, and this one doesn't enable any of the features the pipelined checks
+    /// don't apply: limit overrides, onboarding ramps, adaptive limits, load shedding, throttle
+    /// caches, observers, slow check reports, reputation, offender tracking, usage reporting,
+    /// Redis time or a maximum number of members.
+    fn pipelines_checks_with(&self, other: &SlidingWindowRateLimiter) -> bool {
+        let connection_info = self.redis_client.get_connection_info();
+        let other_connection_info = other.redis_client.get_connection_info();
+
+        self.shards.is_empty()
+            && other.shards.is_empty()
+            && connection_info.addr.to_string() == other_connection_info.addr.to_string()
+            && connection_info.redis.db == other_connection_info.redis.db
+            && !self.limit_overrides
+            && self.onboarding_ramp.is_none()
+            && self.adaptive_limits.is_none()
+            && self.load_shedder.is_none()
+            && self.throttle_cache.is_none()
+            && self.observer.is_none()
+            && self.slow_check_threshold.is_none()
+            && self.reputation.is_none()
+            && self.offender_tracking.is_none()
+            && !self.usage_reporting
+            && !self.redis_time
+            && self.max_members.is_none()
+    }
+
+    /// Returns the state of the given key, without mutating it.
+    fn inspect_key(
+        &self,
+        con: &mut Connection,
+        key: &str,
+    ) -> Result<KeyInspection, RateLimiterError> {
+        let now = self.clock.now();
+        let (limit, window_duration, overridden) = inspect_limits(
+            con,
+            key,
+            self.window_size(),
+            self.window_duration(),
+            self.limit_overrides,
+            self.onboarding_ramp.as_ref(),
+            now,
+        )?;
+
+        let current_ts = if self.redis_time {
+            server_time(con)?
+        } else {
+            now
+        };
+        let window_start_ts = current_ts
+            .checked_sub(window_duration)
+            .ok_or(RateLimiterError::ComputeError)?;
+
+        let (count, expire_in_millis): (u64, i64) = redis::pipe()
+            .zcount(key, as_epoch_time(window_start_ts)? as u64, "+inf")
+            .cmd("PTTL")
+            .arg(key)
+            .query(con)?;
+
+        Ok(KeyInspection {
+            key: key.to_string(),
+            count,
+            limit,
+            window_duration,
+            overridden,
+            expire_in: as_expire_in(expire_in_millis),
+        })
+    }
+
+    /// Returns the requests counted in the current window for each of the given keys.
+    fn current_counts(
+        &self,
+        con: &mut Connection,
+        keys: &[String],
+    ) -> Result<Vec<u64>, RateLimiterError> {
+        let window_start_ts = self
+            .clock
+            .now()
+            .checked_sub(self.window_duration())
+            .ok_or(RateLimiterError::ComputeError)?;
+        let window_start_epoch_time = as_epoch_time(window_start_ts)? as u64;
+
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.zcount(key, window_start_epoch_time, "+inf");
+        }
+        Ok(pipe.query(con)?)
+    }
+
+    /// Returns the number of Redis servers holding distinct keys: all the shards, if keys are
+    /// sharded, or a single one otherwise, as the masters of a quorum hold the same keys.
+    fn distinct_servers(&self) -> usize {
+        if self.quorum {
+            1
+        } else {
+            self.shards.len().max(1)
+        }
+    }
+
+    /// Returns a connection to the Redis server of the given index, out of the distinct ones.
+    fn server_connection(&self, server: usize) -> Result<RedisConnection, RateLimiterError> {
+        match self.shards.get(server) {
+            Some(shard) => shard.connection(),
+            None => self.connection_pool.get(&self.redis_client),
+        }
+    }
+
+    /// Returns a connection to the read replica, if configured, or to the Redis server of the
+    /// given index, out of the distinct ones, otherwise.
+    fn read_server_connection(&self, server: usize) -> Result<RedisConnection, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => read_replica.connection(),
+            None => self.server_connection(server),
+        }
+    }
+
+    /// Returns all the keys that might hold state for the given request key.
+    fn stored_keys(&self, key: &str) -> Vec<String> {
+        let mut keys = identifier_keys(key);
+        if self.usage_reporting {
+            keys.extend(retained_usage_keys(key, self.clock.now()));
+        }
+        keys
+    }
+
+    /// Returns the caches of throttled verdicts of the rate limiter: the throttle cache, if
+    /// configured, and the one of the adaptive limits, if any.
+    fn throttle_caches(&self) -> impl Iterator<Item = &ThrottleCache> {
+        let adaptive_cache = self
+            .adaptive_limits
+            .as_ref()
+            .map(|adaptive_limits| &adaptive_limits.throttle_cache);
+        self.throttle_cache.iter().chain(adaptive_cache)
+    }
+
+    /// Drops the cached throttled verdict of the given key, if any.
+    fn forget_throttled_verdict(&self, key: &str) {
+        for throttle_cache in self.throttle_caches() {
+            throttle_cache.forget(key);
+        }
+    }
+
+    /// Runs the given operation against the Redis server owning the given key or, in quorum mode,
+    /// against all the masters. Returns the results of the servers that succeeded.
+    fn run<T: Send>(
+        &self,
+        key: &str,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        if self.quorum {
+            return on_quorum(&self.shards, |master| op(&mut master.connection()?));
+        }
+        Ok(vec![op(&mut self.connection(key)?)?])
+    }
+
+    /// Runs the given non-mutating operation against the read replica, if configured, or like
+    /// [Self::run] otherwise.
+    fn run_read<T: Send>(
+        &self,
+        key: &str,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => Ok(vec![op(&mut read_replica.connection()?)?]),
+            None => self.run(key, op),
+        }
+    }
+
+    /// Runs the given non-mutating operation against the read replica, if configured, or like
+    /// [Self::run_everywhere] otherwise.
+    fn run_read_everywhere<T: Send>(
+        &self,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        match &self.read_replica {
+            Some(read_replica) => Ok(vec![op(&mut read_replica.connection()?)?]),
+            None => self.run_everywhere(op),
+        }
+    }
+
+    /// Runs the given operation against every Redis server: all the shards, if keys are sharded,
+    /// a quorum of the masters in quorum mode, or the single underlying server otherwise.
+    fn run_everywhere<T: Send>(
+        &self,
+        op: impl Fn(&mut RedisConnection) -> Result<T, RateLimiterError> + Sync,
+    ) -> Result<Vec<T>, RateLimiterError> {
+        if self.quorum {
+            return on_quorum(&self.shards, |master| op(&mut master.connection()?));
+        }
+        if self.shards.is_empty() {
+            return Ok(vec![op(&mut self
+                .connection_pool
+                .get(&self.redis_client)?)?]);
+        }
+        self.shards
+            .iter()
+            .map(|shard| op(&mut shard.connection()?))
+            .collect()
+    }
+
+    /// Returns the Redis commands run by a check, according to the rate limiter configuration.
+    fn check_command_names(&self, check_mode: CheckMode) -> Vec<&'static str> {
+        let mut commands = vec![];
+        if self.limit_overrides {
+            commands.extend_from_slice(OVERRIDE_COMMAND_NAMES);
+        }
+        if self.onboarding_ramp.is_some() {
+            commands.extend_from_slice(ONBOARDING_COMMAND_NAMES);
+        }
+        if self.redis_time {
+            commands.push("TIME");
+        }
+        commands.extend_from_slice(match check_mode {
+            CheckMode::Transaction => CHECK_COMMAND_NAMES,
+            CheckMode::Script => SCRIPTED_CHECK_COMMAND_NAMES,
+            CheckMode::Function => FUNCTION_CHECK_COMMAND_NAMES,
+        });
+        if check_mode == CheckMode::Transaction && self.max_members.is_some() {
+            commands.push("ZREMRANGEBYRANK");
+        }
+        commands
+    }
 }
 
 impl RateLimiter for SlidingWindowRateLimiter {
+    fn build_request_key(&self, request_identifier: RequestIdentifier) -> String {
+        request_key(request_identifier, self.hash_tag)
+    }
+
     /// Function that returns the result of the rate limiter checks. Yields an error in case of troubles
     /// connecting to the underlying redis instance.
     ///
@@ -65,18 +809,24 @@ impl RateLimiter for SlidingWindowRateLimiter {
     /// The implementation of this method heavily relies on Redis commands and [Sorted sets](https://redis.io/docs/data-types/sorted-sets/).
     /// It atomically runs a set commands to:
     ///
-    /// 1. Compute the current timestamp, and the start of the current _window_;
+    /// 1. Compute the current timestamp, from the local clock or, [optionally](crate::builders::sliding_window::SlidingWindowRateLimiterBuilder::with_redis_time), from the Redis server clock, and the start of the current _window_;
     /// 2. Remove all the items (if any) matching the given request identifier and received before the computed window start date;
     /// 3. If not present already, create a sorted set with the given request identifier.
-    /// 4. Add the current request to the sorted set as new item having key and value equal to the current timestamp, computed at step one;
+    /// 4. Add the current request to the sorted set as new item scored with the current timestamp, computed at step one, and a unique value starting with it, so that concurrent requests are all counted, trimming the oldest items beyond the [maximum number of members](crate::builders::sliding_window::SlidingWindowRateLimiterBuilder::with_max_members), if configured;
     /// 5. Count the number of items in the sorted set, later used to indicate the outstanding request budget, in case the request is allowed;
-    /// 6. Retrieve the last request in the updated, valid window and use that to indicate the value of the retry_in information in case the request is throttled.
-    /// 7. Set the sorted set to expire in _window_duration_ in seconds.
+    /// 6. Retrieve the request whose expiry frees quota for a new request, that is the one preceded by _window_size_ - 1 newer requests, and use that to indicate the value of the retry_in information in case the request is throttled. While the window is not full, the oldest request is retrieved instead.
+    /// 7. Set the sorted set to expire in _window_duration_, in milliseconds.
     ///
     /// The above four commands are wrapped into a Redis [transaction](https://redis.io/docs/manual/transactions/) with the helper provided by the underlying redis crate used.
     /// The combination of `WATCH`, `MULTI` and `EXEC` commands here protect this piece of code from race conditions when multiple
     /// clients are modifying the same key simultaneously.
     ///
+    /// With [scripted checks](crate::builders::sliding_window::SlidingWindowRateLimiterBuilder::with_scripted_checks),
+    /// the same commands run in a Lua script instead, invoked with `EVALSHA` in a single round trip. Scripts are atomic, so no
+    /// retries are needed when multiple clients are modifying the same key. The script is loaded on first use, and again
+    /// whenever Redis answers with a `NOSCRIPT` error, like after a restart. With [Redis Functions](crate::builders::sliding_window::SlidingWindowRateLimiterBuilder::with_redis_functions),
+    /// the same script is registered as a function and called with `FCALL` on Redis 7 and above.
+    ///
     /// Below the output of a MONITOR command on a Redis instance when the `is_request_allowed` function is invoked:
     ///
     /// ```ignore
@@ -85,8 +835,9 @@ impl RateLimiter for SlidingWindowRateLimiter {
     /// 1674324083.386600 [0 172.17.0.1:59248] "MULTI"
     /// 1674324083.386670 [0 172.17.0.1:59248] "ZADD" "rl:ip_115.249.235.84" "NX" "1674324083380245000" "1674324083380245000"
     /// 1674324083.386684 [0 172.17.0.1:59248] "ZCOUNT" "rl:ip_115.249.235.84" "-inf" "+inf"
-    /// 1674324083.386698 [0 172.17.0.1:59248] "ZREVRANGEBYSCORE" "rl:ip_115.249.235.84" "+inf" "-inf" "LIMIT" "0" "5"
-    /// 1674324083.386712 [0 172.17.0.1:59248] "EXPIRE" "rl:ip_115.249.235.84" "60"
+    /// 1674324083.386698 [0 172.17.0.1:59248] "ZREVRANGEBYSCORE" "rl:ip_115.249.235.84" "+inf" "-inf" "LIMIT" "4" "1"
+    /// 1674324083.386705 [0 172.17.0.1:59248] "ZRANGE" "rl:ip_115.249.235.84" "0" "0"
+    /// 1674324083.386712 [0 172.17.0.1:59248] "PEXPIRE" "rl:ip_115.249.235.84" "60000"
     /// 1674324083.386719 [0 172.17.0.1:59248] "EXEC"
     /// 1674324083.391000 [0 172.17.0.1:59248] "UNWATCH"
     /// 1674324083.395845 [0 172.17.0.1:59250] "WATCH" "rl:ip_115.249.235.84"
@@ -94,8 +845,9 @@ impl RateLimiter for SlidingWindowRateLimiter {
     /// 1674324083.398027 [0 172.17.0.1:59250] "ZREMRANGEBYSCORE" "rl:ip_115.249.235.84" "-inf" "(1674324023392739000"
     /// 1674324083.398042 [0 172.17.0.1:59250] "ZADD" "rl:ip_115.249.235.84" "NX" "1674324083392739000" "1674324083392739000"
     /// 1674324083.398054 [0 172.17.0.1:59250] "ZCOUNT" "rl:ip_115.249.235.84" "-inf" "+inf"
-    /// 1674324083.398065 [0 172.17.0.1:59250] "ZREVRANGEBYSCORE" "rl:ip_115.249.235.84" "+inf" "-inf" "LIMIT" "0" "5"
-    /// 1674324083.398078 [0 172.17.0.1:59250] "EXPIRE" "rl:ip_115.249.235.84" "60"
+    /// 1674324083.398065 [0 172.17.0.1:59250] "ZREVRANGEBYSCORE" "rl:ip_115.249.235.84" "+inf" "-inf" "LIMIT" "4" "1"
+    /// 1674324083.398071 [0 172.17.0.1:59250] "ZRANGE" "rl:ip_115.249.235.84" "0" "0"
+    /// 1674324083.398078 [0 172.17.0.1:59250] "PEXPIRE" "rl:ip_115.249.235.84" "60000"
     /// 1674324083.398084 [0 172.17.0.1:59250] "EXEC"
     /// 1674324083.400599 [0 172.17.0.1:59250] "UNWATCH"
     /// ```
@@ -103,70 +855,253 @@ impl RateLimiter for SlidingWindowRateLimiter {
         &self,
         request_identifier: crate::RequestIdentifier,
     ) -> Result<crate::RateLimiterResponse, crate::errors::RateLimiterError> {
+        self.check_request_with_context(request_identifier, &RequestContext::default())
+    }
+
+    fn check_request_with_context(
+        &self,
+        request_identifier: RequestIdentifier,
+        context: &RequestContext,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
         let key = &self.build_request_key(request_identifier);
+        let cost = self
+            .cost_function
+            .as_ref()
+            .map_or(1, |cost_function| cost_function.cost(context).max(1));
 
-        let mut con = self.redis_client.get_connection()?;
+        self.check_request_key(key, cost)
+    }
+}
 
-        // Beware that this is NOT monotonic!
-        let current_ts = SystemTime::now();
+impl RedisAdmin for SlidingWindowRateLimiter {
+    fn export_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<IdentifierData, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.run(&key, |con| export_keys(con, &self.stored_keys(&key)))?
+            .into_iter()
+            .next()
+            .ok_or(RateLimiterError::ComputeError)
+    }
 
-        let current_ts_epoch_time = as_epoch_time(current_ts)?;
+    fn purge_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<u64, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        let deleted_keys = self.run(&key, |con| {
+            if let Some(offender_tracking) = &self.offender_tracking {
+                offender_tracking.forget(con, &key, self.clock.now())?;
+            }
+            purge_keys(con, &self.stored_keys(&key))
+        })?;
+        Ok(deleted_keys.into_iter().max().unwrap_or(0))
+    }
 
-        let window_start_ts = current_ts
-            .checked_sub(self.window_duration)
-            .ok_or(RateLimiterError::ComputeError)?;
+    fn reputation(&self, request_identifier: RequestIdentifier) -> Result<f64, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
 
-        let window_start_epoch_time = as_epoch_time(window_start_ts)?;
+        match &self.reputation {
+            Some(reputation) => {
+                let now = self.clock.now();
+                let scores = self.run(&key, |con| reputation.score(con, &key, now))?;
+                Ok(scores.into_iter().fold(0.0, f64::max))
+            }
+            None => Ok(0.0),
+        }
+    }
 
-        let (request_count, oldest_requests_in_current_window): (u64, Vec<String>) =
-            redis::transaction(&mut con, &[key], |con, pipe| {
-                pipe.cmd("ZREMRANGEBYSCORE")
-                    .arg(key)
-                    .arg("-inf")
-                    .arg(format!("({}", window_start_epoch_time))
-                    .ignore()
-                    .cmd("ZADD")
-                    .arg(key)
-                    .arg("NX")
-                    .arg(current_ts_epoch_time as u64)
-                    .arg(current_ts_epoch_time as u64)
-                    .ignore()
-                    .zcount(key, "-inf", "+inf")
-                    .cmd("ZREVRANGEBYSCORE")
-                    .arg(key)
-                    .arg("+inf")
-                    .arg("-inf")
-                    .arg("LIMIT")
-                    .arg("0")
-                    .arg("5")
-                    .cmd("EXPIRE")
-                    .arg(key)
-                    .arg(self.window_duration.as_secs())
-                    .ignore()
-                    .query(con)
-            })?;
+    fn top_offenders(&self, n: usize) -> Result<Vec<Offender>, RateLimiterError> {
+        match &self.offender_tracking {
+            Some(offender_tracking) => {
+                let now = self.clock.now();
+                let throttles =
+                    self.run_read_everywhere(|con| offender_tracking.throttles(con, n, now))?;
+                Ok(top_offenders(throttles, n))
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    fn usage(
+        &self,
+        request_identifier: RequestIdentifier,
+        period: UsagePeriod,
+    ) -> Result<u64, RateLimiterError> {
+        if !self.usage_reporting {
+            return Ok(0);
+        }
+
+        let key = self.build_request_key(request_identifier);
+        let usages = self.run_read(&key, |con| read_usage(con, &key, period))?;
+        Ok(usages.into_iter().max().unwrap_or(0))
+    }
+
+    fn inspect(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<KeyInspection, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.run_read(&key, |con| self.inspect_key(con, &key))?
+            .into_iter()
+            .max_by_key(|inspection| inspection.count)
+            .ok_or(RateLimiterError::ComputeError)
+    }
 
-        let oldest_request_epoch_time: u64 = match oldest_requests_in_current_window.last() {
-            Some(l) => l.parse().map_err(|_e| RateLimiterError::ComputeError)?,
+    fn list_keys(&self, pattern: &str, cursor: ListCursor) -> Result<KeyListing, RateLimiterError> {
+        list_keys(
+            pattern,
+            cursor,
+            self.distinct_servers(),
+            |server| self.read_server_connection(server),
+            |con, keys| self.current_counts(con, keys),
+        )
+    }
+
+    fn export_state(&self) -> Result<StateSnapshot, RateLimiterError> {
+        export_state(self.distinct_servers(), |server| {
+            self.server_connection(server)
+        })
+    }
+
+    fn import_state(&self, snapshot: &StateSnapshot) -> Result<u64, RateLimiterError> {
+        let servers = self.distinct_servers();
+        if snapshot.entries.iter().any(|entry| entry.server >= servers) {
+            return Err(RateLimiterError::ComputeError);
+        }
+
+        let mut restored = 0;
+        for server in 0..servers {
+            let entries: Vec<&SnapshotEntry> = snapshot
+                .entries
+                .iter()
+                .filter(|entry| entry.server == server)
+                .collect();
+            restored += if self.quorum {
+                self.run_everywhere(|con| import_entries(con, &entries, snapshot.taken_at))?
+                    .into_iter()
+                    .max()
+                    .unwrap_or(0)
+            } else {
+                import_entries(
+                    &mut *self.server_connection(server)?,
+                    &entries,
+                    snapshot.taken_at,
+                )?
+            };
+        }
+        Ok(restored)
+    }
+
+    fn update_limits(&self, window_size: u64, window_duration: Duration) {
+        self.limits.update(window_size, window_duration);
+        for throttle_cache in self.throttle_caches() {
+            throttle_cache.clear();
+        }
+    }
+
+    fn set_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+        limit_override: &LimitOverride,
+    ) -> Result<(), RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        self.run(&key, |con| write_override(con, &key, limit_override))?;
+        Ok(())
+    }
+
+    fn remove_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<bool, RateLimiterError> {
+        let key = self.build_request_key(request_identifier);
+        self.forget_throttled_verdict(&key);
+        let deleted = self.run(&key, |con| delete_override(con, &key))?;
+        Ok(deleted.into_iter().any(|deleted| deleted))
+    }
+
+    fn capabilities(&self) -> Result<RedisCapabilities, RateLimiterError> {
+        negotiate(&self.capabilities, || {
+            self.connection_pool.get(&self.redis_client)
+        })
+    }
+}
+
+/// Utility method that returns the current time of the Redis server, with microseconds precision.
+fn server_time(con: &mut Connection) -> Result<SystemTime, RateLimiterError> {
+    let (seconds, microseconds): (u64, u64) = redis::cmd("TIME").query(con)?;
+
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_micros(microseconds))
+}
+
+/// Utility method that computes the response to a request, given the number of requests in its
+/// window, including itself, the members of the requests freeing quota, if any, or else of the
+/// oldest one, and the size of the window before being scaled by the load shedding, if any.
+fn window_response(
+    request_count: u64,
+    quota_freeing_requests: &[String],
+    oldest_requests: &[String],
+    current_ts: SystemTime,
+    window_size: u64,
+    unscaled_window_size: u64,
+    window_duration: Duration,
+) -> Result<RateLimiterResponse, RateLimiterError> {
+    let current_ts_epoch_time = as_epoch_time(current_ts)?;
+    let oldest_request_epoch_time: u64 =
+        match quota_freeing_requests.first().or(oldest_requests.first()) {
+            Some(l) => member_epoch_time(l)?,
             None => 0,
         };
 
-        let response = if request_count <= self.window_size {
-            RateLimiterResponse::RequestAllowed(RequestAllowed {
-                remaining_request_counter: self.window_size - request_count,
-            })
-        } else {
-            let time_passed_from_first_req =
-                Duration::from_nanos(current_ts_epoch_time as u64 - oldest_request_epoch_time);
-            let retry_in = self
-                .window_duration
-                .saturating_sub(time_passed_from_first_req);
+    let time_passed_from_first_req = Duration::from_nanos(
+        (current_ts_epoch_time as u64).saturating_sub(oldest_request_epoch_time),
+    );
+    let reset_in = window_duration.saturating_sub(time_passed_from_first_req);
 
-            RateLimiterResponse::RequestThrottled(RequestThrottled { retry_in })
-        };
+    let status = RateLimitStatus {
+        limit: window_size,
+        window_duration,
+        used: request_count,
+        reset_at: current_ts + reset_in,
+    };
 
-        Ok(response)
-    }
+    Ok(if request_count <= window_size {
+        RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter: window_size - request_count,
+            status,
+        })
+    } else {
+        RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in: reset_in,
+            reason: throttle_reason(request_count, unscaled_window_size),
+            status,
+        })
+    })
+}
+
+/// Utility method that returns a unique sorted set member for a request received at the given
+/// epoch time. The epoch time comes first, so that it can be read back with [member_epoch_time].
+fn request_member(epoch_time: u128) -> String {
+    format!(
+        "{}-{:x}-{}",
+        epoch_time,
+        *MEMBER_NONCE,
+        MEMBER_SEQUENCE.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Utility method that returns the epoch time of a request from its sorted set member, including
+/// the members holding just the epoch time, stored by previous versions.
+fn member_epoch_time(member: &str) -> Result<u64, RateLimiterError> {
+    member
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_e| RateLimiterError::ComputeError)
 }
 
 /// Utility method that returns the given timestamp in epoch time, with nanoseconds precision.
@@ -183,7 +1118,8 @@ mod test {
     use std::{
         cmp,
         net::{IpAddr, Ipv4Addr},
-        time::{Duration, SystemTime},
+        thread,
+        time::{Duration, Instant, SystemTime},
     };
 
     use rand::Rng;
@@ -192,11 +1128,22 @@ mod test {
     use uuid::Uuid;
 
     use crate::{
-        builders::RedisSettings, errors::RateLimiterError, factory::RateLimiterFactory,
-        RateLimiter, RequestIdentifier,
+        adaptive::AdaptivePolicy,
+        builders::RedisSettings,
+        clock::{Clock, ManualClock},
+        cost::RequestContext,
+        data_subject::StoredValue,
+        errors::RateLimiterError,
+        factory::RateLimiterFactory,
+        listing::{ListCursor, ListedKey},
+        overrides::LimitOverride,
+        redis_mock::RedisMock,
+        reputation::ReputationPolicy,
+        testing::RedisContainer,
+        RateLimiter, RedisAdmin, RequestIdentifier, ThrottleReason,
     };
 
-    use super::as_epoch_time;
+    use super::{as_epoch_time, member_epoch_time, request_member, SlidingWindowRateLimiter};
 
     #[rstest]
     #[case::ip(RequestIdentifier::Ip(generate_random_ip()))]
@@ -209,6 +1156,7 @@ mod test {
             .with_redis_settings(RedisSettings {
                 host: "whatever".to_string(),
                 port: 1,
+                ..RedisSettings::default()
             })
             .build()
             .unwrap();
@@ -230,14 +1178,13 @@ mod test {
     #[case::custom_id(
         RequestIdentifier::Custom { key: "a_custom_id".to_string(), value: Uuid::new_v4().to_string() },
     )]
+    #[case::internal(RequestIdentifier::Internal(Uuid::new_v4().to_string()))]
     fn should_check_request_eligibility(#[case] request_identifier: RequestIdentifier) {
         //arrange
         let window_size = 5;
         let window_duration = Duration::from_secs(60);
-        let redis_settings = RedisSettings {
-            host: "127.0.0.1".to_string(),
-            port: 7379,
-        };
+        let redis = RedisContainer::start().unwrap();
+        let redis_settings = redis.redis_settings();
         let rate_limiter = RateLimiterFactory::sliding_window()
             .with_window_size(window_size)
             .with_window_duration(window_duration)
@@ -256,10 +1203,15 @@ mod test {
                 assert_eq!(
                     allowed_res.remaining_request_counter,
                     cmp::max(0, window_size as i64 - n as i64) as u64
-                )
+                );
+                assert_eq!(allowed_res.status.limit, window_size);
+                assert_eq!(allowed_res.status.window_duration, window_duration);
+                assert_eq!(allowed_res.status.used, n);
+                assert!(allowed_res.status.reset_at > SystemTime::now());
             } else {
                 let tolerance_secs = window_duration.as_secs() * 5 / 100;
                 let throttled_res = res.as_throttled();
+                assert_eq!(throttled_res.status.used, n);
                 let retry_in_secs = throttled_res.retry_in.as_secs();
                 assert!(
                     retry_in_secs > 0 && retry_in_secs <= window_duration.as_secs(),
@@ -274,6 +1226,62 @@ mod test {
         }
     }
 
+    #[test]
+    fn should_support_sub_second_windows() {
+        //arrange
+        let redis = RedisContainer::start().unwrap();
+        let window_size = 2;
+        let window_duration = Duration::from_millis(200);
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(window_size)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for _ in 0..window_size {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        assert!(
+            throttled_res.retry_in > Duration::ZERO && throttled_res.retry_in <= window_duration,
+            "retry in is not in valid range"
+        );
+
+        thread::sleep(window_duration + Duration::from_millis(50));
+        let allowed_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_allowed();
+        assert_eq!(allowed_res.remaining_request_counter, window_size - 1);
+    }
+
+    #[test]
+    fn request_member_should_be_unique() {
+        let first = request_member(1674324083380245000);
+        let second = request_member(1674324083380245000);
+
+        assert_ne!(first, second);
+        assert_eq!(member_epoch_time(&first).unwrap(), 1674324083380245000);
+        assert_eq!(member_epoch_time(&second).unwrap(), 1674324083380245000);
+    }
+
+    #[rstest]
+    #[case::legacy("1674324083380245000", Some(1674324083380245000))]
+    #[case::unique("1674324083380245000-1f2e3d-7", Some(1674324083380245000))]
+    #[case::invalid("not-a-timestamp", None)]
+    fn should_read_member_epoch_time(#[case] member: &str, #[case] expected: Option<u64>) {
+        assert_eq!(member_epoch_time(member).ok(), expected);
+    }
+
     #[test]
     fn as_epoch_time_should_return_current_time() {
         let now = SystemTime::now();
@@ -283,6 +1291,503 @@ mod test {
         assert!(now_epoch.is_ok())
     }
 
+    #[test]
+    fn should_export_and_purge_request_identifier_state() {
+        //arrange
+        let redis = RedisContainer::start().unwrap();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_redis_settings(redis.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap();
+
+        //act
+        let exported = rate_limiter
+            .export_identifier(request_identifier.clone())
+            .unwrap();
+        let deleted_keys = rate_limiter
+            .purge_identifier(request_identifier.clone())
+            .unwrap();
+
+        //assert
+        assert_eq!(exported.entries.len(), 1);
+        assert!(matches!(
+            &exported.entries[0].value,
+            StoredValue::Members(members) if members.len() == 1
+        ));
+        assert!(exported.entries[0].expire_in.is_some());
+        assert_eq!(deleted_keys, 1);
+        assert!(rate_limiter
+            .export_identifier(request_identifier)
+            .unwrap()
+            .entries
+            .is_empty());
+    }
+
+    #[rstest]
+    #[case::local_clock(false, false, false)]
+    #[case::redis_clock(true, false, false)]
+    #[case::scripted_checks(false, true, false)]
+    #[case::redis_functions(false, false, true)]
+    fn should_check_request_eligibility_against_redis_mock(
+        #[case] redis_time: bool,
+        #[case] scripted_checks: bool,
+        #[case] redis_functions: bool,
+    ) {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let window_size = 3;
+        let window_duration = Duration::from_secs(60);
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(window_size)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_redis_time(redis_time)
+            .with_scripted_checks(scripted_checks)
+            .with_redis_functions(redis_functions)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=window_size {
+            let allowed_res = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+            assert_eq!(allowed_res.remaining_request_counter, window_size - n);
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+        assert!(
+            throttled_res.retry_in > Duration::ZERO && throttled_res.retry_in <= window_duration,
+            "retry in is not in valid range"
+        );
+        assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+    }
+
+    #[rstest]
+    #[case::small_window(3)]
+    #[case::large_window(10)]
+    fn should_compute_retry_in_for_any_window_size_against_redis_mock(#[case] window_size: u64) {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let window_duration = Duration::from_secs(2);
+        let pause = Duration::from_millis(300);
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(window_size)
+            .with_window_duration(window_duration)
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        for n in 1..=window_size {
+            if n == 3 {
+                thread::sleep(pause);
+            }
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+
+        //assert
+        // quota is freed when the second request expires, as throttled requests are counted too
+        assert!(
+            throttled_res.retry_in > Duration::ZERO
+                && throttled_res.retry_in <= window_duration - pause,
+            "retry in {:?} is not in valid range",
+            throttled_res.retry_in
+        );
+    }
+
+    #[test]
+    fn should_trim_sorted_set_to_max_members_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_max_members(1)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act & assert
+        for n in 1..=10 {
+            let response = rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap();
+            if n <= 2 {
+                response.as_allowed();
+            } else {
+                response.as_throttled();
+            }
+        }
+        let exported = rate_limiter.export_identifier(request_identifier).unwrap();
+        assert!(matches!(
+            &exported.entries[0].value,
+            StoredValue::Members(members) if members.len() == 3
+        ));
+    }
+
+    #[test]
+    fn should_count_cost_of_requests_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let clock = ManualClock::default();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(5)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_clock(clock.clone())
+            .with_cost_function(|_: &RequestContext| 3)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let allowed_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_allowed();
+        clock.advance(Duration::from_secs(10));
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+
+        //assert
+        assert_eq!(allowed_res.remaining_request_counter, 2);
+        assert_eq!(throttled_res.status.used, 6);
+        // the cost only fits once the members of the throttled request expire too
+        assert!(throttled_res.retry_in > Duration::from_secs(59));
+        let exported = rate_limiter.export_identifier(request_identifier).unwrap();
+        assert!(matches!(
+            &exported.entries[0].value,
+            StoredValue::Members(members) if members.len() == 6
+        ));
+    }
+
+    #[test]
+    fn should_store_at_most_one_member_over_window_size_per_request_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(5)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_cost_function(|_: &RequestContext| u64::MAX)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+
+        //assert
+        assert_eq!(throttled_res.reason, ThrottleReason::QuotaExceeded);
+        assert_eq!(throttled_res.status.used, 6);
+        assert!(throttled_res.retry_in > Duration::from_secs(59));
+        let exported = rate_limiter.export_identifier(request_identifier).unwrap();
+        assert!(matches!(
+            &exported.entries[0].value,
+            StoredValue::Members(members) if members.len() == 6
+        ));
+    }
+
+    #[test]
+    fn should_shed_requests_within_quota_while_adaptive_limits_are_tightened_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(10)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_adaptive_limits(AdaptivePolicy {
+                target_latency: Duration::from_secs(1),
+                min_fraction: 0.5,
+                adjust_interval: Duration::from_secs(1),
+            })
+            .build()
+            .unwrap();
+        let adaptive_limits = rate_limiter.adaptive_limits.as_ref().unwrap();
+        let started_at = Instant::now();
+        for second in [0, 3_600] {
+            adaptive_limits.record_latency(
+                Duration::from_secs(10),
+                started_at + Duration::from_secs(second),
+            );
+        }
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        for _ in 0..8 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+        let throttled_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_throttled();
+
+        //assert
+        assert!(adaptive_limits.fraction() < 1.0);
+        assert_eq!(throttled_res.reason, ThrottleReason::LoadShed);
+        assert_eq!(throttled_res.status.limit, 8);
+    }
+
+    #[test]
+    fn should_slide_window_with_clock_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let clock = ManualClock::default();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_clock(clock.clone())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        for _ in 1..=2 {
+            rate_limiter
+                .check_request(request_identifier.clone())
+                .unwrap()
+                .as_allowed();
+        }
+
+        //act
+        clock.advance(Duration::from_secs(59));
+        let throttled_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_throttled();
+        clock.advance(Duration::from_secs(2));
+        let allowed_res = rate_limiter
+            .check_request(request_identifier)
+            .unwrap()
+            .as_allowed();
+
+        //assert
+        assert!(throttled_res.retry_in <= Duration::from_secs(1));
+        assert_eq!(allowed_res.remaining_request_counter, 0);
+        assert!(allowed_res.status.reset_at > clock.now());
+    }
+
+    #[test]
+    fn should_list_request_keys_with_counts_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_reputation(ReputationPolicy {
+                half_life: Duration::from_secs(60),
+                throttle_penalty: 1.0,
+            })
+            .with_window_size(1)
+            .build()
+            .unwrap();
+        let ip = RequestIdentifier::Ip(generate_random_ip());
+        for _ in 0..2 {
+            rate_limiter.check_request(ip.clone()).unwrap();
+        }
+        rate_limiter
+            .check_request(RequestIdentifier::Internal("billing".to_string()))
+            .unwrap();
+
+        //act
+        let mut listed_keys = vec![];
+        let mut cursor = Some(ListCursor::default());
+        while let Some(current_cursor) = cursor {
+            let listing = rate_limiter.list_keys("ip_*", current_cursor).unwrap();
+            listed_keys.extend(listing.keys);
+            cursor = listing.next_cursor;
+        }
+
+        //assert
+        assert_eq!(
+            listed_keys,
+            vec![ListedKey {
+                key: rate_limiter.build_request_key(ip),
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn should_share_window_between_single_and_many_key_checks_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(3)
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        let checks = [(
+            &rate_limiter,
+            rate_limiter.build_request_key(request_identifier.clone()),
+        )];
+
+        //act
+        let first_res = rate_limiter.check_request(request_identifier.clone());
+        let second_res = SlidingWindowRateLimiter::check_many(&checks);
+        let third_res = rate_limiter.check_request(request_identifier);
+        let fourth_res = SlidingWindowRateLimiter::check_many(&checks);
+
+        //assert
+        assert_eq!(first_res.unwrap().as_allowed().remaining_request_counter, 2);
+        assert_eq!(
+            second_res
+                .unwrap()
+                .remove(0)
+                .as_allowed()
+                .remaining_request_counter,
+            1
+        );
+        assert_eq!(third_res.unwrap().as_allowed().remaining_request_counter, 0);
+        fourth_res.unwrap().remove(0).as_throttled();
+    }
+
+    #[test]
+    fn should_apply_limit_overrides_to_many_key_checks_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let overridden_rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(5)
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_limit_overrides(true)
+            .build()
+            .unwrap();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(5)
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        overridden_rate_limiter
+            .set_limit_override(
+                request_identifier.clone(),
+                &LimitOverride {
+                    window_size: Some(1),
+                    window_duration: None,
+                },
+            )
+            .unwrap();
+        let checks = [
+            (
+                &overridden_rate_limiter,
+                overridden_rate_limiter.build_request_key(request_identifier.clone()),
+            ),
+            (
+                &rate_limiter,
+                format!(
+                    "{}:other",
+                    rate_limiter.build_request_key(request_identifier)
+                ),
+            ),
+        ];
+
+        //act
+        let mut first_res = SlidingWindowRateLimiter::check_many(&checks).unwrap();
+        let mut second_res = SlidingWindowRateLimiter::check_many(&checks).unwrap();
+
+        //assert
+        first_res.remove(0).as_allowed();
+        assert_eq!(second_res.remove(0).as_throttled().status.limit, 1);
+        assert_eq!(
+            second_res.remove(0).as_allowed().remaining_request_counter,
+            3
+        );
+    }
+
+    #[test]
+    fn should_inspect_request_identifier_without_counting_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(2)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .with_limit_overrides(true)
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+        rate_limiter
+            .set_limit_override(
+                request_identifier.clone(),
+                &LimitOverride {
+                    window_size: Some(1),
+                    window_duration: None,
+                },
+            )
+            .unwrap();
+        rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap();
+
+        //act
+        let inspection = rate_limiter.inspect(request_identifier.clone()).unwrap();
+
+        //assert
+        assert_eq!(inspection.count, 1);
+        assert_eq!(inspection.limit, 1);
+        assert_eq!(inspection.window_duration, Duration::from_secs(60));
+        assert!(inspection.overridden);
+        assert!(inspection.is_throttled());
+        assert!(inspection.expire_in.unwrap() <= Duration::from_secs(60));
+        assert_eq!(rate_limiter.inspect(request_identifier).unwrap().count, 1);
+    }
+
+    #[test]
+    fn should_inspect_request_identifier_on_read_replica_against_redis_mock() {
+        //arrange
+        let primary_mock = RedisMock::start();
+        let replica_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::sliding_window()
+            .with_window_size(2)
+            .with_redis_settings(primary_mock.redis_settings())
+            .with_read_replica(replica_mock.redis_settings())
+            .build()
+            .unwrap();
+        let request_identifier = RequestIdentifier::Ip(generate_random_ip());
+
+        //act
+        let allowed_res = rate_limiter
+            .check_request(request_identifier.clone())
+            .unwrap()
+            .as_allowed();
+        let inspection = rate_limiter.inspect(request_identifier).unwrap();
+
+        //assert
+        assert_eq!(allowed_res.status.used, 1);
+        // the mocks don't replicate, so the replica doesn't see the request counted on the primary
+        assert_eq!(inspection.count, 0);
+        assert!(rate_limiter
+            .list_keys("*", ListCursor::default())
+            .unwrap()
+            .keys
+            .is_empty());
+    }
+
     fn generate_random_ip() -> IpAddr {
         let mut rng = rand::thread_rng();
         IpAddr::V4(Ipv4Addr::new(rng.gen(), rng.gen(), rng.gen(), rng.gen()))
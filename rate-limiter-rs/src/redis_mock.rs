@@ -0,0 +1,1477 @@
+//! A minimal, in-process Redis server speaking the [RESP](https://redis.io/docs/reference/protocol-spec/)
+//! protocol, used to test the rate limiters hermetically where no Redis container is available.
+//!
+//! ## Implementation details
+//!
+//! Only the commands used by the rate limiters are implemented, with a single logical database
+//! shared by all the connections. Keys expire lazily, when accessed. Transactions are executed
+//! atomically under a lock on `EXEC`: as no other client can interleave, `WATCH` never aborts them.
+//!
+//! As there is no Lua interpreter, scripting commands only run the scripts and the function
+//! library shipped by this crate, recognized by their SHA1 digest and their function names. Their
+//! Lua code is replaced by equivalent Rust code, issuing the same commands against the store,
+//! under the same lock, so that they are as atomic as in Redis. Like Redis, `EVALSHA` answers
+//! `NOSCRIPT` until the script is loaded with `SCRIPT LOAD`, and `FCALL` answers that the function
+//! is not found until the library is loaded with `FUNCTION LOAD`.
+//!
+//! The mock can report an older server version, in which case it rejects, like the matching Redis
+//! versions, the Redis Functions and the options of `PEXPIRE`, which were added in Redis 7. Every
+//! command received from the clients is recorded, so that tests can check what was sent.
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use redis::Script;
+
+use crate::{
+    builders::RedisSettings,
+    capabilities::RedisVersion,
+    functions::{self, FIXED_WINDOW_CHECK, SLIDING_WINDOW_CHECK},
+    rate_limiters::{fixed_window, sliding_window},
+};
+
+/// The server version reported by the `INFO` command
+const REDIS_VERSION: RedisVersion = RedisVersion::new(7, 2, 0);
+
+/// Represents a running mock Redis server, listening on a random local port
+pub(crate) struct RedisMock {
+    port: u16,
+    connections: Arc<AtomicUsize>,
+    commands: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl RedisMock {
+    /// Starts a mock Redis server on a background thread.
+    pub(crate) fn start() -> Self {
+        Self::start_with_version(REDIS_VERSION)
+    }
+
+    /// Starts a mock Redis server reporting the given version on a background thread.
+    pub(crate) fn start_with_version(version: RedisVersion) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("unable to bind Redis mock");
+        let port = listener
+            .local_addr()
+            .expect("unable to get Redis mock address")
+            .port();
+        let store = Arc::new(Mutex::new(Store {
+            version,
+            ..Store::default()
+        }));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let commands = Arc::new(Mutex::new(vec![]));
+
+        let accepted_connections = connections.clone();
+        let received_commands = commands.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                accepted_connections.fetch_add(1, Ordering::Relaxed);
+                let (store, received_commands) = (store.clone(), received_commands.clone());
+                thread::spawn(move || handle_connection(stream, store, received_commands));
+            }
+        });
+
+        RedisMock {
+            port,
+            connections,
+            commands,
+        }
+    }
+
+    /// Returns the number of connections accepted so far.
+    pub(crate) fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Returns the commands received so far, with their arguments, in the order they were received.
+    pub(crate) fn commands(&self) -> Vec<Vec<String>> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Returns the settings to connect to this server.
+    pub(crate) fn redis_settings(&self) -> RedisSettings {
+        RedisSettings {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            ..RedisSettings::default()
+        }
+    }
+}
+
+/// Represents a reply sent back to the client
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Status(s) => out.extend_from_slice(format!("+{}\r\n", s).as_bytes()),
+            Reply::Error(e) => out.extend_from_slice(format!("-{}\r\n", e).as_bytes()),
+            Reply::Integer(i) => out.extend_from_slice(format!(":{}\r\n", i).as_bytes()),
+            Reply::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Bulk(Some(b)) => {
+                out.extend_from_slice(format!("${}\r\n{}\r\n", b.len(), b).as_bytes())
+            }
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                items.iter().for_each(|i| i.write_to(out));
+            }
+        }
+    }
+
+    fn wrong_type() -> Reply {
+        Reply::Error(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        )
+    }
+
+    fn syntax_error() -> Reply {
+        Reply::Error("ERR syntax error".to_string())
+    }
+
+    fn not_an_integer() -> Reply {
+        Reply::Error("ERR value is not an integer or out of range".to_string())
+    }
+}
+
+/// Represents a value stored in the mock
+#[derive(Clone, Debug)]
+enum Value {
+    String(String),
+    /// Members sorted by score, then lexicographically, as in Redis
+    SortedSet(Vec<(f64, String)>),
+    /// Fields in insertion order
+    Hash(Vec<(String, String)>),
+}
+
+impl Value {
+    /// Serializes the value, like `DUMP` but in a format of the mock's own, with fields and
+    /// members separated by control characters
+    fn dump(&self) -> String {
+        let (kind, items): (char, Vec<String>) = match self {
+            Value::String(s) => return format!("s{}", s),
+            Value::SortedSet(set) => (
+                'z',
+                set.iter()
+                    .map(|(score, member)| format!("{}\x1f{}", format_score(*score), member))
+                    .collect(),
+            ),
+            Value::Hash(hash) => (
+                'h',
+                hash.iter()
+                    .map(|(f, v)| format!("{}\x1f{}", f, v))
+                    .collect(),
+            ),
+        };
+        format!("{}{}", kind, items.join("\x1e"))
+    }
+
+    /// Deserializes a value serialized by [Value::dump]
+    fn restore(dump: &str) -> Option<Value> {
+        let mut chars = dump.chars();
+        let kind = chars.next()?;
+        let items = chars.as_str();
+        let pairs = || {
+            items
+                .split('\x1e')
+                .filter(|item| !item.is_empty())
+                .map(|item| item.split_once('\x1f'))
+        };
+        match kind {
+            's' => Some(Value::String(items.to_string())),
+            'z' => pairs()
+                .map(|pair| pair.and_then(|(s, m)| Some((parse_score(s)?, m.to_string()))))
+                .collect::<Option<_>>()
+                .map(Value::SortedSet),
+            'h' => pairs()
+                .map(|pair| pair.map(|(f, v)| (f.to_string(), v.to_string())))
+                .collect::<Option<_>>()
+                .map(Value::Hash),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+/// Represents the Lua code shipped by this crate, run as equivalent Rust code
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Program {
+    FixedWindowCheck,
+    SlidingWindowCheck,
+}
+
+impl Program {
+    /// Returns the program with the given script source, if shipped by this crate.
+    fn from_source(source: &str) -> Option<Program> {
+        if source == fixed_window::CHECK_SCRIPT_SOURCE {
+            Some(Program::FixedWindowCheck)
+        } else if source == sliding_window::CHECK_SCRIPT_SOURCE {
+            Some(Program::SlidingWindowCheck)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the program registered as the given function, if shipped by this crate.
+    fn from_function(name: &str) -> Option<Program> {
+        match name {
+            FIXED_WINDOW_CHECK => Some(Program::FixedWindowCheck),
+            SLIDING_WINDOW_CHECK => Some(Program::SlidingWindowCheck),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of arguments, besides the key, read by the program.
+    fn arity(self) -> usize {
+        match self {
+            Program::FixedWindowCheck => 1,
+            Program::SlidingWindowCheck => 6,
+        }
+    }
+}
+
+/// The keyspace shared by all the connections
+struct Store {
+    entries: HashMap<String, Entry>,
+    /// The scripts loaded with `SCRIPT LOAD`, by SHA1 digest
+    scripts: HashMap<String, Program>,
+    /// Whether the function library was loaded with `FUNCTION LOAD`
+    functions_loaded: bool,
+    /// The server version reported by the `INFO` command
+    version: RedisVersion,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store {
+            entries: HashMap::default(),
+            scripts: HashMap::default(),
+            functions_loaded: false,
+            version: REDIS_VERSION,
+        }
+    }
+}
+
+impl Store {
+    /// Returns the entry stored at the given key, evicting it if expired.
+    fn get(&mut self, key: &str) -> Option<&mut Entry> {
+        let expired = self
+            .entries
+            .get(key)
+            .and_then(|e| e.expires_at)
+            .is_some_and(|expires_at| expires_at <= Instant::now());
+        if expired {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key)
+    }
+
+    fn execute(&mut self, args: &[String]) -> Reply {
+        let command = args[0].to_uppercase();
+        let args = &args[1..];
+        match (command.as_str(), args.len()) {
+            ("PING", _) => Reply::Status("PONG"),
+            ("CLIENT" | "SELECT" | "AUTH", _) => Reply::Status("OK"),
+            ("INFO", _) => Reply::Bulk(Some(format!(
+                "# Server\r\nredis_version:{}\r\n",
+                self.version
+            ))),
+            ("TIME", 0) => {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                Reply::Array(vec![
+                    Reply::Bulk(Some(now.as_secs().to_string())),
+                    Reply::Bulk(Some(now.subsec_micros().to_string())),
+                ])
+            }
+            ("GET", 1) => match self.get(&args[0]) {
+                None => Reply::Bulk(None),
+                Some(Entry {
+                    value: Value::String(s),
+                    ..
+                }) => Reply::Bulk(Some(s.clone())),
+                Some(_) => Reply::wrong_type(),
+            },
+            ("MGET", 1..) => Reply::Array(
+                args.iter()
+                    .map(|k| match self.get(k) {
+                        Some(Entry {
+                            value: Value::String(s),
+                            ..
+                        }) => Reply::Bulk(Some(s.clone())),
+                        _ => Reply::Bulk(None),
+                    })
+                    .collect(),
+            ),
+            ("SET", 2..) => self.set(args),
+            ("SETNX", 2) => match self.get(&args[0]) {
+                Some(_) => Reply::Integer(0),
+                None => {
+                    self.set(args);
+                    Reply::Integer(1)
+                }
+            },
+            ("SCAN", 1..) => self.scan(args),
+            ("DUMP", 1) => Reply::Bulk(self.get(&args[0]).map(|entry| entry.value.dump())),
+            ("RESTORE", 3..) => self.restore(args),
+            ("DEL", 1..) => Reply::Integer(
+                args.iter()
+                    .filter(|k| self.get(k).is_some() && self.entries.remove(*k).is_some())
+                    .count() as i64,
+            ),
+            ("TYPE", 1) => match self.get(&args[0]) {
+                None => Reply::Status("none"),
+                Some(Entry {
+                    value: Value::String(_),
+                    ..
+                }) => Reply::Status("string"),
+                Some(Entry {
+                    value: Value::SortedSet(_),
+                    ..
+                }) => Reply::Status("zset"),
+                Some(_) => Reply::Status("hash"),
+            },
+            ("INCR", 1) => self.incr_by(&args[0], "1"),
+            ("INCRBY", 2) => self.incr_by(&args[0], &args[1]),
+            ("EXPIRE" | "PEXPIRE", 3..) if self.version < RedisVersion::new(7, 0, 0) => {
+                Reply::Error(format!(
+                    "ERR wrong number of arguments for '{}' command",
+                    command.to_lowercase()
+                ))
+            }
+            ("EXPIRE", 2..) => self.expire(args, Duration::from_secs),
+            ("PEXPIRE", 2..) => self.expire(args, Duration::from_millis),
+            ("PEXPIREAT", 2) => match args[1].parse::<u64>() {
+                Ok(at) => {
+                    let now = SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis() as u64;
+                    let args = [args[0].clone(), at.saturating_sub(now).to_string()];
+                    self.expire(&args, Duration::from_millis)
+                }
+                Err(_) => Reply::not_an_integer(),
+            },
+            ("TTL", 1) => self.ttl(&args[0], |d| (d.as_millis() as i64 + 500) / 1000),
+            ("PTTL", 1) => self.ttl(&args[0], |d| d.as_millis() as i64),
+            ("ZADD", 3..) => self.zadd(args),
+            ("ZINCRBY", 3) => self.zincr_by(args),
+            ("ZREM", 2..) => self.zset_mut(&args[0], |set| {
+                let before = set.len();
+                set.retain(|(_, m)| !args[1..].contains(m));
+                Reply::Integer((before - set.len()) as i64)
+            }),
+            ("ZCARD", 1) => self.zset(&args[0], |set| Reply::Integer(set.len() as i64)),
+            ("ZCOUNT", 3) => match (parse_bound(&args[1]), parse_bound(&args[2])) {
+                (Some(min), Some(max)) => self.zset(&args[0], |set| {
+                    Reply::Integer(
+                        set.iter().filter(|(s, _)| min.le(*s) && max.ge(*s)).count() as i64
+                    )
+                }),
+                _ => Reply::syntax_error(),
+            },
+            ("ZREMRANGEBYSCORE", 3) => match (parse_bound(&args[1]), parse_bound(&args[2])) {
+                (Some(min), Some(max)) => self.zset_mut(&args[0], |set| {
+                    let before = set.len();
+                    set.retain(|(s, _)| !(min.le(*s) && max.ge(*s)));
+                    Reply::Integer((before - set.len()) as i64)
+                }),
+                _ => Reply::syntax_error(),
+            },
+            ("ZREMRANGEBYRANK", 3) => match (args[1].parse::<i64>(), args[2].parse::<i64>()) {
+                (Ok(start), Ok(stop)) => self.zset_mut(&args[0], |set| {
+                    let (start, count) = rank_range(set.len(), start, stop);
+                    set.drain(start..start + count);
+                    Reply::Integer(count as i64)
+                }),
+                _ => Reply::not_an_integer(),
+            },
+            ("ZREVRANGEBYSCORE", 3..) => self.zrevrangebyscore(args),
+            ("ZRANGE", 3..) => self.zrange(args),
+            ("ZREVRANGE", 3..) => self.zrevrange(args),
+            ("HSET", 3..) if args.len() % 2 == 1 => self.hset(args),
+            ("HINCRBY", 3) => self.hincr_by(&args[0], &args[1], &args[2]),
+            ("HGET", 2) => self.hash(&args[0], |hash| Reply::Bulk(hash_field(hash, &args[1]))),
+            ("HMGET", 2..) => self.hash(&args[0], |hash| {
+                Reply::Array(
+                    args[1..]
+                        .iter()
+                        .map(|field| Reply::Bulk(hash_field(hash, field)))
+                        .collect(),
+                )
+            }),
+            ("HGETALL", 1) => self.hash(&args[0], |hash| {
+                Reply::Array(
+                    hash.iter()
+                        .flat_map(|(f, v)| {
+                            [Reply::Bulk(Some(f.clone())), Reply::Bulk(Some(v.clone()))]
+                        })
+                        .collect(),
+                )
+            }),
+            ("SCRIPT", 1..) => self.script(args),
+            ("EVALSHA", 2..) => match self.scripts.get(&args[0].to_lowercase()) {
+                Some(program) => self.call(*program, &args[1..]),
+                None => Reply::Error("NOSCRIPT No matching script. Please use EVAL.".to_string()),
+            },
+            ("FUNCTION" | "FCALL", _) if self.version < RedisVersion::new(7, 0, 0) => {
+                Reply::Error(format!("ERR unknown command '{}'", command.to_lowercase()))
+            }
+            ("FUNCTION", 1..) => self.function(args),
+            ("FCALL", 2..) => match Program::from_function(&args[0]) {
+                Some(program) if self.functions_loaded => self.call(program, &args[1..]),
+                _ => Reply::Error("ERR Function not found".to_string()),
+            },
+            _ => Reply::Error(format!("ERR unknown command '{}'", command)),
+        }
+    }
+
+    fn script(&mut self, args: &[String]) -> Reply {
+        match (args[0].to_uppercase().as_str(), args.get(1)) {
+            ("LOAD", Some(source)) => match Program::from_source(source) {
+                Some(program) => {
+                    let sha = Script::new(source).get_hash().to_string();
+                    self.scripts.insert(sha.clone(), program);
+                    Reply::Bulk(Some(sha))
+                }
+                None => Reply::Error("ERR Error compiling script: unknown to the mock".to_string()),
+            },
+            ("FLUSH", _) => {
+                self.scripts.clear();
+                Reply::Status("OK")
+            }
+            _ => Reply::syntax_error(),
+        }
+    }
+
+    fn function(&mut self, args: &[String]) -> Reply {
+        match args[0].to_uppercase().as_str() {
+            "LOAD" => {
+                let replace = args.len() == 3 && args[1].eq_ignore_ascii_case("REPLACE");
+                match args.last() {
+                    Some(library) if args.len() == 2 || replace => {
+                        if *library != *functions::LIBRARY {
+                            return Reply::Error(
+                                "ERR Error compiling function: unknown to the mock".to_string(),
+                            );
+                        }
+                        if self.functions_loaded && !replace {
+                            return Reply::Error(format!(
+                                "ERR Library '{}' already exists",
+                                functions::LIBRARY_NAME
+                            ));
+                        }
+                        self.functions_loaded = true;
+                        Reply::Bulk(Some(functions::LIBRARY_NAME.to_string()))
+                    }
+                    _ => Reply::syntax_error(),
+                }
+            }
+            "FLUSH" => {
+                self.functions_loaded = false;
+                Reply::Status("OK")
+            }
+            _ => Reply::syntax_error(),
+        }
+    }
+
+    /// Runs the given program with the given `numkeys`, keys and arguments, as passed to
+    /// `EVALSHA` and `FCALL`. Like in a Lua script, the first command failing aborts the program.
+    fn call(&mut self, program: Program, args: &[String]) -> Reply {
+        let Ok(numkeys) = args[0].parse::<usize>() else {
+            return Reply::not_an_integer();
+        };
+        if numkeys != 1 || args.len() < 2 + program.arity() {
+            return Reply::Error("ERR wrong number of arguments".to_string());
+        }
+        let (key, argv) = (&args[1], &args[2..]);
+
+        let reply = match program {
+            Program::FixedWindowCheck => self.fixed_window_check(key, argv),
+            Program::SlidingWindowCheck => self.sliding_window_check(key, argv),
+        };
+        reply.unwrap_or_else(|error| error)
+    }
+
+    /// Runs the given command, like `redis.call`, returning errors as such.
+    fn redis_call(&mut self, args: &[&str]) -> Result<Reply, Reply> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match self.execute(&args) {
+            Reply::Error(error) => Err(Reply::Error(error)),
+            reply => Ok(reply),
+        }
+    }
+
+    /// Equivalent of [fixed_window::CHECK_SCRIPT_SOURCE]
+    fn fixed_window_check(&mut self, key: &str, argv: &[String]) -> Result<Reply, Reply> {
+        let counter = self.redis_call(&["INCR", key])?;
+        let mut expire_in = self.redis_call(&["PTTL", key])?;
+        if matches!(expire_in, Reply::Integer(ttl) if ttl < 0) {
+            self.redis_call(&["PEXPIRE", key, &argv[0]])?;
+            expire_in = Reply::Integer(argv[0].parse().map_err(|_| Reply::not_an_integer())?);
+        }
+        Ok(Reply::Array(vec![counter, expire_in]))
+    }
+
+    /// Equivalent of [sliding_window::CHECK_SCRIPT_SOURCE]
+    fn sliding_window_check(&mut self, key: &str, argv: &[String]) -> Result<Reply, Reply> {
+        let window_start = format!("({}", argv[0]);
+        self.redis_call(&["ZREMRANGEBYSCORE", key, "-inf", &window_start])?;
+        self.redis_call(&["ZADD", key, "NX", &argv[1], &argv[2]])?;
+        let max_members: i64 = argv[4].parse().map_err(|_| Reply::not_an_integer())?;
+        if max_members > 0 {
+            let stop = (-max_members - 1).to_string();
+            self.redis_call(&["ZREMRANGEBYRANK", key, "0", &stop])?;
+        }
+        let request_count = self.redis_call(&["ZCOUNT", key, "-inf", "+inf"])?;
+        let quota_freeing_requests = self.redis_call(&[
+            "ZREVRANGEBYSCORE",
+            key,
+            "+inf",
+            "-inf",
+            "LIMIT",
+            &argv[5],
+            "1",
+        ])?;
+        let oldest_requests = self.redis_call(&["ZRANGE", key, "0", "0"])?;
+        self.redis_call(&["PEXPIRE", key, &argv[3]])?;
+        Ok(Reply::Array(vec![
+            request_count,
+            quota_freeing_requests,
+            oldest_requests,
+        ]))
+    }
+
+    fn set(&mut self, args: &[String]) -> Reply {
+        let mut only_if_missing = false;
+        let mut expires_at = None;
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            let unit: fn(u64) -> Duration = match option.to_uppercase().as_str() {
+                "NX" => {
+                    only_if_missing = true;
+                    continue;
+                }
+                "EX" => Duration::from_secs,
+                "PX" => Duration::from_millis,
+                _ => return Reply::syntax_error(),
+            };
+            match options.next().and_then(|v| v.parse().ok()) {
+                Some(v) => expires_at = Some(Instant::now() + unit(v)),
+                None => return Reply::not_an_integer(),
+            }
+        }
+
+        if only_if_missing && self.get(&args[0]).is_some() {
+            return Reply::Bulk(None);
+        }
+        self.entries.insert(
+            args[0].clone(),
+            Entry {
+                value: Value::String(args[1].clone()),
+                expires_at,
+            },
+        );
+        Reply::Status("OK")
+    }
+
+    fn incr_by(&mut self, key: &str, increment: &str) -> Reply {
+        let Ok(increment) = increment.parse::<i64>() else {
+            return Reply::not_an_integer();
+        };
+        let entry = match self.get(key) {
+            Some(entry) => entry,
+            None => self.entries.entry(key.to_string()).or_insert(Entry {
+                value: Value::String("0".to_string()),
+                expires_at: None,
+            }),
+        };
+        match &mut entry.value {
+            Value::String(s) => match s.parse::<i64>() {
+                Ok(current) => {
+                    *s = (current + increment).to_string();
+                    Reply::Integer(current + increment)
+                }
+                Err(_) => Reply::not_an_integer(),
+            },
+            Value::SortedSet(_) | Value::Hash(_) => Reply::wrong_type(),
+        }
+    }
+
+    fn expire(&mut self, args: &[String], unit: fn(u64) -> Duration) -> Reply {
+        let Ok(amount) = args[1].parse::<u64>() else {
+            return Reply::not_an_integer();
+        };
+        let only_if_no_expiry = match args.get(2).map(|o| o.to_uppercase()) {
+            None => false,
+            Some(o) if o == "NX" => true,
+            Some(_) => return Reply::syntax_error(),
+        };
+        match self.get(&args[0]) {
+            None => Reply::Integer(0),
+            Some(entry) if only_if_no_expiry && entry.expires_at.is_some() => Reply::Integer(0),
+            Some(entry) => {
+                entry.expires_at = Some(Instant::now() + unit(amount));
+                Reply::Integer(1)
+            }
+        }
+    }
+
+    fn ttl(&mut self, key: &str, as_unit: fn(Duration) -> i64) -> Reply {
+        match self.get(key) {
+            None => Reply::Integer(-2),
+            Some(Entry {
+                expires_at: None, ..
+            }) => Reply::Integer(-1),
+            Some(Entry {
+                expires_at: Some(expires_at),
+                ..
+            }) => Reply::Integer(as_unit(
+                expires_at.saturating_duration_since(Instant::now()),
+            )),
+        }
+    }
+
+    fn zadd(&mut self, args: &[String]) -> Reply {
+        let only_new_members = args[1].eq_ignore_ascii_case("NX");
+        let pairs = if only_new_members {
+            &args[2..]
+        } else {
+            &args[1..]
+        };
+        if pairs.is_empty() || pairs.len() % 2 != 0 {
+            return Reply::syntax_error();
+        }
+        let mut scored_members = Vec::with_capacity(pairs.len() / 2);
+        for pair in pairs.chunks(2) {
+            match parse_score(&pair[0]) {
+                Some(score) => scored_members.push((score, pair[1].clone())),
+                None => return Reply::Error("ERR value is not a valid float".to_string()),
+            }
+        }
+
+        if self.get(&args[0]).is_none() {
+            self.entries.insert(
+                args[0].clone(),
+                Entry {
+                    value: Value::SortedSet(vec![]),
+                    expires_at: None,
+                },
+            );
+        }
+        self.zset_mut(&args[0], |set| {
+            let mut added = 0;
+            for (score, member) in scored_members {
+                match set.iter().position(|(_, m)| *m == member) {
+                    Some(_) if only_new_members => continue,
+                    Some(i) => set[i].0 = score,
+                    None => {
+                        set.push((score, member));
+                        added += 1;
+                    }
+                }
+            }
+            set.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            Reply::Integer(added)
+        })
+    }
+
+    fn restore(&mut self, args: &[String]) -> Reply {
+        let Ok(ttl) = args[1].parse::<u64>() else {
+            return Reply::not_an_integer();
+        };
+        let replace = match args.get(3).map(|o| o.to_uppercase()) {
+            None => false,
+            Some(o) if o == "REPLACE" => true,
+            Some(_) => return Reply::syntax_error(),
+        };
+        let Some(value) = Value::restore(&args[2]) else {
+            return Reply::Error("ERR DUMP payload version or checksum are wrong".to_string());
+        };
+        if !replace && self.get(&args[0]).is_some() {
+            return Reply::Error("BUSYKEY Target key name already exists.".to_string());
+        }
+
+        self.entries.insert(
+            args[0].clone(),
+            Entry {
+                value,
+                expires_at: (ttl > 0).then(|| Instant::now() + Duration::from_millis(ttl)),
+            },
+        );
+        Reply::Status("OK")
+    }
+
+    fn scan(&mut self, args: &[String]) -> Reply {
+        let Ok(cursor) = args[0].parse::<usize>() else {
+            return Reply::not_an_integer();
+        };
+        let mut pattern = "*";
+        let mut count = 10;
+        let mut options = args[1..].iter();
+        while let Some(option) = options.next() {
+            match (option.to_uppercase().as_str(), options.next()) {
+                ("MATCH", Some(value)) => pattern = value,
+                ("COUNT", Some(value)) => match value.parse() {
+                    Ok(value) => count = value,
+                    Err(_) => return Reply::not_an_integer(),
+                },
+                _ => return Reply::syntax_error(),
+            }
+        }
+
+        let now = Instant::now();
+        let mut keys: Vec<&String> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.expires_at.is_none_or(|expires_at| expires_at > now))
+            .map(|(k, _)| k)
+            .collect();
+        keys.sort();
+        let start = cursor.min(keys.len());
+        let end = start.saturating_add(count).min(keys.len());
+        let next_cursor = if end == keys.len() { 0 } else { end };
+
+        Reply::Array(vec![
+            Reply::Bulk(Some(next_cursor.to_string())),
+            Reply::Array(
+                keys[start..end]
+                    .iter()
+                    .filter(|k| glob_match(pattern.as_bytes(), k.as_bytes()))
+                    .map(|k| Reply::Bulk(Some(k.to_string())))
+                    .collect(),
+            ),
+        ])
+    }
+
+    fn zincr_by(&mut self, args: &[String]) -> Reply {
+        let Some(increment) = parse_score(&args[1]) else {
+            return Reply::Error("ERR value is not a valid float".to_string());
+        };
+
+        if self.get(&args[0]).is_none() {
+            self.entries.insert(
+                args[0].clone(),
+                Entry {
+                    value: Value::SortedSet(vec![]),
+                    expires_at: None,
+                },
+            );
+        }
+        self.zset_mut(&args[0], |set| {
+            let score = match set.iter().position(|(_, m)| *m == args[2]) {
+                Some(i) => set.remove(i).0 + increment,
+                None => increment,
+            };
+            set.push((score, args[2].clone()));
+            set.sort_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            Reply::Bulk(Some(format_score(score)))
+        })
+    }
+
+    fn zrevrangebyscore(&mut self, args: &[String]) -> Reply {
+        let (Some(max), Some(min)) = (parse_bound(&args[1]), parse_bound(&args[2])) else {
+            return Reply::syntax_error();
+        };
+        let Some(options) = RangeOptions::parse(&args[3..]) else {
+            return Reply::syntax_error();
+        };
+        self.zset(&args[0], |set| {
+            let members = set
+                .iter()
+                .rev()
+                .filter(|(s, _)| min.le(*s) && max.ge(*s))
+                .skip(options.offset)
+                .take(options.count.unwrap_or(usize::MAX));
+            options.reply(members)
+        })
+    }
+
+    fn zrange(&mut self, args: &[String]) -> Reply {
+        let (Ok(start), Ok(stop)) = (args[1].parse::<i64>(), args[2].parse::<i64>()) else {
+            return Reply::not_an_integer();
+        };
+        let Some(options) = RangeOptions::parse(&args[3..]) else {
+            return Reply::syntax_error();
+        };
+        self.zset(&args[0], |set| {
+            let (start, count) = rank_range(set.len(), start, stop);
+            options.reply(set.iter().skip(start).take(count))
+        })
+    }
+
+    fn zrevrange(&mut self, args: &[String]) -> Reply {
+        let (Ok(start), Ok(stop)) = (args[1].parse::<i64>(), args[2].parse::<i64>()) else {
+            return Reply::not_an_integer();
+        };
+        let Some(options) = RangeOptions::parse(&args[3..]) else {
+            return Reply::syntax_error();
+        };
+        self.zset(&args[0], |set| {
+            let (start, count) = rank_range(set.len(), start, stop);
+            options.reply(set.iter().rev().skip(start).take(count))
+        })
+    }
+
+    fn hincr_by(&mut self, key: &str, field: &str, increment: &str) -> Reply {
+        let Ok(increment) = increment.parse::<i64>() else {
+            return Reply::not_an_integer();
+        };
+        let entry = match self.get(key) {
+            Some(entry) => entry,
+            None => self.entries.entry(key.to_string()).or_insert(Entry {
+                value: Value::Hash(vec![]),
+                expires_at: None,
+            }),
+        };
+        let Value::Hash(hash) = &mut entry.value else {
+            return Reply::wrong_type();
+        };
+        let index = match hash.iter().position(|(f, _)| f == field) {
+            Some(index) => index,
+            None => {
+                hash.push((field.to_string(), "0".to_string()));
+                hash.len() - 1
+            }
+        };
+        match hash[index].1.parse::<i64>() {
+            Ok(current) => {
+                hash[index].1 = (current + increment).to_string();
+                Reply::Integer(current + increment)
+            }
+            Err(_) => Reply::Error("ERR hash value is not an integer".to_string()),
+        }
+    }
+
+    fn hset(&mut self, args: &[String]) -> Reply {
+        let key = &args[0];
+        if self.get(key).is_none() {
+            self.entries.insert(
+                key.clone(),
+                Entry {
+                    value: Value::Hash(vec![]),
+                    expires_at: None,
+                },
+            );
+        }
+        let hash = match self.get(key) {
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => hash,
+            _ => return Reply::wrong_type(),
+        };
+
+        let mut added = 0;
+        for pair in args[1..].chunks(2) {
+            match hash.iter_mut().find(|(field, _)| *field == pair[0]) {
+                Some(entry) => entry.1 = pair[1].clone(),
+                None => {
+                    hash.push((pair[0].clone(), pair[1].clone()));
+                    added += 1;
+                }
+            }
+        }
+        Reply::Integer(added)
+    }
+
+    /// Runs the given read-only operation on the hash stored at key.
+    /// Missing keys are considered empty hashes.
+    fn hash(&mut self, key: &str, op: impl FnOnce(&[(String, String)]) -> Reply) -> Reply {
+        match self.get(key) {
+            None => op(&[]),
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => op(hash),
+            Some(_) => Reply::wrong_type(),
+        }
+    }
+
+    /// Runs the given read-only operation on the sorted set stored at key.
+    /// Missing keys are considered empty sorted sets.
+    fn zset(&mut self, key: &str, op: impl FnOnce(&[(f64, String)]) -> Reply) -> Reply {
+        match self.get(key) {
+            None => op(&[]),
+            Some(Entry {
+                value: Value::SortedSet(set),
+                ..
+            }) => op(set),
+            Some(_) => Reply::wrong_type(),
+        }
+    }
+
+    /// Runs the given operation on the sorted set stored at key, deleting the key once empty.
+    fn zset_mut(&mut self, key: &str, op: impl FnOnce(&mut Vec<(f64, String)>) -> Reply) -> Reply {
+        let reply = match self.get(key) {
+            None => op(&mut vec![]),
+            Some(Entry {
+                value: Value::SortedSet(set),
+                ..
+            }) => op(set),
+            Some(_) => return Reply::wrong_type(),
+        };
+        if let Some(Entry {
+            value: Value::SortedSet(set),
+            ..
+        }) = self.entries.get(key)
+        {
+            if set.is_empty() {
+                self.entries.remove(key);
+            }
+        }
+        reply
+    }
+}
+
+/// Represents the `WITHSCORES` and `LIMIT` options of sorted set range commands
+struct RangeOptions {
+    with_scores: bool,
+    offset: usize,
+    count: Option<usize>,
+}
+
+impl RangeOptions {
+    fn parse(args: &[String]) -> Option<Self> {
+        let mut options = RangeOptions {
+            with_scores: false,
+            offset: 0,
+            count: None,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.to_uppercase().as_str() {
+                "WITHSCORES" => options.with_scores = true,
+                "LIMIT" => {
+                    options.offset = args.next()?.parse().ok()?;
+                    // a negative count means all the elements from the offset
+                    let count: i64 = args.next()?.parse().ok()?;
+                    options.count = usize::try_from(count).ok();
+                }
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+
+    fn reply<'a>(&self, members: impl Iterator<Item = &'a (f64, String)>) -> Reply {
+        let mut items = vec![];
+        for (score, member) in members {
+            items.push(Reply::Bulk(Some(member.clone())));
+            if self.with_scores {
+                items.push(Reply::Bulk(Some(format_score(*score))));
+            }
+        }
+        Reply::Array(items)
+    }
+}
+
+/// Represents a score interval bound, as accepted by `ZCOUNT` and friends
+#[derive(Clone, Copy)]
+struct Bound {
+    value: f64,
+    exclusive: bool,
+}
+
+impl Bound {
+    /// Whether this bound, used as a minimum, admits the given score
+    fn le(&self, score: f64) -> bool {
+        if self.exclusive {
+            self.value < score
+        } else {
+            self.value <= score
+        }
+    }
+
+    /// Whether this bound, used as a maximum, admits the given score
+    fn ge(&self, score: f64) -> bool {
+        if self.exclusive {
+            self.value > score
+        } else {
+            self.value >= score
+        }
+    }
+}
+
+/// Utility method that returns the first index and the number of the members between the given
+/// ranks, inclusive. Negative ranks count from the end of the sorted set.
+fn rank_range(len: usize, start: i64, stop: i64) -> (usize, usize) {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    (start as usize, (stop - start + 1).max(0) as usize)
+}
+
+fn parse_bound(arg: &str) -> Option<Bound> {
+    match arg.strip_prefix('(') {
+        Some(value) => parse_score(value).map(|value| Bound {
+            value,
+            exclusive: true,
+        }),
+        None => parse_score(arg).map(|value| Bound {
+            value,
+            exclusive: false,
+        }),
+    }
+}
+
+fn parse_score(arg: &str) -> Option<f64> {
+    match arg.to_lowercase().as_str() {
+        "-inf" => Some(f64::NEG_INFINITY),
+        "+inf" | "inf" => Some(f64::INFINITY),
+        score => score.parse().ok().filter(|s: &f64| !s.is_nan()),
+    }
+}
+
+/// Whether the given string matches the given glob-style pattern, supporting `*`, `?` and
+/// escaping with `\`
+fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    match (pattern.first(), string.first()) {
+        (None, _) => string.is_empty(),
+        (Some(b'*'), _) => {
+            glob_match(&pattern[1..], string)
+                || (!string.is_empty() && glob_match(pattern, &string[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &string[1..]),
+        (Some(b'\\'), Some(c)) if pattern.len() > 1 => {
+            pattern[1] == *c && glob_match(&pattern[2..], &string[1..])
+        }
+        (Some(p), Some(c)) => p == c && glob_match(&pattern[1..], &string[1..]),
+        (Some(_), None) => false,
+    }
+}
+
+fn hash_field(hash: &[(String, String)], field: &str) -> Option<String> {
+    hash.iter()
+        .find(|(f, _)| f == field)
+        .map(|(_, v)| v.clone())
+}
+
+fn format_score(score: f64) -> String {
+    if score.fract() == 0.0 && score.abs() < 1e17 {
+        format!("{}", score as i64)
+    } else {
+        format!("{}", score)
+    }
+}
+
+/// Reads a RESP array of bulk strings, the format used by clients to send commands.
+/// Returns `None` once the client closes the connection.
+fn read_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+    let header = read_line(reader)?;
+    let arity: usize = header.strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(arity);
+    for _ in 0..arity {
+        let len: usize = read_line(reader)?.strip_prefix('$')?.parse().ok()?;
+        let mut buf = vec![0; len + 2];
+        reader.read_exact(&mut buf).ok()?;
+        buf.truncate(len);
+        args.push(String::from_utf8(buf).ok()?);
+    }
+    Some(args)
+}
+
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches("\r\n").to_string()),
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    store: Arc<Mutex<Store>>,
+    commands: Arc<Mutex<Vec<Vec<String>>>>,
+) {
+    // replies are written at once, so that they're not delayed waiting for acknowledgements
+    let _ = stream.set_nodelay(true);
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    // commands queued after a MULTI, if any
+    let mut transaction: Option<Vec<Vec<String>>> = None;
+
+    while let Some(args) = read_command(&mut reader) {
+        if args.is_empty() {
+            continue;
+        }
+        commands.lock().unwrap().push(args.clone());
+        let reply = match (args[0].to_uppercase().as_str(), transaction.as_mut()) {
+            ("WATCH" | "UNWATCH", None) => Reply::Status("OK"),
+            ("MULTI", None) => {
+                transaction = Some(vec![]);
+                Reply::Status("OK")
+            }
+            ("EXEC", Some(_)) => {
+                let queued = transaction.take().unwrap_or_default();
+                let mut store = store.lock().unwrap();
+                Reply::Array(queued.iter().map(|c| store.execute(c)).collect())
+            }
+            ("DISCARD", Some(_)) => {
+                transaction = None;
+                Reply::Status("OK")
+            }
+            ("EXEC" | "DISCARD", None) => {
+                Reply::Error(format!("ERR {} without MULTI", args[0].to_uppercase()))
+            }
+            ("MULTI" | "WATCH", Some(_)) => {
+                Reply::Error("ERR Command not allowed inside a transaction".to_string())
+            }
+            (_, Some(queued)) => {
+                queued.push(args);
+                Reply::Status("QUEUED")
+            }
+            (_, None) => store.lock().unwrap().execute(&args),
+        };
+
+        let mut out = vec![];
+        reply.write_to(&mut out);
+        if writer.write_all(&out).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::BufReader, thread, time::Duration};
+
+    use redis::Script;
+
+    use super::{read_command, Reply, Store};
+    use crate::{
+        functions::{LIBRARY, SLIDING_WINDOW_CHECK},
+        rate_limiters::fixed_window,
+    };
+
+    fn execute(store: &mut Store, command: &str) -> Reply {
+        let args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        store.execute(&args)
+    }
+
+    fn bulk(value: &str) -> Reply {
+        Reply::Bulk(Some(value.to_string()))
+    }
+
+    #[test]
+    fn should_parse_resp_commands() {
+        let mut reader = BufReader::new("*2\r\n$4\r\nINCR\r\n$4\r\nrl:a\r\n".as_bytes());
+
+        assert_eq!(
+            read_command(&mut reader),
+            Some(vec!["INCR".to_string(), "rl:a".to_string()])
+        );
+        assert_eq!(read_command(&mut reader), None);
+    }
+
+    #[test]
+    fn should_encode_replies() {
+        let mut out = vec![];
+        Reply::Array(vec![Reply::Integer(1), bulk("a"), Reply::Bulk(None)]).write_to(&mut out);
+
+        assert_eq!(out, b"*3\r\n:1\r\n$1\r\na\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn should_increment_and_expire_counters() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "INCR k"), Reply::Integer(1));
+        assert_eq!(execute(&mut store, "INCRBY k 2"), Reply::Integer(3));
+        assert_eq!(execute(&mut store, "PTTL k"), Reply::Integer(-1));
+        assert_eq!(execute(&mut store, "PEXPIRE k 50 NX"), Reply::Integer(1));
+        assert_eq!(execute(&mut store, "PEXPIRE k 5000 NX"), Reply::Integer(0));
+        assert!(matches!(execute(&mut store, "PTTL k"), Reply::Integer(t) if t > 0 && t <= 50));
+
+        thread::sleep(Duration::from_millis(60));
+
+        assert_eq!(execute(&mut store, "GET k"), Reply::Bulk(None));
+        assert_eq!(execute(&mut store, "TTL k"), Reply::Integer(-2));
+    }
+
+    #[test]
+    fn should_reply_with_server_time() {
+        let mut store = Store::default();
+
+        match execute(&mut store, "TIME") {
+            Reply::Array(time) => assert_eq!(time.len(), 2),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn should_set_values_only_if_missing() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "SET k 1 NX"), Reply::Status("OK"));
+        assert_eq!(execute(&mut store, "SET k 2 NX"), Reply::Bulk(None));
+        assert_eq!(execute(&mut store, "SETNX k 3"), Reply::Integer(0));
+        assert_eq!(execute(&mut store, "GET k"), bulk("1"));
+        assert_eq!(execute(&mut store, "DEL k other"), Reply::Integer(1));
+    }
+
+    #[test]
+    fn should_handle_sorted_sets() {
+        let mut store = Store::default();
+
+        assert_eq!(
+            execute(&mut store, "ZADD z NX 1 a 2 b 3 c"),
+            Reply::Integer(3)
+        );
+        assert_eq!(execute(&mut store, "ZADD z NX 4 a"), Reply::Integer(0));
+        assert_eq!(execute(&mut store, "ZCOUNT z -inf +inf"), Reply::Integer(3));
+        assert_eq!(execute(&mut store, "ZCOUNT z (1 3"), Reply::Integer(2));
+        assert_eq!(
+            execute(&mut store, "ZREVRANGEBYSCORE z +inf -inf LIMIT 0 2"),
+            Reply::Array(vec![bulk("c"), bulk("b")])
+        );
+        assert_eq!(
+            execute(&mut store, "ZRANGE z 0 -1 WITHSCORES"),
+            Reply::Array(vec![
+                bulk("a"),
+                bulk("1"),
+                bulk("b"),
+                bulk("2"),
+                bulk("c"),
+                bulk("3")
+            ])
+        );
+        assert_eq!(
+            execute(&mut store, "ZREMRANGEBYSCORE z -inf (3"),
+            Reply::Integer(2)
+        );
+        assert_eq!(execute(&mut store, "TYPE z"), Reply::Status("zset"));
+        assert_eq!(execute(&mut store, "GET z"), Reply::wrong_type());
+    }
+
+    #[test]
+    fn should_trim_sorted_sets_by_rank() {
+        let mut store = Store::default();
+        execute(&mut store, "ZADD z 1 a 2 b 3 c 4 d");
+
+        assert_eq!(
+            execute(&mut store, "ZREMRANGEBYRANK z 0 -3"),
+            Reply::Integer(2)
+        );
+        assert_eq!(
+            execute(&mut store, "ZRANGE z 0 -1"),
+            Reply::Array(vec![bulk("c"), bulk("d")])
+        );
+        assert_eq!(
+            execute(&mut store, "ZREMRANGEBYRANK z 0 -3"),
+            Reply::Integer(0)
+        );
+    }
+
+    #[test]
+    fn should_increment_and_remove_sorted_set_members() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "ZINCRBY z 1 a"), bulk("1"));
+        assert_eq!(execute(&mut store, "ZINCRBY z 2 a"), bulk("3"));
+        assert_eq!(execute(&mut store, "ZINCRBY z 2 b"), bulk("2"));
+        assert_eq!(execute(&mut store, "ZINCRBY z 1 c"), bulk("1"));
+        assert_eq!(
+            execute(&mut store, "ZREVRANGE z 0 1 WITHSCORES"),
+            Reply::Array(vec![bulk("a"), bulk("3"), bulk("b"), bulk("2")])
+        );
+
+        assert_eq!(execute(&mut store, "ZREM z a c missing"), Reply::Integer(2));
+        assert_eq!(
+            execute(&mut store, "ZREVRANGE z 0 -1"),
+            Reply::Array(vec![bulk("b")])
+        );
+    }
+
+    #[test]
+    fn should_increment_hash_fields_and_expire_at() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "HINCRBY h a 1"), Reply::Integer(1));
+        assert_eq!(execute(&mut store, "HINCRBY h a 2"), Reply::Integer(3));
+        assert_eq!(execute(&mut store, "HINCRBY h b 1"), Reply::Integer(1));
+        assert_eq!(execute(&mut store, "PEXPIREAT h 1"), Reply::Integer(1));
+
+        assert_eq!(execute(&mut store, "HGET h a"), Reply::Bulk(None));
+    }
+
+    #[test]
+    fn should_get_multiple_keys() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "SET a 1"), Reply::Status("OK"));
+        assert_eq!(execute(&mut store, "HSET h f 1"), Reply::Integer(1));
+
+        assert_eq!(
+            execute(&mut store, "MGET a b h"),
+            Reply::Array(vec![
+                Reply::Bulk(Some("1".to_string())),
+                Reply::Bulk(None),
+                Reply::Bulk(None),
+            ])
+        );
+    }
+
+    #[test]
+    fn should_scan_keys_matching_pattern() {
+        let mut store = Store::default();
+        for key in ["rl:a", "rl:b", "other", "rl:c"] {
+            execute(&mut store, &format!("SET {} 1", key));
+        }
+
+        assert_eq!(
+            execute(&mut store, "SCAN 0 MATCH rl:* COUNT 3"),
+            Reply::Array(vec![
+                bulk("3"),
+                Reply::Array(vec![bulk("rl:a"), bulk("rl:b")])
+            ])
+        );
+        assert_eq!(
+            execute(&mut store, "SCAN 3 MATCH rl:? COUNT 3"),
+            Reply::Array(vec![bulk("0"), Reply::Array(vec![bulk("rl:c")])])
+        );
+    }
+
+    #[test]
+    fn should_dump_and_restore_values() {
+        let mut source = Store::default();
+        execute(&mut source, "SET s 1");
+        execute(&mut source, "ZADD z 1 a 2.5 b");
+        execute(&mut source, "HSET h f v");
+        let mut target = Store::default();
+
+        for key in ["s", "z", "h"] {
+            let Reply::Bulk(Some(dump)) = execute(&mut source, &format!("DUMP {}", key)) else {
+                panic!("missing dump of {}", key);
+            };
+            assert_eq!(
+                target.execute(&[
+                    "RESTORE".to_string(),
+                    key.to_string(),
+                    "0".to_string(),
+                    dump
+                ]),
+                Reply::Status("OK")
+            );
+        }
+
+        assert_eq!(execute(&mut target, "GET s"), bulk("1"));
+        assert_eq!(
+            execute(&mut target, "ZRANGE z 0 -1 WITHSCORES"),
+            Reply::Array(vec![bulk("a"), bulk("1"), bulk("b"), bulk("2.5")])
+        );
+        assert_eq!(execute(&mut target, "HGET h f"), bulk("v"));
+        assert_eq!(execute(&mut target, "PTTL s"), Reply::Integer(-1));
+        assert_eq!(execute(&mut source, "DUMP missing"), Reply::Bulk(None));
+    }
+
+    #[test]
+    fn should_handle_hashes() {
+        let mut store = Store::default();
+
+        assert_eq!(execute(&mut store, "HSET h a 1 b 2"), Reply::Integer(2));
+        assert_eq!(execute(&mut store, "HSET h a 3"), Reply::Integer(0));
+        assert_eq!(execute(&mut store, "HGET h a"), bulk("3"));
+        assert_eq!(
+            execute(&mut store, "HMGET h b missing"),
+            Reply::Array(vec![bulk("2"), Reply::Bulk(None)])
+        );
+        assert_eq!(
+            execute(&mut store, "HGETALL h"),
+            Reply::Array(vec![bulk("a"), bulk("3"), bulk("b"), bulk("2")])
+        );
+        assert_eq!(execute(&mut store, "TYPE h"), Reply::Status("hash"));
+        assert_eq!(execute(&mut store, "INCR h"), Reply::wrong_type());
+    }
+
+    #[test]
+    fn should_run_shipped_scripts_once_loaded() {
+        //arrange
+        let mut store = Store::default();
+        let source = fixed_window::CHECK_SCRIPT_SOURCE;
+        let sha = Script::new(source).get_hash().to_string();
+        let evalsha = format!("EVALSHA {} 1 k 1000", sha);
+
+        //act
+        let unloaded_reply = execute(&mut store, &evalsha);
+        let load_reply = store.execute(&["SCRIPT".to_string(), "LOAD".to_string(), source.into()]);
+        let first_reply = execute(&mut store, &evalsha);
+        let second_reply = execute(&mut store, &evalsha);
+
+        //assert
+        assert!(matches!(unloaded_reply, Reply::Error(e) if e.starts_with("NOSCRIPT")));
+        assert_eq!(load_reply, bulk(&sha));
+        assert_eq!(
+            first_reply,
+            Reply::Array(vec![Reply::Integer(1), Reply::Integer(1000)])
+        );
+        assert!(matches!(
+            second_reply,
+            Reply::Array(reply) if reply[0] == Reply::Integer(2)
+        ));
+    }
+
+    #[test]
+    fn should_refuse_unknown_scripts() {
+        let mut store = Store::default();
+
+        let reply = execute(&mut store, "SCRIPT LOAD return");
+
+        assert!(matches!(reply, Reply::Error(e) if e.starts_with("ERR Error compiling")));
+    }
+
+    #[test]
+    fn should_call_shipped_functions_once_loaded() {
+        //arrange
+        let mut store = Store::default();
+        let fcall = format!("FCALL {} 1 z 0 1 a 1000 0 0", SLIDING_WINDOW_CHECK);
+
+        //act
+        let unloaded_reply = execute(&mut store, &fcall);
+        let load_reply = store.execute(&[
+            "FUNCTION".to_string(),
+            "LOAD".to_string(),
+            "REPLACE".to_string(),
+            LIBRARY.to_string(),
+        ]);
+        let reply = execute(&mut store, &fcall);
+
+        //assert
+        assert_eq!(
+            unloaded_reply,
+            Reply::Error("ERR Function not found".to_string())
+        );
+        assert_eq!(load_reply, bulk("rate_limiter_rs"));
+        assert_eq!(
+            reply,
+            Reply::Array(vec![
+                Reply::Integer(1),
+                Reply::Array(vec![bulk("a")]),
+                Reply::Array(vec![bulk("a")])
+            ])
+        );
+        assert!(matches!(execute(&mut store, "PTTL z"), Reply::Integer(t) if t > 0));
+    }
+}
@@ -0,0 +1,139 @@
+//! Module that includes the regional counters used by fixed window rate limiters deployed
+//! active-active across several regions, each with its own replicated Redis deployment.
+//!
+//! ## Implementation details
+//!
+//! Every region only increments its own counter of a request key, so that checks never wait for
+//! a cross-region write, and concurrent writes from different regions never conflict. A check
+//! then sums the counters of all the regions, as replicated to the local Redis deployment so far:
+//!
+//! ```text
+//! rl:ip_172.28.0.6:region:eu-west-1:28046541
+//! rl:ip_172.28.0.6:region:us-east-1:28046541
+//! ```
+//!
+//! Windows are aligned to the clock, and identified by the trailing index, so that the counters
+//! of all the regions share the same boundaries. The global limit is approximated: requests
+//! served by other regions are only counted once replicated, so the limit can be exceeded by the
+//! requests received across regions within the replication lag.
+use redis::Connection;
+
+use crate::{errors::RateLimiterError, rate_limiters::AlignedWindow};
+
+/// The names of the Redis commands run by every check summing regional counters
+pub(crate) const REGIONAL_CHECK_COMMAND_NAMES: &[&str] =
+    &["MULTI", "INCR", "PEXPIREAT", "MGET", "EXEC"];
+
+/// Represents the regions of an active-active deployment, whose counters are summed
+#[derive(Clone, Debug)]
+pub struct RegionalCounters {
+    /// The region the rate limiter runs in, the only one whose counters it increments
+    pub local_region: String,
+
+    /// All the regions of the deployment, whose counters are summed. The local region is always
+    /// included, even if not listed.
+    pub regions: Vec<String>,
+}
+
+impl RegionalCounters {
+    /// Increments the counter of the given key in the local region by the given amount, for the
+    /// current window. Returns the sum of the counters of all the regions, and the expiry of the window in
+    /// milliseconds.
+    pub(crate) fn increment(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        window: &AlignedWindow,
+        increment: u64,
+    ) -> Result<(u64, u64), RateLimiterError> {
+        let local_key = regional_counter_key(key, &self.local_region, window.index);
+        let regional_keys: Vec<String> = self
+            .remote_regions()
+            .map(|region| regional_counter_key(key, region, window.index))
+            .collect();
+
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if increment == 1 {
+            pipe.cmd("INCR").arg(&local_key);
+        } else {
+            pipe.cmd("INCRBY").arg(&local_key).arg(increment);
+        }
+        pipe.cmd("PEXPIREAT")
+            .arg(&local_key)
+            .arg(window.end_millis)
+            .ignore();
+        if regional_keys.is_empty() {
+            let (local_counter,): (u64,) = pipe.query(con)?;
+            return Ok((local_counter, window.expire_in_millis));
+        }
+
+        let (local_counter, remote_counters): (u64, Vec<Option<u64>>) =
+            pipe.cmd("MGET").arg(&regional_keys).query(con)?;
+        let counter = remote_counters
+            .into_iter()
+            .flatten()
+            .fold(local_counter, u64::saturating_add);
+
+        Ok((counter, window.expire_in_millis))
+    }
+
+    /// Returns the sum of the counters of the given key in all the regions, for the given window,
+    /// without incrementing any.
+    pub(crate) fn read(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        window: &AlignedWindow,
+    ) -> Result<u64, RateLimiterError> {
+        let regional_keys: Vec<String> = std::iter::once(&self.local_region)
+            .chain(self.remote_regions())
+            .map(|region| regional_counter_key(key, region, window.index))
+            .collect();
+
+        let counters: Vec<Option<u64>> = redis::cmd("MGET").arg(&regional_keys).query(con)?;
+        Ok(counters.into_iter().flatten().fold(0, u64::saturating_add))
+    }
+
+    /// Returns the regions other than the local one.
+    fn remote_regions(&self) -> impl Iterator<Item = &String> {
+        self.regions
+            .iter()
+            .filter(|region| **region != self.local_region)
+    }
+}
+
+/// Utility method that returns the key holding the counter of the given request key, in the given
+/// region and window.
+fn regional_counter_key(key: &str, region: &str, window_index: u64) -> String {
+    format!("{}:region:{}:{}", key, region, window_index)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{regional_counter_key, RegionalCounters};
+
+    #[test]
+    fn should_build_regional_counter_key() {
+        assert_eq!(
+            regional_counter_key("rl:ip_1.2.3.4", "eu-west-1", 42),
+            "rl:ip_1.2.3.4:region:eu-west-1:42"
+        );
+    }
+
+    #[test]
+    fn should_exclude_local_region_from_remote_regions() {
+        let regional_counters = RegionalCounters {
+            local_region: "eu-west-1".to_string(),
+            regions: vec![
+                "us-east-1".to_string(),
+                "eu-west-1".to_string(),
+                "ap-south-1".to_string(),
+            ],
+        };
+
+        let remote_regions: Vec<&String> = regional_counters.remote_regions().collect();
+
+        assert_eq!(remote_regions, vec!["us-east-1", "ap-south-1"]);
+    }
+}
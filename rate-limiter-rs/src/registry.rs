@@ -0,0 +1,114 @@
+//! Module that includes a registry of named rate limiters, like `login`, `search` or `export`,
+//! so that services with many endpoints can build all their rate limiters from configuration
+//! and look them up by name, instead of wiring each of them individually.
+//!
+//! With the `serde` feature enabled, the configuration of the registry is a map of
+//! [rate limiter configurations](../config/enum.RateLimiterConfig.html) keyed by name:
+//!
+//! ```json
+//! {
+//!     "login": { "algorithm": "fixed_window", "window_size": 5, "window_duration": "1m" },
+//!     "search": { "algorithm": "sliding_window", "window_size": 100 }
+//! }
+//! ```
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    config::RateLimiterConfig, errors::RateLimiterError, factory::RateLimiterFactory, RateLimiter,
+};
+
+/// Registry holding rate limiters by name
+#[derive(Default, Clone)]
+pub struct RateLimiterRegistry {
+    rate_limiters: HashMap<String, Arc<dyn RateLimiter + Send + Sync>>,
+}
+
+impl RateLimiterRegistry {
+    /// Builds a registry holding a rate limiter for each of the given named configurations.
+    /// Returns an error if any of the rate limiters can't be built.
+    pub fn from_config(
+        configs: &HashMap<String, RateLimiterConfig>,
+    ) -> Result<Self, RateLimiterError> {
+        let rate_limiters = configs
+            .iter()
+            .map(|(name, config)| {
+                let rate_limiter = RateLimiterFactory::from_config(config)?;
+                Ok((name.clone(), Arc::from(rate_limiter)))
+            })
+            .collect::<Result<_, RateLimiterError>>()?;
+
+        Ok(RateLimiterRegistry { rate_limiters })
+    }
+
+    /// Adds the given rate limiter to the registry, returning the one previously registered
+    /// under the same name, if any.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        rate_limiter: Arc<dyn RateLimiter + Send + Sync>,
+    ) -> Option<Arc<dyn RateLimiter + Send + Sync>> {
+        self.rate_limiters.insert(name.into(), rate_limiter)
+    }
+
+    /// Returns the rate limiter registered under the given name, if any.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn RateLimiter + Send + Sync>> {
+        self.rate_limiters.get(name).cloned()
+    }
+
+    /// Returns the names of the registered rate limiters, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.rate_limiters.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::RateLimiterRegistry;
+    use crate::{
+        config::{RateLimiterConfig, WindowConfig},
+        factory::RateLimiterFactory,
+        RateLimiter,
+    };
+
+    #[test]
+    fn should_build_registry_from_config() {
+        let configs = HashMap::from([
+            (
+                "login".to_string(),
+                RateLimiterConfig::FixedWindow(WindowConfig {
+                    window_size: Some(5),
+                    ..WindowConfig::default()
+                }),
+            ),
+            (
+                "search".to_string(),
+                RateLimiterConfig::SlidingWindow(WindowConfig::default()),
+            ),
+        ]);
+
+        let registry = RateLimiterRegistry::from_config(&configs).unwrap();
+
+        let mut names: Vec<&str> = registry.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["login", "search"]);
+        assert!(registry.get("login").is_some());
+        assert!(registry.get("export").is_none());
+    }
+
+    #[test]
+    fn should_replace_rate_limiter_registered_under_same_name() {
+        let mut registry = RateLimiterRegistry::default();
+        let first: Arc<dyn RateLimiter + Send + Sync> =
+            Arc::new(RateLimiterFactory::per_second(1).build().unwrap());
+        let second: Arc<dyn RateLimiter + Send + Sync> =
+            Arc::new(RateLimiterFactory::per_minute(1).build().unwrap());
+
+        assert!(registry.register("login", first.clone()).is_none());
+        let replaced = registry.register("login", second.clone()).unwrap();
+
+        assert!(Arc::ptr_eq(&replaced, &first));
+        assert!(Arc::ptr_eq(&registry.get("login").unwrap(), &second));
+    }
+}
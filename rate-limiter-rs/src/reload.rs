@@ -0,0 +1,348 @@
+//! Module that includes the hot reloading of the rate limiters of a
+//! [registry](crate::registry::RateLimiterRegistry) from a policy file, so that the per-route or
+//! per-tenant limits of a running service can be changed without restarting it.
+//!
+//! The policy file maps names to [policies](crate::policy), either as TOML or as YAML:
+//!
+//! ```toml
+//! # policies.toml
+//! login = "5/min"
+//! search = "100 per minute burst 200"
+//! ```
+//!
+//! ```yaml
+//! # policies.yaml
+//! login: 5/min
+//! search: 100 per minute burst 200
+//! ```
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use rate_limiter_rs::reload::{PolicyReloader, ReloadableRegistry};
+//!
+//! let registry = ReloadableRegistry::default();
+//! PolicyReloader::new("policies.toml", registry.clone())
+//!     .with_poll_interval(Duration::from_secs(5))
+//!     .spawn()?;
+//!
+//! let login_rate_limiter = registry.get("login");
+//! # Ok::<_, std::io::Error>(())
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Only flat maps are supported: one `name = "policy"` or `name: policy` entry per line, with
+//! optional quotes, blank lines and `#` comments. Each policy is turned into the
+//! [window configuration](crate::config::WindowConfig) of a sliding window rate limiter, like
+//! [RateLimiterFactory::from_policy](crate::factory::RateLimiterFactory::from_policy) does.
+//!
+//! The file is polled for changes of its modification time or length, rather than watched, so
+//! that no file system notification is needed. On every change, all the rate limiters are built
+//! before being swapped into the registry at once, so that checks see either all the old rate
+//! limiters or all the new ones. A file that can't be read or parsed is logged and ignored,
+//! keeping the rate limiters currently registered. As the counters are stored in Redis under the
+//! request keys, rate limiters whose policy didn't change keep counting where they left off.
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, PoisonError, RwLock, Weak},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    builders::RedisSettings,
+    config::{RateLimiterConfig, WindowConfig},
+    errors::{ConfigError, RateLimiterError},
+    policy::Policy,
+    registry::RateLimiterRegistry,
+    RateLimiter,
+};
+
+/// The default interval between two checks of the policy file for changes
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Represents a registry whose rate limiters can be swapped at once while in use. Clones share
+/// the same registry.
+#[derive(Clone, Default)]
+pub struct ReloadableRegistry {
+    current: Arc<RwLock<Arc<RateLimiterRegistry>>>,
+}
+
+impl ReloadableRegistry {
+    /// Creates a reloadable registry holding the rate limiters of the given one.
+    pub fn new(registry: RateLimiterRegistry) -> Self {
+        ReloadableRegistry {
+            current: Arc::new(RwLock::new(Arc::new(registry))),
+        }
+    }
+
+    /// Returns the registry currently in use. It's not affected by later swaps.
+    pub fn registry(&self) -> Arc<RateLimiterRegistry> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the rate limiter currently registered under the given name, if any.
+    pub fn get(&self, name: &str) -> Option<Arc<dyn RateLimiter + Send + Sync>> {
+        self.registry().get(name)
+    }
+
+    /// Replaces the registry in use with the given one, returning the replaced registry.
+    pub fn swap(&self, registry: RateLimiterRegistry) -> Arc<RateLimiterRegistry> {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        std::mem::replace(&mut *current, Arc::new(registry))
+    }
+}
+
+/// Reloader of the rate limiters of a registry from a policy file
+#[derive(Clone)]
+pub struct PolicyReloader {
+    path: PathBuf,
+    registry: ReloadableRegistry,
+    redis: Option<RedisSettings>,
+    poll_interval: Duration,
+}
+
+impl PolicyReloader {
+    /// Creates a reloader of the rate limiters of the given registry from the policy file at the
+    /// given path.
+    pub fn new(path: impl Into<PathBuf>, registry: ReloadableRegistry) -> Self {
+        PolicyReloader {
+            path: path.into(),
+            registry,
+            redis: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets the Redis server the rate limiters connect to. Defaults to the builder default.
+    pub fn with_redis_settings(mut self, redis_settings: RedisSettings) -> Self {
+        self.redis = Some(redis_settings);
+        self
+    }
+
+    /// Sets the interval between two checks of the policy file for changes. Defaults to 1s.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Reads the policy file, and swaps the rate limiters it defines into the registry. Returns
+    /// an error, leaving the registry untouched, if the file can't be read, or any of its
+    /// policies can't be parsed, or any of its rate limiters built.
+    pub fn reload(&self) -> Result<(), RateLimiterError> {
+        self.registry.swap(self.load()?);
+        Ok(())
+    }
+
+    /// Loads the policy file, and reloads it whenever it changes, from a background thread. The
+    /// thread stops once all the clones of the registry are dropped. Yields an error if the
+    /// thread can't be spawned.
+    pub fn spawn(self) -> io::Result<JoinHandle<()>> {
+        let registry = Arc::downgrade(&self.registry.current);
+        // the thread only holds the registry while reloading, so that dropping it stops the thread
+        let reloader = PolicyReloader {
+            registry: ReloadableRegistry::default(),
+            ..self
+        };
+
+        thread::Builder::new()
+            .name("rate-limiter-policy-reloader".to_string())
+            .spawn(move || reloader.poll(registry))
+    }
+
+    /// Reloads the policy file into the given registry whenever its modification time or length
+    /// changes, until the registry is dropped.
+    fn poll(&self, registry: Weak<RwLock<Arc<RateLimiterRegistry>>>) {
+        let mut last_version: Option<(SystemTime, u64)> = None;
+        while registry.strong_count() > 0 {
+            let version = fs::metadata(&self.path)
+                .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                .ok();
+            if version.is_some() && version != last_version {
+                last_version = version;
+                match (self.load(), registry.upgrade()) {
+                    (Ok(loaded), Some(current)) => {
+                        ReloadableRegistry { current }.swap(loaded);
+                    }
+                    (Err(e), _) => log::warn!(
+                        "unable to reload policies from {}, keeping the current ones: {}",
+                        self.path.display(),
+                        e
+                    ),
+                    (Ok(_), None) => return,
+                }
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Reads the policy file, and builds a registry holding the rate limiters it defines.
+    fn load(&self) -> Result<RateLimiterRegistry, RateLimiterError> {
+        let policies = fs::read_to_string(&self.path).map_err(|e| {
+            ConfigError::UnreadablePolicyFile(format!("{}: {}", self.path.display(), e))
+        })?;
+        let configs = parse_policies(&policies)?
+            .into_iter()
+            .map(|(name, policy)| (name, self.config(&policy)))
+            .collect();
+
+        RateLimiterRegistry::from_config(&configs)
+    }
+
+    /// Returns the configuration of the rate limiter enforcing the given policy.
+    fn config(&self, policy: &Policy) -> RateLimiterConfig {
+        RateLimiterConfig::SlidingWindow(WindowConfig {
+            window_size: Some(policy.window_size()),
+            window_duration: Some(policy.window_duration()),
+            redis: self.redis.clone(),
+            ..WindowConfig::default()
+        })
+    }
+}
+
+/// Utility method that parses the policies of a policy file, keyed by name. Returns an error if
+/// any line isn't a `name = "policy"` or `name: policy` entry, or any policy can't be parsed.
+fn parse_policies(policies: &str) -> Result<HashMap<String, Policy>, RateLimiterError> {
+    let mut parsed = HashMap::new();
+    for line in policies.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line == "---" {
+            continue;
+        }
+
+        let (name, policy) = line
+            .split_once(['=', ':'])
+            .map(|(name, policy)| (unquote(name.trim()), unquote(policy.trim())))
+            .filter(|(name, policy)| !name.is_empty() && !policy.is_empty())
+            .ok_or_else(|| {
+                RateLimiterError::PolicyError(format!("{}: expected 'name = policy'", line))
+            })?;
+        if parsed.insert(name.to_string(), policy.parse()?).is_some() {
+            return Err(RateLimiterError::PolicyError(format!(
+                "{}: duplicate name",
+                line
+            )));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Utility method that strips the matching single or double quotes around the given value, if any.
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env, fs,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use rstest::rstest;
+    use uuid::Uuid;
+
+    use super::{parse_policies, PolicyReloader, ReloadableRegistry};
+    use crate::{errors::RateLimiterError, policy::Policy, registry::RateLimiterRegistry};
+
+    #[rstest]
+    #[case::toml("# policies\nlogin = \"5/min\"\nsearch = '100 per minute burst 200'\n")]
+    #[case::yaml("---\nlogin: 5/min\n\nsearch: 100 per minute burst 200\n")]
+    fn should_parse_policies(#[case] policies: &str) {
+        let policies = parse_policies(policies).unwrap();
+
+        assert_eq!(policies.len(), 2);
+        assert_eq!(policies["login"], "5/min".parse::<Policy>().unwrap());
+        assert_eq!(policies["search"].window_size(), 200);
+    }
+
+    #[rstest]
+    #[case::not_an_entry("[policies]")]
+    #[case::missing_policy("login =")]
+    #[case::invalid_policy("login = \"5 every minute\"")]
+    #[case::duplicate_name("login = 5/min\nlogin = 10/min")]
+    fn should_not_parse_invalid_policies(#[case] policies: &str) {
+        assert!(matches!(
+            parse_policies(policies),
+            Err(RateLimiterError::PolicyError(_))
+        ));
+    }
+
+    #[test]
+    fn should_swap_registries_at_once() {
+        let registry = ReloadableRegistry::default();
+        let snapshot = registry.registry();
+
+        let replaced = registry.swap(RateLimiterRegistry::default());
+
+        assert!(Arc::ptr_eq(&replaced, &snapshot));
+        assert!(!Arc::ptr_eq(&registry.registry(), &snapshot));
+    }
+
+    #[test]
+    fn should_keep_rate_limiters_when_policy_file_is_invalid() {
+        //arrange
+        let path = env::temp_dir().join(format!("policies-{}.toml", Uuid::new_v4()));
+        fs::write(&path, "login = \"5/min\"\n").unwrap();
+        let registry = ReloadableRegistry::default();
+        let reloader = PolicyReloader::new(&path, registry.clone());
+        reloader.reload().unwrap();
+        let login_rate_limiter = registry.get("login").unwrap();
+
+        //act
+        fs::write(&path, "login = \"5 every minute\"\n").unwrap();
+        let result = reloader.reload();
+        fs::remove_file(&path).unwrap();
+
+        //assert
+        assert!(matches!(result, Err(RateLimiterError::PolicyError(_))));
+        assert!(Arc::ptr_eq(
+            &registry.get("login").unwrap(),
+            &login_rate_limiter
+        ));
+    }
+
+    #[test]
+    fn should_reload_rate_limiters_when_policy_file_changes() {
+        //arrange
+        let path = env::temp_dir().join(format!("policies-{}.yaml", Uuid::new_v4()));
+        fs::write(&path, "login: 5/min\n").unwrap();
+        let registry = ReloadableRegistry::default();
+        let reloader = PolicyReloader::new(&path, registry.clone())
+            .with_poll_interval(Duration::from_millis(10))
+            .spawn()
+            .unwrap();
+        let wait_for = |name: &str| {
+            let started_at = Instant::now();
+            while registry.get(name).is_none() && started_at.elapsed() < Duration::from_secs(5) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            registry.get(name)
+        };
+
+        //act
+        let loaded = wait_for("login");
+        fs::write(&path, "search: 100 per minute burst 200\nexport: 1/hour\n").unwrap();
+        let reloaded = wait_for("search");
+        let mut names: Vec<String> = registry.registry().names().map(String::from).collect();
+        drop(registry);
+        reloader.join().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        //assert
+        assert!(loaded.is_some());
+        assert!(reloaded.is_some());
+        names.sort();
+        assert_eq!(names, vec!["export", "search"]);
+    }
+}
@@ -0,0 +1,342 @@
+//! Module that includes the offline replay of request traces, like the ones extracted from
+//! production access logs, simulating the decisions of candidate policies without Redis, so that
+//! limits can be tuned on real traffic before being deployed.
+//!
+//! ```
+//! use rate_limiter_rs::replay::{parse_trace, replay, Candidate, ReplayAlgorithm};
+//!
+//! let trace = parse_trace(
+//!     "1700000000.0 172.28.0.6\n\
+//!      1700000000.5 172.28.0.6\n\
+//!      1700000001.0 172.28.0.7\n",
+//! )
+//! .unwrap();
+//! let candidates = [
+//!     Candidate::from_policy(ReplayAlgorithm::FixedWindow, "1/min").unwrap(),
+//!     Candidate::from_policy(ReplayAlgorithm::SlidingWindow, "2/min").unwrap(),
+//! ];
+//!
+//! let reports = replay(&trace, &candidates);
+//!
+//! assert_eq!(reports[0].throttled, 1);
+//! assert_eq!(reports[1].throttled, 0);
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Traces are made of one request per line, with its timestamp, in seconds since the Unix epoch
+//! with an optional fraction, followed by its identifier, like an IP address or an API key,
+//! separated by a comma or by whitespace. Blank lines and lines starting with `#` are skipped.
+//! Requests are replayed in the order of their timestamps, so that the logs of several servers
+//! can simply be concatenated.
+//!
+//! The simulations follow the Redis implementations of the algorithms: a fixed window starts
+//! with the first request of an identifier after the previous one expired, and a sliding window
+//! holds the requests of the last window duration. In both, throttled requests are counted as
+//! well, so that clients retrying too early keep being throttled.
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime},
+};
+
+use crate::{durations::format_duration, errors::RateLimiterError, policy::Policy};
+
+/// Error returned when a line of a trace can't be parsed
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid trace line {line}: {content}")]
+pub struct InvalidTraceLine {
+    /// the number of the line, starting from 1
+    pub line: usize,
+    /// the content of the line
+    pub content: String,
+}
+
+/// Represents a request of a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    /// when the request was received
+    pub timestamp: SystemTime,
+    /// the identifier of the client, like an IP address or an API key
+    pub identifier: String,
+}
+
+/// Enum that represents the algorithms that can be simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayAlgorithm {
+    FixedWindow,
+    SlidingWindow,
+}
+
+/// Represents a candidate rate limiter, whose decisions are simulated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// the algorithm of the rate limiter
+    pub algorithm: ReplayAlgorithm,
+    /// the maximum number of requests allowed in a single window
+    pub window_size: u64,
+    /// the duration of the window
+    pub window_duration: Duration,
+}
+
+impl Candidate {
+    /// Creates a candidate with the given algorithm, and the window of the given
+    /// [policy](crate::policy), like `100/min` or `10 per second burst 20`.
+    pub fn from_policy(algorithm: ReplayAlgorithm, policy: &str) -> Result<Self, RateLimiterError> {
+        let policy: Policy = policy.parse()?;
+
+        Ok(Candidate {
+            algorithm,
+            window_size: policy.window_size(),
+            window_duration: policy.window_duration(),
+        })
+    }
+
+    /// Returns a short description of the candidate, like `sliding_window 100/1m`.
+    pub fn describe(&self) -> String {
+        let algorithm = match self.algorithm {
+            ReplayAlgorithm::FixedWindow => "fixed_window",
+            ReplayAlgorithm::SlidingWindow => "sliding_window",
+        };
+        format!(
+            "{} {}/{}",
+            algorithm,
+            self.window_size,
+            format_duration(self.window_duration)
+        )
+    }
+}
+
+/// Represents the simulated decisions of a candidate over a trace
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayReport {
+    /// the simulated candidate
+    pub candidate: Candidate,
+    /// the number of replayed requests
+    pub requests: u64,
+    /// the number of requests that would have been throttled
+    pub throttled: u64,
+    /// the number of requests that would have been throttled, by identifier
+    pub throttled_by_identifier: HashMap<String, u64>,
+}
+
+impl ReplayReport {
+    /// Returns the share of the requests that would have been throttled, between 0 and 1.
+    pub fn throttled_share(&self) -> f64 {
+        self.throttled as f64 / self.requests.max(1) as f64
+    }
+
+    /// Returns the given number of identifiers with the most requests that would have been
+    /// throttled, most throttled first.
+    pub fn top_throttled(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut throttled: Vec<(&str, u64)> = self
+            .throttled_by_identifier
+            .iter()
+            .map(|(identifier, count)| (identifier.as_str(), *count))
+            .collect();
+        throttled.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        throttled.truncate(n);
+        throttled
+    }
+}
+
+/// Parses a trace, made of one request per line, like `1700000000.25 172.28.0.6`.
+pub fn parse_trace(trace: &str) -> Result<Vec<TraceEntry>, InvalidTraceLine> {
+    trace
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line, content)| {
+            parse_trace_entry(content).ok_or_else(|| InvalidTraceLine {
+                line,
+                content: content.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Utility method that parses a line of a trace.
+fn parse_trace_entry(line: &str) -> Option<TraceEntry> {
+    let (timestamp, identifier) = line.split_once(|c: char| c == ',' || c.is_whitespace())?;
+    let seconds = timestamp.parse::<f64>().ok().filter(|s| *s >= 0.0)?;
+    let identifier = identifier.trim_start_matches(',').trim();
+    if identifier.is_empty() {
+        return None;
+    }
+
+    Some(TraceEntry {
+        timestamp: SystemTime::UNIX_EPOCH + Duration::try_from_secs_f64(seconds).ok()?,
+        identifier: identifier.to_string(),
+    })
+}
+
+/// Replays the given trace against each of the given candidates, returning their reports in the
+/// same order.
+pub fn replay(trace: &[TraceEntry], candidates: &[Candidate]) -> Vec<ReplayReport> {
+    let mut requests: Vec<&TraceEntry> = trace.iter().collect();
+    requests.sort_by_key(|entry| entry.timestamp);
+
+    candidates
+        .iter()
+        .map(|candidate| simulate(&requests, candidate))
+        .collect()
+}
+
+/// Utility method that simulates the decisions of the given candidate over the given requests,
+/// sorted by timestamp.
+fn simulate(requests: &[&TraceEntry], candidate: &Candidate) -> ReplayReport {
+    // the start and count of the current window of each identifier
+    let mut fixed_windows: HashMap<&str, (SystemTime, u64)> = HashMap::new();
+    // the timestamps of the requests in the current window of each identifier
+    let mut sliding_windows: HashMap<&str, VecDeque<SystemTime>> = HashMap::new();
+    let mut report = ReplayReport {
+        candidate: candidate.clone(),
+        requests: 0,
+        throttled: 0,
+        throttled_by_identifier: HashMap::new(),
+    };
+
+    for request in requests {
+        let count = match candidate.algorithm {
+            ReplayAlgorithm::FixedWindow => {
+                let (started_at, count) = fixed_windows
+                    .entry(&request.identifier)
+                    .or_insert((request.timestamp, 0));
+                if request.timestamp >= *started_at + candidate.window_duration {
+                    *started_at = request.timestamp;
+                    *count = 0;
+                }
+                *count += 1;
+                *count
+            }
+            ReplayAlgorithm::SlidingWindow => {
+                let window = sliding_windows.entry(&request.identifier).or_default();
+                while window.front().is_some_and(|timestamp| {
+                    *timestamp + candidate.window_duration < request.timestamp
+                }) {
+                    window.pop_front();
+                }
+                window.push_back(request.timestamp);
+                window.len() as u64
+            }
+        };
+
+        report.requests += 1;
+        if count > candidate.window_size {
+            report.throttled += 1;
+            *report
+                .throttled_by_identifier
+                .entry(request.identifier.clone())
+                .or_default() += 1;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rstest::rstest;
+
+    use super::{parse_trace, replay, Candidate, ReplayAlgorithm, TraceEntry};
+
+    fn trace(requests: &[(u64, &str)]) -> Vec<TraceEntry> {
+        requests
+            .iter()
+            .map(|(millis, identifier)| TraceEntry {
+                timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(*millis),
+                identifier: identifier.to_string(),
+            })
+            .collect()
+    }
+
+    fn candidate(algorithm: ReplayAlgorithm, window_size: u64) -> Candidate {
+        Candidate {
+            algorithm,
+            window_size,
+            window_duration: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn should_parse_trace() {
+        let trace = parse_trace(
+            "# timestamp identifier\n\
+             1700000000.25 172.28.0.6\n\
+             \n\
+             1700000001,api_key_1\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            trace,
+            vec![
+                TraceEntry {
+                    timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+                    identifier: "172.28.0.6".to_string(),
+                },
+                TraceEntry {
+                    timestamp: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_001),
+                    identifier: "api_key_1".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::missing_identifier("1700000000")]
+    #[case::invalid_timestamp("yesterday 172.28.0.6")]
+    #[case::negative_timestamp("-1 172.28.0.6")]
+    fn should_reject_invalid_trace_line(#[case] line: &str) {
+        let error = parse_trace(&format!("1700000000 172.28.0.6\n{}", line)).unwrap_err();
+
+        assert_eq!(error.line, 2);
+    }
+
+    #[test]
+    fn should_restart_fixed_window_after_it_expired() {
+        //arrange
+        let trace = trace(&[(0, "a"), (400, "a"), (900, "a"), (1000, "a"), (1100, "a")]);
+
+        //act
+        let reports = replay(&trace, &[candidate(ReplayAlgorithm::FixedWindow, 2)]);
+
+        //assert
+        assert_eq!(reports[0].requests, 5);
+        assert_eq!(reports[0].throttled, 1);
+    }
+
+    #[test]
+    fn should_count_throttled_requests_in_sliding_window() {
+        //arrange
+        let trace = trace(&[(0, "a"), (400, "a"), (900, "a"), (1000, "a"), (1100, "a")]);
+
+        //act
+        let reports = replay(&trace, &[candidate(ReplayAlgorithm::SlidingWindow, 2)]);
+
+        //assert
+        assert_eq!(reports[0].throttled, 3);
+    }
+
+    #[test]
+    fn should_report_each_candidate_and_identifier() {
+        //arrange
+        let trace = trace(&[(200, "b"), (0, "a"), (100, "b"), (300, "b"), (100, "a")]);
+        let candidates = [
+            candidate(ReplayAlgorithm::FixedWindow, 1),
+            candidate(ReplayAlgorithm::SlidingWindow, 3),
+        ];
+
+        //act
+        let reports = replay(&trace, &candidates);
+
+        //assert
+        assert_eq!(reports[0].throttled, 3);
+        assert_eq!(reports[0].top_throttled(1), vec![("b", 2)]);
+        assert_eq!(reports[0].throttled_share(), 0.6);
+        assert_eq!(reports[1].throttled, 0);
+        assert_eq!(reports[1].candidate.describe(), "sliding_window 3/1s");
+    }
+}
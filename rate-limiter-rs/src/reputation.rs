@@ -0,0 +1,165 @@
+//! Module that includes the optional reputation subsystem, that keeps track of an abuse score
+//! for every request identifier.
+//!
+//! ## Implementation details
+//!
+//! The abuse score is incremented every time a request identifier gets throttled, and decays
+//! exponentially over time, halving every configured half-life. This way, identifiers that
+//! misbehave repeatedly in a short period of time quickly accumulate a high score, while
+//! occasional offences are forgotten. The score can be used by callers to apply smarter
+//! penalties than fixed counters, like temporarily banning the worst offenders.
+//!
+//! The score is stored in Redis in a dedicated `<request key>:reputation` hash, together with
+//! the time of its last update, so that the decay can be applied lazily when the score is read
+//! or updated. The hash expires once the score has decayed to a negligible value.
+use std::time::{Duration, SystemTime};
+
+use redis::Connection;
+
+use crate::{errors::RateLimiterError, rate_limiters::as_expiry_millis};
+
+/// The number of half-lives after which a score is considered negligible and forgotten
+const RETENTION_HALF_LIVES: u32 = 10;
+
+/// Represents the reputation policy applied to request identifiers
+#[derive(Clone, Debug)]
+pub struct ReputationPolicy {
+    /// The time it takes for an abuse score to halve
+    pub half_life: Duration,
+
+    /// The amount the abuse score is incremented by, every time a request is throttled
+    pub throttle_penalty: f64,
+}
+
+impl ReputationPolicy {
+    /// Computes the value of a score after the given elapsed time.
+    pub fn decay(&self, score: f64, elapsed: Duration) -> f64 {
+        if self.half_life.is_zero() {
+            return 0.0;
+        }
+
+        score * 0.5_f64.powf(elapsed.as_secs_f64() / self.half_life.as_secs_f64())
+    }
+
+    /// Returns the abuse score of the given request key, at the given time.
+    pub(crate) fn score(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<f64, RateLimiterError> {
+        let reputation_key = reputation_key(key);
+        let now_epoch_millis = epoch_millis(now)?;
+
+        let (score, updated_at): (Option<f64>, Option<u64>) = redis::cmd("HMGET")
+            .arg(&reputation_key)
+            .arg("score")
+            .arg("updated_at")
+            .query(con)?;
+
+        Ok(self.decayed_score(score, updated_at, now_epoch_millis))
+    }
+
+    /// Increments the abuse score of the given request key by the throttle penalty, at the given
+    /// time, returning the updated score.
+    pub(crate) fn record_throttle(
+        &self,
+        con: &mut Connection,
+        key: &str,
+        now: SystemTime,
+    ) -> Result<f64, RateLimiterError> {
+        let reputation_key = reputation_key(key);
+        let now_epoch_millis = epoch_millis(now)?;
+        let retention = self.half_life * RETENTION_HALF_LIVES;
+
+        let score = redis::transaction(con, &[&reputation_key], |con, pipe| {
+            let (score, updated_at): (Option<f64>, Option<u64>) = redis::cmd("HMGET")
+                .arg(&reputation_key)
+                .arg("score")
+                .arg("updated_at")
+                .query(con)?;
+            let updated_score =
+                self.decayed_score(score, updated_at, now_epoch_millis) + self.throttle_penalty;
+
+            let committed: Option<()> = pipe
+                .cmd("HSET")
+                .arg(&reputation_key)
+                .arg("score")
+                .arg(updated_score)
+                .arg("updated_at")
+                .arg(now_epoch_millis)
+                .ignore()
+                .cmd("PEXPIRE")
+                .arg(&reputation_key)
+                .arg(as_expiry_millis(retention))
+                .ignore()
+                .query(con)?;
+
+            Ok(committed.map(|_| updated_score))
+        })?;
+
+        Ok(score)
+    }
+
+    /// Utility method that decays the stored score, if any, to the given epoch time in millis.
+    fn decayed_score(&self, score: Option<f64>, updated_at: Option<u64>, now: u64) -> f64 {
+        match (score, updated_at) {
+            (Some(score), Some(updated_at)) => {
+                self.decay(score, Duration::from_millis(now.saturating_sub(updated_at)))
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Utility method that returns the key holding the abuse score of the given request key.
+pub(crate) fn reputation_key(key: &str) -> String {
+    format!("{}:reputation", key)
+}
+
+/// Utility method that returns the given time as epoch time, in milliseconds.
+fn epoch_millis(now: SystemTime) -> Result<u64, RateLimiterError> {
+    Ok(now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|_e| RateLimiterError::ComputeError)?
+        .as_millis() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rstest::rstest;
+
+    use super::{reputation_key, ReputationPolicy};
+
+    const ONE_HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[rstest]
+    #[case::no_time_passed(Duration::ZERO, 8.0)]
+    #[case::one_half_life(ONE_HOUR, 4.0)]
+    #[case::three_half_lives(ONE_HOUR * 3, 1.0)]
+    fn should_decay_score_exponentially(#[case] elapsed: Duration, #[case] expected_score: f64) {
+        let reputation_policy = ReputationPolicy {
+            half_life: ONE_HOUR,
+            throttle_penalty: 1.0,
+        };
+
+        assert_eq!(reputation_policy.decay(8.0, elapsed), expected_score)
+    }
+
+    #[test]
+    fn should_default_to_zero_score() {
+        let reputation_policy = ReputationPolicy {
+            half_life: ONE_HOUR,
+            throttle_penalty: 1.0,
+        };
+
+        assert_eq!(reputation_policy.decayed_score(None, None, 1_000), 0.0)
+    }
+
+    #[test]
+    fn should_build_reputation_key() {
+        assert_eq!(reputation_key("rl:ip_1.2.3.4"), "rl:ip_1.2.3.4:reputation")
+    }
+}
@@ -0,0 +1,244 @@
+//! Module that includes the conversions of the rate limiter responses into the
+//! [http](https://docs.rs/http) types, shared by the middlewares built on them, so that
+//! framework-agnostic services can answer throttled requests in a few lines. Requires the `http`
+//! feature, enabled by the `tower` and `hyper` features.
+//!
+//! ```no_run
+//! use std::net::IpAddr;
+//!
+//! use http::{HeaderMap, Response};
+//! use rate_limiter_rs::{
+//!     factory::RateLimiterFactory, responses::http_response, RateLimiter, RequestIdentifier,
+//! };
+//!
+//! let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+//! let client_ip: IpAddr = "172.28.0.6".parse().unwrap();
+//!
+//! let response = rate_limiter
+//!     .check_request(RequestIdentifier::Ip(client_ip))
+//!     .unwrap();
+//! if let Some(throttled) = http_response::<String>(&response) {
+//!     // answer with the 429 response, instead of serving the request
+//! }
+//! let headers = HeaderMap::from(&response);
+//! ```
+use http::{
+    header::{CONTENT_TYPE, RETRY_AFTER},
+    HeaderMap, HeaderName, HeaderValue, Response, StatusCode,
+};
+
+use crate::{headers::RateLimitHeaders, RateLimiterResponse, RequestThrottled, ThrottleReason};
+
+/// The `RateLimit-Limit` header, lowercase as required by [HeaderName::from_static]
+const RATE_LIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+
+/// The `RateLimit-Remaining` header, lowercase as required by [HeaderName::from_static]
+const RATE_LIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+
+/// The `RateLimit-Reset` header, lowercase as required by [HeaderName::from_static]
+const RATE_LIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// Enum that represents the outcome of the check of a request
+#[cfg(any(feature = "hyper", feature = "tower"))]
+pub(crate) enum CheckOutcome<B> {
+    /// The request is throttled, and should be answered with the given response
+    Throttled(Response<B>),
+    /// The request is allowed, and its response should carry the given headers
+    Allowed(RateLimitHeaders),
+    /// The request has no identifier, or the check failed, and should reach the inner service
+    Unchecked,
+}
+
+/// Builds the response to a throttled request, with an empty body: a `429 Too Many Requests`
+/// status, or a `503 Service Unavailable` status when shed, along with the
+/// [rate limit headers](crate::headers) and a `Retry-After` header.
+pub fn throttled_response<B: Default>(throttled: &RequestThrottled) -> Response<B> {
+    with_throttled_status(Response::new(B::default()), throttled)
+}
+
+/// Returns the response to send for the given check, if the request is throttled: the
+/// [throttled response](throttled_response), with a plain text body. Returns nothing if the
+/// request is allowed.
+pub fn http_response<B: From<&'static str>>(response: &RateLimiterResponse) -> Option<Response<B>> {
+    let RateLimiterResponse::RequestThrottled(throttled) = response else {
+        return None;
+    };
+
+    let body = match throttled.reason {
+        ThrottleReason::QuotaExceeded => "You've been throttled!",
+        ThrottleReason::LoadShed => "Service overloaded, please retry later",
+    };
+    let mut response = with_throttled_status(Response::new(B::from(body)), throttled);
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    Some(response)
+}
+
+impl From<&RateLimiterResponse> for HeaderMap {
+    /// Returns the [rate limit headers](crate::headers) of the given response.
+    fn from(response: &RateLimiterResponse) -> Self {
+        let mut headers = HeaderMap::new();
+        insert_headers(&mut headers, &RateLimitHeaders::from(response));
+        headers
+    }
+}
+
+/// Inserts the given rate limit headers into the given header map, replacing any previous value.
+pub fn insert_headers(headers: &mut HeaderMap, rate_limit_headers: &RateLimitHeaders) {
+    headers.insert(
+        RATE_LIMIT_LIMIT,
+        HeaderValue::from(rate_limit_headers.limit),
+    );
+    headers.insert(
+        RATE_LIMIT_REMAINING,
+        HeaderValue::from(rate_limit_headers.remaining),
+    );
+    headers.insert(
+        RATE_LIMIT_RESET,
+        HeaderValue::from(rate_limit_headers.reset),
+    );
+    if let Some(retry_after) = rate_limit_headers.retry_after {
+        headers.insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+}
+
+/// Utility method that sets the status and the headers of the given response to a throttled
+/// request.
+fn with_throttled_status<B>(
+    mut response: Response<B>,
+    throttled: &RequestThrottled,
+) -> Response<B> {
+    *response.status_mut() = match throttled.reason {
+        ThrottleReason::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+        ThrottleReason::LoadShed => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    insert_headers(response.headers_mut(), &RateLimitHeaders::from(throttled));
+    response
+}
+
+/// Checks a request with the given request identifier, if any, against the given rate limiter,
+/// letting it through when the check fails.
+#[cfg(any(feature = "hyper", feature = "tower"))]
+pub(crate) fn check_http_request<R: crate::RateLimiter + ?Sized, B: Default>(
+    rate_limiter: &R,
+    request_identifier: Option<crate::RequestIdentifier>,
+) -> CheckOutcome<B> {
+    let Some(request_identifier) = request_identifier else {
+        return CheckOutcome::Unchecked;
+    };
+
+    match rate_limiter.check_request(request_identifier) {
+        Ok(RateLimiterResponse::RequestThrottled(throttled)) => {
+            CheckOutcome::Throttled(throttled_response(&throttled))
+        }
+        Ok(RateLimiterResponse::RequestAllowed(allowed)) => {
+            CheckOutcome::Allowed(RateLimitHeaders::from(&allowed))
+        }
+        Err(e) => {
+            log::warn!(
+                "rate limit check failed, letting the request through: {}",
+                e
+            );
+            CheckOutcome::Unchecked
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use http::{HeaderMap, Response, StatusCode};
+    use rstest::rstest;
+
+    use super::{http_response, insert_headers, throttled_response};
+    use crate::{
+        headers::RateLimitHeaders, RateLimitStatus, RateLimiterResponse, RequestAllowed,
+        RequestThrottled, ThrottleReason,
+    };
+
+    fn status(used: u64) -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 3,
+            window_duration: Duration::from_secs(60),
+            used,
+            reset_at: SystemTime::now() + Duration::from_secs(60),
+        }
+    }
+
+    #[rstest]
+    #[case::quota_exceeded(ThrottleReason::QuotaExceeded, StatusCode::TOO_MANY_REQUESTS)]
+    #[case::load_shed(ThrottleReason::LoadShed, StatusCode::SERVICE_UNAVAILABLE)]
+    fn should_map_throttle_reason_to_status(
+        #[case] reason: ThrottleReason,
+        #[case] expected: StatusCode,
+    ) {
+        let response: Response<String> = throttled_response(&RequestThrottled {
+            retry_in: Duration::from_millis(1500),
+            reason,
+            status: RateLimitStatus {
+                limit: 1,
+                window_duration: Duration::from_secs(60),
+                used: 2,
+                reset_at: SystemTime::now(),
+            },
+        });
+
+        assert_eq!(response.status(), expected);
+        assert_eq!(response.headers().get("retry-after").unwrap(), "2");
+        assert_eq!(response.headers().get("ratelimit-limit").unwrap(), "1");
+        assert_eq!(response.headers().get("ratelimit-remaining").unwrap(), "0");
+    }
+
+    #[test]
+    fn should_not_insert_retry_after_for_allowed_requests() {
+        let mut headers = HeaderMap::new();
+
+        insert_headers(
+            &mut headers,
+            &RateLimitHeaders {
+                limit: 10,
+                remaining: 4,
+                reset: 30,
+                retry_after: None,
+            },
+        );
+
+        assert_eq!(headers.get("ratelimit-remaining").unwrap(), "4");
+        assert_eq!(headers.get("ratelimit-reset").unwrap(), "30");
+        assert!(!headers.contains_key("retry-after"));
+    }
+
+    #[test]
+    fn should_convert_allowed_response_to_headers() {
+        let response = RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter: 2,
+            status: status(1),
+        });
+
+        let headers = HeaderMap::from(&response);
+
+        assert_eq!(headers.get("ratelimit-limit").unwrap(), "3");
+        assert_eq!(headers.get("ratelimit-remaining").unwrap(), "2");
+        assert_eq!(headers.get("ratelimit-reset").unwrap(), "60");
+        assert!(http_response::<String>(&response).is_none());
+    }
+
+    #[test]
+    fn should_convert_throttled_response_to_http_response() {
+        let response = RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in: Duration::from_secs(60),
+            reason: ThrottleReason::QuotaExceeded,
+            status: status(4),
+        });
+
+        let http_response: Response<String> = http_response(&response).unwrap();
+
+        assert_eq!(http_response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(http_response.body(), "You've been throttled!");
+        assert_eq!(http_response.headers().get("retry-after").unwrap(), "60");
+        assert!(HeaderMap::from(&response).contains_key("retry-after"));
+    }
+}
@@ -0,0 +1,114 @@
+//! Module that includes the client-side sharding of keys across several independent Redis servers,
+//! so that rate limiters can scale beyond a single server without requiring Redis Cluster.
+//!
+//! ## Implementation details
+//!
+//! Keys are assigned to servers with [rendezvous hashing](https://en.wikipedia.org/wiki/Rendezvous_hashing):
+//! every server is scored by hashing its address together with the key, and the highest score wins.
+//! All the nodes configured with the same servers agree on the owner of every key, in any order,
+//! and adding or removing a server only moves the keys it gains or loses.
+use redis::Client as RedisClient;
+
+use crate::{
+    connection::{ConnectionPool, RedisConnection},
+    errors::RateLimiterError,
+    stable_hash,
+};
+
+/// Represents one of the independent Redis servers keys are sharded across
+#[derive(Clone)]
+pub(crate) struct Shard {
+    /// The address of the server, identifying the shard
+    id: String,
+    pub(crate) redis_client: RedisClient,
+    pub(crate) connection_pool: ConnectionPool,
+}
+
+impl Shard {
+    pub(crate) fn new(redis_client: RedisClient, connection_pool: ConnectionPool) -> Self {
+        Shard {
+            id: redis_client.get_connection_info().addr.to_string(),
+            redis_client,
+            connection_pool,
+        }
+    }
+
+    /// Returns a connection to the server of the shard.
+    pub(crate) fn connection(&self) -> Result<RedisConnection, RateLimiterError> {
+        self.connection_pool.get(&self.redis_client)
+    }
+}
+
+/// Returns the shard owning the given key, if any.
+pub(crate) fn shard_for<'a>(shards: &'a [Shard], key: &str) -> Option<&'a Shard> {
+    owner_index(shards.iter().map(|shard| shard.id.as_str()), key).map(|i| &shards[i])
+}
+
+/// Utility method that returns the index of the shard, among the ones with the given identifiers,
+/// owning the given key.
+fn owner_index<'a>(shard_ids: impl Iterator<Item = &'a str>, key: &str) -> Option<usize> {
+    shard_ids
+        .enumerate()
+        .max_by_key(|(_, id)| rendezvous_score(id, key))
+        .map(|(i, _)| i)
+}
+
+/// Utility method that returns the score of the given shard for the given key. The hash is
+/// finalized like in MurmurHash3, so that scores of similar inputs are not correlated.
+fn rendezvous_score(shard_id: &str, key: &str) -> u64 {
+    let mut score = stable_hash(&format!("{}/{}", shard_id, key));
+    score ^= score >> 33;
+    score = score.wrapping_mul(0xff51afd7ed558ccd);
+    score ^= score >> 33;
+    score = score.wrapping_mul(0xc4ceb9fe1a85ec53);
+    score ^ (score >> 33)
+}
+
+#[cfg(test)]
+mod test {
+    use super::owner_index;
+
+    const SHARDS: [&str; 3] = ["10.0.0.1:6379", "10.0.0.2:6379", "10.0.0.3:6379"];
+
+    fn keys() -> impl Iterator<Item = String> {
+        (0..1000).map(|i| format!("rl:ip_10.1.{}.{}", i / 256, i % 256))
+    }
+
+    #[test]
+    fn should_not_assign_keys_without_shards() {
+        assert_eq!(owner_index([].into_iter(), "rl:ip_1.2.3.4"), None);
+    }
+
+    #[test]
+    fn should_assign_keys_regardless_of_shard_order() {
+        let reversed: Vec<&str> = SHARDS.iter().rev().copied().collect();
+
+        for key in keys() {
+            let owner = owner_index(SHARDS.into_iter(), &key).unwrap();
+            let reversed_owner = owner_index(reversed.iter().copied(), &key).unwrap();
+            assert_eq!(SHARDS[owner], reversed[reversed_owner]);
+        }
+    }
+
+    #[test]
+    fn should_spread_keys_across_shards() {
+        let mut counts = [0; SHARDS.len()];
+
+        for key in keys() {
+            counts[owner_index(SHARDS.into_iter(), &key).unwrap()] += 1;
+        }
+
+        assert!(counts.iter().all(|&count| count > 250), "{:?}", counts);
+    }
+
+    #[test]
+    fn should_only_move_keys_to_added_shard() {
+        let added: Vec<&str> = SHARDS.into_iter().chain(["10.0.0.4:6379"]).collect();
+
+        for key in keys() {
+            let before = owner_index(SHARDS.into_iter(), &key).unwrap();
+            let after = owner_index(added.iter().copied(), &key).unwrap();
+            assert!(after == before || after == SHARDS.len());
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Module that includes the snapshots of the whole state of a rate limiter, so that it can be
+//! migrated between Redis deployments during maintenance, without resetting the budget of every
+//! request identifier.
+//!
+//! ## Implementation details
+//!
+//! All the keys prefixed with `rl:` are listed with `SCAN`, and serialized with `DUMP`, together
+//! with their remaining time to live. Restoring a snapshot runs `RESTORE` with the `REPLACE`
+//! option, so that the keys of the snapshot replace the current ones, while keys created since
+//! are kept. The time elapsed since the snapshot was taken is subtracted from the time to live of
+//! the keys, and keys expired in the meantime are skipped.
+//!
+//! The serialization format of `DUMP` is specific to Redis, and only restorable into the same or a
+//! newer version. Keys are restored into the same Redis server they were dumped from, by index, so
+//! snapshots of sharded keys should be restored into the same number of shards.
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{
+    connection::RedisConnection, errors::RateLimiterError, inspection::as_expire_in,
+    rate_limiters::as_expiry_millis,
+};
+
+/// The pattern matching all the keys of the rate limiter
+#[cfg(feature = "redis")]
+const KEY_PATTERN: &str = "rl:*";
+
+/// The number of keys each `SCAN` call is hinted to go through
+#[cfg(feature = "redis")]
+const SCAN_COUNT: u64 = 1000;
+
+/// Represents the whole state of a rate limiter, at the time it was taken
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateSnapshot {
+    /// When the snapshot was taken
+    pub taken_at: SystemTime,
+
+    /// The Redis entries holding the state of the rate limiter
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// Represents a Redis entry of a snapshot
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SnapshotEntry {
+    /// The index of the Redis server the entry was dumped from, when keys are sharded
+    pub server: usize,
+
+    /// The Redis key of the entry
+    pub key: String,
+
+    /// The value of the entry, as serialized by `DUMP`
+    pub value: Vec<u8>,
+
+    /// The remaining time to live of the entry, when the snapshot was taken, if any
+    pub expire_in: Option<Duration>,
+}
+
+/// Takes a snapshot of the state stored in the given number of Redis servers, connected by the
+/// given function.
+#[cfg(feature = "redis")]
+pub(crate) fn export_state(
+    servers: usize,
+    connect: impl Fn(usize) -> Result<RedisConnection, RateLimiterError>,
+) -> Result<StateSnapshot, RateLimiterError> {
+    let taken_at = SystemTime::now();
+    let mut entries = vec![];
+
+    for server in 0..servers {
+        let mut con = connect(server)?;
+        let mut cursor = 0;
+        loop {
+            let (next_cursor, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(KEY_PATTERN)
+                .arg("COUNT")
+                .arg(SCAN_COUNT)
+                .query(&mut *con)?;
+            entries.extend(dump_keys(&mut con, server, keys)?);
+
+            if next_cursor == 0 {
+                break;
+            }
+            cursor = next_cursor;
+        }
+    }
+
+    Ok(StateSnapshot { taken_at, entries })
+}
+
+/// Restores the given entries of a snapshot taken at the given time, returning the number of
+/// restored keys.
+#[cfg(feature = "redis")]
+pub(crate) fn import_entries(
+    con: &mut Connection,
+    entries: &[&SnapshotEntry],
+    taken_at: SystemTime,
+) -> Result<u64, RateLimiterError> {
+    let elapsed = taken_at.elapsed().unwrap_or_default();
+    let mut pipe = redis::pipe();
+    let mut restored = 0;
+
+    for entry in entries {
+        let ttl_millis = match entry.expire_in {
+            Some(expire_in) if expire_in <= elapsed => continue,
+            Some(expire_in) => as_expiry_millis(expire_in - elapsed),
+            None => 0,
+        };
+        pipe.cmd("RESTORE")
+            .arg(&entry.key)
+            .arg(ttl_millis)
+            .arg(&entry.value)
+            .arg("REPLACE")
+            .ignore();
+        restored += 1;
+    }
+
+    if restored > 0 {
+        pipe.query::<()>(con)?;
+    }
+    Ok(restored)
+}
+
+/// Utility method that dumps the given keys, skipping the ones expired in the meantime.
+#[cfg(feature = "redis")]
+fn dump_keys(
+    con: &mut Connection,
+    server: usize,
+    keys: Vec<String>,
+) -> Result<Vec<SnapshotEntry>, RateLimiterError> {
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut pipe = redis::pipe();
+    for key in &keys {
+        pipe.cmd("DUMP").arg(key).cmd("PTTL").arg(key);
+    }
+    let dumps: Vec<(Option<Vec<u8>>, i64)> = pipe.query(con)?;
+
+    Ok(keys
+        .into_iter()
+        .zip(dumps)
+        .filter_map(|(key, (value, expire_in_millis))| {
+            Some(SnapshotEntry {
+                server,
+                key,
+                value: value?,
+                expire_in: as_expire_in(expire_in_millis),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,98 @@
+//! Module that includes the [tracing](https://docs.rs/tracing) spans instrumenting checks, so that
+//! rate limiters show up in distributed traces. Requires the `tracing` feature, without which
+//! spans are no-ops.
+//!
+//! ## Implementation details
+//!
+//! Every check runs in a `rate_limiter.check` span, carrying the following fields:
+//! - `algorithm`, either `fixed_window` or `sliding_window`;
+//! - `key_hash`, a hash of the request key, so that identifiers like IP addresses don't end up in traces;
+//! - `decision`, one of `allowed`, `throttled` or `error`, once checked;
+//! - `redis_connect_ms` and `redis_commands_ms`, the time spent acquiring a connection to Redis
+//!   and running the commands of the check, once checked.
+//!
+//! Checks against a quorum of masters run in other threads, and don't record the Redis latencies.
+use crate::{errors::RateLimiterError, latency::CheckLatency, RateLimiterResponse};
+
+/// Represents the span of a check
+pub(crate) struct CheckSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl CheckSpan {
+    /// Creates the span of the check of the given key, by the given algorithm.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn new(algorithm: &'static str, key: &str) -> Self {
+        #[cfg(feature = "tracing")]
+        let key_hash = format!("{:016x}", crate::stable_hash(key));
+
+        CheckSpan {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "rate_limiter.check",
+                algorithm = algorithm,
+                key_hash = key_hash.as_str(),
+                decision = tracing::field::Empty,
+                redis_connect_ms = tracing::field::Empty,
+                redis_commands_ms = tracing::field::Empty,
+            ),
+        }
+    }
+
+    /// Runs the given check within the span, recording its decision.
+    pub(crate) fn in_scope(
+        &self,
+        check: impl FnOnce() -> Result<RateLimiterResponse, RateLimiterError>,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        #[cfg(feature = "tracing")]
+        {
+            let res = self.span.in_scope(check);
+            self.span.record("decision", decision(&res));
+            res
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        check()
+    }
+}
+
+/// Records the given Redis latency on the span of the current check, if any.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_latency(latency: &CheckLatency) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current()
+        .record("redis_connect_ms", latency.connect.as_secs_f64() * 1000.0)
+        .record("redis_commands_ms", latency.commands.as_secs_f64() * 1000.0);
+}
+
+/// Utility method that returns the decision of the given check, as recorded on its span.
+#[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+fn decision(res: &Result<RateLimiterResponse, RateLimiterError>) -> &'static str {
+    match res {
+        Ok(RateLimiterResponse::RequestAllowed(_)) => "allowed",
+        Ok(RateLimiterResponse::RequestThrottled(_)) => "throttled",
+        Err(_) => "error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::errors::RateLimiterError;
+
+    use super::{decision, CheckSpan};
+
+    #[test]
+    fn should_describe_errors_as_decisions() {
+        assert_eq!(decision(&Err(RateLimiterError::ComputeError)), "error");
+    }
+
+    #[test]
+    fn should_return_the_result_of_the_check() {
+        let span = CheckSpan::new("fixed_window", "rl:ip_1.2.3.4");
+
+        let res = span.in_scope(|| Err(RateLimiterError::ComputeError));
+
+        assert!(matches!(res, Err(RateLimiterError::ComputeError)));
+    }
+}
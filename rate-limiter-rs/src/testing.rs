@@ -0,0 +1,806 @@
+//! Module that includes mock rate limiters, deciding the outcome of checks without Redis, so that
+//! applications can unit test how they handle allowed, throttled and failed checks, like their
+//! `429` responses, along with a [FaultInjector] wrapping a real rate limiter, for chaos testing
+//! their fail-open or fail-closed behavior. Requires the `testing` feature, usually enabled as a
+//! dev-dependency only.
+//!
+//! With the `testcontainers` feature, integration tests can also run against an ephemeral Redis
+//! server, started in a container by [RedisContainer], instead of relying on a Redis already
+//! running on the machine.
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use rate_limiter_rs::{
+//!     testing::{MockResponse, SequenceRateLimiter},
+//!     RateLimiter, RateLimiterResponse, RequestIdentifier,
+//! };
+//!
+//! let rate_limiter = SequenceRateLimiter::new([
+//!     MockResponse::Allow,
+//!     MockResponse::Throttle {
+//!         retry_in: Duration::from_secs(30),
+//!     },
+//! ]);
+//! let request_identifier = RequestIdentifier::Custom {
+//!     key: "api_key".to_string(),
+//!     value: "test".to_string(),
+//! };
+//!
+//! assert!(matches!(
+//!     rate_limiter.check_request(request_identifier.clone()),
+//!     Ok(RateLimiterResponse::RequestAllowed(_))
+//! ));
+//! assert!(matches!(
+//!     rate_limiter.check_request(request_identifier),
+//!     Ok(RateLimiterResponse::RequestThrottled(_))
+//! ));
+//! assert_eq!(rate_limiter.checked_keys().len(), 2);
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Mocks store no state: their inspections report no counted request, their usage and reputation
+//! are always zero, their snapshots are empty, and limit overrides are accepted and ignored.
+//! Allowed requests are reported against an unbounded limit, while throttled requests are
+//! reported against a limit of zero, with a window lasting until they can be retried.
+//!
+//! Faults are only injected into checks, the other methods being forwarded as they are. Whether
+//! a check is faulted is drawn from a pseudo-random sequence, derived from the seed of the
+//! injector and the number of checks so far, so that a failing chaos test can be replayed with
+//! the same seed.
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+use testcontainers::{
+    core::{IntoContainerPort, WaitFor},
+    runners::SyncRunner,
+    Container, GenericImage, TestcontainersError,
+};
+
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+use crate::{
+    builders::{
+        fixed_window::FixedWindowRateLimiterBuilder,
+        sliding_window::SlidingWindowRateLimiterBuilder, RedisSettings,
+    },
+    factory::RateLimiterFactory,
+};
+use crate::{
+    capabilities::{RedisCapabilities, RedisVersion},
+    cost::RequestContext,
+    data_subject::IdentifierData,
+    errors::RateLimiterError,
+    inspection::KeyInspection,
+    listing::{KeyListing, ListCursor},
+    offenders::Offender,
+    overrides::LimitOverride,
+    snapshot::StateSnapshot,
+    usage::UsagePeriod,
+    RateLimitStatus, RateLimiter, RateLimiterResponse, RedisAdmin, RequestAllowed,
+    RequestIdentifier, RequestThrottled, ThrottleReason,
+};
+
+/// The window duration reported for allowed requests
+const ALLOWED_WINDOW_DURATION: Duration = Duration::from_secs(60);
+
+/// The image of the Redis servers started by [RedisContainer]
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+const REDIS_IMAGE: (&str, &str) = ("redis", "7.4.1-bookworm");
+
+/// The port Redis listens on, within its container
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+const REDIS_CONTAINER_PORT: u16 = 6379;
+
+/// Mock rate limiter that allows every request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlwaysAllow;
+
+impl AlwaysAllow {
+    fn respond(&self, _key: String) -> Result<RateLimiterResponse, RateLimiterError> {
+        Ok(MockResponse::Allow.into_response())
+    }
+
+    fn inspected_limit(&self) -> u64 {
+        u64::MAX
+    }
+}
+
+/// Mock rate limiter that throttles every request, to be retried in the given duration
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlwaysThrottle {
+    /// the duration after which throttled requests should be retried
+    pub retry_in: Duration,
+}
+
+impl AlwaysThrottle {
+    fn respond(&self, _key: String) -> Result<RateLimiterResponse, RateLimiterError> {
+        Ok(MockResponse::Throttle {
+            retry_in: self.retry_in,
+        }
+        .into_response())
+    }
+
+    fn inspected_limit(&self) -> u64 {
+        0
+    }
+}
+
+/// Enum that represents the scripted outcomes of the checks of a [SequenceRateLimiter]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockResponse {
+    /// The request is allowed
+    Allow,
+    /// The request is throttled, to be retried in the given duration
+    Throttle {
+        /// the duration after which the request should be retried
+        retry_in: Duration,
+    },
+    /// The request could not be checked, like when Redis is unreachable
+    Fail,
+}
+
+impl MockResponse {
+    /// Returns the response of a check with this outcome, failing with a
+    /// [compute error](RateLimiterError::ComputeError) for [MockResponse::Fail].
+    fn into_result(self) -> Result<RateLimiterResponse, RateLimiterError> {
+        match self {
+            MockResponse::Fail => Err(RateLimiterError::ComputeError),
+            _ => Ok(self.into_response()),
+        }
+    }
+
+    /// Returns the response of a check with this outcome, allowing the request for
+    /// [MockResponse::Fail].
+    fn into_response(self) -> RateLimiterResponse {
+        let now = SystemTime::now();
+        match self {
+            MockResponse::Throttle { retry_in } => {
+                RateLimiterResponse::RequestThrottled(RequestThrottled {
+                    retry_in,
+                    reason: ThrottleReason::QuotaExceeded,
+                    status: RateLimitStatus {
+                        limit: 0,
+                        window_duration: retry_in,
+                        used: 1,
+                        reset_at: now + retry_in,
+                    },
+                })
+            }
+            MockResponse::Allow | MockResponse::Fail => {
+                RateLimiterResponse::RequestAllowed(RequestAllowed {
+                    remaining_request_counter: u64::MAX - 1,
+                    status: RateLimitStatus {
+                        limit: u64::MAX,
+                        window_duration: ALLOWED_WINDOW_DURATION,
+                        used: 1,
+                        reset_at: now + ALLOWED_WINDOW_DURATION,
+                    },
+                })
+            }
+        }
+    }
+}
+
+/// Mock rate limiter answering checks with the given sequence of outcomes, in order, whatever
+/// the checked request identifier. Once the sequence is exhausted, every request is allowed.
+/// The request keys of the checked identifiers are recorded, so that tests can assert on them.
+#[derive(Debug, Default)]
+pub struct SequenceRateLimiter {
+    responses: Mutex<VecDeque<MockResponse>>,
+    checked_keys: Mutex<Vec<String>>,
+}
+
+impl SequenceRateLimiter {
+    /// Creates a rate limiter answering checks with the given outcomes.
+    pub fn new(responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        SequenceRateLimiter {
+            responses: Mutex::new(responses.into_iter().collect()),
+            checked_keys: Mutex::new(vec![]),
+        }
+    }
+
+    /// Appends the given outcome to the sequence.
+    pub fn push(&self, response: MockResponse) {
+        self.responses.lock().unwrap().push_back(response);
+    }
+
+    /// Returns the request keys of the identifiers checked so far, in order.
+    pub fn checked_keys(&self) -> Vec<String> {
+        self.checked_keys.lock().unwrap().clone()
+    }
+
+    /// Returns the number of outcomes left in the sequence.
+    pub fn remaining_responses(&self) -> usize {
+        self.responses.lock().unwrap().len()
+    }
+
+    fn respond(&self, key: String) -> Result<RateLimiterResponse, RateLimiterError> {
+        self.checked_keys.lock().unwrap().push(key);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(MockResponse::Allow)
+            .into_result()
+    }
+
+    fn inspected_limit(&self) -> u64 {
+        match self.responses.lock().unwrap().front() {
+            Some(MockResponse::Throttle { .. }) => 0,
+            _ => u64::MAX,
+        }
+    }
+}
+
+/// Implements [RateLimiter] and [RedisAdmin] for the given mock, answering checks with its
+/// `respond` method, given the request key, and inspections with the limit returned by its
+/// `inspected_limit` method.
+macro_rules! impl_mock_rate_limiter {
+    ($mock:ty) => {
+        impl RateLimiter for $mock {
+            fn check_request(
+                &self,
+                request_identifier: RequestIdentifier,
+            ) -> Result<RateLimiterResponse, RateLimiterError> {
+                self.respond(self.build_request_key(request_identifier))
+            }
+        }
+
+        impl RedisAdmin for $mock {
+            fn export_identifier(
+                &self,
+                _request_identifier: RequestIdentifier,
+            ) -> Result<IdentifierData, RateLimiterError> {
+                Ok(IdentifierData { entries: vec![] })
+            }
+
+            fn purge_identifier(
+                &self,
+                _request_identifier: RequestIdentifier,
+            ) -> Result<u64, RateLimiterError> {
+                Ok(0)
+            }
+
+            fn reputation(
+                &self,
+                _request_identifier: RequestIdentifier,
+            ) -> Result<f64, RateLimiterError> {
+                Ok(0.0)
+            }
+
+            fn top_offenders(&self, _n: usize) -> Result<Vec<Offender>, RateLimiterError> {
+                Ok(vec![])
+            }
+
+            fn usage(
+                &self,
+                _request_identifier: RequestIdentifier,
+                _period: UsagePeriod,
+            ) -> Result<u64, RateLimiterError> {
+                Ok(0)
+            }
+
+            fn inspect(
+                &self,
+                request_identifier: RequestIdentifier,
+            ) -> Result<KeyInspection, RateLimiterError> {
+                Ok(KeyInspection {
+                    key: self.build_request_key(request_identifier),
+                    count: 0,
+                    limit: self.inspected_limit(),
+                    window_duration: ALLOWED_WINDOW_DURATION,
+                    overridden: false,
+                    expire_in: None,
+                })
+            }
+
+            fn list_keys(
+                &self,
+                _pattern: &str,
+                _cursor: ListCursor,
+            ) -> Result<KeyListing, RateLimiterError> {
+                Ok(KeyListing::default())
+            }
+
+            fn export_state(&self) -> Result<StateSnapshot, RateLimiterError> {
+                Ok(StateSnapshot {
+                    taken_at: SystemTime::now(),
+                    entries: vec![],
+                })
+            }
+
+            fn import_state(&self, _snapshot: &StateSnapshot) -> Result<u64, RateLimiterError> {
+                Ok(0)
+            }
+
+            fn update_limits(&self, _window_size: u64, _window_duration: Duration) {}
+
+            fn set_limit_override(
+                &self,
+                _request_identifier: RequestIdentifier,
+                _limit_override: &LimitOverride,
+            ) -> Result<(), RateLimiterError> {
+                Ok(())
+            }
+
+            fn remove_limit_override(
+                &self,
+                _request_identifier: RequestIdentifier,
+            ) -> Result<bool, RateLimiterError> {
+                Ok(false)
+            }
+
+            fn capabilities(&self) -> Result<RedisCapabilities, RateLimiterError> {
+                Ok(RedisCapabilities {
+                    version: RedisVersion::new(7, 2, 0),
+                    modules: vec![],
+                })
+            }
+        }
+    };
+}
+
+impl_mock_rate_limiter!(AlwaysAllow);
+impl_mock_rate_limiter!(AlwaysThrottle);
+impl_mock_rate_limiter!(SequenceRateLimiter);
+
+/// Represents an ephemeral Redis server, running in a container started with
+/// [testcontainers](https://docs.rs/testcontainers) and removed when dropped, so that integration
+/// tests don't depend on a Redis already running on the machine. Requires Docker, and the
+/// `testcontainers` feature.
+///
+/// ```no_run
+/// use rate_limiter_rs::{testing::RedisContainer, RateLimiter, RequestIdentifier};
+///
+/// let redis = RedisContainer::start().unwrap();
+/// let rate_limiter = redis.fixed_window().with_window_size(1).build().unwrap();
+///
+/// rate_limiter
+///     .check_request(RequestIdentifier::Custom {
+///         key: "api_key".to_string(),
+///         value: "test".to_string(),
+///     })
+///     .unwrap();
+/// ```
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+pub struct RedisContainer {
+    _container: Container<GenericImage>,
+    host: String,
+    port: u16,
+}
+
+#[cfg(any(feature = "testcontainers", all(test, feature = "redis")))]
+impl RedisContainer {
+    /// Starts a Redis server in a new container, once it accepts connections.
+    pub fn start() -> Result<Self, TestcontainersError> {
+        let (name, tag) = REDIS_IMAGE;
+        let container = GenericImage::new(name, tag)
+            .with_exposed_port(REDIS_CONTAINER_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"))
+            .start()?;
+        let host = container.get_host()?.to_string();
+        let port = container.get_host_port_ipv4(REDIS_CONTAINER_PORT.tcp())?;
+
+        Ok(RedisContainer {
+            _container: container,
+            host,
+            port,
+        })
+    }
+
+    /// Returns the settings to connect to this server.
+    pub fn redis_settings(&self) -> RedisSettings {
+        RedisSettings {
+            host: self.host.clone(),
+            port: self.port,
+            ..RedisSettings::default()
+        }
+    }
+
+    /// Returns a builder of fixed window rate limiters connected to this server.
+    pub fn fixed_window(&self) -> FixedWindowRateLimiterBuilder {
+        RateLimiterFactory::fixed_window().with_redis_settings(self.redis_settings())
+    }
+
+    /// Returns a builder of sliding window rate limiters connected to this server.
+    pub fn sliding_window(&self) -> SlidingWindowRateLimiterBuilder {
+        RateLimiterFactory::sliding_window().with_redis_settings(self.redis_settings())
+    }
+}
+
+/// Decorator injecting faults into the checks of the given rate limiter: added latency, failures
+/// like the ones of an unreachable Redis, and flipped decisions. Each fault affects the given
+/// fraction of the checks, between 0 and 1, and none is injected by default.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use rate_limiter_rs::testing::{AlwaysAllow, FaultInjector};
+///
+/// let rate_limiter = FaultInjector::new(AlwaysAllow)
+///     .with_latency(Duration::from_millis(50), 0.2)
+///     .with_error_rate(0.1)
+///     .with_flip_rate(0.05)
+///     .with_seed(42);
+/// ```
+#[derive(Debug)]
+pub struct FaultInjector<R> {
+    rate_limiter: R,
+    latency: Duration,
+    latency_rate: f64,
+    error_rate: f64,
+    flip_rate: f64,
+    seed: u64,
+    checks: AtomicU64,
+}
+
+impl<R: RateLimiter> FaultInjector<R> {
+    /// Wraps the given rate limiter, with a random seed.
+    pub fn new(rate_limiter: R) -> Self {
+        FaultInjector {
+            rate_limiter,
+            latency: Duration::ZERO,
+            latency_rate: 0.0,
+            error_rate: 0.0,
+            flip_rate: 0.0,
+            seed: RandomState::new().build_hasher().finish(),
+            checks: AtomicU64::new(0),
+        }
+    }
+
+    /// Delays the given fraction of the checks by the given latency, before they are performed.
+    pub fn with_latency(mut self, latency: Duration, rate: f64) -> Self {
+        self.latency = latency;
+        self.latency_rate = rate;
+        self
+    }
+
+    /// Fails the given fraction of the checks, without performing them, with the error returned
+    /// when Redis is unreachable.
+    pub fn with_error_rate(mut self, rate: f64) -> Self {
+        self.error_rate = rate;
+        self
+    }
+
+    /// Flips the decision of the given fraction of the checks, throttling allowed requests and
+    /// allowing throttled ones.
+    pub fn with_flip_rate(mut self, rate: f64) -> Self {
+        self.flip_rate = rate;
+        self
+    }
+
+    /// Sets the seed of the faults, to replay the same faults in the same order.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the wrapped rate limiter.
+    pub fn inner(&self) -> &R {
+        &self.rate_limiter
+    }
+
+    /// Returns whether the given fault should be injected into the given check, with the given
+    /// rate, mixing the seed, the check and the fault with SplitMix64.
+    fn roll(&self, check: u64, fault: u64, rate: f64) -> bool {
+        let mut z = self.seed
+            ^ check.wrapping_mul(0x9e3779b97f4a7c15)
+            ^ fault.wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < rate
+    }
+}
+
+/// Returns the error of a check that could not reach Redis.
+fn injected_error() -> RateLimiterError {
+    #[cfg(feature = "redis")]
+    {
+        redis::RedisError::from(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "injected fault",
+        ))
+        .into()
+    }
+    #[cfg(not(feature = "redis"))]
+    {
+        RateLimiterError::ComputeError
+    }
+}
+
+/// Returns the given response with the opposite decision and the same status.
+fn flip(response: RateLimiterResponse) -> RateLimiterResponse {
+    match response {
+        RateLimiterResponse::RequestAllowed(allowed) => {
+            RateLimiterResponse::RequestThrottled(RequestThrottled {
+                retry_in: allowed
+                    .status
+                    .reset_at
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+                reason: ThrottleReason::QuotaExceeded,
+                status: allowed.status,
+            })
+        }
+        RateLimiterResponse::RequestThrottled(throttled) => {
+            RateLimiterResponse::RequestAllowed(RequestAllowed {
+                remaining_request_counter: 0,
+                status: throttled.status,
+            })
+        }
+    }
+}
+
+impl<R: RateLimiter> RateLimiter for FaultInjector<R> {
+    fn build_request_key(&self, request_identifier: RequestIdentifier) -> String {
+        self.rate_limiter.build_request_key(request_identifier)
+    }
+
+    fn check_request(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        self.check_request_with_context(request_identifier, &RequestContext::default())
+    }
+
+    fn check_request_with_context(
+        &self,
+        request_identifier: RequestIdentifier,
+        context: &RequestContext,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        let check = self.checks.fetch_add(1, Ordering::Relaxed);
+        if self.roll(check, 0, self.latency_rate) {
+            thread::sleep(self.latency);
+        }
+        if self.roll(check, 1, self.error_rate) {
+            return Err(injected_error());
+        }
+
+        let response = self
+            .rate_limiter
+            .check_request_with_context(request_identifier, context)?;
+        Ok(if self.roll(check, 2, self.flip_rate) {
+            flip(response)
+        } else {
+            response
+        })
+    }
+}
+
+impl<R: RedisAdmin> RedisAdmin for FaultInjector<R> {
+    fn export_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<IdentifierData, RateLimiterError> {
+        self.rate_limiter.export_identifier(request_identifier)
+    }
+
+    fn purge_identifier(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<u64, RateLimiterError> {
+        self.rate_limiter.purge_identifier(request_identifier)
+    }
+
+    fn reputation(&self, request_identifier: RequestIdentifier) -> Result<f64, RateLimiterError> {
+        self.rate_limiter.reputation(request_identifier)
+    }
+
+    fn top_offenders(&self, n: usize) -> Result<Vec<Offender>, RateLimiterError> {
+        self.rate_limiter.top_offenders(n)
+    }
+
+    fn usage(
+        &self,
+        request_identifier: RequestIdentifier,
+        period: UsagePeriod,
+    ) -> Result<u64, RateLimiterError> {
+        self.rate_limiter.usage(request_identifier, period)
+    }
+
+    fn inspect(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<KeyInspection, RateLimiterError> {
+        self.rate_limiter.inspect(request_identifier)
+    }
+
+    fn list_keys(&self, pattern: &str, cursor: ListCursor) -> Result<KeyListing, RateLimiterError> {
+        self.rate_limiter.list_keys(pattern, cursor)
+    }
+
+    fn export_state(&self) -> Result<StateSnapshot, RateLimiterError> {
+        self.rate_limiter.export_state()
+    }
+
+    fn import_state(&self, snapshot: &StateSnapshot) -> Result<u64, RateLimiterError> {
+        self.rate_limiter.import_state(snapshot)
+    }
+
+    fn update_limits(&self, window_size: u64, window_duration: Duration) {
+        self.rate_limiter
+            .update_limits(window_size, window_duration)
+    }
+
+    fn set_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+        limit_override: &LimitOverride,
+    ) -> Result<(), RateLimiterError> {
+        self.rate_limiter
+            .set_limit_override(request_identifier, limit_override)
+    }
+
+    fn remove_limit_override(
+        &self,
+        request_identifier: RequestIdentifier,
+    ) -> Result<bool, RateLimiterError> {
+        self.rate_limiter.remove_limit_override(request_identifier)
+    }
+
+    fn capabilities(&self) -> Result<RedisCapabilities, RateLimiterError> {
+        self.rate_limiter.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use crate::{errors::RateLimiterError, RateLimiter, RedisAdmin, RequestIdentifier};
+
+    use super::{AlwaysAllow, AlwaysThrottle, FaultInjector, MockResponse, SequenceRateLimiter};
+
+    fn request_identifier() -> RequestIdentifier {
+        RequestIdentifier::Custom {
+            key: "api_key".to_string(),
+            value: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn should_always_allow() {
+        let rate_limiter = AlwaysAllow;
+
+        let res = rate_limiter.check_request(request_identifier()).unwrap();
+
+        res.as_allowed();
+        assert!(!rate_limiter
+            .inspect(request_identifier())
+            .unwrap()
+            .is_throttled());
+    }
+
+    #[test]
+    fn should_always_throttle() {
+        let rate_limiter = AlwaysThrottle {
+            retry_in: Duration::from_secs(30),
+        };
+
+        let throttled = rate_limiter
+            .check_request(request_identifier())
+            .unwrap()
+            .as_throttled();
+
+        assert_eq!(throttled.retry_in, Duration::from_secs(30));
+        assert_eq!(throttled.status.limit, 0);
+        assert!(rate_limiter
+            .inspect(request_identifier())
+            .unwrap()
+            .is_throttled());
+    }
+
+    #[test]
+    fn should_answer_with_the_scripted_sequence() {
+        //arrange
+        let rate_limiter = SequenceRateLimiter::new([
+            MockResponse::Throttle {
+                retry_in: Duration::from_secs(1),
+            },
+            MockResponse::Fail,
+        ]);
+
+        //act
+        let throttled_res = rate_limiter.check_request(request_identifier());
+        let failed_res = rate_limiter.check_request(request_identifier());
+        let exhausted_res = rate_limiter.check_request(request_identifier());
+
+        //assert
+        throttled_res.unwrap().as_throttled();
+        assert!(matches!(failed_res, Err(RateLimiterError::ComputeError)));
+        exhausted_res.unwrap().as_allowed();
+        assert_eq!(
+            rate_limiter.checked_keys(),
+            vec![rate_limiter.build_request_key(request_identifier()); 3]
+        );
+        assert_eq!(rate_limiter.remaining_responses(), 0);
+    }
+
+    #[test]
+    fn should_fail_checks_without_performing_them() {
+        //arrange
+        let rate_limiter = FaultInjector::new(SequenceRateLimiter::default()).with_error_rate(1.0);
+
+        //act
+        let res = rate_limiter.check_request(request_identifier());
+
+        //assert
+        #[cfg(feature = "redis")]
+        assert!(matches!(res, Err(RateLimiterError::IoError(_))));
+        #[cfg(not(feature = "redis"))]
+        assert!(matches!(res, Err(RateLimiterError::ComputeError)));
+        assert!(rate_limiter.inner().checked_keys().is_empty());
+    }
+
+    #[test]
+    fn should_flip_decisions() {
+        let rate_limiter = FaultInjector::new(AlwaysAllow).with_flip_rate(1.0);
+
+        let throttled = rate_limiter
+            .check_request(request_identifier())
+            .unwrap()
+            .as_throttled();
+
+        assert_eq!(throttled.status.limit, u64::MAX);
+        assert!(throttled.retry_in > Duration::ZERO);
+    }
+
+    #[test]
+    fn should_delay_checks() {
+        let rate_limiter =
+            FaultInjector::new(AlwaysAllow).with_latency(Duration::from_millis(20), 1.0);
+        let started_at = Instant::now();
+
+        rate_limiter
+            .check_request(request_identifier())
+            .unwrap()
+            .as_allowed();
+
+        assert!(started_at.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn should_inject_no_fault_by_default() {
+        let rate_limiter = FaultInjector::new(AlwaysAllow);
+
+        for _ in 0..100 {
+            rate_limiter
+                .check_request(request_identifier())
+                .unwrap()
+                .as_allowed();
+        }
+    }
+
+    #[test]
+    fn should_replay_the_same_faults_with_the_same_seed() {
+        //arrange
+        let outcomes = |seed: u64| {
+            let rate_limiter = FaultInjector::new(AlwaysAllow)
+                .with_error_rate(0.5)
+                .with_seed(seed);
+            (0..100)
+                .map(|_| rate_limiter.check_request(request_identifier()).is_ok())
+                .collect::<Vec<_>>()
+        };
+
+        //act
+        let first_run = outcomes(42);
+        let second_run = outcomes(42);
+
+        //assert
+        assert_eq!(first_run, second_run);
+        let failed = first_run.iter().filter(|ok| !**ok).count();
+        assert!((25..75).contains(&failed), "{} failed checks", failed);
+    }
+}
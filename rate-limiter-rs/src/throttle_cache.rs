@@ -0,0 +1,300 @@
+//! Module that includes the optional local cache of throttled verdicts, so that the requests of
+//! a throttled client are rejected in process until it may retry, without a round trip to Redis.
+//! Under attack, most of the load on Redis comes from clients that are already throttled.
+//!
+//! ## Implementation details
+//!
+//! When a check throttles a request, its verdict is cached under the request key until the
+//! retry time of the response. The following checks of the same key, until then, are answered
+//! from the cache with a throttled response, whose retry time counts down to the cached one.
+//!
+//! Requests rejected from the cache are not counted in Redis, so a sliding window client keeps
+//! being throttled until the cached retry time only, instead of for as long as it keeps sending
+//! requests. Also, the cache is local to each process, so the other nodes sharing the window
+//! still check the requests of the client against Redis.
+//!
+//! The cache holds at most the configured number of verdicts: once full, the expired verdicts
+//! are dropped, and new verdicts are not cached until some room is freed. Purging a request
+//! identifier or changing its limits drops its verdict, and updating the limits of the rate
+//! limiter drops them all.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    errors::RateLimiterError, RateLimitStatus, RateLimiterResponse, RequestThrottled,
+    ThrottleReason,
+};
+
+/// Represents a cached throttled verdict
+#[derive(Debug, Clone, Copy)]
+struct CachedVerdict {
+    /// the point in time when the request key may retry
+    retry_at: SystemTime,
+    /// the reason why the requests are throttled
+    reason: ThrottleReason,
+    /// the maximum number of requests allowed in a single window
+    limit: u64,
+    /// the duration of the window
+    window_duration: Duration,
+    /// the number of requests counted in the window, when the verdict was cached
+    used: u64,
+    /// the point in time when the budget of the window is restored
+    reset_at: SystemTime,
+}
+
+/// Represents the local cache of the throttled verdicts of a rate limiter, shared by its clones
+#[derive(Debug, Clone)]
+pub struct ThrottleCache {
+    /// The maximum number of verdicts held at once
+    pub max_entries: usize,
+
+    /// The cached verdicts, by request key
+    verdicts: Arc<Mutex<HashMap<String, CachedVerdict>>>,
+}
+
+impl ThrottleCache {
+    /// Creates an empty cache holding at most the given number of verdicts.
+    pub(crate) fn new(max_entries: usize) -> Self {
+        ThrottleCache {
+            max_entries,
+            verdicts: Arc::default(),
+        }
+    }
+
+    /// Answers the check of the given key at the given time from the cache, if the key is still
+    /// throttled, or else with the given check, caching its verdict if throttled.
+    pub(crate) fn check(
+        &self,
+        key: &str,
+        now: SystemTime,
+        check: impl FnOnce() -> Result<RateLimiterResponse, RateLimiterError>,
+    ) -> Result<RateLimiterResponse, RateLimiterError> {
+        if let Some(response) = self.cached_response(key, now) {
+            return Ok(response);
+        }
+
+        let response = check()?;
+        if let RateLimiterResponse::RequestThrottled(throttled) = &response {
+            self.insert(key, now, throttled);
+        }
+        Ok(response)
+    }
+
+    /// Drops the cached verdict of the given key, if any.
+    pub(crate) fn forget(&self, key: &str) {
+        self.verdicts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(key);
+    }
+
+    /// Drops all the cached verdicts.
+    pub(crate) fn clear(&self) {
+        self.verdicts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Returns the throttled response of the given key at the given time, if its verdict is
+    /// cached and not expired yet.
+    fn cached_response(&self, key: &str, now: SystemTime) -> Option<RateLimiterResponse> {
+        let mut verdicts = self.verdicts.lock().unwrap_or_else(PoisonError::into_inner);
+        let verdict = *verdicts.get(key)?;
+        let Some(retry_in) = verdict
+            .retry_at
+            .duration_since(now)
+            .ok()
+            .filter(|retry_in| !retry_in.is_zero())
+        else {
+            verdicts.remove(key);
+            return None;
+        };
+
+        Some(RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in,
+            reason: verdict.reason,
+            status: RateLimitStatus {
+                limit: verdict.limit,
+                window_duration: verdict.window_duration,
+                used: verdict.used,
+                reset_at: verdict.reset_at,
+            },
+        }))
+    }
+
+    /// Caches the given throttled verdict of the given key, if there's room for it.
+    fn insert(&self, key: &str, now: SystemTime, throttled: &RequestThrottled) {
+        let mut verdicts = self.verdicts.lock().unwrap_or_else(PoisonError::into_inner);
+        if verdicts.len() >= self.max_entries && !verdicts.contains_key(key) {
+            verdicts.retain(|_, verdict| verdict.retry_at > now);
+            if verdicts.len() >= self.max_entries {
+                return;
+            }
+        }
+
+        verdicts.insert(
+            key.to_string(),
+            CachedVerdict {
+                retry_at: now + throttled.retry_in,
+                reason: throttled.reason,
+                limit: throttled.status.limit,
+                window_duration: throttled.status.window_duration,
+                used: throttled.status.used,
+                reset_at: throttled.status.reset_at,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        panic,
+        time::{Duration, SystemTime},
+    };
+
+    use super::ThrottleCache;
+    use crate::{
+        errors::RateLimiterError, RateLimitStatus, RateLimiterResponse, RequestAllowed,
+        RequestThrottled, ThrottleReason,
+    };
+
+    #[test]
+    fn should_answer_throttled_key_from_cache_until_retry_time() {
+        //arrange
+        let cache = ThrottleCache::new(10);
+        let checks = Cell::new(0);
+        let check = || {
+            checks.set(checks.get() + 1);
+            Ok(throttled(Duration::from_secs(10)))
+        };
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        //act
+        cache.check("rl:a", now, check).unwrap().as_throttled();
+        let cached_res = cache
+            .check("rl:a", now + Duration::from_secs(4), check)
+            .unwrap()
+            .as_throttled();
+        cache
+            .check("rl:a", now + Duration::from_secs(10), check)
+            .unwrap();
+
+        //assert
+        assert_eq!(cached_res.retry_in, Duration::from_secs(6));
+        assert_eq!(cached_res.reason, ThrottleReason::QuotaExceeded);
+        assert_eq!(cached_res.status.used, 6);
+        assert_eq!(checks.get(), 2);
+    }
+
+    #[test]
+    fn should_not_cache_allowed_verdicts_nor_errors() {
+        //arrange
+        let cache = ThrottleCache::new(10);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        //act
+        cache.check("rl:a", now, || Ok(allowed())).unwrap();
+        let _ = cache.check("rl:b", now, || Err(RateLimiterError::ComputeError));
+
+        //assert
+        assert!(cache.verdicts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_make_room_for_new_verdicts_dropping_expired_ones() {
+        //arrange
+        let cache = ThrottleCache::new(2);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        cache
+            .check("rl:a", now, || Ok(throttled(Duration::from_secs(1))))
+            .unwrap();
+        cache
+            .check("rl:b", now, || Ok(throttled(Duration::from_secs(10))))
+            .unwrap();
+
+        //act
+        cache
+            .check("rl:c", now, || Ok(throttled(Duration::from_secs(10))))
+            .unwrap();
+        cache
+            .check("rl:d", now + Duration::from_secs(1), || {
+                Ok(throttled(Duration::from_secs(10)))
+            })
+            .unwrap();
+
+        //assert
+        let verdicts = cache.verdicts.lock().unwrap();
+        assert_eq!(verdicts.len(), 2);
+        assert!(verdicts.contains_key("rl:b"));
+        assert!(verdicts.contains_key("rl:d"));
+    }
+
+    #[test]
+    fn should_forget_verdicts() {
+        //arrange
+        let cache = ThrottleCache::new(10);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        for key in ["rl:a", "rl:b", "rl:c"] {
+            cache
+                .check(key, now, || Ok(throttled(Duration::from_secs(10))))
+                .unwrap();
+        }
+
+        //act & assert
+        cache.forget("rl:a");
+        assert_eq!(cache.verdicts.lock().unwrap().len(), 2);
+        cache.clear();
+        assert!(cache.verdicts.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_keep_caching_verdicts_once_poisoned() {
+        //arrange
+        let cache = ThrottleCache::new(10);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let _ = panic::catch_unwind(|| {
+            let _verdicts = cache.verdicts.lock().unwrap();
+            panic!("poisoning the verdicts");
+        });
+
+        //act
+        cache
+            .check("rl:a", now, || Ok(throttled(Duration::from_secs(10))))
+            .unwrap();
+        let cached_res = cache.check("rl:a", now, || Ok(allowed())).unwrap();
+
+        //assert
+        assert!(cache.verdicts.is_poisoned());
+        cached_res.as_throttled();
+    }
+
+    fn allowed() -> RateLimiterResponse {
+        RateLimiterResponse::RequestAllowed(RequestAllowed {
+            remaining_request_counter: 4,
+            status: status(1),
+        })
+    }
+
+    fn throttled(retry_in: Duration) -> RateLimiterResponse {
+        RateLimiterResponse::RequestThrottled(RequestThrottled {
+            retry_in,
+            reason: ThrottleReason::QuotaExceeded,
+            status: status(6),
+        })
+    }
+
+    fn status(used: u64) -> RateLimitStatus {
+        RateLimitStatus {
+            limit: 5,
+            window_duration: Duration::from_secs(60),
+            used,
+            reset_at: SystemTime::UNIX_EPOCH,
+        }
+    }
+}
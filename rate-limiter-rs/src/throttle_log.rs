@@ -0,0 +1,132 @@
+//! Module that includes the structured logging of throttled requests, so that security teams can
+//! feed the events to SIEM tooling.
+//!
+//! ## Implementation details
+//!
+//! The [ThrottleLogger] is an [observer](crate::observer::RateLimiterObserver) emitting one event
+//! per throttled request, with the `rate_limiter::throttle` log target and a fixed set of
+//! `key=value` fields:
+//!
+//! ```text
+//! request throttled: key=rl:ip_172.28.0.6 reason=QuotaExceeded limit=100 used=101 retry_in_ms=5320 suppressed=0
+//! ```
+//!
+//! As an attacker can trigger throttles at will, the events are rate limited themselves, so that
+//! an attack doesn't turn into a log storm: at most the configured number of events is emitted
+//! per interval, and the events suppressed in between are counted by the next emitted event.
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{observer::RateLimiterObserver, RequestThrottled};
+
+/// The target of the throttle events, to route them apart from the other logs
+pub const THROTTLE_LOG_TARGET: &str = "rate_limiter::throttle";
+
+/// Observer that logs a structured event on every throttled request, up to a maximum number of
+/// events per interval.
+#[derive(Debug)]
+pub struct ThrottleLogger {
+    max_events: u32,
+    interval: Duration,
+    state: Mutex<LogBudget>,
+}
+
+/// Represents the events emitted in the current interval, and the ones suppressed since the
+/// last emitted event
+#[derive(Debug, Default)]
+struct LogBudget {
+    interval_start: Option<Instant>,
+    emitted: u32,
+    suppressed: u64,
+}
+
+impl ThrottleLogger {
+    /// Creates a logger emitting at most the given number of events per interval.
+    pub fn new(max_events: u32, interval: Duration) -> Self {
+        ThrottleLogger {
+            max_events,
+            interval,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Returns whether an event can be emitted at the given time, with the number of events
+    /// suppressed since the last emitted one. Otherwise, counts the event as suppressed.
+    fn admit(&self, now: Instant) -> Option<u64> {
+        let mut budget = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        match budget.interval_start {
+            Some(start) if now.saturating_duration_since(start) < self.interval => {}
+            _ => {
+                budget.interval_start = Some(now);
+                budget.emitted = 0;
+            }
+        }
+
+        if budget.emitted >= self.max_events {
+            budget.suppressed += 1;
+            return None;
+        }
+        budget.emitted += 1;
+        Some(std::mem::take(&mut budget.suppressed))
+    }
+}
+
+impl RateLimiterObserver for ThrottleLogger {
+    fn on_throttled(&self, key: &str, throttled: &RequestThrottled, _latency: Duration) {
+        let Some(suppressed) = self.admit(Instant::now()) else {
+            return;
+        };
+
+        log::warn!(
+            target: THROTTLE_LOG_TARGET,
+            "request throttled: key={} reason={:?} limit={} used={} retry_in_ms={} suppressed={}",
+            key,
+            throttled.reason,
+            throttled.status.limit,
+            throttled.status.used,
+            throttled.retry_in.as_millis(),
+            suppressed
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::ThrottleLogger;
+
+    #[test]
+    fn should_emit_up_to_max_events_per_interval() {
+        let logger = ThrottleLogger::new(2, Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert_eq!(logger.admit(now), Some(0));
+        assert_eq!(logger.admit(now), Some(0));
+        assert_eq!(logger.admit(now + Duration::from_millis(500)), None);
+        assert_eq!(logger.admit(now + Duration::from_millis(900)), None);
+    }
+
+    #[test]
+    fn should_report_suppressed_events_in_next_interval() {
+        let logger = ThrottleLogger::new(1, Duration::from_secs(1));
+        let now = Instant::now();
+
+        assert_eq!(logger.admit(now), Some(0));
+        assert_eq!(logger.admit(now), None);
+        assert_eq!(logger.admit(now), None);
+
+        assert_eq!(logger.admit(now + Duration::from_secs(1)), Some(2));
+        assert_eq!(logger.admit(now + Duration::from_secs(2)), Some(0));
+    }
+
+    #[test]
+    fn should_suppress_all_events_without_budget() {
+        let logger = ThrottleLogger::new(0, Duration::from_secs(1));
+
+        assert_eq!(logger.admit(Instant::now()), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Module that includes the extraction of request identifiers from the metadata of
+//! [tonic](https://docs.rs/tonic) requests, like a tenant id or an API key, mirroring the
+//! [API key header extraction](crate::api_key) of HTTP services, so that gRPC services can rate
+//! limit requests by caller. Requires the `tonic` feature.
+//!
+//! ```
+//! use rate_limiter_rs::{api_key::ApiKeyExtractor, tonic::MetadataKeyExtractor, RequestIdentifier};
+//! use tonic::metadata::MetadataMap;
+//!
+//! let key_extractor = MetadataKeyExtractor::new()
+//!     .with_tenant_id("x-tenant-id")
+//!     .with_api_key(ApiKeyExtractor::new("x-api-key").with_hashing(true))
+//!     .with_ip_fallback(true);
+//!
+//! let mut metadata = MetadataMap::new();
+//! metadata.insert("x-tenant-id", "acme".parse().unwrap());
+//! metadata.insert("x-api-key", "secret".parse().unwrap());
+//!
+//! // the tenant id takes precedence over the API key
+//! let request_identifier = key_extractor.extract(&metadata, None);
+//! assert!(matches!(
+//!     request_identifier,
+//!     Some(RequestIdentifier::Custom { key, value }) if key == "tenant_id" && value == "acme"
+//! ));
+//! ```
+//!
+//! ## Implementation details
+//!
+//! Request identifiers are extracted in the following order, and the first one found wins:
+//!
+//! 1. the tenant id, as a [custom identifier](crate::RequestIdentifier::Custom) named
+//!    `tenant_id`, like `rl:cst_tenant_id:acme`, so that all the keys of a tenant share its quota;
+//! 2. the API key, as extracted by the given [ApiKeyExtractor], hashed if configured so;
+//! 3. the IP address of the client, when the fallback is enabled.
+//!
+//! Metadata entries that are missing, empty, binary or not visible ASCII are skipped. Requests
+//! without any identifier are not identified. The fallback of the given [ApiKeyExtractor] is
+//! ignored, in favour of the one of the [MetadataKeyExtractor].
+use std::net::IpAddr;
+
+use tonic::{metadata::MetadataMap, Request};
+
+use crate::{api_key::ApiKeyExtractor, RequestIdentifier};
+
+/// The name of the custom request identifiers holding tenant ids
+pub const TENANT_ID_IDENTIFIER: &str = "tenant_id";
+
+/// Extractor of request identifiers from the metadata of gRPC requests
+#[derive(Debug, Clone, Default)]
+pub struct MetadataKeyExtractor {
+    tenant_id_key: Option<String>,
+    api_key_extractor: Option<ApiKeyExtractor>,
+    ip_fallback: bool,
+}
+
+impl MetadataKeyExtractor {
+    /// Creates an extractor that doesn't identify any request, until configured.
+    pub fn new() -> Self {
+        MetadataKeyExtractor::default()
+    }
+
+    /// Identifies requests by the tenant id carried by the given metadata key, like
+    /// `x-tenant-id`, before any other identifier.
+    pub fn with_tenant_id(mut self, key: impl Into<String>) -> Self {
+        self.tenant_id_key = Some(key.into());
+        self
+    }
+
+    /// Identifies the requests without a tenant id by the API key extracted by the given
+    /// extractor, from the metadata key named after its header.
+    pub fn with_api_key(mut self, api_key_extractor: ApiKeyExtractor) -> Self {
+        self.api_key_extractor = Some(api_key_extractor);
+        self
+    }
+
+    /// Identifies the requests without a tenant id or an API key by the IP address of the client.
+    pub fn with_ip_fallback(mut self, ip_fallback: bool) -> Self {
+        self.ip_fallback = ip_fallback;
+        self
+    }
+
+    /// Returns the request identifier of a request, given its metadata and the IP address of its
+    /// client, if any.
+    pub fn extract(
+        &self,
+        metadata: &MetadataMap,
+        client_ip: Option<IpAddr>,
+    ) -> Option<RequestIdentifier> {
+        let value = |key: &str| {
+            metadata
+                .get(key)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(tenant_id) = self.tenant_id_key.as_deref().and_then(value) {
+            return Some(RequestIdentifier::Custom {
+                key: TENANT_ID_IDENTIFIER.to_string(),
+                value: tenant_id.to_string(),
+            });
+        }
+        if let Some(request_identifier) = self
+            .api_key_extractor
+            .as_ref()
+            .and_then(|extractor| extractor.extract(value(extractor.header()), None))
+        {
+            return Some(request_identifier);
+        }
+        client_ip
+            .filter(|_| self.ip_fallback)
+            .map(RequestIdentifier::Ip)
+    }
+
+    /// Returns the request identifier of the given request, falling back to the address of the
+    /// peer of the connection, if known to the transport.
+    pub fn extract_from_request<T>(&self, request: &Request<T>) -> Option<RequestIdentifier> {
+        self.extract(
+            request.metadata(),
+            request.remote_addr().map(|addr| addr.ip()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use rstest::rstest;
+    use tonic::metadata::MetadataMap;
+
+    use super::MetadataKeyExtractor;
+    use crate::{api_key::ApiKeyExtractor, RequestIdentifier};
+
+    const CLIENT_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(172, 28, 0, 6));
+
+    fn key_extractor() -> MetadataKeyExtractor {
+        MetadataKeyExtractor::new()
+            .with_tenant_id("x-tenant-id")
+            .with_api_key(ApiKeyExtractor::new("x-api-key"))
+            .with_ip_fallback(true)
+    }
+
+    fn metadata(entries: &[(&'static str, &str)]) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        for (key, value) in entries {
+            metadata.insert(*key, value.parse().unwrap());
+        }
+        metadata
+    }
+
+    #[rstest]
+    #[case::tenant_id(&[("x-tenant-id", "acme"), ("x-api-key", "secret")], "tenant_id", "acme")]
+    #[case::api_key(&[("x-api-key", "secret")], "api_key", "secret")]
+    #[case::empty_tenant_id(&[("x-tenant-id", " "), ("x-api-key", "secret")], "api_key", "secret")]
+    fn should_identify_requests_by_precedence(
+        #[case] entries: &[(&'static str, &str)],
+        #[case] expected_key: &str,
+        #[case] expected_value: &str,
+    ) {
+        let request_identifier = key_extractor().extract(&metadata(entries), Some(CLIENT_IP));
+
+        assert!(matches!(
+            request_identifier,
+            Some(RequestIdentifier::Custom { key, value }) if key == expected_key && value == expected_value
+        ))
+    }
+
+    #[test]
+    fn should_fall_back_to_client_ip() {
+        let metadata = metadata(&[("x-request-id", "42")]);
+
+        assert!(matches!(
+            key_extractor().extract(&metadata, Some(CLIENT_IP)),
+            Some(RequestIdentifier::Ip(ip)) if ip == CLIENT_IP
+        ));
+        assert!(key_extractor()
+            .with_ip_fallback(false)
+            .extract(&metadata, Some(CLIENT_IP))
+            .is_none());
+    }
+}
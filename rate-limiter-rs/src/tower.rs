@@ -0,0 +1,232 @@
+//! Module that includes the [tower](https://docs.rs/tower) middleware of the rate limiter, so that
+//! it can be added to any tower based stack, like [axum](https://docs.rs/axum),
+//! [tonic](https://docs.rs/tonic) or [hyper](https://docs.rs/hyper). Requires the `tower` feature.
+//!
+//! ```no_run
+//! use std::{net::SocketAddr, sync::Arc};
+//!
+//! use rate_limiter_rs::{factory::RateLimiterFactory, tower::RateLimitLayer, RequestIdentifier};
+//!
+//! let rate_limiter = RateLimiterFactory::fixed_window().build().unwrap();
+//! let layer = RateLimitLayer::new(Arc::new(rate_limiter), |request: &http::Request<()>| {
+//!     request
+//!         .extensions()
+//!         .get::<SocketAddr>()
+//!         .map(|addr| RequestIdentifier::Ip(addr.ip()))
+//! });
+//! ```
+//!
+//! ## Implementation details
+//!
+//! The request identifier of every request is extracted with the given closure, and requests
+//! without an identifier are not rate limited. Throttled requests are answered with a
+//! `429 Too Many Requests` status, or with a `503 Service Unavailable` status when shed, along
+//! with a `Retry-After` header in whole seconds, rounded up. They never reach the inner service.
+//! The responses to allowed and throttled requests carry the [rate limit headers](crate::headers).
+//!
+//! Checks are performed on the task calling the service, blocking it for the duration of the
+//! Redis round trip. Requests are let through when the check fails, as the middleware can't tell
+//! a misbehaving client apart from an unavailable Redis: [circuit breakers](../breaker/index.html)
+//! should be configured on the rate limiter to serve checks differently.
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{
+    responses::{check_http_request, insert_headers, CheckOutcome},
+    RateLimiter, RequestIdentifier,
+};
+
+/// Layer applying the [RateLimit] middleware to the services it wraps
+pub struct RateLimitLayer<R: ?Sized, F> {
+    rate_limiter: Arc<R>,
+    key_extractor: F,
+}
+
+impl<R: ?Sized, F> RateLimitLayer<R, F> {
+    /// Creates a layer checking the requests against the given rate limiter, with the request
+    /// identifier extracted by the given closure. Requests without an identifier are not rate
+    /// limited.
+    pub fn new(rate_limiter: Arc<R>, key_extractor: F) -> Self {
+        RateLimitLayer {
+            rate_limiter,
+            key_extractor,
+        }
+    }
+}
+
+impl<R: ?Sized, F: Clone> Clone for RateLimitLayer<R, F> {
+    fn clone(&self) -> Self {
+        RateLimitLayer {
+            rate_limiter: self.rate_limiter.clone(),
+            key_extractor: self.key_extractor.clone(),
+        }
+    }
+}
+
+impl<S, R: ?Sized, F: Clone> Layer<S> for RateLimitLayer<R, F> {
+    type Service = RateLimit<S, R, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            rate_limiter: self.rate_limiter.clone(),
+            key_extractor: self.key_extractor.clone(),
+        }
+    }
+}
+
+/// Middleware answering the throttled requests on behalf of the service it wraps
+pub struct RateLimit<S, R: ?Sized, F> {
+    inner: S,
+    rate_limiter: Arc<R>,
+    key_extractor: F,
+}
+
+impl<S: Clone, R: ?Sized, F: Clone> Clone for RateLimit<S, R, F> {
+    fn clone(&self) -> Self {
+        RateLimit {
+            inner: self.inner.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            key_extractor: self.key_extractor.clone(),
+        }
+    }
+}
+
+/// The future returned by the [RateLimit] middleware
+pub type ResponseFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
+
+impl<S, R, F, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimit<S, R, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    R: RateLimiter + ?Sized,
+    F: Fn(&Request<ReqBody>) -> Option<RequestIdentifier>,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = ResponseFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let request_identifier = (self.key_extractor)(&request);
+        match check_http_request(&*self.rate_limiter, request_identifier) {
+            CheckOutcome::Throttled(response) => Box::pin(ready(Ok(response))),
+            CheckOutcome::Allowed(rate_limit_headers) => {
+                let response = self.inner.call(request);
+                Box::pin(async move {
+                    let mut response = response.await?;
+                    insert_headers(response.headers_mut(), &rate_limit_headers);
+                    Ok(response)
+                })
+            }
+            CheckOutcome::Unchecked => Box::pin(self.inner.call(request)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "redis"))]
+mod test {
+    use std::{
+        convert::Infallible,
+        future::{ready, Future, Ready},
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+        task::{Context, Poll, Waker},
+        time::Duration,
+    };
+
+    use http::{Request, Response, StatusCode};
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    use super::RateLimitLayer;
+    use crate::{factory::RateLimiterFactory, redis_mock::RedisMock, RequestIdentifier};
+
+    /// Service answering every request with a `200 OK` status
+    struct Ok200;
+
+    impl Service<Request<()>> for Ok200 {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<String>, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _request: Request<()>) -> Self::Future {
+            ready(Ok(Response::new("ok".to_string())))
+        }
+    }
+
+    fn poll_now<F: Future>(future: F) -> F::Output {
+        match std::pin::pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("the future is not ready"),
+        }
+    }
+
+    #[test]
+    fn should_throttle_requests_through_layer_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let mut service = RateLimitLayer::new(Arc::new(rate_limiter), move |_: &Request<()>| {
+            Some(RequestIdentifier::Ip(ip))
+        })
+        .layer(Ok200);
+
+        //act
+        let first = poll_now(service.call(Request::new(()))).unwrap();
+        let second = poll_now(service.call(Request::new(()))).unwrap();
+
+        //assert
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(first.headers().get("ratelimit-remaining").unwrap(), "0");
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(second.headers().contains_key("retry-after"));
+    }
+
+    #[test]
+    fn should_not_rate_limit_requests_without_identifier_against_redis_mock() {
+        //arrange
+        let redis_mock = RedisMock::start();
+        let rate_limiter = RateLimiterFactory::fixed_window()
+            .with_window_size(1)
+            .with_window_duration(Duration::from_secs(60))
+            .with_redis_settings(redis_mock.redis_settings())
+            .build()
+            .unwrap();
+        let mut service =
+            RateLimitLayer::new(Arc::new(rate_limiter), |_: &Request<()>| None).layer(Ok200);
+
+        //act
+        let responses: Vec<_> = (0..3)
+            .map(|_| poll_now(service.call(Request::new(()))).unwrap())
+            .collect();
+
+        //assert
+        assert!(responses
+            .iter()
+            .all(|response| response.status() == StatusCode::OK));
+    }
+}
@@ -0,0 +1,251 @@
+//! Module that includes the optional usage reporting, that aggregates the requests allowed for
+//! every request identifier per day and per month, so that API products can drive billing and
+//! quota dashboards from the rate limiter itself.
+//!
+//! ## Implementation details
+//!
+//! Every allowed request increments a counter of its request key for the current UTC day, and
+//! one for the current UTC month:
+//!
+//! ```text
+//! rl:ip_172.28.0.6:usage:2026-10-16
+//! rl:ip_172.28.0.6:usage:2026-10
+//! ```
+//!
+//! Daily counters are retained for [DAY_RETENTION_DAYS] days and monthly counters for
+//! [MONTH_RETENTION_MONTHS] months since the last request counted, so that the previous month
+//! can always be billed.
+#[cfg(feature = "redis")]
+use std::time::Duration;
+use std::{fmt, time::SystemTime};
+
+#[cfg(feature = "redis")]
+use redis::Connection;
+
+#[cfg(feature = "redis")]
+use crate::{errors::RateLimiterError, rate_limiters::as_expiry_millis};
+
+/// The number of days daily counters are retained for
+pub const DAY_RETENTION_DAYS: u32 = 62;
+
+/// The number of months monthly counters are retained for
+pub const MONTH_RETENTION_MONTHS: u32 = 13;
+
+/// The number of seconds in a day
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Enum that represents the UTC calendar periods usage is aggregated over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsagePeriod {
+    /// A calendar day
+    Day { year: i32, month: u32, day: u32 },
+    /// A calendar month
+    Month { year: i32, month: u32 },
+}
+
+impl UsagePeriod {
+    /// Returns the day including the given time. Times before the epoch fall on its first day.
+    pub fn day_of(time: SystemTime) -> Self {
+        let (year, month, day) = civil_from_days(epoch_days(time));
+        UsagePeriod::Day { year, month, day }
+    }
+
+    /// Returns the month including the given time. Times before the epoch fall on its first month.
+    pub fn month_of(time: SystemTime) -> Self {
+        let (year, month, _) = civil_from_days(epoch_days(time));
+        UsagePeriod::Month { year, month }
+    }
+
+    /// Returns the month preceding this period.
+    #[cfg(feature = "redis")]
+    fn previous_month(self) -> Self {
+        match self {
+            UsagePeriod::Day { year, month, .. } | UsagePeriod::Month { year, month } => {
+                if month == 1 {
+                    UsagePeriod::Month {
+                        year: year - 1,
+                        month: 12,
+                    }
+                } else {
+                    UsagePeriod::Month {
+                        year,
+                        month: month - 1,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for UsagePeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsagePeriod::Day { year, month, day } => {
+                write!(f, "{:04}-{:02}-{:02}", year, month, day)
+            }
+            UsagePeriod::Month { year, month } => write!(f, "{:04}-{:02}", year, month),
+        }
+    }
+}
+
+/// Counts an allowed request of the given key, in the day and month including the given time.
+#[cfg(feature = "redis")]
+pub(crate) fn record_usage(
+    con: &mut Connection,
+    key: &str,
+    now: SystemTime,
+) -> Result<(), RateLimiterError> {
+    let day_key = usage_key(key, UsagePeriod::day_of(now));
+    let month_key = usage_key(key, UsagePeriod::month_of(now));
+
+    redis::pipe()
+        .cmd("INCR")
+        .arg(&day_key)
+        .ignore()
+        .cmd("PEXPIRE")
+        .arg(&day_key)
+        .arg(as_expiry_millis(retention(DAY_RETENTION_DAYS)))
+        .ignore()
+        .cmd("INCR")
+        .arg(&month_key)
+        .ignore()
+        .cmd("PEXPIRE")
+        .arg(&month_key)
+        .arg(as_expiry_millis(retention(MONTH_RETENTION_MONTHS * 31)))
+        .ignore()
+        .query::<()>(con)?;
+    Ok(())
+}
+
+/// Returns the requests of the given key allowed in the given period.
+#[cfg(feature = "redis")]
+pub(crate) fn read_usage(
+    con: &mut Connection,
+    key: &str,
+    period: UsagePeriod,
+) -> Result<u64, RateLimiterError> {
+    let usage: Option<u64> = redis::cmd("GET").arg(usage_key(key, period)).query(con)?;
+    Ok(usage.unwrap_or(0))
+}
+
+/// Utility method that returns the keys of all the counters of the given key that might still be
+/// retained at the given time, to serve data-subject requests.
+#[cfg(feature = "redis")]
+pub(crate) fn retained_usage_keys(key: &str, now: SystemTime) -> Vec<String> {
+    let days = (0..=DAY_RETENTION_DAYS).map(|days_ago| {
+        UsagePeriod::day_of(
+            now.checked_sub(Duration::from_secs(days_ago as u64 * SECONDS_PER_DAY))
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        )
+    });
+    let months = std::iter::successors(Some(UsagePeriod::month_of(now)), |month| {
+        Some(month.previous_month())
+    })
+    .take(MONTH_RETENTION_MONTHS as usize + 1);
+
+    days.chain(months)
+        .map(|period| usage_key(key, period))
+        .collect()
+}
+
+/// Utility method that returns the key holding the usage of the given request key in the given period.
+#[cfg(feature = "redis")]
+fn usage_key(key: &str, period: UsagePeriod) -> String {
+    format!("{}:usage:{}", key, period)
+}
+
+/// Utility method that returns the given number of days as a duration.
+#[cfg(feature = "redis")]
+fn retention(days: u32) -> Duration {
+    Duration::from_secs(days as u64 * SECONDS_PER_DAY)
+}
+
+/// Utility method that returns the number of whole days between the epoch and the given time.
+fn epoch_days(time: SystemTime) -> i64 {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_secs() / SECONDS_PER_DAY) as i64
+}
+
+/// Utility method that converts days since the epoch into a year, month and day of the proleptic
+/// Gregorian calendar, as per Howard Hinnant's `civil_from_days` algorithm.
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = (year_of_era + era * 400 + i64::from(month <= 2)) as i32;
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, SystemTime};
+
+    use rstest::rstest;
+
+    use super::UsagePeriod;
+    #[cfg(feature = "redis")]
+    use super::{retained_usage_keys, usage_key, DAY_RETENTION_DAYS, MONTH_RETENTION_MONTHS};
+
+    #[rstest]
+    #[case::epoch(0, "1970-01-01", "1970-01")]
+    #[case::leap_day(1_709_208_000, "2024-02-29", "2024-02")]
+    #[case::end_of_year(1_767_225_599, "2025-12-31", "2025-12")]
+    #[case::start_of_year(1_767_225_600, "2026-01-01", "2026-01")]
+    fn should_compute_utc_periods(
+        #[case] epoch_seconds: u64,
+        #[case] expected_day: &str,
+        #[case] expected_month: &str,
+    ) {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(epoch_seconds);
+
+        assert_eq!(UsagePeriod::day_of(time).to_string(), expected_day);
+        assert_eq!(UsagePeriod::month_of(time).to_string(), expected_month);
+    }
+
+    #[test]
+    #[cfg(feature = "redis")]
+    fn should_build_usage_key() {
+        assert_eq!(
+            usage_key(
+                "rl:ip_1.2.3.4",
+                UsagePeriod::Month {
+                    year: 2026,
+                    month: 3
+                }
+            ),
+            "rl:ip_1.2.3.4:usage:2026-03"
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "redis")]
+    fn should_list_retained_usage_keys() {
+        // 2026-01-01
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_767_225_600);
+
+        let keys = retained_usage_keys("rl:ip_1.2.3.4", now);
+
+        assert_eq!(
+            keys.len(),
+            (DAY_RETENTION_DAYS + MONTH_RETENTION_MONTHS + 2) as usize
+        );
+        assert!(keys.contains(&"rl:ip_1.2.3.4:usage:2026-01-01".to_string()));
+        assert!(keys.contains(&"rl:ip_1.2.3.4:usage:2025-12-31".to_string()));
+        assert!(keys.contains(&"rl:ip_1.2.3.4:usage:2026-01".to_string()));
+        assert!(keys.contains(&"rl:ip_1.2.3.4:usage:2024-12".to_string()));
+    }
+}